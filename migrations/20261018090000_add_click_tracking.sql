@@ -0,0 +1,10 @@
+ALTER TABLE t_newsletter_issues ADD COLUMN track_clicks BOOLEAN NOT NULL DEFAULT false;
+CREATE TABLE t_link_clicks (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES t_newsletter_issues (newsletter_issue_id),
+    subscriber_id uuid NOT NULL
+        REFERENCES t_subscriptions (id),
+    url VARCHAR NOT NULL,
+    clicked_at TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX idx_link_clicks_newsletter_issue_id ON t_link_clicks (newsletter_issue_id);
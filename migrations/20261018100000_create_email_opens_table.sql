@@ -0,0 +1,10 @@
+CREATE TABLE t_email_opens (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES t_newsletter_issues (newsletter_issue_id),
+    subscriber_id uuid NOT NULL
+        REFERENCES t_subscriptions (id),
+    open_count INTEGER NOT NULL,
+    first_opened_at TIMESTAMPTZ NOT NULL,
+    last_opened_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
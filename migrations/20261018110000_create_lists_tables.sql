@@ -0,0 +1,25 @@
+CREATE TABLE t_lists (
+    list_id uuid NOT NULL,
+    name VARCHAR NOT NULL UNIQUE,
+    is_default BOOLEAN NOT NULL DEFAULT false,
+    created_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (list_id)
+);
+-- at most one list can be the default one
+CREATE UNIQUE INDEX idx_lists_is_default ON t_lists (is_default) WHERE is_default;
+
+CREATE TABLE t_list_memberships (
+    list_id uuid NOT NULL
+        REFERENCES t_lists (list_id),
+    subscriber_id uuid NOT NULL
+        REFERENCES t_subscriptions (id),
+    PRIMARY KEY (list_id, subscriber_id)
+);
+CREATE INDEX idx_list_memberships_subscriber_id ON t_list_memberships (subscriber_id);
+
+-- everybody who subscribed before lists existed belongs to the default list
+INSERT INTO t_lists (list_id, name, is_default, created_at)
+VALUES ('5b1f8ac3-6f0e-4bb1-9d0a-0c5d8cb0e2a4', 'Newsletter', true, now());
+INSERT INTO t_list_memberships (list_id, subscriber_id)
+SELECT '5b1f8ac3-6f0e-4bb1-9d0a-0c5d8cb0e2a4', id
+FROM t_subscriptions;
@@ -0,0 +1,20 @@
+ALTER TABLE t_subscriptions
+    ADD COLUMN digest_frequency VARCHAR NOT NULL DEFAULT 'immediate',
+    ADD COLUMN paused_until TIMESTAMPTZ NULL;
+
+ALTER TABLE t_issue_delivery_queue
+    ADD COLUMN execute_after TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE TABLE t_preference_tokens (
+    preference_token VARCHAR NOT NULL,
+    subscriber_id uuid NOT NULL UNIQUE
+        REFERENCES t_subscriptions (id),
+    PRIMARY KEY (preference_token)
+);
+
+-- existing subscribers get a token too, so that their next issue carries a working link
+INSERT INTO t_preference_tokens (preference_token, subscriber_id)
+SELECT
+    replace(gen_random_uuid()::text, '-', '') || replace(gen_random_uuid()::text, '-', ''),
+    id
+FROM t_subscriptions;
@@ -0,0 +1,7 @@
+CREATE TABLE t_subscriber_tags (
+    subscriber_id uuid NOT NULL
+        REFERENCES t_subscriptions (id),
+    tag VARCHAR NOT NULL,
+    PRIMARY KEY (subscriber_id, tag)
+);
+CREATE INDEX idx_subscriber_tags_tag ON t_subscriber_tags (tag);
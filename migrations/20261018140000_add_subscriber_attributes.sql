@@ -0,0 +1,13 @@
+ALTER TABLE t_subscriptions
+    ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}';
+
+CREATE TABLE t_attribute_definitions (
+    key VARCHAR NOT NULL,
+    label VARCHAR NOT NULL,
+    kind VARCHAR NOT NULL,
+    required BOOLEAN NOT NULL DEFAULT false,
+    min DOUBLE PRECISION NULL,
+    max DOUBLE PRECISION NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (key)
+);
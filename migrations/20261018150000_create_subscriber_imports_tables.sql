@@ -0,0 +1,26 @@
+CREATE TABLE t_subscriber_imports (
+    import_id uuid NOT NULL,
+    imported_count INT NOT NULL,
+    rejected_count INT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (import_id)
+);
+
+CREATE TABLE t_subscriber_import_rejections (
+    import_id uuid NOT NULL
+        REFERENCES t_subscriber_imports (import_id),
+    row_number INT NOT NULL,
+    email TEXT NOT NULL,
+    name TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    PRIMARY KEY (import_id, row_number)
+);
+
+-- confirmation emails for bulk imports are sent in the background
+CREATE TABLE t_confirmation_email_queue (
+    subscriber_id uuid NOT NULL
+        REFERENCES t_subscriptions (id),
+    subscription_token TEXT NOT NULL,
+    enqueued_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (subscriber_id)
+);
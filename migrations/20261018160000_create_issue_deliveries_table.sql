@@ -0,0 +1,11 @@
+-- one row per issue and recipient, written by the delivery worker once the
+-- queued task has been processed
+CREATE TABLE t_issue_deliveries (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES t_newsletter_issues (newsletter_issue_id),
+    subscriber_email VARCHAR NOT NULL,
+    outcome VARCHAR NOT NULL,
+    error TEXT NULL,
+    attempted_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_email)
+);
@@ -0,0 +1,12 @@
+-- audit trail of data subject erasures; addresses are only kept as a
+-- SHA-256 hash of their lowercase form. The requesting admin is not a foreign
+-- key so that the record outlives their account.
+CREATE TABLE t_erasure_log (
+    erasure_id uuid NOT NULL,
+    email_hash TEXT NOT NULL,
+    requested_by_user_id uuid NULL,
+    subscription_count INT NOT NULL,
+    erased_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (erasure_id)
+);
+CREATE INDEX idx_erasure_log_email_hash ON t_erasure_log (email_hash);
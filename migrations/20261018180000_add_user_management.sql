@@ -0,0 +1,21 @@
+-- Invited users have no password until they follow their invitation link.
+ALTER TABLE t_users
+    ADD COLUMN email TEXT NULL UNIQUE,
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'active',
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(),
+    ALTER COLUMN password_hash DROP NOT NULL;
+
+ALTER TABLE t_idempotency
+    DROP CONSTRAINT t_idempotency_user_id_fkey,
+    ADD CONSTRAINT t_idempotency_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES t_users(user_id) ON DELETE CASCADE;
+
+-- One-time tokens emailed to users. Only a hash is stored, so that a
+-- database leak does not hand out working links.
+CREATE TABLE t_user_tokens (
+    token_hash TEXT PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES t_users(user_id) ON DELETE CASCADE,
+    purpose TEXT NOT NULL,
+    expires_at timestamptz NOT NULL
+);
+CREATE INDEX t_user_tokens_user_id_idx ON t_user_tokens (user_id);
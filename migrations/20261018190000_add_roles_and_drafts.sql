@@ -0,0 +1,13 @@
+-- Users who existed before roles keep full access.
+ALTER TABLE t_users ADD COLUMN role TEXT NOT NULL DEFAULT 'owner';
+ALTER TABLE t_users ALTER COLUMN role DROP DEFAULT;
+
+CREATE TABLE t_newsletter_drafts (
+    draft_id uuid PRIMARY KEY,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    track_clicks BOOLEAN NOT NULL,
+    updated_by uuid NULL REFERENCES t_users(user_id) ON DELETE SET NULL,
+    updated_at timestamptz NOT NULL
+);
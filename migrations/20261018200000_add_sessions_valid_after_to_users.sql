@@ -0,0 +1,2 @@
+-- Sessions started before this time are rejected, e.g. after a password reset.
+ALTER TABLE t_users ADD COLUMN sessions_valid_after timestamptz NULL;
@@ -0,0 +1,13 @@
+-- The secret is set when enrolment starts and only enforced once confirmed
+-- with a first code, recorded in totp_enabled_at.
+ALTER TABLE t_users
+    ADD COLUMN totp_secret BYTEA NULL,
+    ADD COLUMN totp_enabled_at timestamptz NULL,
+    ADD COLUMN totp_last_used_step BIGINT NULL;
+
+CREATE TABLE t_recovery_codes (
+    user_id uuid NOT NULL REFERENCES t_users(user_id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at timestamptz NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
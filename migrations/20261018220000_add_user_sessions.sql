@@ -0,0 +1,12 @@
+-- One row per logged-in session. The session itself lives in Redis and
+-- stores session_id; deleting the row revokes it.
+CREATE TABLE t_user_sessions (
+    session_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES t_users(user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    last_seen_at timestamptz NOT NULL DEFAULT now(),
+    ip_address TEXT NULL,
+    user_agent TEXT NULL
+);
+
+CREATE INDEX t_user_sessions_user_id_idx ON t_user_sessions (user_id);
@@ -0,0 +1,13 @@
+-- Personal tokens for programmatic access to /admin. Only a hash of each
+-- token is stored; revoking a token deletes its row.
+CREATE TABLE t_api_tokens (
+    token_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES t_users(user_id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    last_used_at timestamptz NULL
+);
+
+CREATE INDEX t_api_tokens_user_id_idx ON t_api_tokens (user_id);
@@ -0,0 +1,4 @@
+-- The request an idempotency key was first used for, so that reusing the key
+-- for another request is refused instead of replaying the wrong response.
+-- Keys saved before this column existed match any request.
+ALTER TABLE t_idempotency ADD COLUMN request_fingerprint TEXT;
@@ -0,0 +1,3 @@
+-- Password resets look users up by `lower(email)`, so two addresses that only
+-- differ in case would be ambiguous.
+CREATE UNIQUE INDEX t_users_lower_email_idx ON t_users (lower(email));
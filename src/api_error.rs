@@ -0,0 +1,190 @@
+//! Errors of the JSON API under `/api/v1`, which are returned as
+//! `application/problem+json` documents (RFC 9457) rather than HTML pages.
+use std::fmt;
+
+use actix_web::{
+    http::{header::WWW_AUTHENTICATE, StatusCode},
+    HttpResponse, ResponseError,
+};
+
+use crate::{
+    authentication::AuthError,
+    error,
+    idempotency::IdempotencyError,
+    mailing_lists::ListSelectionError,
+    routes::{FieldError, LoginError, SubscribeError},
+    segment::SegmentParseError,
+};
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// The body of every error of the JSON API.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct Problem {
+    /// Always `about:blank`: the status code is the type of the problem.
+    #[serde(rename = "type")]
+    kind: &'static str,
+    title: &'static str,
+    status: u16,
+    /// What went wrong, for humans.
+    detail: String,
+    /// Every invalid field, when the request was rejected because of them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+}
+
+#[derive(thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("Some fields are invalid.")]
+    InvalidFields(Vec<FieldError>),
+    #[error("An API token is required.")]
+    MissingToken,
+    #[error("The API token is invalid or has been revoked.")]
+    InvalidToken,
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    UnprocessableEntity(String),
+    #[error("{0}")]
+    TooManyRequests(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        error::error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::ValidationError(_) | ApiError::InvalidFields(_) => StatusCode::BAD_REQUEST,
+            ApiError::MissingToken | ApiError::InvalidToken => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        // The cause of an unexpected error is logged, not shown to the client.
+        let detail = match self {
+            ApiError::UnexpectedError(_) => "Something went wrong.".to_owned(),
+            e => e.to_string(),
+        };
+        let mut response = HttpResponse::build(status);
+        match self {
+            ApiError::MissingToken => {
+                response.insert_header((WWW_AUTHENTICATE, "Bearer"));
+            }
+            ApiError::InvalidToken => {
+                response.insert_header((WWW_AUTHENTICATE, r#"Bearer error="invalid_token""#));
+            }
+            _ => {}
+        }
+        response.content_type(PROBLEM_JSON).json(Problem {
+            kind: "about:blank",
+            title: status.canonical_reason().unwrap_or_default(),
+            status: status.as_u16(),
+            detail,
+            errors: match self {
+                ApiError::InvalidFields(errors) => errors.clone(),
+                _ => Vec::new(),
+            },
+        })
+    }
+}
+
+impl From<SubscribeError> for ApiError {
+    fn from(e: SubscribeError) -> Self {
+        match e {
+            SubscribeError::ValidationError(e) => ApiError::ValidationError(e),
+            SubscribeError::InvalidFields(errors) => ApiError::InvalidFields(errors),
+            e @ SubscribeError::TooManyAttempts => ApiError::TooManyRequests(e.to_string()),
+            SubscribeError::UnexpectedError(e) => ApiError::UnexpectedError(e),
+        }
+    }
+}
+
+impl From<ListSelectionError> for ApiError {
+    fn from(e: ListSelectionError) -> Self {
+        match e {
+            ListSelectionError::UnexpectedError(e) => ApiError::UnexpectedError(e),
+            e => ApiError::ValidationError(e.to_string()),
+        }
+    }
+}
+
+impl From<SegmentParseError> for ApiError {
+    fn from(e: SegmentParseError) -> Self {
+        ApiError::ValidationError(e.to_string())
+    }
+}
+
+impl From<IdempotencyError> for ApiError {
+    fn from(e: IdempotencyError) -> Self {
+        match e {
+            e @ IdempotencyError::KeyReused => ApiError::UnprocessableEntity(e.to_string()),
+            IdempotencyError::UnexpectedError(e) => ApiError::UnexpectedError(e),
+        }
+    }
+}
+
+impl From<AuthError> for ApiError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::InvalidCredentials(_) => ApiError::InvalidToken,
+            AuthError::UnexpectedError(e) => ApiError::UnexpectedError(e),
+        }
+    }
+}
+
+impl From<LoginError> for ApiError {
+    fn from(e: LoginError) -> Self {
+        match e {
+            LoginError::AuthError(_) => ApiError::InvalidToken,
+            e @ LoginError::Throttled => ApiError::TooManyRequests(e.to_string()),
+            LoginError::UnexpectedError(e) => ApiError::UnexpectedError(e),
+        }
+    }
+}
+
+/// Turns the errors of the JSON, path and query extractors into problems too.
+pub fn extractor_error(err: impl fmt::Display) -> actix_web::Error {
+    ApiError::ValidationError(err.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{body::to_bytes, ResponseError};
+
+    use super::ApiError;
+
+    #[tokio::test]
+    async fn unexpected_errors_do_not_leak_their_cause() {
+        let response =
+            ApiError::UnexpectedError(anyhow::anyhow!("password=hunter2")).error_response();
+        assert_eq!(response.status(), 500);
+        assert_eq!(
+            response.headers().get("Content-Type").unwrap(),
+            "application/problem+json"
+        );
+        let body = to_bytes(response.into_body()).await.unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["title"], "Internal Server Error");
+        assert_eq!(problem["status"], 500);
+        assert_eq!(problem["detail"], "Something went wrong.");
+    }
+}
@@ -0,0 +1,374 @@
+//! Admin-defined custom fields collected at signup and stored in the
+//! `attributes` JSONB column of `t_subscriptions`.
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use anyhow::Context;
+use chrono::NaiveDate;
+use serde_json::{Map, Value};
+use sqlx::PgPool;
+
+/// Signup form inputs for custom fields are named `attr.<key>`.
+pub const FORM_FIELD_PREFIX: &str = "attr.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AttributeKind {
+    String,
+    Number,
+    Boolean,
+    Date,
+}
+
+impl AttributeKind {
+    pub const ALL: [AttributeKind; 4] = [
+        AttributeKind::String,
+        AttributeKind::Number,
+        AttributeKind::Boolean,
+        AttributeKind::Date,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttributeKind::String => "string",
+            AttributeKind::Number => "number",
+            AttributeKind::Boolean => "boolean",
+            AttributeKind::Date => "date",
+        }
+    }
+}
+
+impl TryFrom<String> for AttributeKind {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|k| k.as_str() == s)
+            .ok_or_else(|| format!("{} is not a supported attribute type.", s))
+    }
+}
+
+/// `min` and `max` bound the length of strings and the value of numbers;
+/// they are ignored for other kinds.
+#[derive(Debug)]
+pub struct AttributeDefinition {
+    pub key: String,
+    pub label: String,
+    pub kind: AttributeKind,
+    pub required: bool,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum AttributeError {
+    #[error("{0} is required.")]
+    Missing(String),
+    #[error("{label} {reason}.")]
+    Invalid { label: String, reason: String },
+}
+
+/// Keys are used in form field names, placeholders and segment filters.
+pub fn parse_key(key: &str) -> Result<String, String> {
+    let key = key.trim().to_lowercase();
+    let is_valid = !key.is_empty()
+        && key.len() <= 64
+        && key.starts_with(|c: char| c.is_ascii_lowercase())
+        && key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if is_valid {
+        Ok(key)
+    } else {
+        Err(format!(
+            "{} is not a valid field key: use lowercase letters, digits and underscores.",
+            key
+        ))
+    }
+}
+
+#[tracing::instrument(name = "Get attribute definitions", skip(pool))]
+pub async fn get_definitions(pool: &PgPool) -> Result<Vec<AttributeDefinition>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT key, label, kind, required, min, max
+        FROM t_attribute_definitions
+        ORDER BY created_at
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve attribute definitions.")?;
+
+    rows.into_iter()
+        .map(|r| {
+            Ok(AttributeDefinition {
+                kind: r.kind.try_into().map_err(anyhow::Error::msg)?,
+                key: r.key,
+                label: r.label,
+                required: r.required,
+                min: r.min,
+                max: r.max,
+            })
+        })
+        .collect()
+}
+
+/// Checks the submitted form values against the definitions and returns the
+/// typed attributes to store. Values for undefined fields are dropped.
+pub fn validate(
+    definitions: &[AttributeDefinition],
+    submitted: &HashMap<String, String>,
+) -> Result<Map<String, Value>, AttributeError> {
+    validate_all(definitions, submitted).map_err(|mut errors| errors.swap_remove(0).1)
+}
+
+/// Like [`validate`], but reports every invalid value, with the key of its
+/// definition, rather than stopping at the first one.
+pub fn validate_all(
+    definitions: &[AttributeDefinition],
+    submitted: &HashMap<String, String>,
+) -> Result<Map<String, Value>, Vec<(String, AttributeError)>> {
+    let mut attributes = Map::new();
+    let mut errors = Vec::new();
+    for definition in definitions {
+        let raw = submitted
+            .get(&format!("{}{}", FORM_FIELD_PREFIX, definition.key))
+            .map(|v| v.trim())
+            .unwrap_or_default();
+        match definition.parse_value(raw) {
+            Ok(Some(value)) => {
+                attributes.insert(definition.key.clone(), value);
+            }
+            Ok(None) => {}
+            Err(e) => errors.push((definition.key.clone(), e)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(attributes)
+    } else {
+        Err(errors)
+    }
+}
+
+impl AttributeDefinition {
+    fn parse_value(&self, raw: &str) -> Result<Option<Value>, AttributeError> {
+        let invalid = |reason: String| AttributeError::Invalid {
+            label: self.label.clone(),
+            reason,
+        };
+        if raw.is_empty() {
+            // an unchecked checkbox is not submitted at all
+            return match (self.kind, self.required) {
+                (_, true) => Err(AttributeError::Missing(self.label.clone())),
+                (AttributeKind::Boolean, false) => Ok(Some(Value::Bool(false))),
+                (_, false) => Ok(None),
+            };
+        }
+
+        let value = match self.kind {
+            AttributeKind::String => {
+                let length = raw.chars().count() as f64;
+                if let Some(min) = self.min.filter(|min| length < *min) {
+                    return Err(invalid(format!("must be at least {min} characters long")));
+                }
+                if let Some(max) = self.max.filter(|max| length > *max) {
+                    return Err(invalid(format!("must be at most {max} characters long")));
+                }
+                Value::String(raw.to_owned())
+            }
+            AttributeKind::Number => {
+                let number = raw
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|n| n.is_finite())
+                    .ok_or_else(|| invalid("must be a number".into()))?;
+                if let Some(min) = self.min.filter(|min| number < *min) {
+                    return Err(invalid(format!("must be at least {min}")));
+                }
+                if let Some(max) = self.max.filter(|max| number > *max) {
+                    return Err(invalid(format!("must be at most {max}")));
+                }
+                // store whole numbers as integers so that `attr.seats:5` matches in segments
+                if number.fract() == 0.0 && number.abs() < i64::MAX as f64 {
+                    Value::from(number as i64)
+                } else {
+                    Value::from(number)
+                }
+            }
+            AttributeKind::Boolean => match raw {
+                "true" | "on" | "1" => Value::Bool(true),
+                "false" | "off" | "0" if !self.required => Value::Bool(false),
+                "false" | "off" | "0" => return Err(AttributeError::Missing(self.label.clone())),
+                _ => return Err(invalid("must be true or false".into())),
+            },
+            AttributeKind::Date => NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+                .map(|date| Value::String(date.to_string()))
+                .map_err(|_| invalid("must be a date formatted as YYYY-MM-DD".into()))?,
+        };
+        Ok(Some(value))
+    }
+}
+
+/// Renders one input per definition for the signup form.
+pub fn form_fields_html(definitions: &[AttributeDefinition]) -> String {
+    let mut html = String::new();
+    for definition in definitions {
+        let name = format!("{}{}", FORM_FIELD_PREFIX, definition.key);
+        let required = if definition.required { " required" } else { "" };
+        let input = match definition.kind {
+            AttributeKind::String => format!(r#"<input type="text" name="{name}"{required}>"#),
+            AttributeKind::Number => {
+                format!(r#"<input type="number" step="any" name="{name}"{required}>"#)
+            }
+            AttributeKind::Boolean => {
+                format!(r#"<input type="checkbox" name="{name}" value="true"{required}>"#)
+            }
+            AttributeKind::Date => format!(r#"<input type="date" name="{name}"{required}>"#),
+        };
+        writeln!(
+            html,
+            "<label>{}\n    {}\n</label>\n<br>",
+            htmlescape::encode_minimal(&definition.label),
+            input
+        )
+        .unwrap();
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use claim::{assert_err, assert_ok};
+    use serde_json::json;
+
+    use super::{
+        parse_key, validate, validate_all, AttributeDefinition, AttributeError, AttributeKind,
+    };
+
+    fn definition(kind: AttributeKind, required: bool) -> AttributeDefinition {
+        AttributeDefinition {
+            key: "field".into(),
+            label: "Field".into(),
+            kind,
+            required,
+            min: None,
+            max: None,
+        }
+    }
+
+    fn submit(
+        definition: AttributeDefinition,
+        value: &str,
+    ) -> Result<serde_json::Value, AttributeError> {
+        let submitted = HashMap::from([("attr.field".to_owned(), value.to_owned())]);
+        validate(&[definition], &submitted).map(serde_json::Value::Object)
+    }
+
+    #[test]
+    fn values_are_stored_with_their_type() {
+        assert_eq!(
+            submit(definition(AttributeKind::String, false), " Acme "),
+            Ok(json!({ "field": "Acme" }))
+        );
+        assert_eq!(
+            submit(definition(AttributeKind::Number, false), "5"),
+            Ok(json!({ "field": 5 }))
+        );
+        assert_eq!(
+            submit(definition(AttributeKind::Number, false), "2.5"),
+            Ok(json!({ "field": 2.5 }))
+        );
+        assert_eq!(
+            submit(definition(AttributeKind::Boolean, false), "true"),
+            Ok(json!({ "field": true }))
+        );
+        assert_eq!(
+            submit(definition(AttributeKind::Date, false), "2026-03-01"),
+            Ok(json!({ "field": "2026-03-01" }))
+        );
+    }
+
+    #[test]
+    fn missing_optional_values_are_skipped_and_unchecked_boxes_are_false() {
+        assert_eq!(
+            submit(definition(AttributeKind::String, false), ""),
+            Ok(json!({}))
+        );
+        assert_eq!(
+            submit(definition(AttributeKind::Boolean, false), ""),
+            Ok(json!({ "field": false }))
+        );
+    }
+
+    #[test]
+    fn missing_required_values_are_rejected() {
+        for kind in AttributeKind::ALL {
+            assert_eq!(
+                submit(definition(kind, true), ""),
+                Err(AttributeError::Missing("Field".into()))
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_values_are_rejected() {
+        assert_err!(submit(definition(AttributeKind::Number, false), "five"));
+        assert_err!(submit(definition(AttributeKind::Number, false), "NaN"));
+        assert_err!(submit(definition(AttributeKind::Boolean, false), "maybe"));
+        assert_err!(submit(definition(AttributeKind::Date, false), "01/03/2026"));
+    }
+
+    #[test]
+    fn bounds_apply_to_string_length_and_number_value() {
+        let bounded = |kind| AttributeDefinition {
+            min: Some(2.0),
+            max: Some(4.0),
+            ..definition(kind, false)
+        };
+        assert_ok!(submit(bounded(AttributeKind::String), "abc"));
+        assert_err!(submit(bounded(AttributeKind::String), "a"));
+        assert_err!(submit(bounded(AttributeKind::String), "abcde"));
+        assert_ok!(submit(bounded(AttributeKind::Number), "3"));
+        assert_err!(submit(bounded(AttributeKind::Number), "1"));
+        assert_err!(submit(bounded(AttributeKind::Number), "10"));
+    }
+
+    #[test]
+    fn every_invalid_value_is_reported() {
+        let definitions = [
+            definition(AttributeKind::Number, false),
+            AttributeDefinition {
+                key: "company".into(),
+                label: "Company".into(),
+                ..definition(AttributeKind::String, true)
+            },
+        ];
+        let submitted = HashMap::from([("attr.field".to_owned(), "five".to_owned())]);
+
+        let errors = validate_all(&definitions, &submitted).unwrap_err();
+
+        let keys: Vec<_> = errors.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["field", "company"]);
+        assert_eq!(errors[1].1, AttributeError::Missing("Company".into()));
+    }
+
+    #[test]
+    fn undefined_fields_are_dropped() {
+        let submitted = HashMap::from([("attr.other".to_owned(), "x".to_owned())]);
+        assert_eq!(validate(&[], &submitted), Ok(serde_json::Map::new()));
+    }
+
+    #[test]
+    fn keys_must_be_identifiers() {
+        assert_eq!(parse_key(" Company "), Ok("company".into()));
+        assert_ok!(parse_key("referral_source2"));
+        assert_err!(parse_key("2fa"));
+        assert_err!(parse_key("referral source"));
+        assert_err!(parse_key("attr.x"));
+        assert_err!(parse_key(""));
+    }
+}
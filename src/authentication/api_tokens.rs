@@ -0,0 +1,203 @@
+//! Personal API tokens, which let scripts use `/api/v1` and `/admin` without
+//! a session.
+//! A token acts for the user who created it, and is limited both by their
+//! role and by its scopes.
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::distributions::DistString;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{user_token::hash_token, Role};
+
+/// Makes tokens easy to recognise, e.g. by secret scanners.
+const TOKEN_PREFIX: &str = "z2p_";
+
+/// What requests made with a token may do, on top of what the role of its
+/// user allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiScope {
+    NewslettersRead,
+    NewslettersPublish,
+    SubscribersRead,
+    SubscribersWrite,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 4] = [
+        ApiScope::NewslettersRead,
+        ApiScope::NewslettersPublish,
+        ApiScope::SubscribersRead,
+        ApiScope::SubscribersWrite,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::NewslettersRead => "newsletters:read",
+            ApiScope::NewslettersPublish => "newsletters:publish",
+            ApiScope::SubscribersRead => "subscribers:read",
+            ApiScope::SubscribersWrite => "subscribers:write",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<ApiScope, String> {
+        ApiScope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid scope.", s))
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ApiScope::NewslettersRead => "View issues and their delivery statistics",
+            ApiScope::NewslettersPublish => "Draft and send newsletter issues",
+            ApiScope::SubscribersRead => "View and export subscribers",
+            ApiScope::SubscribersWrite => "Import and change subscribers",
+        }
+    }
+}
+
+/// The scopes of the token a request was made with, set as a request
+/// extension by [`super::authenticate_api_tokens`].
+#[derive(Debug, Clone)]
+pub struct ApiTokenScopes(pub Vec<ApiScope>);
+
+impl ApiTokenScopes {
+    pub fn allows(&self, scope: ApiScope) -> bool {
+        self.0.contains(&scope)
+    }
+}
+
+pub struct ApiToken {
+    pub token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// The user a valid token acts for.
+pub struct ApiTokenOwner {
+    pub user_id: Uuid,
+    pub role: Role,
+    pub scopes: ApiTokenScopes,
+}
+
+/// Creates a token for `user_id` and returns it. Only its hash is stored, so
+/// it cannot be shown again.
+#[tracing::instrument(name = "Issue an API token", skip(pool))]
+pub async fn issue_api_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiScope],
+) -> Result<String, anyhow::Error> {
+    let token = format!(
+        "{}{}",
+        TOKEN_PREFIX,
+        rand::distributions::Alphanumeric.sample_string(&mut rand::thread_rng(), 40)
+    );
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_owned()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO t_api_tokens (token_id, user_id, name, token_hash, scopes)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        hash_token(&token),
+        &scopes
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store an API token.")?;
+    Ok(token)
+}
+
+#[tracing::instrument(name = "List API tokens", skip(pool))]
+pub async fn list_user_api_tokens(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<ApiToken>, anyhow::Error> {
+    sqlx::query_as!(
+        ApiToken,
+        r#"
+        SELECT token_id, name, scopes, created_at, last_used_at
+        FROM t_api_tokens
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the API tokens of a user.")
+}
+
+/// Returns `false` if the user has no such token.
+#[tracing::instrument(name = "Delete an API token", skip(pool))]
+pub async fn delete_api_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    token_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM t_api_tokens WHERE token_id = $1 AND user_id = $2",
+        token_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to delete an API token.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Returns who `token` acts for, if it exists and its user is still active,
+/// and records that it has been used.
+#[tracing::instrument(name = "Authenticate an API token", skip(pool, token))]
+pub async fn find_api_token_owner(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<ApiTokenOwner>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE t_api_tokens t
+        SET last_used_at = now()
+        FROM t_users u
+        WHERE t.token_hash = $1 AND u.user_id = t.user_id AND u.status = 'active'
+        RETURNING t.user_id, t.scopes, u.role
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up an API token.")?;
+    row.map(|row| {
+        Ok(ApiTokenOwner {
+            user_id: row.user_id,
+            role: Role::parse(&row.role).map_err(anyhow::Error::msg)?,
+            // Scopes that no longer exist grant nothing.
+            scopes: ApiTokenScopes(
+                row.scopes
+                    .iter()
+                    .filter_map(|s| ApiScope::parse(s).ok())
+                    .collect(),
+            ),
+        })
+    })
+    .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApiScope;
+
+    #[test]
+    fn scopes_round_trip_through_their_name() {
+        for scope in ApiScope::ALL {
+            assert_eq!(ApiScope::parse(scope.as_str()), Ok(scope));
+        }
+        assert!(ApiScope::parse("newsletters:write").is_err());
+    }
+}
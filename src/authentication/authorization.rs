@@ -0,0 +1,282 @@
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ResourceDef, ServiceRequest, ServiceResponse},
+    http::{header::ContentType, Method},
+    middleware::Next,
+    Error, HttpMessage, HttpResponse, ResponseError,
+};
+
+use super::{ApiScope, ApiTokenScopes};
+use crate::api_error::ApiError;
+
+/// What a user may do under `/admin` and `/api/v1`. Each role can do everything the roles
+/// after it can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Owner,
+    Editor,
+    Viewer,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Owner, Role::Editor, Role::Viewer];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Editor => "editor",
+            Role::Viewer => "viewer",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Role, String> {
+        Role::ALL
+            .into_iter()
+            .find(|r| r.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid role.", s))
+    }
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        match permission {
+            Permission::Read => true,
+            Permission::Edit => matches!(self, Role::Owner | Role::Editor),
+            Permission::Publish | Permission::ManageUsers => *self == Role::Owner,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Viewing pages and exports, and managing one's own account.
+    Read,
+    /// Changing subscribers, lists and fields, and saving drafts.
+    Edit,
+    /// Sending an issue to subscribers.
+    Publish,
+    ManageUsers,
+}
+
+/// The scope an API token needs for a request. Requests that need none
+/// cannot be made with a token.
+fn required_scope(method: &Method, path: &str) -> Option<ApiScope> {
+    let is_read = method == Method::GET || method == Method::HEAD;
+    if let Some(resource) = path.strip_prefix("/api/v1/") {
+        return match (resource.split('/').next(), is_read) {
+            (Some("subscribers"), true) => Some(ApiScope::SubscribersRead),
+            (Some("subscribers"), false) => Some(ApiScope::SubscribersWrite),
+            (Some("issues"), true) => Some(ApiScope::NewslettersRead),
+            (Some("issues"), false) => Some(ApiScope::NewslettersPublish),
+            _ => None,
+        };
+    }
+
+    let is_subscribers_path =
+        path == "/admin/subscribers" || path.starts_with("/admin/subscribers/");
+    if is_read {
+        if path == "/admin/issues" || path.starts_with("/admin/issues/") {
+            return Some(ApiScope::NewslettersRead);
+        }
+        return is_subscribers_path.then_some(ApiScope::SubscribersRead);
+    }
+    match path {
+        "/admin/newsletters" => Some(ApiScope::NewslettersPublish),
+        _ => is_subscribers_path.then_some(ApiScope::SubscribersWrite),
+    }
+}
+
+/// The permission needed for a request. Requests that change
+/// something need more than [`Permission::Read`] unless they only concern
+/// the user making them.
+fn required_permission(method: &Method, path: &str) -> Permission {
+    if path == "/admin/users" || path.starts_with("/admin/users/") {
+        return Permission::ManageUsers;
+    }
+    if method == Method::GET || method == Method::HEAD {
+        return Permission::Read;
+    }
+    if path.starts_with("/admin/2fa/")
+        || path.starts_with("/admin/sessions/")
+        || path == "/admin/api_tokens"
+        || path.starts_with("/admin/api_tokens/")
+    {
+        return Permission::Read;
+    }
+    match path {
+        "/admin/logout" | "/admin/password" => Permission::Read,
+        "/admin/newsletters" => Permission::Publish,
+        p if ResourceDef::new("/api/v1/issues/{issue_id}/publish").is_match(p) => {
+            Permission::Publish
+        }
+        _ => Permission::Edit,
+    }
+}
+
+/// The path the router matches on, in which percent-encoded characters
+/// other than reserved ones are decoded: `/admin/%6Eewsletters` is routed to
+/// the same handler as `/admin/newsletters` and needs the same permission.
+fn routed_path(req: &ServiceRequest) -> &str {
+    req.match_info().as_str()
+}
+
+/// Must run after [`super::reject_anonymous_users`] or
+/// [`super::reject_requests_without_api_token`], which store the role of the
+/// user in the request extensions.
+pub async fn authorize(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let role = req
+        .extensions()
+        .get::<Role>()
+        .copied()
+        .expect("The role is set by reject_anonymous_users");
+    let path = routed_path(&req);
+    let permission = required_permission(req.method(), path);
+    // A request made with an API token needs both the role and the scope.
+    let scope = required_scope(req.method(), path);
+    let token_allows = match req.extensions().get::<ApiTokenScopes>() {
+        Some(scopes) => scope.is_some_and(|scope| scopes.allows(scope)),
+        None => true,
+    };
+    if role.allows(permission) && token_allows {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    tracing::warn!(
+        role = role.as_str(),
+        ?permission,
+        ?scope,
+        path,
+        "Forbidden request"
+    );
+    let response = if path.starts_with("/api/") {
+        ApiError::Forbidden("The API token or its user may not make this request.".into())
+            .error_response()
+    } else {
+        HttpResponse::Forbidden()
+            .content_type(ContentType::html())
+            .body(include_str!("forbidden.html"))
+    };
+    Ok(req.into_response(response).map_into_right_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::Method;
+
+    use super::{required_permission, required_scope, ApiScope, Permission, Role};
+
+    #[test]
+    fn viewers_can_only_read_and_manage_their_own_account() {
+        for (method, path) in [
+            (Method::GET, "/admin/subscribers"),
+            (Method::GET, "/admin/subscribers/export"),
+            (Method::POST, "/admin/password"),
+            (Method::POST, "/admin/logout"),
+            (Method::POST, "/admin/2fa/setup"),
+            (Method::POST, "/admin/sessions/revoke_others"),
+        ] {
+            assert!(Role::Viewer.allows(required_permission(&method, path)));
+        }
+        for path in ["/admin/lists", "/admin/newsletters/drafts", "/admin/tags"] {
+            assert!(!Role::Viewer.allows(required_permission(&Method::POST, path)));
+        }
+    }
+
+    #[test]
+    fn editors_can_draft_but_not_publish() {
+        assert!(Role::Editor.allows(required_permission(
+            &Method::POST,
+            "/admin/newsletters/drafts"
+        )));
+        assert!(!Role::Editor.allows(required_permission(&Method::POST, "/admin/newsletters")));
+        assert!(Role::Owner.allows(required_permission(&Method::POST, "/admin/newsletters")));
+
+        assert!(Role::Editor.allows(required_permission(&Method::POST, "/api/v1/issues")));
+        let publish = "/api/v1/issues/7c9e2bc5-7b47-4b3c-8d8e-2c0a1f0b9a41/publish";
+        assert!(!Role::Editor.allows(required_permission(&Method::POST, publish)));
+        assert!(Role::Owner.allows(required_permission(&Method::POST, publish)));
+    }
+
+    #[test]
+    fn only_owners_can_see_or_manage_users() {
+        for (method, path) in [
+            (Method::GET, "/admin/users"),
+            (
+                Method::POST,
+                "/admin/users/7c9e2bc5-7b47-4b3c-8d8e-2c0a1f0b9a41/delete",
+            ),
+        ] {
+            assert_eq!(required_permission(&method, path), Permission::ManageUsers);
+            assert!(!Role::Editor.allows(required_permission(&method, path)));
+        }
+        assert_eq!(
+            required_permission(&Method::GET, "/admin/usersettings"),
+            Permission::Read
+        );
+    }
+
+    #[test]
+    fn api_tokens_only_reach_the_paths_of_their_scopes() {
+        for (method, path, scope) in [
+            (Method::GET, "/admin/subscribers", ApiScope::SubscribersRead),
+            (
+                Method::GET,
+                "/admin/subscribers/export",
+                ApiScope::SubscribersRead,
+            ),
+            (
+                Method::POST,
+                "/admin/subscribers/import",
+                ApiScope::SubscribersWrite,
+            ),
+            (
+                Method::POST,
+                "/admin/newsletters",
+                ApiScope::NewslettersPublish,
+            ),
+            (Method::GET, "/admin/issues", ApiScope::NewslettersRead),
+            (
+                Method::GET,
+                "/api/v1/subscribers",
+                ApiScope::SubscribersRead,
+            ),
+            (
+                Method::POST,
+                "/api/v1/subscribers",
+                ApiScope::SubscribersWrite,
+            ),
+            (
+                Method::GET,
+                "/api/v1/issues/7c9e2bc5-7b47-4b3c-8d8e-2c0a1f0b9a41",
+                ApiScope::NewslettersRead,
+            ),
+            (
+                Method::POST,
+                "/api/v1/issues/7c9e2bc5-7b47-4b3c-8d8e-2c0a1f0b9a41/publish",
+                ApiScope::NewslettersPublish,
+            ),
+        ] {
+            assert_eq!(required_scope(&method, path), Some(scope), "{}", path);
+        }
+        for (method, path) in [
+            (Method::GET, "/admin/dashboard"),
+            (Method::GET, "/admin/newsletters"),
+            (Method::POST, "/admin/api_tokens"),
+            (Method::POST, "/admin/password"),
+            (Method::GET, "/admin/subscribersettings"),
+            (Method::GET, "/api/v1/users"),
+        ] {
+            assert_eq!(required_scope(&method, path), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn roles_round_trip_through_their_name() {
+        for role in Role::ALL {
+            assert_eq!(Role::parse(role.as_str()), Ok(role));
+        }
+        assert!(Role::parse("admin").is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use actix_session::SessionInsertError;
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    http::{header::ContentType, Method},
+    middleware::Next,
+    mime, web, Error, FromRequest, HttpMessage, HttpResponse,
+};
+use futures_util::StreamExt;
+use rand::distributions::DistString;
+
+use super::ApiTokenScopes;
+use crate::{session_state::TypedSession, utils};
+
+/// A secret tied to the session, which every form that changes something
+/// sends back, so that another site cannot submit the form for the user.
+#[derive(Clone, Debug)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    const FIELD: &'static str = "csrf_token";
+
+    fn generate() -> Self {
+        Self(rand::distributions::Alphanumeric.sample_string(&mut rand::thread_rng(), 32))
+    }
+
+    /// The hidden input to add to every form that is submitted with POST.
+    pub fn form_field(&self) -> String {
+        format!(
+            r#"<input type="hidden" name="{}" value="{}">"#,
+            Self::FIELD,
+            self.0
+        )
+    }
+
+    /// Compares every byte, so that the time taken does not tell how much of
+    /// `submitted` is right.
+    fn matches(&self, submitted: &str) -> bool {
+        self.0.len() == submitted.len()
+            && self
+                .0
+                .bytes()
+                .zip(submitted.bytes())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+/// Gives the session a new token, so that forms opened before logging in are
+/// not accepted afterwards.
+pub fn rotate_csrf_token(session: &TypedSession) -> Result<(), SessionInsertError> {
+    session.insert_csrf_token(&CsrfToken::generate().0)
+}
+
+/// How much of a multipart body is read, at most, to find the token in.
+const MULTIPART_TOKEN_LIMIT: usize = 4096;
+
+#[derive(serde::Deserialize)]
+struct SubmittedToken {
+    csrf_token: Option<String>,
+}
+
+/// Issues the session's [`CsrfToken`] to the handlers, which render it into
+/// their forms, and rejects any request other than a GET or HEAD that does
+/// not send it back.
+pub async fn verify_csrf_token(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    // A browser never sends an API token on its own, so requests made with
+    // one cannot be forged. Their pages get a token that is never stored.
+    if req.extensions().contains::<ApiTokenScopes>() {
+        req.extensions_mut().insert(CsrfToken::generate());
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let token = match session.get_csrf_token().map_err(utils::e500)? {
+        Some(token) => CsrfToken(token),
+        None => {
+            let token = CsrfToken::generate();
+            session.insert_csrf_token(&token.0).map_err(utils::e500)?;
+            token
+        }
+    };
+
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        let submitted = submitted_token(&mut req).await?;
+        if !submitted.is_some_and(|submitted| token.matches(&submitted)) {
+            tracing::warn!(
+                path = req.path(),
+                "Rejected a request without a valid CSRF token"
+            );
+            let response = HttpResponse::Forbidden()
+                .content_type(ContentType::html())
+                .body(include_str!("csrf_failure.html"));
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    }
+
+    req.extensions_mut().insert(token);
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+/// Reads the token from a URL-encoded body, or from the first field of a
+/// multipart body. What is read is put back for the handler. The token is
+/// never read from the query string, which ends up in logs and `Referer`s.
+async fn submitted_token(req: &mut ServiceRequest) -> Result<Option<String>, Error> {
+    if req.content_type() == ContentType::form_url_encoded().essence_str() {
+        let body = req.extract::<web::Bytes>().await?;
+        let payload = futures_util::stream::once(std::future::ready(Ok(body.clone())));
+        req.set_payload(Payload::Stream {
+            payload: Box::pin(payload),
+        });
+        return Ok(serde_html_form::from_bytes::<SubmittedToken>(&body)
+            .ok()
+            .and_then(|form| form.csrf_token));
+    }
+
+    let boundary = match req.mime_type() {
+        Ok(Some(mime)) if mime.essence_str() == mime::MULTIPART_FORM_DATA.essence_str() => {
+            match mime.get_param(mime::BOUNDARY) {
+                Some(boundary) => boundary.as_str().to_owned(),
+                None => return Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    };
+    // Uploads can be large, so only the start of the body is read, and the
+    // rest is streamed to the handler after it.
+    let mut payload = req.take_payload();
+    let mut start = web::BytesMut::new();
+    let token = loop {
+        if let Some(token) = first_multipart_field(&start, &boundary, CsrfToken::FIELD) {
+            break token;
+        }
+        if start.len() > MULTIPART_TOKEN_LIMIT {
+            break None;
+        }
+        match payload.next().await {
+            Some(chunk) => start.extend_from_slice(&chunk?),
+            None => break None,
+        }
+    };
+    let start = futures_util::stream::once(std::future::ready(Ok(start.freeze())));
+    req.set_payload(Payload::Stream {
+        payload: Box::pin(start.chain(payload)),
+    });
+    Ok(token)
+}
+
+/// The value of the first field of a multipart body if it is called `name`,
+/// or `None` until the whole field has been read.
+fn first_multipart_field(body: &[u8], boundary: &str, name: &str) -> Option<Option<String>> {
+    let body = body.strip_prefix(format!("--{boundary}\r\n").as_bytes())?;
+    let headers_end = find(body, b"\r\n\r\n")?;
+    let value_start = headers_end + 4;
+    let value_end =
+        value_start + find(&body[value_start..], format!("\r\n--{boundary}").as_bytes())?;
+
+    let headers = String::from_utf8_lossy(&body[..headers_end]).to_lowercase();
+    let is_named = headers.lines().any(|header| {
+        header.starts_with("content-disposition:")
+            && header
+                .split(';')
+                .any(|param| param.trim() == format!("name=\"{name}\""))
+    });
+    Some(
+        is_named
+            .then(|| String::from_utf8(body[value_start..value_end].to_vec()).ok())
+            .flatten(),
+    )
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{first_multipart_field, CsrfToken};
+
+    #[test]
+    fn only_the_exact_token_matches() {
+        let token = CsrfToken::generate();
+        assert!(token.matches(&token.0.clone()));
+        assert!(!token.matches(&token.0[1..]));
+        assert!(!token.matches(&CsrfToken::generate().0));
+        assert!(!token.matches(""));
+    }
+
+    #[test]
+    fn the_token_is_read_from_the_first_multipart_field() {
+        let body =
+            "--b\r\nContent-Disposition: form-data; name=\"csrf_token\"\r\n\r\nabc\r\n--b\r\n";
+        assert_eq!(
+            first_multipart_field(body.as_bytes(), "b", "csrf_token"),
+            Some(Some("abc".into()))
+        );
+        assert_eq!(
+            first_multipart_field(&body.as_bytes()[..50], "b", "csrf_token"),
+            None
+        );
+        let body = "--b\r\nContent-Disposition: form-data; name=\"mode\"\r\n\r\nabc\r\n--b\r\n";
+        assert_eq!(
+            first_multipart_field(body.as_bytes(), "b", "csrf_token"),
+            Some(None)
+        );
+    }
+}
@@ -1,15 +1,24 @@
 use std::ops::Deref;
 
 use actix_web::{
-    body::MessageBody,
+    body::{EitherBody, MessageBody},
     dev::{ServiceRequest, ServiceResponse},
-    error::InternalError,
+    http::{header::AUTHORIZATION, Method},
     middleware::Next,
-    Error, FromRequest, HttpMessage,
+    web, Error, FromRequest, HttpMessage, ResponseError,
 };
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::{session_state::TypedSession, utils};
+use super::{
+    api_tokens::find_api_token_owner, revoke_session, touch_session, ApiTokenScopes, Role,
+};
+use crate::{
+    api_error::ApiError, configuration::SessionTimeoutSettings, session_state::TypedSession, utils,
+};
 
 #[derive(Copy, Clone, Debug)]
 pub struct UserId(Uuid);
@@ -28,25 +37,287 @@ impl Deref for UserId {
     }
 }
 
+/// Authenticates requests that carry an `Authorization: Bearer` API token,
+/// setting the same extensions as [`reject_anonymous_users`] does for a
+/// session. Requests without the header, or with another scheme such as the
+/// `Basic` credentials of a proxy in front of the admin pages, are left to the
+/// session.
+pub async fn authenticate_api_tokens(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| {
+            let (scheme, token) = header.split_once(' ').unwrap_or((header, ""));
+            scheme
+                .eq_ignore_ascii_case("Bearer")
+                .then(|| token.trim().to_owned())
+        });
+    let Some(token) = token else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .expect("The connection pool is registered as app data");
+    let owner = find_api_token_owner(pool, &token)
+        .await
+        .map_err(utils::e500)?;
+
+    match owner {
+        Some(owner) => {
+            req.extensions_mut().insert(UserId(owner.user_id));
+            req.extensions_mut().insert(owner.role);
+            req.extensions_mut().insert(owner.scopes);
+            Ok(next.call(req).await?.map_into_left_body())
+        }
+        None => {
+            let response = ApiError::InvalidToken.error_response();
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+/// The JSON API only accepts API tokens: unlike a session cookie, a browser
+/// never sends one on its own, so its requests cannot be forged by another
+/// site. Must run after [`authenticate_api_tokens`].
+pub async fn reject_requests_without_api_token(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    if req.extensions().contains::<ApiTokenScopes>() {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+    let response = ApiError::MissingToken.error_response();
+    Ok(req.into_response(response).map_into_right_body())
+}
+
 pub async fn reject_anonymous_users(
     mut req: ServiceRequest,
     next: Next<impl MessageBody>,
-) -> Result<ServiceResponse<impl MessageBody>, Error> {
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    // Already authenticated by [`authenticate_api_tokens`], without a session.
+    if req.extensions().contains::<UserId>() {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
     let session = {
         let (http_request, payload) = req.parts_mut();
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    match session.get_user_id().map_err(utils::e500)? {
+    let user = match session.get_user_id().map_err(utils::e500)? {
         Some(user_id) => {
+            let pool = req
+                .app_data::<web::Data<PgPool>>()
+                .expect("The connection pool is registered as app data");
+            let timeouts = req
+                .app_data::<web::Data<SessionTimeoutSettings>>()
+                .expect("The session timeouts are registered as app data");
+            // Disabling or deleting a user, or resetting their password, must end
+            // the sessions they already have.
+            let logged_in_at = session.get_logged_in_at().map_err(utils::e500)?;
+            let user = get_active_user(user_id, pool)
+                .await
+                .map_err(utils::e500)?
+                .filter(|user| user.accepts_session_from(logged_in_at));
+            let session_id = session.get_session_id().map_err(utils::e500)?;
+            let now = Utc::now();
+            let expiry = session_expiry(
+                timeouts,
+                logged_in_at,
+                session.get_last_seen_at().map_err(utils::e500)?,
+                now,
+            );
+            match (user, session_id, expiry) {
+                (Some(_), Some(session_id), Some(expiry)) => {
+                    revoke_session(pool, user_id, session_id)
+                        .await
+                        .map_err(utils::e500)?;
+                    session.log_out();
+                    FlashMessage::info(expiry.message()).send();
+                    Err(login_page_returning_to(&req))
+                }
+                // So must revoking the session, which deletes its record.
+                (Some(user), Some(session_id), None)
+                    if touch_session(pool, user_id, session_id)
+                        .await
+                        .map_err(utils::e500)? =>
+                {
+                    session.insert_last_seen_at(now).map_err(utils::e500)?;
+                    Ok((user_id, user.role))
+                }
+                _ => {
+                    session.log_out();
+                    Err("/login".to_owned())
+                }
+            }
+        }
+        // Someone who has entered their password but not yet their second
+        // factor is not logged in, and is sent back to the code form.
+        None => match session.get_pending_second_factor().map_err(utils::e500)? {
+            Some(pending) if !pending.has_expired() => Err("/login/2fa".to_owned()),
+            _ => Err("/login".to_owned()),
+        },
+    };
+
+    match user {
+        Ok((user_id, role)) => {
             req.extensions_mut().insert(UserId(user_id));
-            next.call(req).await
+            req.extensions_mut().insert(role);
+            Ok(next.call(req).await?.map_into_left_body())
         }
-        None => {
-            let response = utils::see_other("/login");
-            let error = anyhow::anyhow!("The user has not logged in");
+        // A response rather than an error, so that the flash message and the
+        // end of the session are written to the cookies.
+        Err(location) => {
+            let response = utils::see_other(&location);
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
 
-            Err(InternalError::from_response(error, response).into())
+/// Only a page can be returned to after logging in again: a form submission
+/// would be lost anyway.
+fn login_page_returning_to(req: &ServiceRequest) -> String {
+    match req.uri().path_and_query() {
+        Some(page) if req.method() == Method::GET => {
+            format!("/login?return_to={}", urlencoding::encode(page.as_str()))
         }
+        _ => "/login".to_owned(),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum SessionExpiry {
+    Idle,
+    Absolute,
+}
+
+impl SessionExpiry {
+    fn message(&self) -> &'static str {
+        match self {
+            SessionExpiry::Idle => {
+                "You have been logged out after a period of inactivity. Please log in again."
+            }
+            SessionExpiry::Absolute => "Your session has expired. Please log in again.",
+        }
+    }
+}
+
+/// A session ends once it has not been used for the idle timeout, and in any
+/// case once the absolute timeout has passed since the user logged in.
+fn session_expiry(
+    timeouts: &SessionTimeoutSettings,
+    logged_in_at: Option<DateTime<Utc>>,
+    last_seen_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Option<SessionExpiry> {
+    let Some(logged_in_at) = logged_in_at else {
+        return Some(SessionExpiry::Absolute);
+    };
+    if now - logged_in_at > Duration::seconds(timeouts.absolute_seconds as i64) {
+        return Some(SessionExpiry::Absolute);
+    }
+    let last_seen_at = last_seen_at.unwrap_or(logged_in_at);
+    if now - last_seen_at > Duration::seconds(timeouts.idle_seconds as i64) {
+        return Some(SessionExpiry::Idle);
+    }
+    None
+}
+
+struct ActiveUser {
+    role: Role,
+    sessions_valid_after: Option<DateTime<Utc>>,
+}
+
+impl ActiveUser {
+    fn accepts_session_from(&self, logged_in_at: Option<DateTime<Utc>>) -> bool {
+        match (self.sessions_valid_after, logged_in_at) {
+            (None, _) => true,
+            (Some(valid_after), Some(logged_in_at)) => logged_in_at >= valid_after,
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// Returns `None` if the user is no longer active.
+#[tracing::instrument(name = "Get an active user", skip(pool))]
+async fn get_active_user(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<ActiveUser>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT role, sessions_valid_after
+        FROM t_users
+        WHERE user_id = $1 AND status = 'active'
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve an active user.")?;
+    row.map(|row| {
+        Ok(ActiveUser {
+            role: Role::parse(&row.role).map_err(anyhow::Error::msg)?,
+            sessions_valid_after: row.sessions_valid_after,
+        })
+    })
+    .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::{session_expiry, ActiveUser, Role, SessionExpiry};
+    use crate::configuration::SessionTimeoutSettings;
+
+    #[test]
+    fn only_sessions_started_after_a_reset_are_accepted() {
+        let reset_at = Utc::now();
+        let user = ActiveUser {
+            role: Role::Owner,
+            sessions_valid_after: Some(reset_at),
+        };
+        assert!(!user.accepts_session_from(Some(reset_at - Duration::seconds(1))));
+        assert!(!user.accepts_session_from(None));
+        assert!(user.accepts_session_from(Some(reset_at)));
+
+        let user = ActiveUser {
+            role: Role::Owner,
+            sessions_valid_after: None,
+        };
+        assert!(user.accepts_session_from(None));
+    }
+
+    #[test]
+    fn sessions_expire_when_idle_or_too_old() {
+        let timeouts = SessionTimeoutSettings {
+            idle_seconds: 30 * 60,
+            absolute_seconds: 12 * 60 * 60,
+        };
+        let now = Utc::now();
+        let minutes_ago = |m| Some(now - Duration::minutes(m));
+
+        assert_eq!(
+            session_expiry(&timeouts, minutes_ago(60), minutes_ago(10), now),
+            None
+        );
+        assert_eq!(
+            session_expiry(&timeouts, minutes_ago(60), minutes_ago(31), now),
+            Some(SessionExpiry::Idle)
+        );
+        assert_eq!(session_expiry(&timeouts, minutes_ago(20), None, now), None);
+        assert_eq!(
+            session_expiry(&timeouts, minutes_ago(13 * 60), minutes_ago(1), now),
+            Some(SessionExpiry::Absolute)
+        );
+        assert_eq!(
+            session_expiry(&timeouts, None, minutes_ago(1), now),
+            Some(SessionExpiry::Absolute)
+        );
     }
 }
@@ -1,5 +1,33 @@
+mod api_tokens;
+mod authorization;
+mod csrf;
 mod middleware;
 mod password;
+mod password_policy;
+mod sessions;
+mod throttle;
+mod two_factor;
+mod user_token;
 
-pub use middleware::{reject_anonymous_users, UserId};
-pub use password::{change_password, validate_credentials, AuthError, Credentials};
+pub use api_tokens::{
+    delete_api_token, issue_api_token, list_user_api_tokens, ApiScope, ApiToken, ApiTokenScopes,
+};
+pub use authorization::{authorize, Permission, Role};
+pub use csrf::{rotate_csrf_token, verify_csrf_token, CsrfToken};
+pub use middleware::{
+    authenticate_api_tokens, reject_anonymous_users, reject_requests_without_api_token, UserId,
+};
+pub use password::{
+    change_password, hash_password, validate_credentials, AuthError, Credentials, PasswordHashing,
+};
+pub use password_policy::{strength_score, PasswordPolicy};
+pub use sessions::{
+    list_user_sessions, register_session, revoke_other_sessions, revoke_session, touch_session,
+    UserSession,
+};
+pub use throttle::LoginThrottle;
+pub use two_factor::{
+    confirm_enrolment, disable_two_factor, get_two_factor_status, is_two_factor_enabled,
+    replace_recovery_codes, start_enrolment, verify_second_factor, TotpSecret, TwoFactorStatus,
+};
+pub use user_token::{consume_user_token, find_user_token, issue_user_token, UserTokenPurpose};
@@ -7,7 +7,7 @@ use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::telemetry::spawn_blocking_with_tracing;
+use crate::{configuration::PasswordHashingSettings, telemetry::spawn_blocking_with_tracing};
 
 #[derive(thiserror::Error, Debug)]
 pub enum AuthError {
@@ -17,20 +17,63 @@ pub enum AuthError {
     UnexpectedError(#[from] anyhow::Error),
 }
 
+/// The Argon2id parameters that new password hashes are computed with.
+pub struct PasswordHashing {
+    params: Params,
+    /// Verified against when the username is unknown, so that the response
+    /// takes as long as for a known one.
+    dummy_hash: String,
+}
+
+impl PasswordHashing {
+    pub fn new(settings: &PasswordHashingSettings) -> Result<Self, anyhow::Error> {
+        let params = Params::new(
+            settings.memory_kib,
+            settings.iterations,
+            settings.parallelism,
+            None,
+        )
+        .context("Invalid Argon2 parameters.")?;
+        let dummy_hash =
+            compute_password_hash(Secret::new(Uuid::new_v4().to_string()), params.clone())?;
+        Ok(Self {
+            params,
+            dummy_hash: dummy_hash.expose_secret().to_owned(),
+        })
+    }
+
+    /// Whether a stored hash was computed with another algorithm or with
+    /// weaker parameters than the current ones.
+    fn is_outdated(&self, password_hash: &str) -> bool {
+        let Ok(password_hash) = PasswordHash::new(password_hash) else {
+            return false;
+        };
+        let Ok(params) = Params::try_from(&password_hash) else {
+            return false;
+        };
+        password_hash.algorithm != argon2::Algorithm::Argon2id.ident()
+            || password_hash.version != Some(Version::V0x13.into())
+            || params.m_cost() < self.params.m_cost()
+            || params.t_cost() < self.params.t_cost()
+            || params.p_cost() < self.params.p_cost()
+    }
+}
+
 pub struct Credentials {
     pub username: String,
     pub password: Secret<String>,
 }
 
-#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+/// Once the password is known to be correct, a hash computed with outdated
+/// parameters is replaced by one computed with the current ones.
+#[tracing::instrument(name = "Validate credentials", skip(credentials, pool, hashing))]
 pub async fn validate_credentials(
     credentials: Credentials,
     pool: &PgPool,
+    hashing: &PasswordHashing,
 ) -> Result<uuid::Uuid, AuthError> {
     let mut user_id = None;
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$8IKX9HPbtAcLjsMNmh3VAw$jAMZZuiHZyqOYYbmbvPzepp6q1zds5nlLsgwnwCXANQ".to_string()
-    );
+    let mut expected_password_hash = Secret::new(hashing.dummy_hash.clone());
     if let Some((stored_user_id, stored_password_hash)) =
         get_stored_credentials(&credentials.username, pool)
             .await
@@ -39,6 +82,14 @@ pub async fn validate_credentials(
         user_id = Some(stored_user_id);
         expected_password_hash = stored_password_hash;
     }
+    let outdated_hash = user_id
+        .filter(|_| hashing.is_outdated(expected_password_hash.expose_secret()))
+        .map(|_| {
+            (
+                expected_password_hash.expose_secret().to_owned(),
+                Secret::new(credentials.password.expose_secret().to_owned()),
+            )
+        });
 
     spawn_blocking_with_tracing(move || {
         verify_password_hash(expected_password_hash, credentials.password)
@@ -47,7 +98,49 @@ pub async fn validate_credentials(
     .context("Failed to spawn blocking task.")
     .map_err(AuthError::UnexpectedError)??;
 
-    user_id.ok_or_else(|| AuthError::InvalidCredentials(anyhow!("Unknown username.")))
+    let user_id =
+        user_id.ok_or_else(|| AuthError::InvalidCredentials(anyhow!("Unknown username.")))?;
+    if let Some((old_hash, password)) = outdated_hash {
+        // The user is logged in whether or not the upgrade succeeds.
+        if let Err(e) = upgrade_password_hash(user_id, &old_hash, password, pool, hashing).await {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to upgrade a password hash.",
+            );
+        }
+    }
+    Ok(user_id)
+}
+
+/// Only replaces the hash if it has not changed since it was verified, so
+/// that a concurrent password change is not undone.
+#[tracing::instrument(
+    name = "Upgrade password hash",
+    skip(old_hash, password, pool, hashing)
+)]
+async fn upgrade_password_hash(
+    user_id: Uuid,
+    old_hash: &str,
+    password: Secret<String>,
+    pool: &PgPool,
+    hashing: &PasswordHashing,
+) -> Result<(), anyhow::Error> {
+    let password_hash = hash_password(password, hashing).await?;
+    sqlx::query!(
+        r#"
+        UPDATE t_users
+        SET password_hash = $1
+        WHERE user_id = $2 AND password_hash = $3
+        "#,
+        password_hash.expose_secret(),
+        user_id,
+        old_hash
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store an upgraded password hash.")?;
+    Ok(())
 }
 
 #[tracing::instrument(name = "Verify password hash", skip(expected_password_hash, password))]
@@ -72,9 +165,9 @@ async fn get_stored_credentials(
 ) -> Result<Option<(Uuid, Secret<String>)>, anyhow::Error> {
     let row = sqlx::query!(
         r#"
-            SELECT user_id, password_hash
+            SELECT user_id, password_hash AS "password_hash!"
             FROM t_users
-            WHERE username = $1
+            WHERE username = $1 AND status = 'active' AND password_hash IS NOT NULL
         "#,
         username,
     )
@@ -86,15 +179,14 @@ async fn get_stored_credentials(
     Ok(row)
 }
 
-#[tracing::instrument(name = "Change user password", skip(password, pool))]
+#[tracing::instrument(name = "Change user password", skip(password, pool, hashing))]
 pub async fn change_password(
     user_id: Uuid,
     password: Secret<String>,
     pool: &PgPool,
+    hashing: &PasswordHashing,
 ) -> Result<(), anyhow::Error> {
-    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
-        .await?
-        .context("Failed to hash password")?;
+    let password_hash = hash_password(password, hashing).await?;
     sqlx::query!(
         r#"
         UPDATE t_users
@@ -111,16 +203,59 @@ pub async fn change_password(
     Ok(())
 }
 
+pub async fn hash_password(
+    password: Secret<String>,
+    hashing: &PasswordHashing,
+) -> Result<Secret<String>, anyhow::Error> {
+    let params = hashing.params.clone();
+    spawn_blocking_with_tracing(move || compute_password_hash(password, params))
+        .await?
+        .context("Failed to hash password")
+}
+
 #[tracing::instrument(name = "Computing password hash", skip(password))]
-fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+fn compute_password_hash(
+    password: Secret<String>,
+    params: Params,
+) -> Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::new(
-        argon2::Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
-    )
-    .hash_password(password.expose_secret().as_bytes(), &salt)?
-    .to_string();
+    let password_hash = Argon2::new(argon2::Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password(password.expose_secret().as_bytes(), &salt)?
+        .to_string();
 
     Ok(Secret::new(password_hash))
 }
+
+#[cfg(test)]
+mod tests {
+    use argon2::Params;
+    use secrecy::{ExposeSecret, Secret};
+
+    use super::{compute_password_hash, PasswordHashing};
+    use crate::configuration::PasswordHashingSettings;
+
+    fn hash_with(m_cost: u32, t_cost: u32, p_cost: u32) -> String {
+        let params = Params::new(m_cost, t_cost, p_cost, None).unwrap();
+        compute_password_hash(Secret::new("password".into()), params)
+            .unwrap()
+            .expose_secret()
+            .to_owned()
+    }
+
+    #[test]
+    fn only_hashes_with_weaker_parameters_are_outdated() {
+        let hashing = PasswordHashing::new(&PasswordHashingSettings {
+            memory_kib: 64,
+            iterations: 2,
+            parallelism: 1,
+        })
+        .unwrap();
+        assert!(!hashing.is_outdated(&hash_with(64, 2, 1)));
+        assert!(!hashing.is_outdated(&hash_with(128, 3, 1)));
+        assert!(hashing.is_outdated(&hash_with(32, 2, 1)));
+        assert!(hashing.is_outdated(&hash_with(64, 1, 1)));
+        assert!(hashing.is_outdated(
+            "$argon2i$v=19$m=64,t=2,p=1$8IKX9HPbtAcLjsMNmh3VAw$jAMZZuiHZyqOYYbmbvPzepp6q1zds5nlLsgwnwCXANQ"
+        ));
+    }
+}
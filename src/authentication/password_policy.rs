@@ -0,0 +1,282 @@
+//! What a new password must satisfy, whether it is chosen after an
+//! invitation, a reset or from the admin area.
+use std::{collections::HashMap, collections::HashSet, sync::LazyLock};
+
+use secrecy::{ExposeSecret, Secret};
+use sha1::{Digest, Sha1};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::configuration::PasswordPolicySettings;
+
+/// Common passwords and words, most common first.
+static COMMON_PASSWORDS: LazyLock<HashMap<&'static str, usize>> = LazyLock::new(|| {
+    include_str!("common_passwords.txt")
+        .lines()
+        .enumerate()
+        .map(|(rank, word)| (word, rank + 1))
+        .collect()
+});
+
+/// Upper-case SHA-1 hashes of passwords known from public breaches, one per
+/// line, in the format Have I Been Pwned publishes. The bundled list holds
+/// the most common ones and can be swapped for a larger extract.
+static BREACHED_PASSWORD_HASHES: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
+    include_str!("breached_password_hashes.txt")
+        .lines()
+        .collect()
+});
+
+const KEYBOARD_ROWS: [&str; 4] = ["qwertyuiop", "asdfghjkl", "zxcvbnm", "1234567890"];
+
+/// Longer common words and patterns are matched as several shorter ones.
+const MAX_PATTERN_LENGTH: usize = 24;
+
+pub struct PasswordPolicy {
+    settings: PasswordPolicySettings,
+}
+
+impl PasswordPolicy {
+    pub fn new(settings: PasswordPolicySettings) -> Self {
+        Self { settings }
+    }
+
+    /// Checks a new password and its confirmation for `username`, returning
+    /// the message to show the user when they are not acceptable.
+    pub fn check_new_password(
+        &self,
+        username: &str,
+        password: &Secret<String>,
+        password_check: &Secret<String>,
+    ) -> Result<(), String> {
+        let password = password.expose_secret();
+        let PasswordPolicySettings {
+            min_length,
+            max_length,
+            ..
+        } = self.settings;
+        let length = password.graphemes(true).count();
+        if length < min_length {
+            return Err(format!(
+                "The new password is too short - its length must be between {} and {} characters.",
+                min_length, max_length
+            ));
+        } else if length > max_length {
+            return Err(format!(
+                "The new password is too long - its length must be between {} and {} characters.",
+                min_length, max_length
+            ));
+        }
+
+        if password != password_check.expose_secret() {
+            return Err("You entered two different password - the field values must match.".into());
+        }
+
+        // Very short usernames would rule out too many passwords.
+        let username = username.trim().to_lowercase();
+        if username.graphemes(true).count() >= 3 && password.to_lowercase().contains(&username) {
+            return Err("The new password must not contain your username.".into());
+        }
+
+        if self.settings.reject_breached && is_breached(password) {
+            return Err(
+                "This password has appeared in a data breach - please choose another one.".into(),
+            );
+        }
+
+        if strength_score(password) < self.settings.min_strength {
+            return Err("The new password is too easy to guess - \
+                try a longer one, or several unrelated words."
+                .into());
+        }
+
+        Ok(())
+    }
+}
+
+fn is_breached(password: &str) -> bool {
+    let hash = format!("{:X}", Sha1::digest(password.as_bytes()));
+    BREACHED_PASSWORD_HASHES.contains(hash.as_str())
+}
+
+/// From 0 (guessed almost at once) to 4 (very unlikely to be guessed), on the
+/// scale zxcvbn uses.
+pub fn strength_score(password: &str) -> u8 {
+    match estimate_guesses_log10(password) {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    }
+}
+
+/// Estimates, in the spirit of zxcvbn, how many guesses an attacker needs:
+/// the password is split into the cheapest sequence of common words,
+/// repeated characters, keyboard or alphabetical runs and single characters,
+/// and the guesses for each part are multiplied.
+fn estimate_guesses_log10(password: &str) -> f64 {
+    let chars: Vec<char> = password.chars().collect();
+    let mut best = vec![f64::INFINITY; chars.len() + 1];
+    best[0] = 0.0;
+    for end in 1..=chars.len() {
+        for start in end.saturating_sub(MAX_PATTERN_LENGTH)..end {
+            let guesses = segment_guesses(&chars[start..end]);
+            best[end] = best[end].min(best[start] + guesses.log10());
+        }
+    }
+    best[chars.len()]
+}
+
+/// The guesses needed for one part of a password, or infinity if it does not
+/// match any pattern and is not a single character.
+fn segment_guesses(segment: &[char]) -> f64 {
+    if let [c] = segment {
+        return cardinality(*c);
+    }
+    if segment.len() < 3 {
+        return f64::INFINITY;
+    }
+    let mut guesses = f64::INFINITY;
+
+    let has_upper = segment.iter().any(|c| c.is_uppercase());
+    let word: String = segment.iter().flat_map(|c| c.to_lowercase()).collect();
+    let unleeted: String = word.chars().map(unleet).collect();
+    for (candidate, substituted) in [(&word, false), (&unleeted, true)] {
+        if let Some(rank) = COMMON_PASSWORDS.get(candidate.as_str()) {
+            let mut g = *rank as f64;
+            if has_upper {
+                g *= 2.0;
+            }
+            if substituted && unleeted != word {
+                g *= 2.0;
+            }
+            guesses = guesses.min(g);
+        }
+    }
+
+    if segment.iter().all(|c| *c == segment[0]) {
+        guesses = guesses.min(cardinality(segment[0]) * segment.len() as f64);
+    }
+
+    let steps: Vec<i64> = segment
+        .windows(2)
+        .map(|w| w[1] as i64 - w[0] as i64)
+        .collect();
+    if steps.iter().all(|s| *s == 1) || steps.iter().all(|s| *s == -1) {
+        let start_is_obvious = "aAzZ019".contains(segment[0]);
+        let base = if start_is_obvious { 4.0 } else { 26.0 };
+        guesses = guesses.min(base * segment.len() as f64);
+    }
+
+    if KEYBOARD_ROWS.iter().any(|row| {
+        row.contains(word.as_str()) || row.chars().rev().collect::<String>().contains(&word)
+    }) {
+        guesses = guesses.min(20.0 * segment.len() as f64);
+    }
+
+    guesses
+}
+
+/// How many characters a single one of this kind could have been.
+fn cardinality(c: char) -> f64 {
+    if c.is_ascii_digit() {
+        10.0
+    } else if c.is_ascii_lowercase() || c.is_ascii_uppercase() {
+        26.0
+    } else if c.is_ascii() {
+        33.0
+    } else {
+        100.0
+    }
+}
+
+/// The letter a common substitution stands for.
+fn unleet(c: char) -> char {
+    match c {
+        '4' | '@' => 'a',
+        '3' => 'e',
+        '1' | '!' => 'i',
+        '0' => 'o',
+        '5' | '$' => 's',
+        '7' => 't',
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    use super::{is_breached, strength_score, PasswordPolicy};
+    use crate::configuration::PasswordPolicySettings;
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy::new(PasswordPolicySettings {
+            min_length: 8,
+            max_length: 128,
+            min_strength: 3,
+            reject_breached: true,
+        })
+    }
+
+    fn check(username: &str, password: &str) -> Result<(), String> {
+        let password = Secret::new(password.to_owned());
+        policy().check_new_password(username, &password, &password)
+    }
+
+    #[test]
+    fn length_is_counted_in_graphemes() {
+        // Eight graphemes, but many more bytes.
+        assert_ok!(check("ursula", "🦀🐙🦑🐳🦈🐬🦭🐡"));
+        // Seven graphemes made of fourteen chars.
+        assert_err!(check(
+            "ursula",
+            "e\u{301}e\u{301}e\u{301}e\u{301}e\u{301}e\u{301}e\u{301}"
+        ));
+    }
+
+    #[test]
+    fn common_patterns_are_weak() {
+        for password in [
+            "password",
+            "P@ssw0rd1",
+            "qwertyuiop",
+            "aaaaaaaaaaaa",
+            "abcdefgh12345678",
+            "sunshine2024!",
+            "12345678",
+        ] {
+            assert!(strength_score(password) < 3, "{} is not weak", password);
+        }
+    }
+
+    #[test]
+    fn long_random_or_multi_word_passwords_are_strong() {
+        for password in [
+            "correct horse battery staple",
+            "f47ac10b-58cc-4372-a567-0e02b2c3d479",
+            "vK9#pL2$wQ7!",
+        ] {
+            assert_eq!(strength_score(password), 4, "{} is not strong", password);
+        }
+    }
+
+    #[test]
+    fn passwords_containing_the_username_are_rejected() {
+        assert_eq!(
+            check("Ursula", "my-ursula-vK9#pL2$"),
+            Err("The new password must not contain your username.".into())
+        );
+    }
+
+    #[test]
+    fn breached_passwords_are_rejected() {
+        assert!(is_breached("Sunshine123"));
+        assert!(!is_breached("vK9#pL2$wQ7!"));
+        assert_eq!(
+            check("ursula", "Sunshine123"),
+            Err("This password has appeared in a data breach - please choose another one.".into())
+        );
+    }
+}
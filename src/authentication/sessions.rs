@@ -0,0 +1,148 @@
+//! A record of the sessions each user is logged in with, so that they can
+//! see them and revoke any of them. A session whose record is gone is no
+//! longer accepted by [`super::reject_anonymous_users`].
+use actix_web::{http::header::USER_AGENT, HttpRequest};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::client_address::client_address;
+
+/// User agents are only shown to help recognise a device.
+const MAX_USER_AGENT_LENGTH: usize = 256;
+
+pub struct UserSession {
+    pub session_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// Records a new session for `user_id` and returns its id, to be stored in
+/// the session itself. Records that have not been seen for longer than a
+/// session lasts in Redis are cleared at the same time.
+#[tracing::instrument(name = "Register a session", skip(pool, request))]
+pub async fn register_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    request: &HttpRequest,
+) -> Result<Uuid, anyhow::Error> {
+    let ip_address = client_address(request).map(|a| a.to_string());
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.chars().take(MAX_USER_AGENT_LENGTH).collect::<String>());
+
+    sqlx::query!(
+        r#"
+        DELETE FROM t_user_sessions
+        WHERE user_id = $1 AND last_seen_at < now() - interval '1 day'
+        "#,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to clear expired sessions.")?;
+
+    let session_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO t_user_sessions (session_id, user_id, ip_address, user_agent)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        session_id,
+        user_id,
+        ip_address,
+        user_agent
+    )
+    .execute(pool)
+    .await
+    .context("Failed to register a session.")?;
+    Ok(session_id)
+}
+
+/// Marks the session as seen now. Returns `false` if it has been revoked.
+#[tracing::instrument(name = "Touch a session", skip(pool))]
+pub async fn touch_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE t_user_sessions
+        SET last_seen_at = now()
+        WHERE session_id = $1 AND user_id = $2
+        "#,
+        session_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the last use of a session.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// The sessions of a user that may still be alive, most recently seen first.
+#[tracing::instrument(name = "List sessions", skip(pool))]
+pub async fn list_user_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<UserSession>, anyhow::Error> {
+    sqlx::query_as!(
+        UserSession,
+        r#"
+        SELECT session_id, created_at, last_seen_at, ip_address, user_agent
+        FROM t_user_sessions
+        WHERE user_id = $1 AND last_seen_at >= now() - interval '1 day'
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to list the sessions of a user.")
+}
+
+/// Returns `false` if the user has no such session.
+#[tracing::instrument(name = "Revoke a session", skip(pool))]
+pub async fn revoke_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM t_user_sessions WHERE session_id = $1 AND user_id = $2",
+        session_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke a session.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Revokes every session of the user but `keep`, or all of them if `keep`
+/// is `None`.
+#[tracing::instrument(name = "Revoke other sessions", skip(executor))]
+pub async fn revoke_other_sessions(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    keep: Option<Uuid>,
+) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM t_user_sessions
+        WHERE user_id = $1 AND session_id IS DISTINCT FROM $2
+        "#,
+        user_id,
+        keep
+    )
+    .execute(executor)
+    .await
+    .context("Failed to revoke the sessions of a user.")?;
+    Ok(result.rows_affected())
+}
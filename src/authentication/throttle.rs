@@ -0,0 +1,179 @@
+use anyhow::Context;
+use chrono::Utc;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::configuration::LoginThrottleSettings;
+
+/// Lockouts are forgotten after a day without one, and the next lockout is
+/// back to the shortest period.
+const LOCKOUT_MEMORY_SECONDS: i64 = 24 * 60 * 60;
+
+/// Limits password guessing. Failed logins are counted over a sliding window
+/// both per username and per client address; too many for a username locks
+/// it for a period that doubles with each lockout.
+///
+/// Usernames are counted whether or not a user has them, so that neither the
+/// lockout nor its message tells an attacker which usernames exist.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    redis: ConnectionManager,
+    settings: LoginThrottleSettings,
+}
+
+impl LoginThrottle {
+    pub async fn new(
+        redis_uri: &Secret<String>,
+        settings: LoginThrottleSettings,
+    ) -> Result<Self, anyhow::Error> {
+        let redis = redis::Client::open(redis_uri.expose_secret().as_str())
+            .context("Invalid Redis URI.")?
+            .get_connection_manager()
+            .await
+            .context("Failed to connect to Redis.")?;
+        Ok(Self { redis, settings })
+    }
+
+    /// Usernames are hashed so that arbitrary input cannot make keys of any size.
+    fn username_key(&self, kind: &str, username: &str) -> String {
+        format!(
+            "{}:{}:{:x}",
+            self.settings.key_prefix,
+            kind,
+            Sha256::digest(username.as_bytes())
+        )
+    }
+
+    fn address_key(&self, address: &str) -> String {
+        format!("{}:address:{}", self.settings.key_prefix, address)
+    }
+
+    fn window_millis(&self) -> i64 {
+        self.settings.window_seconds as i64 * 1000
+    }
+
+    /// Whether a login attempt must be refused without checking the password.
+    #[tracing::instrument(name = "Check login throttling", skip(self, username))]
+    pub async fn is_blocked(&self, username: &str, address: &str) -> Result<bool, anyhow::Error> {
+        let window_start = Utc::now().timestamp_millis() - self.window_millis();
+        let (locked, address_failures): (bool, u32) = redis::pipe()
+            .exists(self.username_key("lockout", username))
+            .zcount(self.address_key(address), window_start, "+inf")
+            .query_async(&mut self.redis.clone())
+            .await
+            .context("Failed to read login attempts from Redis.")?;
+        if locked {
+            tracing::warn!("Login attempt for a locked-out username");
+            return Ok(true);
+        }
+        if address_failures >= self.settings.max_failures_per_address {
+            tracing::warn!(
+                address_failures,
+                "Login attempts from this address are throttled"
+            );
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    #[tracing::instrument(name = "Record a failed login", skip(self, username))]
+    pub async fn record_failure(&self, username: &str, address: &str) -> Result<(), anyhow::Error> {
+        let now = Utc::now().timestamp_millis();
+        let window_start = now - self.window_millis();
+        let attempt = format!("{}-{}", now, Uuid::new_v4());
+        let attempts_key = self.username_key("failures", username);
+        let address_key = self.address_key(address);
+        let mut redis = self.redis.clone();
+
+        let (username_failures,): (u32,) = redis::pipe()
+            .atomic()
+            .zadd(&attempts_key, &attempt, now)
+            .ignore()
+            .zrembyscore(&attempts_key, "-inf", window_start)
+            .ignore()
+            .pexpire(&attempts_key, self.window_millis())
+            .ignore()
+            .zadd(&address_key, &attempt, now)
+            .ignore()
+            .zrembyscore(&address_key, "-inf", window_start)
+            .ignore()
+            .pexpire(&address_key, self.window_millis())
+            .ignore()
+            .zcard(&attempts_key)
+            .query_async(&mut redis)
+            .await
+            .context("Failed to record a failed login in Redis.")?;
+        if username_failures < self.settings.max_failures_per_username {
+            return Ok(());
+        }
+
+        let lockouts_key = self.username_key("lockouts", username);
+        let (lockouts,): (u32,) = redis::pipe()
+            .atomic()
+            .incr(&lockouts_key, 1)
+            .expire(&lockouts_key, LOCKOUT_MEMORY_SECONDS)
+            .ignore()
+            .del(&attempts_key)
+            .ignore()
+            .query_async(&mut redis)
+            .await
+            .context("Failed to count lockouts in Redis.")?;
+        let lockout_seconds = lockout_seconds(&self.settings, lockouts);
+        redis
+            .set_ex::<_, _, ()>(self.username_key("lockout", username), 1, lockout_seconds)
+            .await
+            .context("Failed to store a lockout in Redis.")?;
+        tracing::warn!(
+            username_failures,
+            lockouts,
+            lockout_seconds,
+            "Username locked out after repeated failed logins"
+        );
+        Ok(())
+    }
+
+    /// Forgets the failures for a username once its password has been entered.
+    #[tracing::instrument(name = "Reset failed logins", skip(self, username))]
+    pub async fn record_success(&self, username: &str) -> Result<(), anyhow::Error> {
+        self.redis
+            .clone()
+            .del::<_, ()>(&[
+                self.username_key("failures", username),
+                self.username_key("lockouts", username),
+            ])
+            .await
+            .context("Failed to reset failed logins in Redis.")
+    }
+}
+
+/// Each lockout within a day lasts twice as long as the one before.
+fn lockout_seconds(settings: &LoginThrottleSettings, lockouts: u32) -> u64 {
+    let factor = 2u64.saturating_pow(lockouts.saturating_sub(1));
+    settings
+        .base_lockout_seconds
+        .saturating_mul(factor)
+        .min(settings.max_lockout_seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lockout_seconds;
+    use crate::configuration::LoginThrottleSettings;
+
+    #[test]
+    fn lockouts_double_up_to_the_maximum() {
+        let settings = LoginThrottleSettings {
+            key_prefix: "test".into(),
+            window_seconds: 900,
+            max_failures_per_username: 5,
+            max_failures_per_address: 20,
+            base_lockout_seconds: 60,
+            max_lockout_seconds: 3600,
+        };
+        let periods: Vec<u64> = (1..=8).map(|n| lockout_seconds(&settings, n)).collect();
+        assert_eq!(periods, [60, 120, 240, 480, 960, 1920, 3600, 3600]);
+        assert_eq!(lockout_seconds(&settings, u32::MAX), 3600);
+    }
+}
@@ -0,0 +1,390 @@
+//! Time-based one-time passwords (RFC 6238) as a second login factor, with
+//! single-use recovery codes for when the authenticator is lost.
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::{distributions::DistString, RngCore};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+const DIGITS: u32 = 6;
+const PERIOD_SECONDS: i64 = 30;
+/// Codes from the periods either side of the current one are accepted too,
+/// to allow for clock drift between the server and the authenticator.
+const ALLOWED_DRIFT: i64 = 1;
+const RECOVERY_CODE_COUNT: usize = 10;
+const ISSUER: &str = "Newsletter";
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+pub struct TotpSecret(Vec<u8>);
+
+impl TotpSecret {
+    /// 160 bits, the length RFC 4226 recommends for HMAC-SHA1.
+    pub fn generate() -> Self {
+        let mut secret = vec![0; 20];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Self(secret)
+    }
+
+    /// The secret as authenticator apps expect it to be typed in.
+    pub fn to_base32(&self) -> String {
+        let mut encoded = String::new();
+        for chunk in self.0.chunks(5) {
+            let mut buffer = [0u8; 5];
+            buffer[..chunk.len()].copy_from_slice(chunk);
+            let bits = buffer
+                .iter()
+                .fold(0u64, |acc, b| (acc << 8) | u64::from(*b));
+            let chars = (chunk.len() * 8).div_ceil(5);
+            for i in 0..chars {
+                let index = (bits >> (35 - i * 5)) & 0b11111;
+                encoded.push(BASE32_ALPHABET[index as usize] as char);
+            }
+        }
+        encoded
+    }
+
+    /// The `otpauth://` URI that authenticator apps read from a QR code.
+    pub fn provisioning_uri(&self, username: &str) -> String {
+        format!(
+            "otpauth://totp/{issuer}:{username}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={PERIOD_SECONDS}",
+            issuer = urlencoding::encode(ISSUER),
+            username = urlencoding::encode(username),
+            secret = self.to_base32(),
+        )
+    }
+
+    /// HOTP (RFC 4226) for the given time step.
+    fn code_at(&self, step: i64) -> u32 {
+        let mut mac =
+            Hmac::<Sha1>::new_from_slice(&self.0).expect("HMAC can take a key of any size");
+        mac.update(&step.to_be_bytes());
+        let hash = mac.finalize().into_bytes();
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let truncated = u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap());
+        (truncated & 0x7fff_ffff) % 10u32.pow(DIGITS)
+    }
+
+    /// Returns the time step of the code if it is valid at `now`.
+    pub fn verify(&self, code: &str, now: DateTime<Utc>) -> Option<i64> {
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let code: u32 = code.parse().ok()?;
+        let current_step = now.timestamp() / PERIOD_SECONDS;
+        (current_step - ALLOWED_DRIFT..=current_step + ALLOWED_DRIFT)
+            .find(|step| self.code_at(*step) == code)
+    }
+}
+
+/// Recovery codes are shown once and only their hash is stored. Dashes,
+/// spaces and case are ignored when one is typed back.
+fn hash_recovery_code(code: &str) -> String {
+    let normalised: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    format!("{:x}", Sha256::digest(normalised.as_bytes()))
+}
+
+fn generate_recovery_code() -> String {
+    let code = rand::distributions::Alphanumeric
+        .sample_string(&mut rand::thread_rng(), 10)
+        .to_ascii_lowercase();
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub pending_secret: Option<TotpSecret>,
+    pub unused_recovery_codes: i64,
+}
+
+#[tracing::instrument(name = "Get the two-factor status of a user", skip(executor))]
+pub async fn get_two_factor_status(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+) -> Result<TwoFactorStatus, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            totp_secret,
+            totp_enabled_at IS NOT NULL AS "enabled!",
+            (
+                SELECT count(*)
+                FROM t_recovery_codes c
+                WHERE c.user_id = u.user_id AND c.used_at IS NULL
+            ) AS "unused_recovery_codes!"
+        FROM t_users u
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(executor)
+    .await
+    .context("Failed to retrieve the two-factor status of a user.")?;
+
+    Ok(TwoFactorStatus {
+        enabled: row.enabled,
+        pending_secret: row.totp_secret.filter(|_| !row.enabled).map(TotpSecret),
+        unused_recovery_codes: row.unused_recovery_codes,
+    })
+}
+
+/// Stores a new secret, which is not required at login until
+/// [`confirm_enrolment`] is called.
+#[tracing::instrument(name = "Start two-factor enrolment", skip(pool))]
+pub async fn start_enrolment(pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE t_users
+        SET totp_secret = $1, totp_last_used_step = NULL
+        WHERE user_id = $2 AND totp_enabled_at IS NULL
+        "#,
+        TotpSecret::generate().0,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a TOTP secret.")?;
+    Ok(())
+}
+
+/// Checks a first code against the pending secret and, if it is valid,
+/// enables two-factor authentication and returns a fresh set of recovery codes.
+#[tracing::instrument(name = "Confirm two-factor enrolment", skip(pool, code))]
+pub async fn confirm_enrolment(
+    pool: &PgPool,
+    user_id: Uuid,
+    code: &str,
+) -> Result<Option<Vec<String>>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let secret = sqlx::query_scalar!(
+        r#"
+        SELECT totp_secret AS "totp_secret!"
+        FROM t_users
+        WHERE user_id = $1 AND totp_enabled_at IS NULL AND totp_secret IS NOT NULL
+        FOR UPDATE
+        "#,
+        user_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve a pending TOTP secret.")?;
+    let Some(step) = secret.and_then(|s| TotpSecret(s).verify(code, Utc::now())) else {
+        return Ok(None);
+    };
+
+    sqlx::query!(
+        r#"
+        UPDATE t_users
+        SET totp_enabled_at = now(), totp_last_used_step = $1
+        WHERE user_id = $2
+        "#,
+        step,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to enable two-factor authentication.")?;
+    let recovery_codes = replace_recovery_codes(&mut transaction, user_id).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to enable two-factor authentication.")?;
+    Ok(Some(recovery_codes))
+}
+
+/// Invalidates every recovery code of the user and returns new ones.
+#[tracing::instrument(name = "Replace recovery codes", skip(transaction))]
+pub async fn replace_recovery_codes(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<Vec<String>, anyhow::Error> {
+    sqlx::query!("DELETE FROM t_recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to delete recovery codes.")?;
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect();
+    let hashes: Vec<String> = codes.iter().map(|c| hash_recovery_code(c)).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO t_recovery_codes (user_id, code_hash)
+        SELECT $1, * FROM UNNEST($2::text[])
+        "#,
+        user_id,
+        &hashes
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to store recovery codes.")?;
+    Ok(codes)
+}
+
+#[tracing::instrument(name = "Disable two-factor authentication", skip(pool))]
+pub async fn disable_two_factor(pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    sqlx::query!(
+        r#"
+        UPDATE t_users
+        SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_used_step = NULL
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to disable two-factor authentication.")?;
+    sqlx::query!("DELETE FROM t_recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete recovery codes.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to disable two-factor authentication.")?;
+    Ok(())
+}
+
+/// Whether the user must enter a code after their password.
+#[tracing::instrument(name = "Check whether a user has two-factor enabled", skip(pool))]
+pub async fn is_two_factor_enabled(pool: &PgPool, user_id: Uuid) -> Result<bool, anyhow::Error> {
+    let enabled = sqlx::query_scalar!(
+        r#"SELECT totp_enabled_at IS NOT NULL AS "enabled!" FROM t_users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to check whether two-factor authentication is enabled.")?;
+    Ok(enabled)
+}
+
+/// Accepts either a TOTP code or an unused recovery code, which is then used
+/// up. A TOTP code is rejected if it is not newer than the last one accepted,
+/// so that an observed code cannot be replayed within its period.
+#[tracing::instrument(name = "Verify a second factor", skip(pool, code))]
+pub async fn verify_second_factor(
+    pool: &PgPool,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let row = sqlx::query!(
+        r#"
+        SELECT totp_secret AS "totp_secret!", totp_last_used_step
+        FROM t_users
+        WHERE user_id = $1 AND totp_enabled_at IS NOT NULL AND totp_secret IS NOT NULL
+        FOR UPDATE
+        "#,
+        user_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve a TOTP secret.")?;
+    let Some(row) = row else {
+        return Ok(false);
+    };
+
+    let totp_step = TotpSecret(row.totp_secret)
+        .verify(code, Utc::now())
+        .filter(|step| row.totp_last_used_step.is_none_or(|last| *step > last));
+    let accepted = match totp_step {
+        Some(step) => {
+            sqlx::query!(
+                "UPDATE t_users SET totp_last_used_step = $1 WHERE user_id = $2",
+                step,
+                user_id
+            )
+            .execute(&mut *transaction)
+            .await
+            .context("Failed to record a used TOTP code.")?;
+            true
+        }
+        None => {
+            sqlx::query!(
+                r#"
+                UPDATE t_recovery_codes
+                SET used_at = now()
+                WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+                "#,
+                user_id,
+                hash_recovery_code(code)
+            )
+            .execute(&mut *transaction)
+            .await
+            .context("Failed to use a recovery code.")?
+            .rows_affected()
+                == 1
+        }
+    };
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to verify a second factor.")?;
+    Ok(accepted)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::{hash_recovery_code, TotpSecret};
+
+    /// The SHA-1 test vectors of RFC 6238, appendix B, truncated to six digits.
+    #[test]
+    fn codes_match_the_rfc_6238_test_vectors() {
+        let secret = TotpSecret(b"12345678901234567890".to_vec());
+        for (time, code) in [
+            (59, 287082),
+            (1111111109, 81804),
+            (1111111111, 50471),
+            (1234567890, 5924),
+            (2000000000, 279037),
+        ] {
+            assert_eq!(secret.code_at(time / 30), code);
+        }
+    }
+
+    #[test]
+    fn codes_are_accepted_in_adjacent_periods_only() {
+        let secret = TotpSecret(b"12345678901234567890".to_vec());
+        let at = |t| DateTime::from_timestamp(t, 0).unwrap();
+        assert_eq!(secret.verify("081804", at(1111111109)), Some(37037036));
+        assert_eq!(
+            secret.verify("081 804", at(1111111109 + 30)),
+            Some(37037036)
+        );
+        assert_eq!(secret.verify("081804", at(1111111109 + 90)), None);
+        assert_eq!(secret.verify("81804", at(1111111109)), None);
+    }
+
+    #[test]
+    fn the_secret_is_base32_encoded_without_padding() {
+        let secret = TotpSecret(b"12345678901234567890".to_vec());
+        assert_eq!(secret.to_base32(), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(TotpSecret(b"f".to_vec()).to_base32(), "MY");
+    }
+
+    #[test]
+    fn recovery_codes_ignore_dashes_and_case() {
+        assert_eq!(
+            hash_recovery_code("abcde-12345"),
+            hash_recovery_code(" ABCDE12345")
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use rand::distributions::DistString;
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, Postgres, Transaction};
+use uuid::Uuid;
+
+/// What a user token lets its bearer do. A token issued for one purpose is
+/// never accepted for another.
+#[derive(Debug, Clone, Copy)]
+pub enum UserTokenPurpose {
+    Invitation,
+    PasswordReset,
+}
+
+impl UserTokenPurpose {
+    fn as_str(&self) -> &'static str {
+        match self {
+            UserTokenPurpose::Invitation => "invitation",
+            UserTokenPurpose::PasswordReset => "password_reset",
+        }
+    }
+}
+
+pub(super) fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Creates a token for `user_id`, replacing any earlier one with the same
+/// purpose, and returns it. Only its hash is stored.
+#[tracing::instrument(name = "Issue a user token", skip(transaction))]
+pub async fn issue_user_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    purpose: UserTokenPurpose,
+    validity: Duration,
+) -> Result<String, anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM t_user_tokens WHERE user_id = $1 AND purpose = $2",
+        user_id,
+        purpose.as_str()
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to delete earlier user tokens.")?;
+
+    let token = rand::distributions::Alphanumeric.sample_string(&mut rand::thread_rng(), 48);
+    sqlx::query!(
+        r#"
+        INSERT INTO t_user_tokens (token_hash, user_id, purpose, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        hash_token(&token),
+        user_id,
+        purpose.as_str(),
+        Utc::now() + validity
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to store a user token.")?;
+
+    Ok(token)
+}
+
+/// Returns the user a token was issued to, if it is valid for `purpose` and
+/// has not expired. The token remains usable.
+#[tracing::instrument(name = "Look up a user token", skip(executor, token))]
+pub async fn find_user_token(
+    executor: impl PgExecutor<'_>,
+    token: &str,
+    purpose: UserTokenPurpose,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT user_id
+        FROM t_user_tokens
+        WHERE token_hash = $1 AND purpose = $2 AND expires_at > now()
+        "#,
+        hash_token(token),
+        purpose.as_str()
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to look up a user token.")
+}
+
+/// Like [`find_user_token`], but deletes the token so that it cannot be used
+/// a second time.
+#[tracing::instrument(name = "Consume a user token", skip(transaction, token))]
+pub async fn consume_user_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+    purpose: UserTokenPurpose,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        DELETE FROM t_user_tokens
+        WHERE token_hash = $1 AND purpose = $2
+        RETURNING user_id, expires_at
+        "#,
+        hash_token(token),
+        purpose.as_str()
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to consume a user token.")?;
+
+    Ok(row
+        .filter(|row| row.expires_at > Utc::now())
+        .map(|row| row.user_id))
+}
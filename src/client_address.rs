@@ -0,0 +1,95 @@
+//! The address of the client of a request, which login and signup attempts
+//! are counted per. Behind a reverse proxy every connection comes from the
+//! proxy, so the address it forwards in `X-Forwarded-For` is used instead,
+//! but only for connections from a proxy listed in the configuration: anyone
+//! else can put any address in that header.
+use std::net::IpAddr;
+
+use actix_web::{web, HttpRequest};
+
+const X_FORWARDED_FOR: &str = "X-Forwarded-For";
+
+/// The proxies whose `X-Forwarded-For` header is trusted.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+/// The address of the client, or `None` if the connection has none, as in
+/// some tests.
+pub fn client_address(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let Some(proxies) = req.app_data::<web::Data<TrustedProxies>>() else {
+        return Some(peer);
+    };
+    if !proxies.0.contains(&peer) {
+        return Some(peer);
+    }
+
+    // Each proxy appends the address it got the request from, so the client
+    // is the last address that is not one of our proxies. Addresses before it
+    // were sent by the client and cannot be trusted.
+    let forwarded: Vec<&str> = req
+        .headers()
+        .get_all(X_FORWARDED_FOR)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+    for address in forwarded.into_iter().rev() {
+        match address.trim().parse::<IpAddr>() {
+            Ok(address) if proxies.0.contains(&address) => continue,
+            Ok(address) => return Some(address),
+            Err(_) => break,
+        }
+    }
+    Some(peer)
+}
+
+/// [`client_address`] as a string, to build rate limiting keys with.
+pub fn client_address_key(req: &HttpRequest) -> String {
+    client_address(req).map_or_else(|| "unknown".to_owned(), |a| a.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, SocketAddr};
+
+    use actix_web::{test::TestRequest, web};
+
+    use super::{client_address, TrustedProxies};
+
+    const PROXY: &str = "10.0.0.1";
+
+    fn address(peer: &str, forwarded_for: Option<&str>) -> Option<IpAddr> {
+        let mut req = TestRequest::default()
+            .peer_addr(SocketAddr::new(peer.parse().unwrap(), 443))
+            .app_data(web::Data::new(TrustedProxies(vec![PROXY.parse().unwrap()])));
+        if let Some(forwarded_for) = forwarded_for {
+            req = req.insert_header(("X-Forwarded-For", forwarded_for));
+        }
+        client_address(&req.to_http_request())
+    }
+
+    #[test]
+    fn the_forwarded_address_is_only_trusted_from_a_proxy() {
+        assert_eq!(
+            address(PROXY, Some("203.0.113.7")),
+            Some("203.0.113.7".parse().unwrap())
+        );
+        assert_eq!(
+            address("198.51.100.2", Some("203.0.113.7")),
+            Some("198.51.100.2".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn addresses_made_up_by_the_client_are_ignored() {
+        assert_eq!(
+            address(PROXY, Some("192.0.2.1, 203.0.113.7, 10.0.0.1")),
+            Some("203.0.113.7".parse().unwrap())
+        );
+        assert_eq!(
+            address(PROXY, Some("203.0.113.7, not-an-address")),
+            Some(PROXY.parse().unwrap())
+        );
+        assert_eq!(address(PROXY, None), Some(PROXY.parse().unwrap()));
+    }
+}
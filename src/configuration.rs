@@ -1,4 +1,7 @@
-use std::{net::Ipv4Addr, time::Duration};
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    time::Duration,
+};
 
 use secrecy::{ExposeSecret, Secret};
 use sqlx::{
@@ -14,6 +17,13 @@ pub struct Settings {
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
     pub redis_uri: Secret<String>,
+    pub tracking: TrackingSettings,
+    pub login_throttle: LoginThrottleSettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub password_policy: PasswordPolicySettings,
+    pub session_timeouts: SessionTimeoutSettings,
+    pub signup_protection: SignupProtectionSettings,
+    pub subscriber_import: SubscriberImportSettings,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -32,6 +42,11 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub base_url: String,
     pub hmac_secret: HmacSecret,
+    /// The reverse proxies in front of the application, whose
+    /// `X-Forwarded-For` header gives the address of the client. Without
+    /// any, the address of the connection is used.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -42,6 +57,70 @@ pub struct EmailClientSettings {
     pub timeout_millis: u64,
 }
 
+#[derive(serde::Deserialize, Clone)]
+pub struct TrackingSettings {
+    pub track_opens: bool,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct LoginThrottleSettings {
+    /// Prefix of the Redis keys, so that several instances can share a Redis.
+    pub key_prefix: String,
+    pub window_seconds: u64,
+    pub max_failures_per_username: u32,
+    pub max_failures_per_address: u32,
+    pub base_lockout_seconds: u64,
+    pub max_lockout_seconds: u64,
+}
+
+/// Argon2id parameters for new password hashes. Raising them upgrades the
+/// hash of each user the next time they log in.
+#[derive(serde::Deserialize, Clone)]
+pub struct PasswordHashingSettings {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct PasswordPolicySettings {
+    /// Lengths are counted in graphemes, what a user sees as characters.
+    pub min_length: usize,
+    pub max_length: usize,
+    /// From 0 to 4, see [`crate::authentication::strength_score`].
+    pub min_strength: u8,
+    pub reject_breached: bool,
+}
+
+/// How long a session under `/admin` lasts without being used, and at most.
+#[derive(serde::Deserialize, Clone)]
+pub struct SessionTimeoutSettings {
+    pub idle_seconds: u64,
+    pub absolute_seconds: u64,
+}
+
+/// Where the signup widget may be embedded, and how it keeps bots out.
+#[derive(serde::Deserialize, Clone)]
+pub struct SignupProtectionSettings {
+    /// Origins of the sites allowed to post signups from the browser, such as
+    /// `https://blog.example.com`.
+    pub allowed_origins: Vec<String>,
+    /// A signup sent sooner after its form was shown was not typed by a person.
+    pub min_fill_seconds: i64,
+    /// Forms shown longer ago than this have to be reloaded.
+    pub max_fill_seconds: i64,
+    /// Prefix of the Redis keys, so that several instances can share a Redis.
+    pub key_prefix: String,
+    pub window_seconds: u64,
+    pub max_attempts_per_address: u32,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SubscriberImportSettings {
+    /// Larger CSV files are refused while they are being uploaded.
+    pub max_file_bytes: usize,
+}
+
 impl DatabaseSettings {
     pub fn with_db(&self) -> PgConnectOptions {
         self.without_db()
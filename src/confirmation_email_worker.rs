@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use sqlx::PgPool;
+use tracing::{field::display, Span};
+
+use crate::{
+    configuration::Settings,
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+    email_client::EmailClient,
+    issue_delivery_worker::ExecutionOutcome,
+    routes::send_confirmation_email,
+    startup::{get_connection_pool, ApplicationBaseUrl},
+};
+
+/// Sends the confirmation emails queued by bulk imports.
+pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let email_client = configuration.email_client.client();
+    let base_url = ApplicationBaseUrl(configuration.application.base_url);
+
+    worker_loop(connection_pool, email_client, base_url).await
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: ApplicationBaseUrl,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_send_confirmation_email(&pool, &email_client, &base_url).await {
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+#[tracing::instrument(skip_all, fields(subscriber_email = tracing::field::Empty), err)]
+pub async fn try_send_confirmation_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &ApplicationBaseUrl,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query!(
+        r#"
+        SELECT q.subscriber_id, q.subscription_token, s.email, s.name
+        FROM t_confirmation_email_queue q
+        JOIN t_subscriptions s ON s.id = q.subscriber_id
+        ORDER BY q.enqueued_at
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(task) = task else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current().record("subscriber_email", display(&task.email));
+
+    // imported rows went through the same validation, so this only fails if
+    // the rules have been tightened since
+    match (
+        SubscriberEmail::parse(task.email),
+        SubscriberName::parse(task.name),
+    ) {
+        (Ok(email), Ok(name)) => {
+            let new_subscriber = NewSubscriber { email, name };
+            if let Err(e) = send_confirmation_email(
+                email_client,
+                &new_subscriber,
+                base_url,
+                &task.subscription_token,
+            )
+            .await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send a confirmation email to an imported subscriber. Skipping.",
+                );
+            }
+        }
+        _ => tracing::error!(
+            "Skipping an imported subscriber. Their stored contact details are invalid."
+        ),
+    }
+
+    sqlx::query!(
+        "DELETE FROM t_confirmation_email_queue WHERE subscriber_id = $1",
+        task.subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete a sent confirmation email from the queue.")?;
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
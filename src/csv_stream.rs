@@ -0,0 +1,150 @@
+//! Incremental CSV reading for uploads that arrive in chunks, and CSV writing
+//! for downloadable reports.
+use csv_core::ReadRecordResult;
+
+/// Parses CSV records out of a byte stream without buffering it whole.
+/// Invalid UTF-8 is replaced rather than rejected: the affected values will
+/// fail validation further down the line anyway.
+pub struct CsvRecords {
+    reader: csv_core::Reader,
+    output: Vec<u8>,
+    output_len: usize,
+    ends: Vec<usize>,
+    ends_len: usize,
+}
+
+impl Default for CsvRecords {
+    fn default() -> Self {
+        Self {
+            reader: csv_core::Reader::new(),
+            output: vec![0; 1024],
+            output_len: 0,
+            ends: vec![0; 16],
+            ends_len: 0,
+        }
+    }
+}
+
+impl CsvRecords {
+    /// Returns the records completed by `chunk`.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<Vec<String>> {
+        let mut records = Vec::new();
+        if !chunk.is_empty() {
+            self.read(chunk, &mut records);
+        }
+        records
+    }
+
+    /// Signals the end of the input, returning the last record if it was not
+    /// terminated by a newline.
+    pub fn finish(&mut self) -> Vec<Vec<String>> {
+        let mut records = Vec::new();
+        self.read(&[], &mut records);
+        records
+    }
+
+    fn read(&mut self, mut input: &[u8], records: &mut Vec<Vec<String>>) {
+        loop {
+            let (result, n_in, n_out, n_ends) = self.reader.read_record(
+                input,
+                &mut self.output[self.output_len..],
+                &mut self.ends[self.ends_len..],
+            );
+            input = &input[n_in..];
+            self.output_len += n_out;
+            self.ends_len += n_ends;
+            match result {
+                ReadRecordResult::InputEmpty | ReadRecordResult::End => return,
+                ReadRecordResult::OutputFull => self.output.resize(self.output.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::Record => records.push(self.take_record()),
+            }
+        }
+    }
+
+    fn take_record(&mut self) -> Vec<String> {
+        let mut start = 0;
+        let fields = self.ends[..self.ends_len]
+            .iter()
+            .map(|&end| {
+                let field = String::from_utf8_lossy(&self.output[start..end]).into_owned();
+                start = end;
+                field
+            })
+            .collect();
+        self.output_len = 0;
+        self.ends_len = 0;
+        fields
+    }
+}
+
+/// Appends one CSV line to `out`, quoting fields where needed.
+pub fn write_record<S: AsRef<str>>(out: &mut String, fields: &[S]) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let field = field.as_ref();
+        if field.contains([',', '"', '\n', '\r']) {
+            out.push('"');
+            out.push_str(&field.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(field);
+        }
+    }
+    out.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_record, CsvRecords};
+
+    fn parse_in_chunks(input: &str, chunk_size: usize) -> Vec<Vec<String>> {
+        let mut reader = CsvRecords::default();
+        let mut records = Vec::new();
+        for chunk in input.as_bytes().chunks(chunk_size) {
+            records.extend(reader.feed(chunk));
+        }
+        records.extend(reader.finish());
+        records
+    }
+
+    #[test]
+    fn records_split_across_chunks_are_reassembled() {
+        let input = "email,name\r\nursula@example.com,\"Le Guin, Ursula\"\nged@example.com,\"Sparrow\nhawk\"";
+        for chunk_size in [1, 3, 7, 1024] {
+            assert_eq!(
+                parse_in_chunks(input, chunk_size),
+                vec![
+                    vec!["email", "name"],
+                    vec!["ursula@example.com", "Le Guin, Ursula"],
+                    vec!["ged@example.com", "Sparrow\nhawk"],
+                ],
+                "chunk size {}",
+                chunk_size
+            );
+        }
+    }
+
+    #[test]
+    fn long_records_grow_the_buffers() {
+        let fields: Vec<String> = (0..100).map(|i| "x".repeat(i * 10)).collect();
+        let mut input = String::new();
+        write_record(&mut input, &fields);
+        assert_eq!(parse_in_chunks(&input, 64), vec![fields]);
+    }
+
+    #[test]
+    fn fields_are_quoted_when_needed() {
+        let mut out = String::new();
+        write_record(
+            &mut out,
+            &["plain", "with,comma", "with \"quotes\"", "multi\nline"],
+        );
+        assert_eq!(
+            out,
+            "plain,\"with,comma\",\"with \"\"quotes\"\"\",\"multi\nline\"\r\n"
+        );
+    }
+}
@@ -0,0 +1,56 @@
+/// How often a subscriber wants to receive newsletter issues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestFrequency {
+    /// Every issue is delivered as soon as it is published.
+    Immediate,
+    /// Issues are bundled into a single email delivered once a day.
+    Daily,
+    /// Issues are bundled into a single email delivered once a week.
+    Weekly,
+}
+
+impl DigestFrequency {
+    pub const ALL: [DigestFrequency; 3] = [Self::Immediate, Self::Daily, Self::Weekly];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestFrequency::Immediate => "immediate",
+            DigestFrequency::Daily => "daily",
+            DigestFrequency::Weekly => "weekly",
+        }
+    }
+}
+
+impl TryFrom<String> for DigestFrequency {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.as_str() == value)
+            .ok_or_else(|| format!("{} is not a supported digest frequency.", value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok_eq};
+
+    use super::DigestFrequency;
+
+    #[test]
+    fn every_frequency_round_trips_through_its_string_form() {
+        for frequency in DigestFrequency::ALL {
+            assert_ok_eq!(
+                DigestFrequency::try_from(frequency.as_str().to_owned()),
+                frequency
+            );
+        }
+    }
+
+    #[test]
+    fn an_unknown_frequency_is_rejected() {
+        assert_err!(DigestFrequency::try_from("hourly".to_owned()));
+    }
+}
@@ -1,7 +1,14 @@
+mod digest_frequency;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_tag;
 
+pub use digest_frequency::DigestFrequency;
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_tag::SubscriberTag;
+
+/// Values of `t_subscriptions.status`.
+pub const SUBSCRIPTION_STATUSES: [&str; 3] = ["pending_confirmation", "confirmed", "unsubscribed"];
@@ -0,0 +1,58 @@
+const MAX_TAG_LENGTH: usize = 64;
+
+/// A free-form label attached to subscribers by admins (e.g. `beta-tester`).
+/// Tags are case-insensitive and stored lowercased.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    pub fn parse(s: String) -> Result<SubscriberTag, String> {
+        let tag = s.trim().to_lowercase();
+        let is_empty = tag.is_empty();
+        let is_too_long = tag.chars().count() > MAX_TAG_LENGTH;
+        let contains_forbidden_characters = tag
+            .chars()
+            .any(|ch| !(ch.is_alphanumeric() || ch == '-' || ch == '_'));
+
+        if is_empty || is_too_long || contains_forbidden_characters {
+            Err(format!("{} is not a valid tag.", s))
+        } else {
+            Ok(Self(tag))
+        }
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberTag;
+    use claim::assert_err;
+
+    #[test]
+    fn tags_are_trimmed_and_lowercased() {
+        let tag = SubscriberTag::parse(" Beta-Tester ".to_owned()).unwrap();
+        assert_eq!(tag.as_ref(), "beta-tester");
+    }
+
+    #[test]
+    fn empty_tag_is_rejected() {
+        assert_err!(SubscriberTag::parse("  ".to_owned()));
+    }
+
+    #[test]
+    fn too_long_tag_is_rejected() {
+        assert_err!(SubscriberTag::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn tags_with_spaces_or_punctuation_are_rejected() {
+        for tag in ["beta tester", "beta,tester", "vip!", "a:b"] {
+            assert_err!(SubscriberTag::parse(tag.to_owned()));
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Streams query results to the client as CSV or NDJSON.
+//!
+//! Rows are read through a server-side cursor a batch at a time, so memory
+//! usage does not depend on the size of the export.
+use actix_web::{web::Bytes, HttpResponse};
+use futures_util::stream;
+use sqlx::{postgres::PgRow, FromRow, PgPool, Postgres, QueryBuilder, Transaction};
+
+use crate::csv_stream;
+
+const CURSOR_NAME: &str = "export_cursor";
+const FETCH_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// Describes how the rows of an export are serialized.
+pub trait Export: 'static {
+    type Row: for<'r> FromRow<'r, PgRow> + Send + Unpin;
+
+    fn csv_header(&self) -> Vec<String>;
+    fn csv_record(&self, row: &Self::Row) -> Vec<String>;
+    fn json(&self, row: &Self::Row) -> serde_json::Value;
+
+    fn write(&self, format: ExportFormat, row: &Self::Row, out: &mut String) {
+        match format {
+            ExportFormat::Csv => csv_stream::write_record(out, &self.csv_record(row)),
+            ExportFormat::Ndjson => {
+                out.push_str(&self.json(row).to_string());
+                out.push('\n');
+            }
+        }
+    }
+}
+
+/// Returns a query builder to append the exported `SELECT` statement to.
+pub fn cursor_query() -> QueryBuilder<'static, Postgres> {
+    QueryBuilder::new(format!("DECLARE {} NO SCROLL CURSOR FOR ", CURSOR_NAME))
+}
+
+/// Opens the cursor declared by `query`, built from [`cursor_query`]. The
+/// transaction holding the cursor stays open for as long as the response is
+/// being streamed.
+#[tracing::instrument(name = "Open export cursor", skip_all)]
+pub async fn open_cursor(
+    pool: &PgPool,
+    mut query: QueryBuilder<'_, Postgres>,
+) -> Result<Transaction<'static, Postgres>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    query.build().execute(&mut *transaction).await?;
+    Ok(transaction)
+}
+
+pub fn streaming_response<E: Export>(
+    transaction: Transaction<'static, Postgres>,
+    export: E,
+    format: ExportFormat,
+    filename: &str,
+) -> HttpResponse {
+    let mut header = String::new();
+    if format == ExportFormat::Csv {
+        csv_stream::write_record(&mut header, &export.csv_header());
+    }
+    let header = stream::iter([Ok::<_, sqlx::Error>(Bytes::from(header))]);
+    let rows = stream::try_unfold(Some((transaction, export)), move |state| async move {
+        let Some((mut transaction, export)) = state else {
+            return Ok(None);
+        };
+        let rows: Vec<E::Row> =
+            sqlx::query_as(&format!("FETCH {} FROM {}", FETCH_SIZE, CURSOR_NAME))
+                .fetch_all(&mut *transaction)
+                .await
+                .inspect_err(|e| tracing::error!(error.message = %e, "Export failed"))?;
+        let mut chunk = String::new();
+        for row in &rows {
+            export.write(format, row, &mut chunk);
+        }
+        if rows.len() < FETCH_SIZE {
+            // the cursor is exhausted: this is the last chunk
+            transaction.commit().await?;
+            return Ok(Some((Bytes::from(chunk), None)));
+        }
+        Ok(Some((Bytes::from(chunk), Some((transaction, export)))))
+    });
+
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!(
+                "attachment; filename=\"{}.{}\"",
+                filename,
+                format.extension()
+            ),
+        ))
+        .streaming(futures_util::StreamExt::chain(header, rows))
+}
+
+/// Formats timestamps the same way in every export.
+pub fn format_timestamp(at: chrono::DateTime<chrono::Utc>) -> String {
+    at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExportFormat;
+
+    #[derive(serde::Deserialize)]
+    struct Query {
+        #[serde(default)]
+        format: ExportFormat,
+    }
+
+    #[test]
+    fn csv_is_the_default_format() {
+        let query: Query = serde_html_form::from_str("").unwrap();
+        assert_eq!(query.format, ExportFormat::Csv);
+        let query: Query = serde_html_form::from_str("format=ndjson").unwrap();
+        assert_eq!(query.format, ExportFormat::Ndjson);
+        assert!(serde_html_form::from_str::<Query>("format=xml").is_err());
+    }
+}
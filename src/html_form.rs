@@ -0,0 +1,81 @@
+use std::{
+    future::{ready, Future, Ready},
+    ops::Deref,
+    pin::Pin,
+};
+
+use actix_web::{web, FromRequest, HttpRequest};
+use serde::de::DeserializeOwned;
+
+use crate::utils;
+
+/// URL-encoded form extractor that, unlike [`web::Form`], collects repeated keys
+/// (e.g. a group of checkboxes sharing the same name) into a `Vec`.
+pub struct HtmlForm<T>(pub T);
+
+impl<T> HtmlForm<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for HtmlForm<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> FromRequest for HtmlForm<T>
+where
+    T: DeserializeOwned + 'static,
+{
+    type Error = actix_web::Error;
+
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut actix_web::dev::Payload) -> Self::Future {
+        let body = web::Bytes::from_request(req, payload);
+        Box::pin(async move {
+            let body = body.await?;
+            serde_html_form::from_bytes(&body)
+                .map(HtmlForm)
+                .map_err(utils::e400)
+        })
+    }
+}
+
+/// Query string counterpart of [`HtmlForm`].
+pub struct HtmlQuery<T>(pub T);
+
+impl<T> HtmlQuery<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for HtmlQuery<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> FromRequest for HtmlQuery<T>
+where
+    T: DeserializeOwned,
+{
+    type Error = actix_web::Error;
+
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+        ready(
+            serde_html_form::from_str(req.query_string())
+                .map(HtmlQuery)
+                .map_err(utils::e400),
+        )
+    }
+}
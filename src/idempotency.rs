@@ -1,6 +1,7 @@
 use std::convert::TryFrom;
 
 use actix_web::{body::to_bytes, HttpResponse};
+use anyhow::Context;
 use reqwest::StatusCode;
 use sqlx::{postgres::PgHasArrayType, PgPool, Postgres, Transaction};
 use uuid::Uuid;
@@ -125,37 +126,72 @@ pub async fn save_response(
     Ok(response_head.set_body(body).map_into_boxed_body())
 }
 
+#[derive(thiserror::Error, Debug)]
+pub enum IdempotencyError {
+    #[error("The idempotency key has already been used for another request.")]
+    KeyReused,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+#[allow(clippy::large_enum_variant)]
 pub enum NextAction {
     StartProcessing(Transaction<'static, Postgres>),
     ReturnSavedResponse(HttpResponse),
 }
 
+/// `request_fingerprint` identifies what the key is used for, e.g. the
+/// method and path of the request: a key is only replayed for the request it
+/// was first used for.
 pub async fn try_processing(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
     user_id: &Uuid,
-) -> Result<NextAction, anyhow::Error> {
-    let mut transaction = pool.begin().await?;
+    request_fingerprint: &str,
+) -> Result<NextAction, IdempotencyError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
     let count_of_inserted_rows = sqlx::query!(
         r#"
         INSERT INTO t_idempotency (
             user_id,
             idempotency_key,
+            request_fingerprint,
             created_at
         )
-        VALUES ($1, $2, now())
+        VALUES ($1, $2, $3, now())
         ON CONFLICT DO NOTHING
     "#,
         user_id,
-        idempotency_key.as_ref()
+        idempotency_key.as_ref(),
+        request_fingerprint
     )
     .execute(&mut *transaction)
-    .await?
+    .await
+    .context("Failed to store an idempotency key.")?
     .rows_affected();
 
     if count_of_inserted_rows > 0 {
         Ok(NextAction::StartProcessing(transaction))
     } else {
+        let saved_fingerprint = sqlx::query_scalar!(
+            r#"
+            SELECT request_fingerprint
+            FROM t_idempotency
+            WHERE user_id = $1
+                AND idempotency_key = $2
+            "#,
+            user_id,
+            idempotency_key.as_ref()
+        )
+        .fetch_one(pool)
+        .await
+        .context("Failed to look up an idempotency key.")?;
+        if saved_fingerprint.is_some_and(|f| f != request_fingerprint) {
+            return Err(IdempotencyError::KeyReused);
+        }
         let saved_response = get_saved_response(pool, idempotency_key, user_id)
             .await?
             .ok_or_else(|| anyhow::anyhow!("Expected to find a saved response"))?;
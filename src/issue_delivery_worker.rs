@@ -5,20 +5,36 @@ use tracing::{field::display, Span};
 use uuid::Uuid;
 
 use crate::{
-    configuration::Settings, domain::SubscriberEmail, email_client::EmailClient,
-    startup::get_connection_pool,
+    configuration::Settings,
+    domain::{DigestFrequency, SubscriberEmail},
+    email_client::EmailClient,
+    personalisation::{self, Placeholders},
+    startup::{get_connection_pool, ApplicationBaseUrl},
+    tracking::Tracker,
+    utils,
 };
 
 pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
     let email_client = configuration.email_client.client();
+    let tracker = Tracker::new(
+        configuration.application.base_url.clone(),
+        configuration.application.hmac_secret,
+        &configuration.tracking,
+    );
+    let base_url = ApplicationBaseUrl(configuration.application.base_url);
 
-    worker_loop(connection_pool, email_client).await
+    worker_loop(connection_pool, email_client, tracker, base_url).await
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    tracker: Tracker,
+    base_url: ApplicationBaseUrl,
+) -> Result<(), anyhow::Error> {
     loop {
-        match try_execute_task(&pool, &email_client).await {
+        match try_execute_task(&pool, &email_client, &tracker, &base_url).await {
             Ok(ExecutionOutcome::TaskCompleted) => {}
             Ok(ExecutionOutcome::EmptyQueue) => {
                 tokio::time::sleep(Duration::from_secs(10)).await;
@@ -36,6 +52,23 @@ pub enum ExecutionOutcome {
     EmptyQueue,
 }
 
+/// What happened to an issue sent to a subscriber, as stored in `t_issue_deliveries`.
+enum DeliveryOutcome {
+    Delivered,
+    Failed(String),
+    InvalidAddress,
+}
+
+impl DeliveryOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryOutcome::Delivered => "delivered",
+            DeliveryOutcome::Failed(_) => "failed",
+            DeliveryOutcome::InvalidAddress => "invalid_address",
+        }
+    }
+}
+
 #[tracing::instrument(
     skip_all,
     fields(
@@ -47,36 +80,53 @@ pub enum ExecutionOutcome {
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    tracker: &Tracker,
+    base_url: &ApplicationBaseUrl,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
     let task = dequeue_task(pool).await?;
     if task.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
 
-    let (transaction, issue_id, email) = task.unwrap();
+    let (mut transaction, issue_id, email) = task.unwrap();
 
     Span::current()
-        .record("newsletter_issue_id", &display(&issue_id))
-        .record("subscriber_email", &display(&email));
+        .record("newsletter_issue_id", display(&issue_id))
+        .record("subscriber_email", display(&email));
+
+    let recipient = get_recipient(pool, &email).await?;
+    // Digest subscribers get every issue that is due for them in a single email
+    let mut issue_ids = vec![issue_id];
+    if recipient
+        .as_ref()
+        .is_some_and(|r| r.digest_frequency != DigestFrequency::Immediate)
+    {
+        issue_ids.extend(dequeue_digest_tasks(&mut transaction, &email, issue_id).await?);
+    }
 
-    match SubscriberEmail::parse(email.clone()) {
-        Ok(email) => {
-            let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
+    let outcome = match SubscriberEmail::parse(email.clone()) {
+        Ok(subscriber_email) => {
+            let message =
+                compose_email(pool, tracker, base_url, &issue_ids, recipient.as_ref()).await?;
+            match email_client
                 .send_email(
-                    &email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
+                    &subscriber_email,
+                    &message.subject,
+                    &message.html,
+                    &message.text,
                 )
                 .await
             {
-                tracing::error!(
-                    error.cause_chain = ?e,
-                    error.message = %e,
-                    "Failed to deliver issue to a confirmed subscriber. \
-                    Skipping.",
-                )
+                Ok(()) => DeliveryOutcome::Delivered,
+                Err(e) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to deliver issue to a confirmed subscriber. \
+                        Skipping.",
+                    );
+                    DeliveryOutcome::Failed(e.to_string())
+                }
             }
         }
         Err(e) => {
@@ -86,16 +136,115 @@ pub async fn try_execute_task(
                 "Skipping a confirmed subscriber. \
                 Their stored contact details are invalid",
             );
+            DeliveryOutcome::InvalidAddress
         }
-    }
+    };
+    record_delivery(&mut transaction, &issue_ids, &email, &outcome).await?;
 
     // TODO: not retry if delivery fails
-    // exercise: add n_retries and execute_after columns to allow retries
-    delete_task(transaction, issue_id, &email).await?;
+    // exercise: add an n_retries column and push execute_after back to allow retries
+    delete_tasks(transaction, &issue_ids, &email).await?;
 
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
+struct Recipient {
+    subscriber_id: Uuid,
+    email: String,
+    name: String,
+    attributes: serde_json::Map<String, serde_json::Value>,
+    digest_frequency: DigestFrequency,
+    preference_token: Option<String>,
+}
+
+impl Recipient {
+    fn placeholders(&self) -> Placeholders<'_> {
+        Placeholders {
+            name: &self.name,
+            email: &self.email,
+            attributes: &self.attributes,
+        }
+    }
+}
+
+struct Email {
+    subject: String,
+    html: String,
+    text: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn compose_email(
+    pool: &PgPool,
+    tracker: &Tracker,
+    base_url: &ApplicationBaseUrl,
+    issue_ids: &[Uuid],
+    recipient: Option<&Recipient>,
+) -> Result<Email, anyhow::Error> {
+    let mut issues = Vec::with_capacity(issue_ids.len());
+    for issue_id in issue_ids {
+        issues.push((*issue_id, get_issue(pool, *issue_id).await?));
+    }
+    let personalised = |content: &str, escape_html: bool| match recipient {
+        Some(r) => personalisation::render(content, &r.placeholders(), escape_html),
+        None => content.to_owned(),
+    };
+    let personalised_html = |issue_id: Uuid, issue: &NewsletterIssue| {
+        let html = personalised(&issue.html_content, true);
+        match recipient {
+            Some(r) => tracker.instrument(&html, issue_id, r.subscriber_id, issue.track_clicks),
+            None => html,
+        }
+    };
+
+    let mut email = match issues.as_slice() {
+        [(issue_id, issue)] => Email {
+            subject: personalised(&issue.title, false),
+            html: personalised_html(*issue_id, issue),
+            text: personalised(&issue.text_content, false),
+        },
+        issues => {
+            let mut html = String::new();
+            let mut text = String::new();
+            for (issue_id, issue) in issues {
+                html.push_str(&format!(
+                    "<h1>{}</h1>\n{}\n<hr>\n",
+                    htmlescape::encode_minimal(&issue.title),
+                    personalised_html(*issue_id, issue)
+                ));
+                text.push_str(&format!(
+                    "{}\n\n{}\n\n---\n\n",
+                    issue.title,
+                    personalised(&issue.text_content, false)
+                ));
+            }
+            let frequency = recipient.map_or("", |r| r.digest_frequency.as_str());
+            Email {
+                subject: format!("Your {} digest: {} new issues", frequency, issues.len()),
+                html,
+                text,
+            }
+        }
+    };
+
+    if let Some(token) = recipient.and_then(|r| r.preference_token.as_ref()) {
+        let preferences_link = format!("{}/subscriptions/preferences?token={}", base_url.0, token);
+        utils::append_to_html_body(
+            &mut email.html,
+            &format!(
+                r#"<p><a href="{}">Manage your subscription</a></p>"#,
+                preferences_link
+            ),
+        );
+        email.text.push_str(&format!(
+            "\n\nManage your subscription: {}",
+            preferences_link
+        ));
+    }
+
+    Ok(email)
+}
+
 type PgTransaction = Transaction<'static, Postgres>;
 
 #[tracing::instrument(skip_all)]
@@ -107,6 +256,7 @@ async fn dequeue_task(
         r#"
         SELECT newsletter_issue_id, subscriber_email
         FROM t_issue_delivery_queue
+        WHERE execute_after <= now()
         FOR UPDATE
         SKIP LOCKED
         LIMIT 1
@@ -127,18 +277,76 @@ async fn dequeue_task(
 }
 
 #[tracing::instrument(skip_all)]
-async fn delete_task(
+async fn dequeue_digest_tasks(
+    transaction: &mut PgTransaction,
+    email: &str,
+    dequeued_issue_id: Uuid,
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    let issue_ids = sqlx::query_scalar!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM t_issue_delivery_queue
+        WHERE subscriber_email = $1
+            AND newsletter_issue_id != $2
+            AND execute_after <= now()
+        FOR UPDATE
+        SKIP LOCKED
+        "#,
+        email,
+        dequeued_issue_id
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+
+    Ok(issue_ids)
+}
+
+#[tracing::instrument(skip_all)]
+async fn record_delivery(
+    transaction: &mut PgTransaction,
+    issue_ids: &[Uuid],
+    email: &str,
+    outcome: &DeliveryOutcome,
+) -> Result<(), anyhow::Error> {
+    let error = match outcome {
+        DeliveryOutcome::Failed(error) => Some(error.as_str()),
+        _ => None,
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO t_issue_deliveries (
+            newsletter_issue_id, subscriber_email, outcome, error, attempted_at
+        )
+        SELECT newsletter_issue_id, $2, $3, $4, now()
+        FROM unnest($1::uuid[]) AS newsletter_issue_id
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET outcome = EXCLUDED.outcome,
+            error = EXCLUDED.error,
+            attempted_at = EXCLUDED.attempted_at
+        "#,
+        issue_ids,
+        email,
+        outcome.as_str(),
+        error
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_tasks(
     mut transaction: PgTransaction,
-    issue_id: Uuid,
+    issue_ids: &[Uuid],
     email: &str,
 ) -> Result<(), anyhow::Error> {
     sqlx::query!(
         r#"
         DELETE FROM t_issue_delivery_queue
-        WHERE newsletter_issue_id = $1
+        WHERE newsletter_issue_id = ANY($1)
             AND subscriber_email = $2
         "#,
-        issue_id,
+        issue_ids,
         email
     )
     .execute(&mut *transaction)
@@ -153,6 +361,7 @@ struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
+    track_clicks: bool,
 }
 
 #[tracing::instrument(skip_all)]
@@ -160,7 +369,7 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     let issue = sqlx::query_as!(
         NewsletterIssue,
         r#"
-        SELECT title, text_content, html_content
+        SELECT title, text_content, html_content, track_clicks
         FROM t_newsletter_issues
         WHERE newsletter_issue_id = $1
         "#,
@@ -171,3 +380,41 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
 
     Ok(issue)
 }
+
+#[tracing::instrument(skip_all)]
+async fn get_recipient(pool: &PgPool, email: &str) -> Result<Option<Recipient>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            s.id,
+            s.name,
+            s.attributes,
+            s.digest_frequency,
+            t.preference_token AS "preference_token?"
+        FROM t_subscriptions s
+        LEFT JOIN t_preference_tokens t ON t.subscriber_id = s.id
+        WHERE s.email = $1
+        "#,
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(|r| {
+        Ok(Recipient {
+            subscriber_id: r.id,
+            email: email.to_owned(),
+            name: r.name,
+            attributes: match r.attributes {
+                serde_json::Value::Object(attributes) => attributes,
+                _ => serde_json::Map::new(),
+            },
+            digest_frequency: r
+                .digest_frequency
+                .try_into()
+                .map_err(|e: String| anyhow::anyhow!(e))?,
+            preference_token: r.preference_token,
+        })
+    })
+    .transpose()
+}
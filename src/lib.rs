@@ -1,12 +1,28 @@
+pub mod api_error;
+pub mod attributes;
 pub mod authentication;
+pub mod client_address;
 pub mod configuration;
+pub mod confirmation_email_worker;
+pub mod csv_stream;
 pub mod domain;
 pub mod email_client;
 pub mod error;
+pub mod export;
+pub mod html_form;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod mailing_lists;
+pub mod negotiation;
+pub mod openapi;
+pub mod personal_data;
+pub mod personalisation;
 pub mod routes;
+pub mod segment;
 pub mod session_state;
+pub mod signed_token;
+pub mod signup_protection;
 pub mod startup;
 pub mod telemetry;
+pub mod tracking;
 pub mod utils;
@@ -0,0 +1,107 @@
+use std::fmt::Write;
+
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ListSelectionError {
+    #[error("{0} is not a known mailing list.")]
+    UnknownList(Uuid),
+    #[error("At least one mailing list must be selected.")]
+    NoListSelected,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub name: String,
+    pub is_default: bool,
+}
+
+#[tracing::instrument(name = "Get mailing lists", skip(pool))]
+pub async fn get_lists(pool: &PgPool) -> Result<Vec<MailingList>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT list_id, name, is_default
+        FROM t_lists
+        ORDER BY is_default DESC, name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve mailing lists.")?;
+
+    Ok(lists)
+}
+
+/// Checks that every requested list exists. Falls back to the default list
+/// when no list has been requested.
+#[tracing::instrument(name = "Resolve mailing lists", skip(transaction))]
+pub async fn resolve_list_ids(
+    transaction: &mut Transaction<'_, Postgres>,
+    requested: &[Uuid],
+) -> Result<Vec<Uuid>, ListSelectionError> {
+    let list_ids: Vec<Uuid> = sqlx::query_scalar!(
+        r#"
+        SELECT list_id
+        FROM t_lists
+        WHERE list_id = ANY($1)
+            OR (cardinality($1) = 0 AND is_default)
+        "#,
+        requested
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .context("Failed to perform a query to resolve mailing lists.")?;
+
+    if let Some(unknown) = requested.iter().find(|id| !list_ids.contains(id)) {
+        return Err(ListSelectionError::UnknownList(*unknown));
+    }
+    if list_ids.is_empty() {
+        return Err(ListSelectionError::NoListSelected);
+    }
+
+    Ok(list_ids)
+}
+
+#[tracing::instrument(name = "Add subscriber to mailing lists", skip(transaction))]
+pub async fn add_memberships(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO t_list_memberships (list_id, subscriber_id)
+        SELECT list_id, $2
+        FROM unnest($1::uuid[]) AS list_id
+        ON CONFLICT DO NOTHING
+        "#,
+        list_ids,
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("A database error was encountered while adding list memberships.")?;
+
+    Ok(())
+}
+
+/// Renders a checkbox per list, named `list_id`. The default list is pre-checked.
+pub fn checkboxes_html(lists: &[MailingList]) -> String {
+    let mut html = String::new();
+    for list in lists {
+        writeln!(
+            html,
+            r#"<label><input type="checkbox" name="list_id" value="{}"{}> {}</label><br>"#,
+            list.list_id,
+            if list.is_default { " checked" } else { "" },
+            htmlescape::encode_minimal(&list.name),
+        )
+        .unwrap();
+    }
+    html
+}
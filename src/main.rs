@@ -1,8 +1,8 @@
 use std::fmt::{Debug, Display};
 
 use tokio::task::JoinError;
-use zero2prod::issue_delivery_worker::run_worker_until_stopped;
 use zero2prod::startup::Application;
+use zero2prod::{confirmation_email_worker, issue_delivery_worker};
 
 use tracing::level_filters::LevelFilter;
 use zero2prod::configuration::get_configuration;
@@ -18,11 +18,17 @@ async fn main() -> Result<(), anyhow::Error> {
     let application = Application::build(configuration.clone()).await?;
     let application_task = tokio::spawn(application.run_until_stopped());
 
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+    let worker_task = tokio::spawn(issue_delivery_worker::run_worker_until_stopped(
+        configuration.clone(),
+    ));
+    let confirmation_worker_task = tokio::spawn(
+        confirmation_email_worker::run_worker_until_stopped(configuration),
+    );
 
     tokio::select! {
         o = application_task => report_exit("API", o),
         o = worker_task => report_exit("Backgroun worker", o),
+        o = confirmation_worker_task => report_exit("Confirmation email worker", o),
     };
 
     Ok(())
@@ -0,0 +1,89 @@
+//! Content negotiation for the endpoints that serve both browsers, which post
+//! HTML forms and follow redirects, and scripts, which post and expect JSON.
+use actix_web::{
+    http::header::{self, Header},
+    HttpMessage, HttpRequest,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    Html,
+    Json,
+}
+
+impl ResponseFormat {
+    /// Follows the `Accept` header. Clients accepting anything get the format
+    /// of their request body.
+    pub fn negotiate(req: &HttpRequest) -> Self {
+        if let Ok(accept) = header::Accept::parse(req) {
+            for mime in accept.ranked() {
+                match (mime.type_().as_str(), mime.subtype().as_str()) {
+                    ("application", "json") | ("application", "problem+json") => {
+                        return ResponseFormat::Json
+                    }
+                    ("text", "html") | ("application", "xhtml+xml") => return ResponseFormat::Html,
+                    ("*", "*") => break,
+                    _ => {}
+                }
+            }
+        }
+        if has_json_body(req) {
+            ResponseFormat::Json
+        } else {
+            ResponseFormat::Html
+        }
+    }
+}
+
+/// Whether the body of the request is declared as JSON rather than as a form.
+pub fn has_json_body(req: &HttpRequest) -> bool {
+    let content_type = req.content_type();
+    content_type == "application/json" || content_type.ends_with("+json")
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::header, test::TestRequest};
+
+    use super::ResponseFormat;
+
+    fn negotiate(accept: Option<&str>, content_type: &str) -> ResponseFormat {
+        let mut request = TestRequest::post().insert_header((header::CONTENT_TYPE, content_type));
+        if let Some(accept) = accept {
+            request = request.insert_header((header::ACCEPT, accept));
+        }
+        ResponseFormat::negotiate(&request.to_http_request())
+    }
+
+    #[test]
+    fn the_accept_header_wins() {
+        let form = "application/x-www-form-urlencoded";
+        assert_eq!(
+            negotiate(Some("application/json"), form),
+            ResponseFormat::Json
+        );
+        assert_eq!(
+            negotiate(Some("text/html"), "application/json"),
+            ResponseFormat::Html
+        );
+        // what browsers send when submitting a form
+        assert_eq!(
+            negotiate(
+                Some("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"),
+                form
+            ),
+            ResponseFormat::Html
+        );
+    }
+
+    #[test]
+    fn clients_accepting_anything_get_the_format_of_their_request() {
+        for accept in [None, Some("*/*")] {
+            assert_eq!(negotiate(accept, "application/json"), ResponseFormat::Json);
+            assert_eq!(
+                negotiate(accept, "application/x-www-form-urlencoded"),
+                ResponseFormat::Html
+            );
+        }
+    }
+}
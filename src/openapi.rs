@@ -0,0 +1,189 @@
+//! The OpenAPI document of the application, generated from the annotations
+//! of the handlers and from the types they read and return. It is served at
+//! `/api/openapi.json`, and rendered by a bundled Swagger UI at `/api/docs/`.
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::{
+    export::ExportFormat,
+    routes::{
+        admin::{
+            api_tokens, attributes, dashboard, drafts, exports, issues, lists, logout, newsletters,
+            password, sessions, subscribers, tags, two_factor, users,
+        },
+        api, health_check, home, login, set_password, subscriptions, subscriptions_confirm,
+        subscriptions_preferences, subscriptions_privacy, tracking,
+    },
+};
+
+/// The path the document is served at.
+pub const OPENAPI_JSON_PATH: &str = "/api/openapi.json";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "zero2prod",
+        description = "A newsletter delivery service. The pages under `/admin` need a \
+            session cookie, and their forms a `csrf_token` field, unless they are called \
+            with an API token. In multipart forms it has to be the first field. The JSON API under `/api/v1` only accepts API tokens."
+    ),
+    modifiers(&SecuritySchemes),
+    // Query parameters refer to their schemas without registering them.
+    components(schemas(ExportFormat)),
+    tags(
+        (name = "public", description = "Pages for readers and subscribers"),
+        (name = "account", description = "Logging in and choosing a password"),
+        (name = "admin", description = "The admin panel"),
+        (name = "api", description = "The versioned JSON API"),
+    ),
+    paths(
+        home::home,
+        health_check::health_check,
+        subscriptions::subscribe_form,
+        subscriptions::subscribe,
+        subscriptions::subscription_thank_you,
+        subscriptions::signup_widget,
+        subscriptions::signup_form_token,
+        subscriptions_confirm::confirm_subscription,
+        subscriptions_preferences::preferences_form,
+        subscriptions_preferences::update_preferences,
+        subscriptions_preferences::unsubscribe,
+        subscriptions_privacy::privacy_form,
+        subscriptions_privacy::request_privacy_link,
+        subscriptions_privacy::download_personal_data,
+        subscriptions_privacy::erasure_form,
+        subscriptions_privacy::erase_personal_data,
+        tracking::track_click,
+        tracking::track_open,
+        login::login_form,
+        login::login,
+        login::two_factor::login_second_factor_form,
+        login::two_factor::login_second_factor,
+        set_password::password_reset_form,
+        set_password::request_password_reset,
+        set_password::reset_password_form,
+        set_password::reset_password,
+        set_password::set_password_form,
+        set_password::set_password,
+        api::subscribers::list_subscribers,
+        api::subscribers::create_subscriber,
+        api::subscribers::get_subscriber,
+        api::issues::list_issues,
+        api::issues::create_issue,
+        api::issues::get_issue,
+        api::issues::publish_issue,
+        dashboard::admin_dashboard,
+        newsletters::get_newsletter_form,
+        newsletters::publish_newsletter,
+        newsletters::count_newsletter_recipients,
+        drafts::save_draft,
+        drafts::delete_draft,
+        issues::list_issues,
+        issues::issue_details,
+        exports::export_issue_deliveries,
+        exports::export_subscribers,
+        lists::list_mailing_lists,
+        lists::create_mailing_list,
+        subscribers::list::list_subscribers,
+        subscribers::import::import_subscribers_form,
+        subscribers::import::import_subscribers,
+        subscribers::import::download_import_rejections,
+        subscribers::detail::subscriber_details,
+        subscribers::actions::confirm_subscriber,
+        subscribers::actions::unsubscribe_subscriber,
+        subscribers::actions::resend_confirmation,
+        subscribers::actions::export_subscriber_data,
+        subscribers::actions::erase_subscriber,
+        subscribers::actions::delete_subscriber,
+        tags::list_tags,
+        tags::update_subscriber_tags,
+        attributes::list_attribute_definitions,
+        attributes::create_attribute_definition,
+        users::list::list_users,
+        users::actions::invite_user,
+        users::actions::resend_invitation,
+        users::actions::change_user_role,
+        users::actions::disable_user,
+        users::actions::enable_user,
+        users::actions::delete_user,
+        two_factor::two_factor_settings,
+        two_factor::set_up_two_factor,
+        two_factor::confirm_two_factor,
+        two_factor::turn_off_two_factor,
+        two_factor::regenerate_recovery_codes,
+        sessions::list_sessions,
+        sessions::revoke_other_user_sessions,
+        sessions::revoke_user_session,
+        api_tokens::list_api_tokens,
+        api_tokens::create_api_token,
+        api_tokens::revoke_api_token,
+        password::get::change_password_form,
+        password::post::change_password,
+        logout::log_out,
+    )
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "session_cookie",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new("id"))),
+        );
+        components.add_security_scheme(
+            "api_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use utoipa::OpenApi;
+
+    use super::ApiDoc;
+
+    #[test]
+    fn the_document_is_openapi_3_1() {
+        let document = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        assert!(document["openapi"].as_str().unwrap().starts_with("3.1"));
+        assert_eq!(
+            document["components"]["schemas"]["Problem"]["properties"]["type"]["type"],
+            "string"
+        );
+    }
+
+    #[test]
+    fn every_schema_reference_resolves() {
+        let document = ApiDoc::openapi();
+        let schemas = &document.components.as_ref().unwrap().schemas;
+        let json = document.to_json().unwrap();
+        for reference in json.split("\"#/components/schemas/").skip(1) {
+            let name = &reference[..reference.find('"').unwrap()];
+            assert!(schemas.contains_key(name), "{} is not registered", name);
+        }
+    }
+
+    #[test]
+    fn operation_ids_are_unique() {
+        let document = ApiDoc::openapi();
+        let mut operation_ids = HashSet::new();
+        for item in document.paths.paths.values() {
+            for operation in [&item.get, &item.post].into_iter().flatten() {
+                let operation_id = operation.operation_id.clone().unwrap();
+                assert!(
+                    operation_ids.insert(operation_id.clone()),
+                    "{} is used twice",
+                    operation_id
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,340 @@
+//! Answers data subject requests: everything stored about an email address
+//! can be exported as a JSON bundle, or erased.
+//!
+//! Addresses are matched case-insensitively, since the same person may have
+//! signed up with different spellings of their address.
+use anyhow::Context;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::export::format_timestamp;
+
+/// Who asked for an erasure, as stored in the audit log.
+#[derive(Debug, Clone, Copy)]
+pub enum ErasureRequester {
+    Subscriber,
+    Admin(Uuid),
+}
+
+/// The audit log identifies erased addresses by this hash, so that it can
+/// tell whether an address was erased without storing it.
+pub fn email_hash(email: &str) -> String {
+    format!(
+        "{:x}",
+        Sha256::digest(email.trim().to_lowercase().as_bytes())
+    )
+}
+
+#[tracing::instrument(name = "Check whether personal data is held", skip(pool, email))]
+pub async fn holds_data_about(pool: &PgPool, email: &str) -> Result<bool, anyhow::Error> {
+    let found = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM t_subscriptions WHERE lower(email) = lower($1))
+            OR EXISTS (SELECT 1 FROM t_issue_deliveries WHERE lower(subscriber_email) = lower($1))
+            OR EXISTS (
+                SELECT 1 FROM t_subscriber_import_rejections WHERE lower(email) = lower($1)
+            ) AS "found!"
+        "#,
+        email
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to look up personal data.")?;
+    Ok(found)
+}
+
+/// Gathers every row referencing `email` into a single JSON document.
+#[tracing::instrument(name = "Collect personal data", skip(pool, email))]
+pub async fn collect(pool: &PgPool, email: &str) -> Result<Value, anyhow::Error> {
+    let subscriptions = sqlx::query!(
+        r#"
+        SELECT
+            s.id,
+            s.email,
+            s.name,
+            s.status,
+            s.subscribed_at,
+            s.digest_frequency,
+            s.paused_until,
+            s.attributes,
+            ARRAY(
+                SELECT l.name::text
+                FROM t_list_memberships lm
+                JOIN t_lists l ON l.list_id = lm.list_id
+                WHERE lm.subscriber_id = s.id
+                ORDER BY l.name
+            ) AS "lists!",
+            ARRAY(
+                SELECT t.tag::text
+                FROM t_subscriber_tags t
+                WHERE t.subscriber_id = s.id
+                ORDER BY t.tag
+            ) AS "tags!"
+        FROM t_subscriptions s
+        WHERE lower(s.email) = lower($1)
+        ORDER BY s.subscribed_at
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve subscriptions.")?;
+    let subscriber_ids: Vec<Uuid> = subscriptions.iter().map(|s| s.id).collect();
+
+    let deliveries = sqlx::query!(
+        r#"
+        SELECT d.newsletter_issue_id, i.title, d.outcome, d.attempted_at
+        FROM t_issue_deliveries d
+        JOIN t_newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        WHERE lower(d.subscriber_email) = lower($1)
+        ORDER BY d.attempted_at
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve deliveries.")?;
+
+    let pending_deliveries = sqlx::query!(
+        r#"
+        SELECT q.newsletter_issue_id, i.title
+        FROM t_issue_delivery_queue q
+        JOIN t_newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE lower(q.subscriber_email) = lower($1)
+        ORDER BY i.published_at
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve pending deliveries.")?;
+
+    let opens = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, open_count, first_opened_at, last_opened_at
+        FROM t_email_opens
+        WHERE subscriber_id = ANY($1)
+        ORDER BY first_opened_at
+        "#,
+        &subscriber_ids
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve email opens.")?;
+
+    let clicks = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, url, clicked_at
+        FROM t_link_clicks
+        WHERE subscriber_id = ANY($1)
+        ORDER BY clicked_at
+        "#,
+        &subscriber_ids
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve link clicks.")?;
+
+    let import_rejections = sqlx::query!(
+        r#"
+        SELECT r.email, r.name, r.reason, i.created_at
+        FROM t_subscriber_import_rejections r
+        JOIN t_subscriber_imports i ON i.import_id = r.import_id
+        WHERE lower(r.email) = lower($1)
+        ORDER BY i.created_at
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve rejected import rows.")?;
+
+    let pending_confirmations = sqlx::query_scalar!(
+        r#"
+        SELECT DISTINCT subscriber_id
+        FROM t_subscription_tokens
+        WHERE subscriber_id = ANY($1)
+        "#,
+        &subscriber_ids
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve confirmation tokens.")?;
+
+    Ok(json!({
+        "email": email,
+        "generated_at": format_timestamp(chrono::Utc::now()),
+        "subscriptions": subscriptions.iter().map(|s| json!({
+            "email": s.email,
+            "name": s.name,
+            "status": s.status,
+            "subscribed_at": format_timestamp(s.subscribed_at.and_utc()),
+            "digest_frequency": s.digest_frequency,
+            "paused_until": s.paused_until.map(format_timestamp),
+            "lists": s.lists,
+            "tags": s.tags,
+            "attributes": s.attributes,
+            "has_confirmation_link": pending_confirmations.contains(&s.id),
+        })).collect::<Vec<_>>(),
+        "deliveries": deliveries.iter().map(|d| json!({
+            "newsletter_issue_id": d.newsletter_issue_id,
+            "title": d.title,
+            "outcome": d.outcome,
+            "attempted_at": format_timestamp(d.attempted_at),
+        })).collect::<Vec<_>>(),
+        "pending_deliveries": pending_deliveries.iter().map(|d| json!({
+            "newsletter_issue_id": d.newsletter_issue_id,
+            "title": d.title,
+        })).collect::<Vec<_>>(),
+        "opens": opens.iter().map(|o| json!({
+            "newsletter_issue_id": o.newsletter_issue_id,
+            "open_count": o.open_count,
+            "first_opened_at": format_timestamp(o.first_opened_at),
+            "last_opened_at": format_timestamp(o.last_opened_at),
+        })).collect::<Vec<_>>(),
+        "clicks": clicks.iter().map(|c| json!({
+            "newsletter_issue_id": c.newsletter_issue_id,
+            "url": c.url,
+            "clicked_at": format_timestamp(c.clicked_at),
+        })).collect::<Vec<_>>(),
+        "import_rejections": import_rejections.iter().map(|r| json!({
+            "email": r.email,
+            "name": r.name,
+            "reason": r.reason,
+            "imported_at": format_timestamp(r.created_at),
+        })).collect::<Vec<_>>(),
+    }))
+}
+
+/// Erases `email` from every table and records the erasure in the audit log.
+///
+/// Subscriber rows are anonymised rather than deleted so that opens, clicks
+/// and delivery outcomes still add up in issue statistics; everything that
+/// identifies the person or lets them be contacted again is removed.
+#[tracing::instrument(name = "Erase personal data", skip(transaction, email))]
+pub async fn erase(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    requested_by: ErasureRequester,
+) -> Result<(), anyhow::Error> {
+    let subscriber_ids = sqlx::query_scalar!(
+        "SELECT id FROM t_subscriptions WHERE lower(email) = lower($1)",
+        email
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .context("Failed to look up subscriptions to erase.")?;
+
+    for query in [
+        sqlx::query!(
+            "DELETE FROM t_subscription_tokens WHERE subscriber_id = ANY($1)",
+            &subscriber_ids
+        ),
+        sqlx::query!(
+            "DELETE FROM t_confirmation_email_queue WHERE subscriber_id = ANY($1)",
+            &subscriber_ids
+        ),
+        sqlx::query!(
+            "DELETE FROM t_preference_tokens WHERE subscriber_id = ANY($1)",
+            &subscriber_ids
+        ),
+        sqlx::query!(
+            "DELETE FROM t_list_memberships WHERE subscriber_id = ANY($1)",
+            &subscriber_ids
+        ),
+        sqlx::query!(
+            "DELETE FROM t_subscriber_tags WHERE subscriber_id = ANY($1)",
+            &subscriber_ids
+        ),
+        // Links are personalised before they are instrumented, so the url of
+        // a click can contain the address or any attribute of the subscriber.
+        sqlx::query!(
+            "UPDATE t_link_clicks SET url = '(erased)' WHERE subscriber_id = ANY($1)",
+            &subscriber_ids
+        ),
+        sqlx::query!(
+            r#"
+            UPDATE t_subscriptions
+            SET email = 'erased-' || id || '@invalid',
+                name = 'Erased subscriber',
+                status = 'unsubscribed',
+                attributes = '{}',
+                paused_until = NULL
+            WHERE id = ANY($1)
+            "#,
+            &subscriber_ids
+        ),
+    ] {
+        query
+            .execute(&mut **transaction)
+            .await
+            .context("Failed to erase subscription data.")?;
+    }
+
+    for query in [
+        sqlx::query!(
+            "DELETE FROM t_issue_delivery_queue WHERE lower(subscriber_email) = lower($1)",
+            email
+        ),
+        sqlx::query!(
+            r#"
+            UPDATE t_issue_deliveries
+            SET subscriber_email = 'erased-' || gen_random_uuid() || '@invalid',
+                error = NULL
+            WHERE lower(subscriber_email) = lower($1)
+            "#,
+            email
+        ),
+        sqlx::query!(
+            "DELETE FROM t_subscriber_import_rejections WHERE lower(email) = lower($1)",
+            email
+        ),
+    ] {
+        query
+            .execute(&mut **transaction)
+            .await
+            .context("Failed to erase delivery data.")?;
+    }
+
+    let requested_by_user_id = match requested_by {
+        ErasureRequester::Subscriber => None,
+        ErasureRequester::Admin(user_id) => Some(user_id),
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO t_erasure_log (
+            erasure_id, email_hash, requested_by_user_id, subscription_count, erased_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        "#,
+        Uuid::new_v4(),
+        email_hash(email),
+        requested_by_user_id,
+        subscriber_ids.len() as i32
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to record an erasure in the audit log.")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::email_hash;
+
+    #[test]
+    fn the_email_hash_ignores_case_and_surrounding_whitespace() {
+        assert_eq!(
+            email_hash(" Ursula@Example.com"),
+            email_hash("ursula@example.com")
+        );
+        assert_ne!(
+            email_hash("ursula@example.com"),
+            email_hash("ged@example.com")
+        );
+        assert_eq!(email_hash("ursula@example.com").len(), 64);
+    }
+}
@@ -0,0 +1,118 @@
+//! `{{ placeholder }}` substitution in issue content.
+//!
+//! Supported placeholders are `{{ name }}`, `{{ email }}` and
+//! `{{ attr.<key> }}` for custom attributes. A fallback can be given for
+//! values a subscriber does not have: `{{ attr.company | your team }}`.
+//! Anything else between braces is left untouched.
+use serde_json::{Map, Value};
+
+pub struct Placeholders<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub attributes: &'a Map<String, Value>,
+}
+
+impl Placeholders<'_> {
+    fn lookup(&self, field: &str) -> Option<Option<String>> {
+        match field {
+            "name" => Some(Some(self.name.to_owned())),
+            "email" => Some(Some(self.email.to_owned())),
+            field => field.strip_prefix("attr.").map(|key| {
+                self.attributes.get(key).and_then(|value| match value {
+                    Value::String(s) => Some(s.clone()),
+                    Value::Null => None,
+                    value => Some(value.to_string()),
+                })
+            }),
+        }
+    }
+}
+
+/// Substitutes placeholders in `template`. Values are HTML-escaped when
+/// `escape_html` is set.
+pub fn render(template: &str, placeholders: &Placeholders, escape_html: bool) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start..].find("}}") else {
+            break;
+        };
+        let (expression, fallback) = match rest[start + 2..start + length].split_once('|') {
+            Some((expression, fallback)) => (expression.trim(), Some(fallback.trim())),
+            None => (rest[start + 2..start + length].trim(), None),
+        };
+        rendered.push_str(&rest[..start]);
+        match placeholders.lookup(expression) {
+            Some(value) => {
+                let value = value.as_deref().or(fallback).unwrap_or_default();
+                if escape_html {
+                    rendered.push_str(&htmlescape::encode_minimal(value));
+                } else {
+                    rendered.push_str(value);
+                }
+            }
+            None => rendered.push_str(&rest[start..start + length + 2]),
+        }
+        rest = &rest[start + length + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{render, Placeholders};
+
+    fn render_for_ursula(template: &str, escape_html: bool) -> String {
+        let attributes = json!({ "company": "Earthsea & co", "seats": 5, "beta": true });
+        render(
+            template,
+            &Placeholders {
+                name: "Ursula",
+                email: "ursula@example.com",
+                attributes: attributes.as_object().unwrap(),
+            },
+            escape_html,
+        )
+    }
+
+    #[test]
+    fn placeholders_are_substituted() {
+        assert_eq!(
+            render_for_ursula(
+                "Hi {{name}} ({{ email }}), {{attr.seats}} {{attr.beta}}",
+                false
+            ),
+            "Hi Ursula (ursula@example.com), 5 true"
+        );
+    }
+
+    #[test]
+    fn values_are_escaped_in_html() {
+        assert_eq!(
+            render_for_ursula("<p>{{ attr.company }}</p>", true),
+            "<p>Earthsea &amp; co</p>"
+        );
+        assert_eq!(
+            render_for_ursula("{{ attr.company }}", false),
+            "Earthsea & co"
+        );
+    }
+
+    #[test]
+    fn missing_attributes_use_the_fallback() {
+        assert_eq!(
+            render_for_ursula("Hi {{ attr.team | there }}!", false),
+            "Hi there!"
+        );
+        assert_eq!(render_for_ursula("Hi {{ attr.team }}!", false), "Hi !");
+    }
+
+    #[test]
+    fn unknown_placeholders_and_stray_braces_are_left_alone() {
+        let template = "{{ unknown }} {{ name and {{";
+        assert_eq!(render_for_ursula(template, false), template);
+    }
+}
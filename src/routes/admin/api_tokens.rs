@@ -0,0 +1,164 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use std::fmt::Write;
+
+use crate::{
+    authentication::{
+        delete_api_token, issue_api_token, list_user_api_tokens, ApiScope, CsrfToken, UserId,
+    },
+    html_form::HtmlForm,
+    utils,
+};
+
+const MAX_NAME_LENGTH: usize = 100;
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[schema(as = NewApiTokenFormData)]
+pub struct FormData {
+    name: String,
+    #[serde(default, rename = "scope")]
+    scopes: Vec<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/api_tokens",
+    tag = "admin",
+    summary = "API tokens",
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The API tokens of the user", content_type = "text/html", body = String),
+    )
+)]
+pub async fn list_api_tokens(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut tokens_html = String::new();
+    for token in list_user_api_tokens(&pool, **user_id)
+        .await
+        .map_err(utils::e500)?
+    {
+        writeln!(
+            tokens_html,
+            r#"<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td><form action="/admin/api_tokens/{}/revoke" method="post">{}<button type="submit">Revoke</button></form></td></tr>"#,
+            htmlescape::encode_minimal(&token.name),
+            token.scopes.join(", "),
+            token.created_at.format("%Y-%m-%d %H:%M"),
+            token
+                .last_used_at
+                .map_or_else(|| "Never".to_owned(), |at| at.format("%Y-%m-%d %H:%M").to_string()),
+            token.token_id,
+            csrf_token.form_field(),
+        )
+        .unwrap();
+    }
+    let mut scopes_html = String::new();
+    for scope in ApiScope::ALL {
+        writeln!(
+            scopes_html,
+            r#"<label><input type="checkbox" name="scope" value="{}"> {}</label><br>"#,
+            scope.as_str(),
+            scope.description(),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("api_tokens.html"),
+            msg_html = msg_html,
+            tokens_html = tokens_html,
+            scopes_html = scopes_html,
+            csrf_field = csrf_token.form_field(),
+        )))
+}
+
+/// Shows the new token once; only its hash is kept.
+#[utoipa::path(
+    post,
+    path = "/admin/api_tokens",
+    tag = "admin",
+    summary = "Create an API token",
+    request_body(content = FormData, content_type = "application/x-www-form-urlencoded"),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The new token, shown once", content_type = "text/html", body = String),
+        (status = 303, description = "Back to the tokens when the form is invalid"),
+    )
+)]
+#[tracing::instrument(name = "Create an API token", skip(form, pool))]
+pub async fn create_api_token(
+    form: HtmlForm<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { name, scopes } = form.into_inner();
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        FlashMessage::error(format!(
+            "The name must be between 1 and {} characters.",
+            MAX_NAME_LENGTH
+        ))
+        .send();
+        return Ok(utils::see_other("/admin/api_tokens"));
+    }
+    let scopes = scopes
+        .iter()
+        .map(|s| ApiScope::parse(s))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(utils::e400)?;
+    if scopes.is_empty() {
+        FlashMessage::error("Choose at least one scope.").send();
+        return Ok(utils::see_other("/admin/api_tokens"));
+    }
+
+    let token = issue_api_token(&pool, **user_id, name, &scopes)
+        .await
+        .map_err(utils::e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("api_token_created.html"),
+            name = htmlescape::encode_minimal(name),
+            token = token,
+        )))
+}
+
+/// Requests made with the token are rejected from now on.
+#[utoipa::path(
+    post,
+    path = "/admin/api_tokens/{token_id}/revoke",
+    tag = "admin",
+    summary = "Revoke an API token",
+    params(("token_id" = Uuid, Path, description = "Id of the token")),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the tokens"),
+    )
+)]
+pub async fn revoke_api_token(
+    token_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !delete_api_token(&pool, **user_id, token_id.into_inner())
+        .await
+        .map_err(utils::e500)?
+    {
+        return Err(utils::e404("There is no such API token."));
+    }
+    FlashMessage::info("The API token has been revoked.").send();
+    Ok(utils::see_other("/admin/api_tokens"))
+}
@@ -0,0 +1,160 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use std::fmt::Write;
+
+use crate::{
+    attributes::{self, AttributeKind},
+    authentication::CsrfToken,
+    html_form::HtmlForm,
+    utils,
+};
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[schema(as = NewAttributeFormData)]
+pub struct FormData {
+    key: String,
+    label: String,
+    kind: AttributeKind,
+    #[serde(default)]
+    required: bool,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/attributes",
+    tag = "admin",
+    summary = "Custom fields",
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The custom fields of the signup form", content_type = "text/html", body = String),
+    )
+)]
+pub async fn list_attribute_definitions(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let bound = |b: Option<f64>| b.map(|b| b.to_string()).unwrap_or_default();
+    let mut definitions_html = String::new();
+    for definition in attributes::get_definitions(&pool)
+        .await
+        .map_err(utils::e500)?
+    {
+        writeln!(
+            definitions_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            definition.key,
+            htmlescape::encode_minimal(&definition.label),
+            definition.kind.as_str(),
+            if definition.required { "yes" } else { "no" },
+            bound(definition.min),
+            bound(definition.max),
+        )
+        .unwrap();
+    }
+    let mut kinds_html = String::new();
+    for kind in AttributeKind::ALL {
+        writeln!(
+            kinds_html,
+            r#"<option value="{0}">{0}</option>"#,
+            kind.as_str()
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("attributes.html"),
+            msg_html = msg_html,
+            definitions_html = definitions_html,
+            kinds_html = kinds_html,
+            csrf_field = csrf_token.form_field(),
+        )))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/attributes",
+    tag = "admin",
+    summary = "Add a custom field",
+    request_body(content = FormData, content_type = "application/x-www-form-urlencoded"),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the custom fields, with a flash message"),
+    )
+)]
+#[tracing::instrument(name = "Create an attribute definition", skip(form, pool))]
+pub async fn create_attribute_definition(
+    form: HtmlForm<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form = form.into_inner();
+    let key = match attributes::parse_key(&form.key) {
+        Ok(key) => key,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(utils::see_other("/admin/attributes"));
+        }
+    };
+    let label = form.label.trim();
+    if label.is_empty() {
+        FlashMessage::error("The field label cannot be empty.").send();
+        return Ok(utils::see_other("/admin/attributes"));
+    }
+    if let (Some(min), Some(max)) = (form.min, form.max) {
+        if min > max {
+            FlashMessage::error("The minimum cannot be greater than the maximum.").send();
+            return Ok(utils::see_other("/admin/attributes"));
+        }
+    }
+
+    let inserted = insert_definition(&key, label, &form, &pool)
+        .await
+        .map_err(utils::e500)?;
+    if inserted {
+        FlashMessage::info(format!("The field '{}' has been created.", key)).send();
+    } else {
+        FlashMessage::error(format!("A field with key '{}' already exists.", key)).send();
+    }
+    Ok(utils::see_other("/admin/attributes"))
+}
+
+/// Returns `false` if a definition with the same key already exists.
+#[tracing::instrument(name = "Insert an attribute definition", skip(form, pool))]
+async fn insert_definition(
+    key: &str,
+    label: &str,
+    form: &FormData,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO t_attribute_definitions (key, label, kind, required, min, max, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        ON CONFLICT (key) DO NOTHING
+        "#,
+        key,
+        label,
+        form.kind.as_str(),
+        form.required,
+        form.min,
+        form.max
+    )
+    .execute(pool)
+    .await
+    .context("Failed to insert an attribute definition.")?
+    .rows_affected();
+
+    Ok(inserted > 0)
+}
@@ -1,20 +1,64 @@
 use actix_web::{http::header::ContentType, web, HttpResponse};
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::{authentication::UserId, utils};
+use std::fmt::Write;
 
+use crate::{
+    authentication::{CsrfToken, Permission, Role, UserId},
+    utils,
+};
+
+#[utoipa::path(
+    get,
+    path = "/admin/dashboard",
+    tag = "admin",
+    summary = "Dashboard",
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "Links to the pages the user can access", content_type = "text/html", body = String),
+    )
+)]
 pub async fn admin_dashboard(
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     let username = get_username(*user_id, &pool).await.map_err(utils::e500)?;
+    let issues = get_issue_open_stats(&pool).await.map_err(utils::e500)?;
+
+    let mut issues_html = String::new();
+    for issue in issues {
+        writeln!(
+            issues_html,
+            r#"<tr><td><a href="/admin/issues/{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            issue.newsletter_issue_id,
+            htmlescape::encode_minimal(&issue.title),
+            issue.published_at.format("%Y-%m-%d %H:%M"),
+            issue.unique_opens,
+            issue.total_opens,
+        )
+        .unwrap();
+    }
 
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(format!(include_str!("dashboard.html"), username = username)))
+        .body(format!(
+            include_str!("dashboard.html"),
+            username = username,
+            role = role.as_str(),
+            users_link_html = if role.allows(Permission::ManageUsers) {
+                r#"<li><a href="/admin/users">Users</a></li>"#
+            } else {
+                ""
+            },
+            issues_html = issues_html,
+            csrf_field = csrf_token.form_field(),
+        )))
 }
 
 #[tracing::instrument(name = "Get username", skip(pool))]
@@ -33,3 +77,35 @@ pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow
 
     Ok(row.username)
 }
+
+struct IssueOpenStats {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+    unique_opens: i64,
+    total_opens: i64,
+}
+
+#[tracing::instrument(name = "Get issue open statistics", skip(pool))]
+async fn get_issue_open_stats(pool: &PgPool) -> Result<Vec<IssueOpenStats>, anyhow::Error> {
+    let stats = sqlx::query_as!(
+        IssueOpenStats,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at,
+            COUNT(o.subscriber_id) AS "unique_opens!",
+            COALESCE(SUM(o.open_count), 0) AS "total_opens!"
+        FROM t_newsletter_issues i
+        LEFT JOIN t_email_opens o USING (newsletter_issue_id)
+        GROUP BY i.newsletter_issue_id
+        ORDER BY i.published_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve issue open statistics.")?;
+
+    Ok(stats)
+}
@@ -0,0 +1,202 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{authentication::UserId, html_form::HtmlForm, utils};
+
+/// The content of an issue, saved so that it can be finished later or handed
+/// over to an owner for publishing. Recipients are chosen when publishing.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct DraftData {
+    draft_id: Option<Uuid>,
+    title: String,
+    plaintext: String,
+    html: String,
+    #[serde(default)]
+    track_clicks: bool,
+}
+
+pub struct Draft {
+    pub draft_id: Uuid,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub track_clicks: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+fn draft_page(draft_id: Uuid) -> String {
+    format!("/admin/newsletters?draft_id={}", draft_id)
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/newsletters/drafts",
+    tag = "admin",
+    summary = "Save a draft",
+    request_body(content = DraftData, content_type = "application/x-www-form-urlencoded"),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "To the newsletter form, with the draft loaded"),
+    )
+)]
+#[tracing::instrument(name = "Save a newsletter draft", skip(form, pool, user_id))]
+pub async fn save_draft(
+    form: HtmlForm<DraftData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let DraftData {
+        draft_id,
+        title,
+        plaintext,
+        html,
+        track_clicks,
+    } = form.0;
+    let draft_id = draft_id.unwrap_or_else(Uuid::new_v4);
+
+    store_draft(
+        pool.get_ref(),
+        draft_id,
+        &title,
+        &plaintext,
+        &html,
+        track_clicks,
+        **user_id,
+    )
+    .await
+    .map_err(utils::e500)?;
+
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(utils::see_other(&draft_page(draft_id)))
+}
+
+/// Creates the draft, or updates it if it already exists.
+#[tracing::instrument(name = "Store a newsletter draft", skip_all)]
+pub async fn store_draft<'c, E>(
+    executor: E,
+    draft_id: Uuid,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    track_clicks: bool,
+    user_id: Uuid,
+) -> Result<(), anyhow::Error>
+where
+    E: sqlx::PgExecutor<'c>,
+{
+    sqlx::query!(
+        r#"
+        INSERT INTO t_newsletter_drafts (
+            draft_id, title, text_content, html_content, track_clicks, updated_by, updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        ON CONFLICT (draft_id) DO UPDATE
+        SET title = EXCLUDED.title,
+            text_content = EXCLUDED.text_content,
+            html_content = EXCLUDED.html_content,
+            track_clicks = EXCLUDED.track_clicks,
+            updated_by = EXCLUDED.updated_by,
+            updated_at = EXCLUDED.updated_at
+        "#,
+        draft_id,
+        title,
+        text_content,
+        html_content,
+        track_clicks,
+        user_id
+    )
+    .execute(executor)
+    .await
+    .context("Failed to save a newsletter draft.")?;
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/newsletters/drafts/{draft_id}/delete",
+    tag = "admin",
+    summary = "Delete a draft",
+    params(("draft_id" = Uuid, Path, description = "Id of the draft")),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the newsletter form"),
+    )
+)]
+#[tracing::instrument(name = "Delete a newsletter draft", skip(pool))]
+pub async fn delete_draft(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let deleted = sqlx::query!(
+        "DELETE FROM t_newsletter_drafts WHERE draft_id = $1",
+        draft_id.into_inner()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to delete a newsletter draft.")
+    .map_err(utils::e500)?
+    .rows_affected();
+    if deleted == 0 {
+        return Err(utils::e404("The draft does not exist."));
+    }
+
+    FlashMessage::info("The draft has been deleted.").send();
+    Ok(utils::see_other("/admin/newsletters"))
+}
+
+#[tracing::instrument(name = "Get newsletter drafts", skip(pool))]
+pub async fn get_drafts(pool: &PgPool) -> Result<Vec<Draft>, anyhow::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT draft_id, title, text_content, html_content, track_clicks, updated_at
+        FROM t_newsletter_drafts
+        ORDER BY updated_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve newsletter drafts.")
+}
+
+/// Locks the draft until the end of the transaction, so that it cannot be
+/// published twice.
+#[tracing::instrument(name = "Get a newsletter draft", skip(transaction))]
+pub async fn get_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+) -> Result<Option<Draft>, anyhow::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT draft_id, title, text_content, html_content, track_clicks, updated_at
+        FROM t_newsletter_drafts
+        WHERE draft_id = $1
+        FOR UPDATE
+        "#,
+        draft_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to retrieve a newsletter draft.")
+}
+
+/// Called when an issue is published from a draft, which has then served its
+/// purpose.
+pub async fn remove_published_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM t_newsletter_drafts WHERE draft_id = $1",
+        draft_id
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to delete a published draft.")?;
+    Ok(())
+}
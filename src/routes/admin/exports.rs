@@ -0,0 +1,317 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    attributes::{self, AttributeDefinition},
+    export::{self, format_timestamp, Export, ExportFormat},
+    utils,
+};
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: NaiveDateTime,
+    digest_frequency: String,
+    paused_until: Option<DateTime<Utc>>,
+    lists: Vec<String>,
+    tags: Vec<String>,
+    attributes: Value,
+}
+
+/// CSV exports get one `attr.<key>` column per custom field definition, NDJSON
+/// exports the whole attributes object.
+struct SubscribersExport {
+    definitions: Vec<AttributeDefinition>,
+}
+
+impl Export for SubscribersExport {
+    type Row = SubscriberRow;
+
+    fn csv_header(&self) -> Vec<String> {
+        let mut header: Vec<String> = [
+            "id",
+            "email",
+            "name",
+            "status",
+            "subscribed_at",
+            "digest_frequency",
+            "paused_until",
+            "lists",
+            "tags",
+        ]
+        .map(String::from)
+        .into();
+        header.extend(
+            self.definitions
+                .iter()
+                .map(|d| format!("{}{}", attributes::FORM_FIELD_PREFIX, d.key)),
+        );
+        header
+    }
+
+    fn csv_record(&self, row: &SubscriberRow) -> Vec<String> {
+        let mut record = vec![
+            row.id.to_string(),
+            row.email.clone(),
+            row.name.clone(),
+            row.status.clone(),
+            format_timestamp(row.subscribed_at.and_utc()),
+            row.digest_frequency.clone(),
+            row.paused_until.map(format_timestamp).unwrap_or_default(),
+            row.lists.join(";"),
+            row.tags.join(";"),
+        ];
+        record.extend(
+            self.definitions
+                .iter()
+                .map(|d| match row.attributes.get(&d.key) {
+                    None | Some(Value::Null) => String::new(),
+                    Some(Value::String(s)) => s.clone(),
+                    Some(value) => value.to_string(),
+                }),
+        );
+        record
+    }
+
+    fn json(&self, row: &SubscriberRow) -> Value {
+        json!({
+            "id": row.id,
+            "email": row.email,
+            "name": row.name,
+            "status": row.status,
+            "subscribed_at": format_timestamp(row.subscribed_at.and_utc()),
+            "digest_frequency": row.digest_frequency,
+            "paused_until": row.paused_until.map(format_timestamp),
+            "lists": row.lists,
+            "tags": row.tags,
+            "attributes": row.attributes,
+        })
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/subscribers/export",
+    tag = "admin",
+    summary = "Export subscribers",
+    params(ExportQuery),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "Every subscriber", content((String = "text/csv"), (String = "application/x-ndjson"))),
+    )
+)]
+#[tracing::instrument(name = "Export subscribers", skip(query, pool))]
+pub async fn export_subscribers(
+    query: web::Query<ExportQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let definitions = attributes::get_definitions(&pool)
+        .await
+        .map_err(utils::e500)?;
+
+    let mut cursor = export::cursor_query();
+    cursor.push(
+        r#"
+        SELECT
+            s.id,
+            s.email,
+            s.name,
+            s.status,
+            s.subscribed_at,
+            s.digest_frequency,
+            s.paused_until,
+            ARRAY(
+                SELECT l.name::text
+                FROM t_list_memberships lm
+                JOIN t_lists l ON l.list_id = lm.list_id
+                WHERE lm.subscriber_id = s.id
+                ORDER BY l.name
+            ) AS lists,
+            ARRAY(
+                SELECT t.tag::text
+                FROM t_subscriber_tags t
+                WHERE t.subscriber_id = s.id
+                ORDER BY t.tag
+            ) AS tags,
+            s.attributes
+        FROM t_subscriptions s
+        ORDER BY s.subscribed_at, s.id
+        "#,
+    );
+    let transaction = export::open_cursor(&pool, cursor)
+        .await
+        .context("Failed to open a cursor over subscribers.")
+        .map_err(utils::e500)?;
+
+    Ok(export::streaming_response(
+        transaction,
+        SubscribersExport { definitions },
+        query.format,
+        "subscribers",
+    ))
+}
+
+#[derive(sqlx::FromRow)]
+pub struct DeliveryRow {
+    subscriber_email: String,
+    outcome: String,
+    error: Option<String>,
+    attempted_at: Option<DateTime<Utc>>,
+    open_count: i32,
+    first_opened_at: Option<DateTime<Utc>>,
+    click_count: i64,
+}
+
+struct DeliveriesExport;
+
+impl Export for DeliveriesExport {
+    type Row = DeliveryRow;
+
+    fn csv_header(&self) -> Vec<String> {
+        [
+            "subscriber_email",
+            "outcome",
+            "error",
+            "attempted_at",
+            "open_count",
+            "first_opened_at",
+            "click_count",
+        ]
+        .map(String::from)
+        .into()
+    }
+
+    fn csv_record(&self, row: &DeliveryRow) -> Vec<String> {
+        vec![
+            row.subscriber_email.clone(),
+            row.outcome.clone(),
+            row.error.clone().unwrap_or_default(),
+            row.attempted_at.map(format_timestamp).unwrap_or_default(),
+            row.open_count.to_string(),
+            row.first_opened_at
+                .map(format_timestamp)
+                .unwrap_or_default(),
+            row.click_count.to_string(),
+        ]
+    }
+
+    fn json(&self, row: &DeliveryRow) -> Value {
+        json!({
+            "subscriber_email": row.subscriber_email,
+            "outcome": row.outcome,
+            "error": row.error,
+            "attempted_at": row.attempted_at.map(format_timestamp),
+            "open_count": row.open_count,
+            "first_opened_at": row.first_opened_at.map(format_timestamp),
+            "click_count": row.click_count,
+        })
+    }
+}
+
+/// Exports one row per recipient of an issue. Deliveries still in the queue
+/// are reported as `pending`.
+#[utoipa::path(
+    get,
+    path = "/admin/issues/{issue_id}/deliveries",
+    tag = "admin",
+    summary = "Export deliveries",
+    params(("issue_id" = Uuid, Path, description = "Id of the newsletter issue"), ExportQuery),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The outcome of every delivery of the issue", content((String = "text/csv"), (String = "application/x-ndjson"))),
+    )
+)]
+#[tracing::instrument(name = "Export issue deliveries", skip(query, pool))]
+pub async fn export_issue_deliveries(
+    issue_id: web::Path<Uuid>,
+    query: web::Query<ExportQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let exists = sqlx::query_scalar!(
+        "SELECT 1 FROM t_newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to perform a query to retrieve a newsletter issue.")
+    .map_err(utils::e500)?;
+    if exists.is_none() {
+        return Err(utils::e404("The newsletter issue does not exist."));
+    }
+
+    let mut cursor = export::cursor_query();
+    cursor
+        .push(
+            r#"
+        WITH recipients AS (
+            SELECT subscriber_email, outcome, error, attempted_at
+            FROM t_issue_deliveries
+            WHERE newsletter_issue_id = "#,
+        )
+        .push_bind(issue_id)
+        .push(
+            r#"
+            UNION ALL
+            SELECT subscriber_email, 'pending', NULL, NULL
+            FROM t_issue_delivery_queue
+            WHERE newsletter_issue_id = "#,
+        )
+        .push_bind(issue_id)
+        .push(
+            r#"
+        )
+        SELECT
+            r.subscriber_email::text,
+            r.outcome::text,
+            r.error,
+            r.attempted_at,
+            COALESCE(o.open_count, 0) AS open_count,
+            o.first_opened_at,
+            (
+                SELECT count(*)
+                FROM t_link_clicks c
+                WHERE c.newsletter_issue_id = "#,
+        )
+        .push_bind(issue_id)
+        .push(
+            r#"
+                    AND c.subscriber_id = s.id
+            ) AS click_count
+        FROM recipients r
+        LEFT JOIN t_subscriptions s ON s.email = r.subscriber_email
+        LEFT JOIN t_email_opens o
+            ON o.subscriber_id = s.id AND o.newsletter_issue_id = "#,
+        )
+        .push_bind(issue_id)
+        .push(
+            r#"
+        ORDER BY r.subscriber_email
+        "#,
+        );
+    let transaction = export::open_cursor(&pool, cursor)
+        .await
+        .context("Failed to open a cursor over issue deliveries.")
+        .map_err(utils::e500)?;
+
+    Ok(export::streaming_response(
+        transaction,
+        DeliveriesExport,
+        query.format,
+        &format!("issue-{}-deliveries", issue_id),
+    ))
+}
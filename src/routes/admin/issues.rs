@@ -0,0 +1,161 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use std::fmt::Write;
+
+use crate::utils;
+
+#[utoipa::path(
+    get,
+    path = "/admin/issues",
+    operation_id = "admin_list_issues",
+    tag = "admin",
+    summary = "Issues",
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The published issues", content_type = "text/html", body = String),
+    )
+)]
+pub async fn list_issues(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let issues = get_issues(&pool).await.map_err(utils::e500)?;
+
+    let mut issues_html = String::new();
+    for issue in issues {
+        writeln!(
+            issues_html,
+            r#"<li><a href="/admin/issues/{}">{}</a> ({})</li>"#,
+            issue.newsletter_issue_id,
+            htmlescape::encode_minimal(&issue.title),
+            issue.published_at.format("%Y-%m-%d %H:%M"),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("issues.html"),
+            issues_html = issues_html
+        )))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/issues/{issue_id}",
+    tag = "admin",
+    summary = "Issue",
+    params(("issue_id" = Uuid, Path, description = "Id of the newsletter issue")),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The delivery and engagement statistics of an issue", content_type = "text/html", body = String),
+        (status = 404, description = "The issue does not exist"),
+    )
+)]
+pub async fn issue_details(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let issue = get_issue(issue_id, &pool)
+        .await
+        .map_err(utils::e500)?
+        .ok_or_else(|| utils::e404("The newsletter issue does not exist."))?;
+    let clicks = get_click_counts(issue_id, &pool)
+        .await
+        .map_err(utils::e500)?;
+
+    let mut clicks_html = String::new();
+    for c in clicks {
+        writeln!(
+            clicks_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&c.url),
+            c.total_clicks,
+            c.unique_clicks,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("issue.html"),
+            issue_id = issue_id,
+            title = htmlescape::encode_minimal(&issue.title),
+            published_at = issue.published_at.format("%Y-%m-%d %H:%M"),
+            clicks_html = clicks_html,
+        )))
+}
+
+struct IssueSummary {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get newsletter issues", skip(pool))]
+async fn get_issues(pool: &PgPool) -> Result<Vec<IssueSummary>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT newsletter_issue_id, title, published_at
+        FROM t_newsletter_issues
+        ORDER BY published_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve newsletter issues.")?;
+
+    Ok(issues)
+}
+
+#[tracing::instrument(name = "Get newsletter issue", skip(pool))]
+async fn get_issue(issue_id: Uuid, pool: &PgPool) -> Result<Option<IssueSummary>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT newsletter_issue_id, title, published_at
+        FROM t_newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve a newsletter issue.")?;
+
+    Ok(issue)
+}
+
+struct ClickCount {
+    url: String,
+    total_clicks: i64,
+    unique_clicks: i64,
+}
+
+#[tracing::instrument(name = "Get link click counts", skip(pool))]
+async fn get_click_counts(issue_id: Uuid, pool: &PgPool) -> Result<Vec<ClickCount>, anyhow::Error> {
+    let clicks = sqlx::query_as!(
+        ClickCount,
+        r#"
+        SELECT
+            url,
+            COUNT(*) AS "total_clicks!",
+            COUNT(DISTINCT subscriber_id) AS "unique_clicks!"
+        FROM t_link_clicks
+        WHERE newsletter_issue_id = $1
+        GROUP BY url
+        ORDER BY 2 DESC, url
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve link click counts.")?;
+
+    Ok(clicks)
+}
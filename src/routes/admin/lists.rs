@@ -0,0 +1,138 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use std::fmt::Write;
+
+use crate::{authentication::CsrfToken, utils};
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[schema(as = NewListFormData)]
+pub struct FormData {
+    name: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/lists",
+    tag = "admin",
+    summary = "Mailing lists",
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The mailing lists and their members", content_type = "text/html", body = String),
+    )
+)]
+pub async fn list_mailing_lists(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut lists_html = String::new();
+    for list in get_list_sizes(&pool).await.map_err(utils::e500)? {
+        writeln!(
+            lists_html,
+            "<tr><td>{}{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&list.name),
+            if list.is_default { " (default)" } else { "" },
+            list.confirmed_members,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("lists.html"),
+            msg_html = msg_html,
+            lists_html = lists_html,
+            csrf_field = csrf_token.form_field(),
+        )))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/lists",
+    tag = "admin",
+    summary = "Create a mailing list",
+    request_body(content = FormData, content_type = "application/x-www-form-urlencoded"),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the lists, with a flash message"),
+    )
+)]
+#[tracing::instrument(name = "Create a mailing list", skip(form, pool))]
+pub async fn create_mailing_list(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.0.name.trim();
+    if name.is_empty() {
+        FlashMessage::error("The list name cannot be empty.").send();
+        return Ok(utils::see_other("/admin/lists"));
+    }
+
+    let inserted = insert_list(name, &pool).await.map_err(utils::e500)?;
+    let name = htmlescape::encode_minimal(name);
+    if inserted {
+        FlashMessage::info(format!("The list '{}' has been created.", name)).send();
+    } else {
+        FlashMessage::error(format!("A list named '{}' already exists.", name)).send();
+    }
+    Ok(utils::see_other("/admin/lists"))
+}
+
+struct ListSize {
+    name: String,
+    is_default: bool,
+    confirmed_members: i64,
+}
+
+#[tracing::instrument(name = "Get mailing list sizes", skip(pool))]
+async fn get_list_sizes(pool: &PgPool) -> Result<Vec<ListSize>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        ListSize,
+        r#"
+        SELECT
+            l.name,
+            l.is_default,
+            COUNT(s.id) AS "confirmed_members!"
+        FROM t_lists l
+        LEFT JOIN t_list_memberships m ON m.list_id = l.list_id
+        LEFT JOIN t_subscriptions s ON s.id = m.subscriber_id AND s.status = 'confirmed'
+        GROUP BY l.list_id
+        ORDER BY l.is_default DESC, l.name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve mailing list sizes.")?;
+
+    Ok(lists)
+}
+
+/// Returns `false` if a list with the same name already exists.
+#[tracing::instrument(name = "Insert a mailing list", skip(pool))]
+async fn insert_list(name: &str, pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO t_lists (list_id, name, created_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name
+    )
+    .execute(pool)
+    .await
+    .context("Failed to insert a mailing list.")?
+    .rows_affected();
+
+    Ok(inserted > 0)
+}
@@ -1,9 +1,33 @@
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
-use crate::{session_state::TypedSession, utils};
+use crate::{
+    authentication::{revoke_session, UserId},
+    session_state::TypedSession,
+    utils,
+};
 
-pub async fn log_out(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
+#[utoipa::path(
+    post,
+    path = "/admin/logout",
+    tag = "admin",
+    summary = "Log out",
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "To the login form"),
+    )
+)]
+pub async fn log_out(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(session_id) = session.get_session_id().map_err(utils::e500)? {
+        revoke_session(&pool, **user_id, session_id)
+            .await
+            .map_err(utils::e500)?;
+    }
     session.log_out();
     FlashMessage::info("You have successfully logged out.").send();
     Ok(utils::see_other("/login"))
@@ -1,9 +1,43 @@
-mod dashboard;
-mod logout;
-mod newsletters;
-mod password;
+pub(crate) mod api_tokens;
+pub(crate) mod attributes;
+pub(crate) mod dashboard;
+pub(crate) mod drafts;
+pub(crate) mod exports;
+pub(crate) mod issues;
+pub(crate) mod lists;
+pub(crate) mod logout;
+pub(crate) mod newsletters;
+pub(crate) mod password;
+pub(crate) mod sessions;
+pub(crate) mod subscribers;
+pub(crate) mod tags;
+pub(crate) mod two_factor;
+pub(crate) mod users;
 
+pub use api_tokens::{create_api_token, list_api_tokens, revoke_api_token};
+pub use attributes::{create_attribute_definition, list_attribute_definitions};
 pub use dashboard::admin_dashboard;
+pub use drafts::{delete_draft, save_draft};
+pub(crate) use drafts::{get_draft, remove_published_draft, store_draft};
+pub use exports::{export_issue_deliveries, export_subscribers};
+pub use issues::{issue_details, list_issues};
+pub use lists::{create_mailing_list, list_mailing_lists};
 pub use logout::log_out;
-pub use newsletters::{get_newsletter_form, publish_newsletter};
+pub use newsletters::{count_newsletter_recipients, get_newsletter_form, publish_newsletter};
+pub(crate) use newsletters::{enqueue_delivery_tasks, insert_newsletter_issue};
 pub use password::{change_password, change_password_form};
+pub use sessions::{list_sessions, revoke_other_user_sessions, revoke_user_session};
+pub use subscribers::{
+    confirm_subscriber, delete_subscriber, download_import_rejections, erase_subscriber,
+    export_subscriber_data, import_subscribers, import_subscribers_form, list_subscribers,
+    resend_confirmation, subscriber_details, unsubscribe_subscriber,
+};
+pub use tags::{list_tags, update_subscriber_tags};
+pub use two_factor::{
+    confirm_two_factor, regenerate_recovery_codes, set_up_two_factor, turn_off_two_factor,
+    two_factor_settings,
+};
+pub use users::{
+    change_user_role, delete_user, disable_user, enable_user, invite_user, list_users,
+    resend_invitation,
+};
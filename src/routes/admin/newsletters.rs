@@ -1,40 +1,163 @@
 use actix_web::{http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
 use anyhow::Context;
-use sqlx::{PgPool, Postgres, Transaction};
+use sqlx::{PgPool, Postgres, QueryBuilder, Transaction};
 use uuid::Uuid;
 
 use std::fmt::Write;
 
+use super::drafts;
 use crate::{
-    authentication::UserId,
-    idempotency::{self, IdempotencyKey},
+    authentication::{CsrfToken, Permission, Role, UserId},
+    html_form::{HtmlForm, HtmlQuery},
+    idempotency::{self, IdempotencyError, IdempotencyKey},
+    mailing_lists::{self, ListSelectionError},
+    segment::Segment,
     utils::{self, see_other},
 };
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::ToSchema)]
 pub struct BodyData {
     title: String,
     plaintext: String,
     html: String,
+    #[serde(default)]
+    track_clicks: bool,
+    #[serde(default, rename = "list_id")]
+    list_ids: Vec<Uuid>,
+    #[serde(default)]
+    segment: String,
     idempotency_key: String,
+    draft_id: Option<Uuid>,
 }
 
-pub async fn get_newsletter_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FormQuery {
+    draft_id: Option<Uuid>,
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RecipientsQuery {
+    #[serde(default, rename = "list_id")]
+    list_ids: Vec<Uuid>,
+    #[serde(default)]
+    segment: String,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct RecipientCount {
+    recipients: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/newsletters",
+    tag = "admin",
+    summary = "Newsletter form",
+    params(FormQuery),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The form to write, save and publish an issue", content_type = "text/html", body = String),
+    )
+)]
+pub async fn get_newsletter_form(
+    query: web::Query<FormQuery>,
+    flash_messages: IncomingFlashMessages,
+    connection_pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
     let mut msg_html = String::new();
     for m in flash_messages.iter() {
         writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
 
-    HttpResponse::Ok()
+    let drafts = drafts::get_drafts(&connection_pool)
+        .await
+        .map_err(utils::e500)?;
+    let draft = match query.draft_id {
+        Some(draft_id) => Some(
+            drafts
+                .iter()
+                .find(|d| d.draft_id == draft_id)
+                .ok_or_else(|| utils::e404("The draft does not exist."))?,
+        ),
+        None => None,
+    };
+    let mut drafts_html = String::new();
+    for d in &drafts {
+        writeln!(
+            drafts_html,
+            r#"<tr><td><a href="/admin/newsletters?draft_id={0}">{1}</a></td><td>{2}</td><td><form action="/admin/newsletters/drafts/{0}/delete" method="post">{3}<button type="submit">Delete</button></form></td></tr>"#,
+            d.draft_id,
+            htmlescape::encode_minimal(&d.title),
+            d.updated_at.format("%Y-%m-%d %H:%M"),
+            csrf_token.form_field(),
+        )
+        .unwrap();
+    }
+    let send_button_html = if role.allows(Permission::Publish) {
+        r#"<button type="submit">Send</button>"#
+    } else {
+        "<p>Only owners can send issues: save a draft for one of them to publish.</p>"
+    };
+    let lists = mailing_lists::get_lists(&connection_pool)
+        .await
+        .map_err(utils::e500)?;
+    let default_list_ids: Vec<Uuid> = lists
+        .iter()
+        .filter(|l| l.is_default)
+        .map(|l| l.list_id)
+        .collect();
+    let recipient_count = count_recipients(
+        connection_pool.get_ref(),
+        &default_list_ids,
+        &Segment::default(),
+    )
+    .await
+    .map_err(utils::e500)?;
+
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
             include_str!("newsletters.html"),
             msg_html = msg_html,
-            idempotency_key = uuid::Uuid::new_v4()
-        ))
+            lists_html = mailing_lists::checkboxes_html(&lists),
+            recipient_count = recipient_count,
+            idempotency_key = uuid::Uuid::new_v4(),
+            drafts_html = drafts_html,
+            draft_id_html = draft
+                .map(|d| format!(
+                    r#"<input hidden type="text" name="draft_id" value="{}">"#,
+                    d.draft_id
+                ))
+                .unwrap_or_default(),
+            title = htmlescape::encode_attribute(draft.map_or("", |d| &d.title)),
+            plaintext = htmlescape::encode_minimal(draft.map_or("", |d| &d.text_content)),
+            html = htmlescape::encode_minimal(draft.map_or("", |d| &d.html_content)),
+            track_clicks_checked = if draft.is_some_and(|d| d.track_clicks) {
+                " checked"
+            } else {
+                ""
+            },
+            send_button_html = send_button_html,
+            csrf_field = csrf_token.form_field(),
+        )))
 }
 
+#[utoipa::path(
+    post,
+    path = "/admin/newsletters",
+    tag = "admin",
+    summary = "Publish an issue",
+    request_body(content = BodyData, content_type = "application/x-www-form-urlencoded"),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the form, with a flash message"),
+    )
+)]
 #[tracing::instrument(
     name = "Publish a newsletter issue",
     skip(user_id, body, connection_pool),
@@ -42,7 +165,7 @@ pub async fn get_newsletter_form(flash_messages: IncomingFlashMessages) -> HttpR
 )]
 pub async fn publish_newsletter(
     user_id: web::ReqData<UserId>,
-    body: web::Form<BodyData>,
+    body: HtmlForm<BodyData>,
     connection_pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let success_message = FlashMessage::info("The newsletter issues has been accepted!");
@@ -53,30 +176,61 @@ pub async fn publish_newsletter(
         title,
         plaintext,
         html,
+        track_clicks,
+        list_ids,
+        segment,
         idempotency_key,
+        draft_id,
     } = body.0;
 
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(utils::e400)?;
-    let mut transaction =
-        match idempotency::try_processing(&connection_pool, &idempotency_key, &user_id)
-            .await
-            .map_err(utils::e500)?
-        {
-            idempotency::NextAction::StartProcessing(t) => t,
-            idempotency::NextAction::ReturnSavedResponse(saved_response) => {
-                success_message.send();
-                return Ok(saved_response);
-            }
-        };
-
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &plaintext, &html)
-        .await
-        .context("Failed to store newsletter issue details")
-        .map_err(utils::e500)?;
-    enqueue_delivery_tasks(&mut transaction, issue_id)
+    let segment = Segment::parse(&segment).map_err(utils::e400)?;
+    let next_action = match idempotency::try_processing(
+        &connection_pool,
+        &idempotency_key,
+        &user_id,
+        "POST /admin/newsletters",
+    )
+    .await
+    {
+        Ok(next_action) => next_action,
+        Err(e @ IdempotencyError::KeyReused) => return Err(utils::e400(e)),
+        Err(IdempotencyError::UnexpectedError(e)) => return Err(utils::e500(e)),
+    };
+    let mut transaction = match next_action {
+        idempotency::NextAction::StartProcessing(t) => t,
+        idempotency::NextAction::ReturnSavedResponse(saved_response) => {
+            success_message.send();
+            return Ok(saved_response);
+        }
+    };
+
+    let list_ids = match mailing_lists::resolve_list_ids(&mut transaction, &list_ids).await {
+        Ok(list_ids) => list_ids,
+        Err(ListSelectionError::UnexpectedError(e)) => return Err(utils::e500(e)),
+        Err(e) => return Err(utils::e400(e)),
+    };
+    let issue_id = Uuid::new_v4();
+    insert_newsletter_issue(
+        &mut transaction,
+        issue_id,
+        &title,
+        &plaintext,
+        &html,
+        track_clicks,
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(utils::e500)?;
+    enqueue_delivery_tasks(&mut transaction, issue_id, &list_ids, &segment)
         .await
         .context("Failed to enqueue delivery tasks")
         .map_err(utils::e500)?;
+    if let Some(draft_id) = draft_id {
+        drafts::remove_published_draft(&mut transaction, draft_id)
+            .await
+            .map_err(utils::e500)?;
+    }
 
     let response = see_other("/admin/newsletters");
     let response = idempotency::save_response(transaction, &idempotency_key, &user_id, response)
@@ -88,13 +242,14 @@ pub async fn publish_newsletter(
 }
 
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
     title: &str,
     text_content: &str,
     html_content: &str,
-) -> Result<Uuid, sqlx::Error> {
-    let newsletter_id = Uuid::new_v4();
+    track_clicks: bool,
+) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"
         INSERT INTO t_newsletter_issues (
@@ -102,40 +257,128 @@ async fn insert_newsletter_issue(
             title,
             text_content,
             html_content,
+            track_clicks,
             published_at
         )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, $3, $4, $5, now())
         "#,
-        newsletter_id,
+        newsletter_issue_id,
         title,
         text_content,
         html_content,
+        track_clicks,
     )
     .execute(&mut **transaction)
     .await?;
 
-    Ok(newsletter_id)
+    Ok(())
 }
 
 #[tracing::instrument(skip_all)]
 pub async fn enqueue_delivery_tasks(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: Uuid,
+    list_ids: &[Uuid],
+    segment: &Segment,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+    // Paused subscribers miss the issue, digest subscribers get it with their next digest.
+    let mut query = QueryBuilder::new(
         r#"
         INSERT INTO t_issue_delivery_queue (
             newsletter_issue_id,
-            subscriber_email
+            subscriber_email,
+            execute_after
         )
-        SELECT $1, email
-        FROM t_subscriptions
-        WHERE status = 'confirmed'
-        "#,
-        newsletter_issue_id
-    )
-    .execute(&mut **transaction)
-    .await?;
+        SELECT "#,
+    );
+    query.push_bind(newsletter_issue_id).push(
+        r#",
+            s.email,
+            CASE s.digest_frequency
+                WHEN 'daily' THEN date_trunc('day', now()) + interval '1 day'
+                WHEN 'weekly' THEN date_trunc('week', now()) + interval '1 week'
+                ELSE now()
+            END"#,
+    );
+    push_recipients(&mut query, list_ids, segment);
+    query.build().execute(&mut **transaction).await?;
 
     Ok(())
 }
+
+/// Returns how many subscribers an issue sent with this selection would reach,
+/// so that admins can check their segment before publishing.
+#[utoipa::path(
+    get,
+    path = "/admin/newsletters/recipients",
+    tag = "admin",
+    summary = "Count recipients",
+    params(RecipientsQuery),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "How many confirmed subscribers an issue would be sent to", body = RecipientCount),
+    )
+)]
+#[tracing::instrument(name = "Count newsletter recipients", skip(query, connection_pool))]
+pub async fn count_newsletter_recipients(
+    query: HtmlQuery<RecipientsQuery>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let segment = Segment::parse(&query.segment).map_err(utils::e400)?;
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(utils::e500)?;
+    let list_ids = match mailing_lists::resolve_list_ids(&mut transaction, &query.list_ids).await {
+        Ok(list_ids) => list_ids,
+        Err(ListSelectionError::UnexpectedError(e)) => return Err(utils::e500(e)),
+        Err(e) => return Err(utils::e400(e)),
+    };
+    let recipients = count_recipients(&mut *transaction, &list_ids, &segment)
+        .await
+        .map_err(utils::e500)?;
+
+    Ok(HttpResponse::Ok().json(RecipientCount { recipients }))
+}
+
+#[tracing::instrument(skip_all)]
+async fn count_recipients<'c, E>(
+    executor: E,
+    list_ids: &[Uuid],
+    segment: &Segment,
+) -> Result<i64, anyhow::Error>
+where
+    E: sqlx::PgExecutor<'c>,
+{
+    let mut query = QueryBuilder::new("SELECT COUNT(*)");
+    push_recipients(&mut query, list_ids, segment);
+    let count = query
+        .build_query_scalar()
+        .fetch_one(executor)
+        .await
+        .context("Failed to count newsletter recipients.")?;
+
+    Ok(count)
+}
+
+/// Pushes the `FROM`/`WHERE` clauses selecting the confirmed, non-paused
+/// members of any of `list_ids` that match `segment`. Being a member of several
+/// of the chosen lists still yields a single row.
+fn push_recipients(query: &mut QueryBuilder<'_, Postgres>, list_ids: &[Uuid], segment: &Segment) {
+    query
+        .push(
+            r#"
+        FROM t_subscriptions s
+        WHERE s.status = 'confirmed'
+            AND (s.paused_until IS NULL OR s.paused_until <= now())
+            AND EXISTS (
+                SELECT 1
+                FROM t_list_memberships m
+                WHERE m.subscriber_id = s.id
+                    AND m.list_id = ANY("#,
+        )
+        .push_bind(list_ids.to_vec())
+        .push("))");
+    segment.push_sql_filter(query);
+}
@@ -1,10 +1,23 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 
 use std::fmt::Write;
 
+use crate::authentication::CsrfToken;
+
+#[utoipa::path(
+    get,
+    path = "/admin/password",
+    tag = "admin",
+    summary = "Change password form",
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The form to change the password", content_type = "text/html", body = String),
+    )
+)]
 pub async fn change_password_form(
     flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut msg_html = String::new();
     for m in flash_messages.iter() {
@@ -15,6 +28,7 @@ pub async fn change_password_form(
         .content_type(ContentType::html())
         .body(format!(
             include_str!("change_password_form.html"),
-            msg_html = msg_html
+            msg_html = msg_html,
+            csrf_field = csrf_token.form_field(),
         )))
 }
@@ -1,35 +1,57 @@
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
-use secrecy::{ExposeSecret, Secret};
+use secrecy::Secret;
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{self, validate_credentials, AuthError, Credentials, UserId},
+    authentication::{
+        self, revoke_other_sessions, validate_credentials, AuthError, Credentials, PasswordHashing,
+        PasswordPolicy, UserId,
+    },
     routes::admin::dashboard::get_username,
+    session_state::TypedSession,
     utils,
 };
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[schema(as = ChangePasswordFormData)]
 pub struct FormData {
+    #[schema(value_type = String, format = Password)]
     current_password: Secret<String>,
+    #[schema(value_type = String, format = Password)]
     new_password: Secret<String>,
+    #[schema(value_type = String, format = Password)]
     new_password_check: Secret<String>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/admin/password",
+    tag = "admin",
+    summary = "Change password",
+    request_body(content = FormData, content_type = "application/x-www-form-urlencoded"),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the form, with a flash message"),
+    )
+)]
 pub async fn change_password(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    password_hashing: web::Data<PasswordHashing>,
+    password_policy: web::Data<PasswordPolicy>,
+    session: TypedSession,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     let username = get_username(*user_id, &pool).await.map_err(utils::e500)?;
 
     let credentials = Credentials {
-        username: username,
+        username: username.clone(),
         password: form.current_password.clone(),
     };
 
-    if let Err(e) = validate_credentials(credentials, &pool).await {
+    if let Err(e) = validate_credentials(credentials, &pool, &password_hashing).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
                 FlashMessage::error("The current password is incorrect.").send();
@@ -39,28 +61,19 @@ pub async fn change_password(
         };
     }
 
-    let password_length = form.new_password.expose_secret().len();
-    if password_length < 8 {
-        FlashMessage::error(
-            "The new password is too short - its length must be between 8 and 128 characters.",
-        )
-        .send();
-        return Ok(utils::see_other("/admin/password"));
-    } else if password_length > 128 {
-        FlashMessage::error(
-            "The new password is too long - its length must be between 8 and 128 characters.",
-        )
-        .send();
-        return Ok(utils::see_other("/admin/password"));
-    }
-
-    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
-        FlashMessage::error("You entered two different password - the field values must match.")
-            .send();
+    if let Err(message) =
+        password_policy.check_new_password(&username, &form.new_password, &form.new_password_check)
+    {
+        FlashMessage::error(message).send();
         return Ok(utils::see_other("/admin/password"));
     }
 
-    authentication::change_password(*user_id, form.0.new_password, &pool)
+    authentication::change_password(*user_id, form.0.new_password, &pool, &password_hashing)
+        .await
+        .map_err(utils::e500)?;
+    // Whoever knew the old password must not stay logged in elsewhere.
+    let current_session_id = session.get_session_id().map_err(utils::e500)?;
+    revoke_other_sessions(pool.get_ref(), *user_id, current_session_id)
         .await
         .map_err(utils::e500)?;
     FlashMessage::info("Your password has been changed.").send();
@@ -0,0 +1,125 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use std::fmt::Write;
+
+use crate::{
+    authentication::{
+        list_user_sessions, revoke_other_sessions, revoke_session, CsrfToken, UserId,
+    },
+    session_state::TypedSession,
+    utils,
+};
+
+#[utoipa::path(
+    get,
+    path = "/admin/sessions",
+    tag = "admin",
+    summary = "Sessions",
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The sessions of the user", content_type = "text/html", body = String),
+    )
+)]
+pub async fn list_sessions(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let current_session_id = session.get_session_id().map_err(utils::e500)?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut sessions_html = String::new();
+    for s in list_user_sessions(&pool, **user_id)
+        .await
+        .map_err(utils::e500)?
+    {
+        let action_html = if Some(s.session_id) == current_session_id {
+            "This session".to_owned()
+        } else {
+            format!(
+                r#"<form action="/admin/sessions/{}/revoke" method="post">{}<button type="submit">Revoke</button></form>"#,
+                s.session_id,
+                csrf_token.form_field()
+            )
+        };
+        writeln!(
+            sessions_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            s.created_at.format("%Y-%m-%d %H:%M"),
+            s.last_seen_at.format("%Y-%m-%d %H:%M"),
+            htmlescape::encode_minimal(s.ip_address.as_deref().unwrap_or("Unknown")),
+            htmlescape::encode_minimal(s.user_agent.as_deref().unwrap_or("Unknown")),
+            action_html,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("sessions.html"),
+            msg_html = msg_html,
+            sessions_html = sessions_html,
+            csrf_field = csrf_token.form_field(),
+        )))
+}
+
+/// Logs out one session of the current user. It is rejected at its next
+/// request.
+#[utoipa::path(
+    post,
+    path = "/admin/sessions/{session_id}/revoke",
+    tag = "admin",
+    summary = "Log out a session",
+    params(("session_id" = Uuid, Path, description = "Id of the session")),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the sessions"),
+    )
+)]
+pub async fn revoke_user_session(
+    session_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !revoke_session(&pool, **user_id, session_id.into_inner())
+        .await
+        .map_err(utils::e500)?
+    {
+        return Err(utils::e404("There is no such session."));
+    }
+    FlashMessage::info("The session has been logged out.").send();
+    Ok(utils::see_other("/admin/sessions"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/sessions/revoke_others",
+    tag = "admin",
+    summary = "Log out other sessions",
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the sessions"),
+    )
+)]
+pub async fn revoke_other_user_sessions(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let current_session_id = session.get_session_id().map_err(utils::e500)?;
+    let revoked = revoke_other_sessions(pool.get_ref(), **user_id, current_session_id)
+        .await
+        .map_err(utils::e500)?;
+    FlashMessage::info(format!("{} other sessions have been logged out.", revoked)).send();
+    Ok(utils::see_other("/admin/sessions"))
+}
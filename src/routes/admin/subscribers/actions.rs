@@ -0,0 +1,346 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::UserId,
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+    email_client::EmailClient,
+    personal_data::{self, ErasureRequester},
+    routes::{
+        generate_subscription_token, mark_as_unsubscribed, personal_data_response,
+        send_confirmation_email, store_token,
+    },
+    startup::ApplicationBaseUrl,
+    utils,
+};
+
+struct Subscriber {
+    email: String,
+    name: String,
+    status: String,
+}
+
+fn details_page(subscriber_id: Uuid) -> String {
+    format!("/admin/subscribers/{}", subscriber_id)
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/subscribers/{subscriber_id}/confirm",
+    tag = "admin",
+    summary = "Confirm a subscriber",
+    params(("subscriber_id" = Uuid, Path, description = "Id of the subscriber")),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the subscriber"),
+    )
+)]
+#[tracing::instrument(name = "Manually confirm a subscriber", skip(pool))]
+pub async fn confirm_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = get_subscriber(subscriber_id, &pool).await?;
+
+    sqlx::query!(
+        "UPDATE t_subscriptions SET status = 'confirmed' WHERE id = $1",
+        subscriber_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to confirm a subscriber.")
+    .map_err(utils::e500)?;
+
+    FlashMessage::info(format!(
+        "{} has been confirmed.",
+        htmlescape::encode_minimal(&subscriber.email)
+    ))
+    .send();
+    Ok(utils::see_other(&details_page(subscriber_id)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/subscribers/{subscriber_id}/unsubscribe",
+    tag = "admin",
+    summary = "Unsubscribe a subscriber",
+    params(("subscriber_id" = Uuid, Path, description = "Id of the subscriber")),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the subscriber"),
+    )
+)]
+#[tracing::instrument(name = "Manually unsubscribe a subscriber", skip(pool))]
+pub async fn unsubscribe_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = get_subscriber(subscriber_id, &pool).await?;
+
+    mark_as_unsubscribed(&pool, subscriber_id)
+        .await
+        .map_err(utils::e500)?;
+
+    FlashMessage::info(format!(
+        "{} has been unsubscribed.",
+        htmlescape::encode_minimal(&subscriber.email)
+    ))
+    .send();
+    Ok(utils::see_other(&details_page(subscriber_id)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/subscribers/{subscriber_id}/resend_confirmation",
+    tag = "admin",
+    summary = "Resend the confirmation email",
+    params(("subscriber_id" = Uuid, Path, description = "Id of the subscriber")),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the subscriber"),
+    )
+)]
+#[tracing::instrument(
+    name = "Resend a confirmation email",
+    skip(pool, email_client, base_url)
+)]
+pub async fn resend_confirmation(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = get_subscriber(subscriber_id, &pool).await?;
+    if subscriber.status != "pending_confirmation" {
+        FlashMessage::error(
+            "Only subscribers pending confirmation can be sent a confirmation email.",
+        )
+        .send();
+        return Ok(utils::see_other(&details_page(subscriber_id)));
+    }
+    let new_subscriber = NewSubscriber {
+        email: SubscriberEmail::parse(subscriber.email).map_err(utils::e500)?,
+        name: SubscriberName::parse(subscriber.name).map_err(utils::e500)?,
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(utils::e500)?;
+    let token = generate_subscription_token();
+    store_token(&mut transaction, &subscriber_id, &token)
+        .await
+        .map_err(utils::e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a confirmation token.")
+        .map_err(utils::e500)?;
+    send_confirmation_email(&email_client, &new_subscriber, &base_url, &token)
+        .await
+        .context("Failed to send a confirmation email.")
+        .map_err(utils::e500)?;
+
+    FlashMessage::info(format!(
+        "A new confirmation email has been sent to {}.",
+        htmlescape::encode_minimal(new_subscriber.email.as_ref())
+    ))
+    .send();
+    Ok(utils::see_other(&details_page(subscriber_id)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/subscribers/{subscriber_id}/delete",
+    tag = "admin",
+    summary = "Delete a subscriber",
+    params(("subscriber_id" = Uuid, Path, description = "Id of the subscriber")),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "To the subscribers"),
+    )
+)]
+#[tracing::instrument(name = "Delete a subscriber", skip(pool))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = get_subscriber(subscriber_id, &pool).await?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(utils::e500)?;
+    delete_subscriber_data(&mut transaction, subscriber_id, &subscriber.email)
+        .await
+        .map_err(utils::e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a subscriber.")
+        .map_err(utils::e500)?;
+
+    FlashMessage::info(format!(
+        "{} has been deleted.",
+        htmlescape::encode_minimal(&subscriber.email)
+    ))
+    .send();
+    Ok(utils::see_other("/admin/subscribers"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/subscribers/{subscriber_id}/data",
+    tag = "admin",
+    summary = "Export personal data",
+    params(("subscriber_id" = Uuid, Path, description = "Id of the subscriber")),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "Everything stored about the subscriber", content_type = "application/json"),
+    )
+)]
+#[tracing::instrument(name = "Export the personal data of a subscriber", skip(pool))]
+pub async fn export_subscriber_data(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber = get_subscriber(subscriber_id.into_inner(), &pool).await?;
+    let bundle = personal_data::collect(&pool, &subscriber.email)
+        .await
+        .map_err(utils::e500)?;
+    Ok(personal_data_response(&bundle))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/subscribers/{subscriber_id}/erase",
+    tag = "admin",
+    summary = "Erase personal data",
+    params(("subscriber_id" = Uuid, Path, description = "Id of the subscriber")),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "To the subscribers"),
+    )
+)]
+#[tracing::instrument(name = "Erase the personal data of a subscriber", skip(pool))]
+pub async fn erase_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber = get_subscriber(subscriber_id.into_inner(), &pool).await?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(utils::e500)?;
+    personal_data::erase(
+        &mut transaction,
+        &subscriber.email,
+        ErasureRequester::Admin(*user_id.into_inner()),
+    )
+    .await
+    .map_err(utils::e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase personal data.")
+        .map_err(utils::e500)?;
+
+    FlashMessage::info(format!(
+        "The personal data of {} has been erased.",
+        htmlescape::encode_minimal(&subscriber.email)
+    ))
+    .send();
+    Ok(utils::see_other("/admin/subscribers"))
+}
+
+async fn get_subscriber(
+    subscriber_id: Uuid,
+    pool: &PgPool,
+) -> Result<Subscriber, actix_web::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        "SELECT email, name, status FROM t_subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve a subscriber.")
+    .map_err(utils::e500)?
+    .ok_or_else(|| utils::e404("The subscriber does not exist."))
+}
+
+/// Removes the subscriber along with every row referencing them.
+#[tracing::instrument(name = "Delete subscriber data", skip(transaction, email))]
+async fn delete_subscriber_data(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    email: &str,
+) -> Result<(), anyhow::Error> {
+    for query in [
+        sqlx::query!(
+            "DELETE FROM t_subscription_tokens WHERE subscriber_id = $1",
+            subscriber_id
+        ),
+        sqlx::query!(
+            "DELETE FROM t_confirmation_email_queue WHERE subscriber_id = $1",
+            subscriber_id
+        ),
+        sqlx::query!(
+            "DELETE FROM t_preference_tokens WHERE subscriber_id = $1",
+            subscriber_id
+        ),
+        sqlx::query!(
+            "DELETE FROM t_list_memberships WHERE subscriber_id = $1",
+            subscriber_id
+        ),
+        sqlx::query!(
+            "DELETE FROM t_subscriber_tags WHERE subscriber_id = $1",
+            subscriber_id
+        ),
+        sqlx::query!(
+            "DELETE FROM t_link_clicks WHERE subscriber_id = $1",
+            subscriber_id
+        ),
+        sqlx::query!(
+            "DELETE FROM t_email_opens WHERE subscriber_id = $1",
+            subscriber_id
+        ),
+    ] {
+        query
+            .execute(&mut **transaction)
+            .await
+            .context("Failed to delete data referencing a subscriber.")?;
+    }
+    sqlx::query!(
+        "DELETE FROM t_issue_delivery_queue WHERE subscriber_email = $1",
+        email
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to delete pending deliveries of a subscriber.")?;
+    sqlx::query!(
+        "DELETE FROM t_issue_deliveries WHERE subscriber_email = $1",
+        email
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to delete the delivery history of a subscriber.")?;
+    sqlx::query!("DELETE FROM t_subscriptions WHERE id = $1", subscriber_id)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to delete a subscriber.")?;
+
+    Ok(())
+}
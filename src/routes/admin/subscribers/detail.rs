@@ -0,0 +1,171 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use std::fmt::Write;
+
+use crate::{authentication::CsrfToken, utils};
+
+#[utoipa::path(
+    get,
+    path = "/admin/subscribers/{subscriber_id}",
+    tag = "admin",
+    summary = "Subscriber",
+    params(("subscriber_id" = Uuid, Path, description = "Id of the subscriber")),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The subscriber and their history", content_type = "text/html", body = String),
+        (status = 404, description = "The subscriber does not exist"),
+    )
+)]
+pub async fn subscriber_details(
+    subscriber_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = get_subscriber_details(subscriber_id, &pool)
+        .await
+        .map_err(utils::e500)?
+        .ok_or_else(|| utils::e404("The subscriber does not exist."))?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut attributes_html = String::new();
+    if let serde_json::Value::Object(attributes) = &subscriber.attributes {
+        for (key, value) in attributes {
+            writeln!(
+                attributes_html,
+                "<tr><th>{}</th><td>{}</td></tr>",
+                htmlescape::encode_minimal(key),
+                htmlescape::encode_minimal(&match value {
+                    serde_json::Value::String(s) => s.clone(),
+                    value => value.to_string(),
+                }),
+            )
+            .unwrap();
+        }
+    }
+
+    let csrf_field = csrf_token.form_field();
+    let action = |action: &str, label: &str| {
+        format!(
+            r#"<form action="/admin/subscribers/{}/{}" method="post">{}<button type="submit">{}</button></form>"#,
+            subscriber_id, action, csrf_field, label
+        )
+    };
+    let mut actions_html = String::new();
+    if subscriber.status != "confirmed" {
+        writeln!(actions_html, "{}", action("confirm", "Confirm")).unwrap();
+    }
+    if subscriber.status == "pending_confirmation" {
+        writeln!(
+            actions_html,
+            "{}",
+            action("resend_confirmation", "Resend confirmation email")
+        )
+        .unwrap();
+    }
+    if subscriber.status != "unsubscribed" {
+        writeln!(actions_html, "{}", action("unsubscribe", "Unsubscribe")).unwrap();
+    }
+    writeln!(
+        actions_html,
+        r#"<p><a href="/admin/subscribers/{}/data">Download personal data (JSON)</a></p>"#,
+        subscriber_id
+    )
+    .unwrap();
+    writeln!(actions_html, "{}", action("erase", "Erase personal data")).unwrap();
+    writeln!(actions_html, "{}", action("delete", "Delete")).unwrap();
+
+    let paused_until = match subscriber.paused_until {
+        Some(paused_until) if paused_until > Utc::now() => {
+            paused_until.format("%Y-%m-%d").to_string()
+        }
+        _ => "-".to_owned(),
+    };
+    let escaped_list = |items: &[String]| {
+        items
+            .iter()
+            .map(|i| htmlescape::encode_minimal(i))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("detail.html"),
+            msg_html = msg_html,
+            email = htmlescape::encode_minimal(&subscriber.email),
+            name = htmlescape::encode_minimal(&subscriber.name),
+            status = subscriber.status,
+            subscribed_at = subscriber.subscribed_at.format("%Y-%m-%d %H:%M"),
+            digest_frequency = subscriber.digest_frequency,
+            paused_until = paused_until,
+            lists = escaped_list(&subscriber.lists),
+            tags = escaped_list(&subscriber.tags),
+            attributes_html = attributes_html,
+            actions_html = actions_html,
+        )))
+}
+
+struct SubscriberDetails {
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: NaiveDateTime,
+    digest_frequency: String,
+    paused_until: Option<DateTime<Utc>>,
+    attributes: serde_json::Value,
+    lists: Vec<String>,
+    tags: Vec<String>,
+}
+
+#[tracing::instrument(name = "Get subscriber details", skip(pool))]
+async fn get_subscriber_details(
+    subscriber_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<SubscriberDetails>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+        SELECT
+            s.email,
+            s.name,
+            s.status,
+            s.subscribed_at,
+            s.digest_frequency,
+            s.paused_until,
+            s.attributes,
+            ARRAY(
+                SELECT l.name
+                FROM t_list_memberships m
+                JOIN t_lists l ON l.list_id = m.list_id
+                WHERE m.subscriber_id = s.id
+                ORDER BY l.name
+            ) AS "lists!",
+            ARRAY(
+                SELECT t.tag
+                FROM t_subscriber_tags t
+                WHERE t.subscriber_id = s.id
+                ORDER BY t.tag
+            ) AS "tags!"
+        FROM t_subscriptions s
+        WHERE s.id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve a subscriber.")?;
+
+    Ok(subscriber)
+}
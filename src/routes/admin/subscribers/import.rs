@@ -0,0 +1,776 @@
+use actix_multipart::{Field, Multipart, MultipartError};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::{
+    authentication::CsrfToken,
+    configuration::SubscriberImportSettings,
+    csv_stream::{self, CsvRecords},
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+    mailing_lists::{self, ListSelectionError},
+    routes::generate_subscription_token,
+    utils,
+};
+
+const IMPORT_PAGE: &str = "/admin/subscribers/import";
+/// Rows are deduplicated against the database and inserted this many at a
+/// time, and rejected rows are stored for the report this many at a time.
+const BATCH_SIZE: usize = 1000;
+
+/// The fields of the upload form, which is read as a stream rather than
+/// deserialized. Only used to describe the request in the API documentation.
+#[allow(dead_code)]
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct ImportUpload {
+    /// A CSV file with a header row, with at least an `email` and a `name` column.
+    #[schema(content_media_type = "text/csv")]
+    file: String,
+    /// `confirmed` or `send_confirmation`.
+    mode: String,
+    #[serde(default, rename = "list_id")]
+    list_ids: Vec<Uuid>,
+}
+
+#[derive(thiserror::Error, Debug)]
+enum ImportError {
+    #[error("Choose a CSV file to import.")]
+    EmptyFile,
+    #[error("The file must have a header row with an `email` and a `name` column.")]
+    MissingColumns,
+    #[error("The file is larger than the limit of {0} bytes.")]
+    TooLarge(usize),
+    #[error("The upload could not be read.")]
+    InvalidUpload(#[source] MultipartError),
+    #[error("{0}")]
+    InvalidLists(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl From<ListSelectionError> for ImportError {
+    fn from(e: ListSelectionError) -> Self {
+        match e {
+            ListSelectionError::UnexpectedError(e) => ImportError::UnexpectedError(e),
+            e => ImportError::InvalidLists(e.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ImportMode {
+    /// Imported subscribers are confirmed straight away, e.g. when migrating
+    /// from another provider.
+    Confirmed,
+    /// Imported subscribers are sent a confirmation email in the background.
+    SendConfirmation,
+}
+
+impl TryFrom<&str> for ImportMode {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s {
+            "confirmed" => Ok(ImportMode::Confirmed),
+            "send_confirmation" => Ok(ImportMode::SendConfirmation),
+            s => Err(format!("{} is not a valid import mode.", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Rejection {
+    row_number: i32,
+    email: String,
+    name: String,
+    reason: String,
+}
+
+struct Candidate {
+    row_number: i32,
+    subscriber: NewSubscriber,
+}
+
+/// Validates the records of an uploaded file as they are parsed. The first
+/// record is the header; the other ones end up either as candidates for
+/// insertion or as rejections, which are taken out a batch at a time.
+#[derive(Default)]
+struct ImportRows {
+    columns: Option<(usize, usize)>,
+    row_number: i32,
+    /// The first row of every email address, to report duplicates. It is
+    /// bounded by the size limit of the file.
+    seen: HashMap<String, i32>,
+    candidates: Vec<Candidate>,
+    rejections: Vec<Rejection>,
+}
+
+impl ImportRows {
+    fn push(&mut self, record: Vec<String>) -> Result<(), ImportError> {
+        self.row_number += 1;
+        let Some((email_column, name_column)) = self.columns else {
+            self.columns = Some(find_columns(&record)?);
+            return Ok(());
+        };
+        if record.iter().all(|field| field.trim().is_empty()) {
+            return Ok(());
+        }
+
+        let field = |i: usize| record.get(i).map(|f| f.trim()).unwrap_or_default();
+        let (email, name) = (field(email_column), field(name_column));
+        let validated = SubscriberEmail::parse(email.to_owned()).and_then(|email| {
+            SubscriberName::parse(name.to_owned()).map(|name| NewSubscriber { email, name })
+        });
+        let reason = match validated {
+            Err(reason) => reason,
+            Ok(subscriber) => match self.seen.get(&email.to_lowercase()) {
+                Some(first_row) => format!("Duplicate of row {}.", first_row),
+                None => {
+                    self.seen.insert(email.to_lowercase(), self.row_number);
+                    self.candidates.push(Candidate {
+                        row_number: self.row_number,
+                        subscriber,
+                    });
+                    return Ok(());
+                }
+            },
+        };
+        self.rejections.push(Rejection {
+            row_number: self.row_number,
+            email: email.to_owned(),
+            name: name.to_owned(),
+            reason,
+        });
+        Ok(())
+    }
+}
+
+fn find_columns(header: &[String]) -> Result<(usize, usize), ImportError> {
+    let position = |column: &str| {
+        header.iter().position(|h| {
+            h.trim_start_matches('\u{feff}')
+                .trim()
+                .eq_ignore_ascii_case(column)
+        })
+    };
+    match (position("email"), position("name")) {
+        (Some(email), Some(name)) => Ok((email, name)),
+        _ => Err(ImportError::MissingColumns),
+    }
+}
+
+struct ImportSummary {
+    import_id: Uuid,
+    imported: i32,
+    rejected: i32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/subscribers/import",
+    tag = "admin",
+    summary = "Import form",
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The CSV upload form and the recent imports", content_type = "text/html", body = String),
+    )
+)]
+pub async fn import_subscribers_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let lists = mailing_lists::get_lists(&pool).await.map_err(utils::e500)?;
+    let imports = get_recent_imports(&pool).await.map_err(utils::e500)?;
+
+    let mut imports_html = String::new();
+    for i in imports {
+        let report_html = if i.rejected_count > 0 {
+            format!(
+                r#"<a href="{}/{}/rejected.csv">Download the report</a>"#,
+                IMPORT_PAGE, i.import_id
+            )
+        } else {
+            String::new()
+        };
+        writeln!(
+            imports_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            i.created_at.format("%Y-%m-%d %H:%M"),
+            i.imported_count,
+            i.rejected_count,
+            report_html
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("import.html"),
+            msg_html = msg_html,
+            lists_html = mailing_lists::checkboxes_html(&lists),
+            csrf_field = csrf_token.form_field(),
+            imports_html = imports_html,
+        )))
+}
+
+struct ImportRow {
+    import_id: Uuid,
+    imported_count: i32,
+    rejected_count: i32,
+    created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_recent_imports(pool: &PgPool) -> Result<Vec<ImportRow>, anyhow::Error> {
+    let imports = sqlx::query_as!(
+        ImportRow,
+        r#"
+        SELECT import_id, imported_count, rejected_count, created_at
+        FROM t_subscriber_imports
+        ORDER BY created_at DESC
+        LIMIT 10
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve recent imports.")?;
+
+    Ok(imports)
+}
+
+/// The rows of the file are imported as it is uploaded, so the mode and the
+/// lists have to come before it, as they do in the form. The CSRF token is
+/// the first field of all.
+#[utoipa::path(
+    post,
+    path = "/admin/subscribers/import",
+    tag = "admin",
+    summary = "Import subscribers",
+    request_body(content = ImportUpload, content_type = "multipart/form-data"),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the import form, with a summary"),
+    )
+)]
+#[tracing::instrument(name = "Import subscribers", skip(payload, pool, settings))]
+pub async fn import_subscribers(
+    mut payload: Multipart,
+    pool: web::Data<PgPool>,
+    settings: web::Data<SubscriberImportSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut mode = None;
+    let mut requested_list_ids = Vec::new();
+    let mut summary = None;
+
+    while let Some(mut field) = payload.try_next().await.map_err(utils::e400)? {
+        match field.name() {
+            Some("file") if summary.is_none() => {
+                let mode = mode.ok_or_else(|| {
+                    utils::e400("The import mode and the lists must be sent before the file.")
+                })?;
+                let imported = import_file(
+                    &mut field,
+                    &pool,
+                    mode,
+                    &requested_list_ids,
+                    settings.max_file_bytes,
+                )
+                .await;
+                match imported {
+                    Ok(s) => summary = Some(s),
+                    Err(ImportError::UnexpectedError(e)) => return Err(utils::e500(e)),
+                    Err(ImportError::InvalidUpload(e)) => return Err(utils::e400(e)),
+                    Err(ImportError::InvalidLists(e)) => return Err(utils::e400(e)),
+                    Err(e) => {
+                        FlashMessage::error(e.to_string()).send();
+                        return Ok(utils::see_other(IMPORT_PAGE));
+                    }
+                }
+            }
+            Some("mode") => {
+                let value = read_text(&mut field).await?;
+                mode = Some(ImportMode::try_from(value.as_str()).map_err(utils::e400)?);
+            }
+            Some("list_id") => {
+                let value = read_text(&mut field).await?;
+                requested_list_ids.push(Uuid::parse_str(&value).map_err(utils::e400)?);
+            }
+            _ => {}
+        }
+    }
+    if mode.is_none() {
+        return Err(utils::e400("An import mode must be selected."));
+    }
+    let Some(summary) = summary else {
+        FlashMessage::error(ImportError::EmptyFile.to_string()).send();
+        return Ok(utils::see_other(IMPORT_PAGE));
+    };
+
+    let imported_msg = match summary.imported {
+        1 => "Imported 1 subscriber.".to_owned(),
+        n => format!("Imported {} subscribers.", n),
+    };
+    if summary.rejected == 0 {
+        FlashMessage::info(imported_msg).send();
+    } else {
+        FlashMessage::info(format!(
+            "{} {} rows were rejected: their report is listed below.",
+            imported_msg, summary.rejected
+        ))
+        .send();
+    }
+    tracing::info!(
+        import_id = %summary.import_id,
+        imported = summary.imported,
+        rejected = summary.rejected,
+        "Subscribers imported"
+    );
+    Ok(utils::see_other(IMPORT_PAGE))
+}
+
+async fn read_text(field: &mut Field) -> Result<String, actix_web::Error> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = field.try_next().await.map_err(utils::e400)? {
+        bytes.extend_from_slice(&chunk);
+    }
+    String::from_utf8(bytes).map_err(utils::e400)
+}
+
+/// Imports the rows of the file as its chunks arrive, in a single transaction:
+/// the import is either stored whole or not at all.
+async fn import_file(
+    field: &mut Field,
+    pool: &PgPool,
+    mode: ImportMode,
+    requested_list_ids: &[Uuid],
+    max_file_bytes: usize,
+) -> Result<ImportSummary, ImportError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let list_ids = mailing_lists::resolve_list_ids(&mut transaction, requested_list_ids).await?;
+    let import_id = store_import(&mut transaction).await?;
+    let mut import = Import {
+        transaction,
+        mode,
+        list_ids,
+        summary: ImportSummary {
+            import_id,
+            imported: 0,
+            rejected: 0,
+        },
+        rows: ImportRows::default(),
+    };
+
+    let mut reader = CsvRecords::default();
+    let mut file_bytes = 0;
+    while let Some(chunk) = field.try_next().await.map_err(ImportError::InvalidUpload)? {
+        file_bytes += chunk.len();
+        if file_bytes > max_file_bytes {
+            return Err(ImportError::TooLarge(max_file_bytes));
+        }
+        for record in reader.feed(&chunk) {
+            import.push(record).await?;
+        }
+    }
+    for record in reader.finish() {
+        import.push(record).await?;
+    }
+    import.finish().await
+}
+
+/// An import in progress, which writes its rows a batch at a time.
+struct Import {
+    transaction: Transaction<'static, Postgres>,
+    mode: ImportMode,
+    list_ids: Vec<Uuid>,
+    summary: ImportSummary,
+    rows: ImportRows,
+}
+
+impl Import {
+    async fn push(&mut self, record: Vec<String>) -> Result<(), ImportError> {
+        self.rows.push(record)?;
+        if self.rows.candidates.len() >= BATCH_SIZE || self.rows.rejections.len() >= BATCH_SIZE {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), anyhow::Error> {
+        let candidates = std::mem::take(&mut self.rows.candidates);
+        if !candidates.is_empty() {
+            self.summary.imported += import_batch(
+                &mut self.transaction,
+                candidates,
+                self.mode,
+                &self.list_ids,
+                &mut self.rows.rejections,
+            )
+            .await?;
+        }
+        let rejections = std::mem::take(&mut self.rows.rejections);
+        for batch in rejections.chunks(BATCH_SIZE) {
+            store_rejections(&mut self.transaction, self.summary.import_id, batch).await?;
+        }
+        self.summary.rejected += rejections.len() as i32;
+        Ok(())
+    }
+
+    async fn finish(mut self) -> Result<ImportSummary, ImportError> {
+        if self.rows.columns.is_none() {
+            return Err(ImportError::EmptyFile);
+        }
+        self.flush().await?;
+        sqlx::query!(
+            r#"
+            UPDATE t_subscriber_imports
+            SET imported_count = $2, rejected_count = $3
+            WHERE import_id = $1
+            "#,
+            self.summary.import_id,
+            self.summary.imported,
+            self.summary.rejected
+        )
+        .execute(&mut *self.transaction)
+        .await
+        .context("Failed to store the counts of an import.")?;
+        self.transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to import subscribers.")?;
+        Ok(self.summary)
+    }
+}
+
+/// Inserts the candidates that are not subscribed yet and returns how many
+/// were inserted. The other ones are added to `rejections`.
+#[tracing::instrument(skip_all, fields(batch_size = batch.len()))]
+async fn import_batch(
+    transaction: &mut Transaction<'_, Postgres>,
+    batch: Vec<Candidate>,
+    mode: ImportMode,
+    list_ids: &[Uuid],
+    rejections: &mut Vec<Rejection>,
+) -> Result<i32, anyhow::Error> {
+    let lowercase_emails: Vec<String> = batch
+        .iter()
+        .map(|c| c.subscriber.email.as_ref().to_lowercase())
+        .collect();
+    let existing = sqlx::query_scalar!(
+        r#"
+        SELECT lower(email) AS "email!"
+        FROM t_subscriptions
+        WHERE lower(email) = ANY($1)
+        "#,
+        &lowercase_emails
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .context("Failed to look up existing subscribers.")?;
+
+    let (new, already_subscribed): (Vec<Candidate>, Vec<Candidate>) = batch
+        .into_iter()
+        .partition(|c| !existing.contains(&c.subscriber.email.as_ref().to_lowercase()));
+
+    let ids: Vec<Uuid> = new.iter().map(|_| Uuid::new_v4()).collect();
+    let emails: Vec<String> = new
+        .iter()
+        .map(|c| c.subscriber.email.as_ref().to_owned())
+        .collect();
+    let names: Vec<String> = new
+        .iter()
+        .map(|c| c.subscriber.name.as_ref().to_owned())
+        .collect();
+    let status = match mode {
+        ImportMode::Confirmed => "confirmed",
+        ImportMode::SendConfirmation => "pending_confirmation",
+    };
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO t_subscriptions (id, email, name, subscribed_at, status)
+        SELECT id, email, name, $4, $5
+        FROM unnest($1::uuid[], $2::text[], $3::text[]) AS t(id, email, name)
+        ON CONFLICT DO NOTHING
+        RETURNING id, email
+        "#,
+        &ids,
+        &emails,
+        &names,
+        Utc::now().naive_utc(),
+        status
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .context("Failed to insert imported subscribers.")?;
+
+    // rows inserted concurrently since the lookup above are caught by the conflict clause
+    let inserted_emails: Vec<&str> = inserted.iter().map(|r| r.email.as_str()).collect();
+    for candidate in already_subscribed.into_iter().chain(
+        new.into_iter()
+            .filter(|c| !inserted_emails.contains(&c.subscriber.email.as_ref())),
+    ) {
+        rejections.push(Rejection {
+            row_number: candidate.row_number,
+            email: candidate.subscriber.email.as_ref().to_owned(),
+            name: candidate.subscriber.name.as_ref().to_owned(),
+            reason: "Already subscribed.".into(),
+        });
+    }
+
+    let subscriber_ids: Vec<Uuid> = inserted.iter().map(|r| r.id).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO t_list_memberships (list_id, subscriber_id)
+        SELECT list_id, subscriber_id
+        FROM unnest($1::uuid[]) AS list_id
+        CROSS JOIN unnest($2::uuid[]) AS subscriber_id
+        "#,
+        list_ids,
+        &subscriber_ids
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to add imported subscribers to mailing lists.")?;
+
+    let preference_tokens: Vec<String> = subscriber_ids
+        .iter()
+        .map(|_| generate_subscription_token())
+        .collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO t_preference_tokens (preference_token, subscriber_id)
+        SELECT * FROM unnest($1::text[], $2::uuid[])
+        "#,
+        &preference_tokens,
+        &subscriber_ids
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to store preference tokens for imported subscribers.")?;
+
+    if mode == ImportMode::SendConfirmation {
+        let subscription_tokens: Vec<String> = subscriber_ids
+            .iter()
+            .map(|_| generate_subscription_token())
+            .collect();
+        sqlx::query!(
+            r#"
+            INSERT INTO t_subscription_tokens (subscription_token, subscriber_id)
+            SELECT * FROM unnest($1::text[], $2::uuid[])
+            "#,
+            &subscription_tokens,
+            &subscriber_ids
+        )
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to store confirmation tokens for imported subscribers.")?;
+        sqlx::query!(
+            r#"
+            INSERT INTO t_confirmation_email_queue (subscriber_id, subscription_token)
+            SELECT * FROM unnest($1::uuid[], $2::text[])
+            "#,
+            &subscriber_ids,
+            &subscription_tokens
+        )
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to enqueue confirmation emails for imported subscribers.")?;
+    }
+
+    Ok(subscriber_ids.len() as i32)
+}
+
+/// Stores an import as it starts, to attach its rejected rows to. The counts
+/// are filled in once the whole file has been read.
+#[tracing::instrument(skip_all)]
+async fn store_import(transaction: &mut Transaction<'_, Postgres>) -> Result<Uuid, anyhow::Error> {
+    let import_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO t_subscriber_imports (import_id, imported_count, rejected_count, created_at)
+        VALUES ($1, 0, 0, now())
+        "#,
+        import_id
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to store an import.")?;
+
+    Ok(import_id)
+}
+
+#[tracing::instrument(skip_all, fields(batch_size = batch.len()))]
+async fn store_rejections(
+    transaction: &mut Transaction<'_, Postgres>,
+    import_id: Uuid,
+    batch: &[Rejection],
+) -> Result<(), anyhow::Error> {
+    let row_numbers: Vec<i32> = batch.iter().map(|r| r.row_number).collect();
+    let emails: Vec<&str> = batch.iter().map(|r| r.email.as_str()).collect();
+    let names: Vec<&str> = batch.iter().map(|r| r.name.as_str()).collect();
+    let reasons: Vec<&str> = batch.iter().map(|r| r.reason.as_str()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO t_subscriber_import_rejections (import_id, row_number, email, name, reason)
+        SELECT $1, * FROM unnest($2::int[], $3::text[], $4::text[], $5::text[])
+        "#,
+        import_id,
+        &row_numbers,
+        &emails as &[&str],
+        &names as &[&str],
+        &reasons as &[&str]
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to store rejected import rows.")?;
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/subscribers/import/{import_id}/rejected.csv",
+    tag = "admin",
+    summary = "Rejected rows",
+    params(("import_id" = Uuid, Path, description = "Id of the import")),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The rows of an import that were rejected, with the reason", content_type = "text/csv"),
+    )
+)]
+#[tracing::instrument(name = "Download rejected import rows", skip(pool))]
+pub async fn download_import_rejections(
+    import_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let import_id = import_id.into_inner();
+    let exists = sqlx::query_scalar!(
+        "SELECT 1 FROM t_subscriber_imports WHERE import_id = $1",
+        import_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to perform a query to retrieve an import.")
+    .map_err(utils::e500)?;
+    if exists.is_none() {
+        return Err(utils::e404("The import does not exist."));
+    }
+
+    let rejections = sqlx::query_as!(
+        Rejection,
+        r#"
+        SELECT row_number, email, name, reason
+        FROM t_subscriber_import_rejections
+        WHERE import_id = $1
+        ORDER BY row_number
+        "#,
+        import_id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to perform a query to retrieve rejected import rows.")
+    .map_err(utils::e500)?;
+
+    let mut csv = String::new();
+    csv_stream::write_record(&mut csv, &["row", "email", "name", "reason"]);
+    for r in rejections {
+        csv_stream::write_record(
+            &mut csv,
+            &[&r.row_number.to_string(), &r.email, &r.name, &r.reason],
+        );
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"import-{}-rejected.csv\"", import_id),
+        ))
+        .body(csv))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImportError, ImportRows};
+
+    fn push_all(rows: &mut ImportRows, records: &[&[&str]]) -> Result<(), ImportError> {
+        for record in records {
+            rows.push(record.iter().map(|f| f.to_string()).collect())?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn columns_are_found_by_name_in_any_order() {
+        let mut rows = ImportRows::default();
+        push_all(
+            &mut rows,
+            &[
+                &["\u{feff}Name", "company", " EMAIL "],
+                &["Ursula", "Earthsea", "ursula@example.com"],
+            ],
+        )
+        .unwrap();
+        assert_eq!(rows.columns, Some((2, 0)));
+        assert_eq!(rows.candidates.len(), 1);
+        assert_eq!(
+            rows.candidates[0].subscriber.email.as_ref(),
+            "ursula@example.com"
+        );
+    }
+
+    #[test]
+    fn a_header_without_email_or_name_is_rejected() {
+        let mut rows = ImportRows::default();
+        assert!(matches!(
+            push_all(&mut rows, &[&["email", "full name"]]),
+            Err(ImportError::MissingColumns)
+        ));
+    }
+
+    #[test]
+    fn invalid_and_duplicate_rows_are_rejected_with_a_reason() {
+        let mut rows = ImportRows::default();
+        push_all(
+            &mut rows,
+            &[
+                &["email", "name"],
+                &["ursula@example.com", "Ursula"],
+                &["not-an-email", "Ged"],
+                &[],
+                &["URSULA@example.com", "Ursula again"],
+                &["tenar@example.com", ""],
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(rows.candidates.len(), 1);
+        let reasons: Vec<(i32, &str)> = rows
+            .rejections
+            .iter()
+            .map(|r| (r.row_number, r.reason.as_str()))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (3, "not-an-email is not a valid email address."),
+                (5, "Duplicate of row 2."),
+                (6, " is not a valid subscruber name."),
+            ]
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use std::fmt::Write;
+
+use crate::{domain::SUBSCRIPTION_STATUSES, utils};
+
+const PAGE_SIZE: i64 = 25;
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueryParams {
+    #[serde(default)]
+    q: String,
+    #[serde(default)]
+    status: String,
+    page: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/subscribers",
+    operation_id = "admin_list_subscribers",
+    tag = "admin",
+    summary = "Subscribers",
+    params(QueryParams),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "A page of subscribers matching the search", content_type = "text/html", body = String),
+    )
+)]
+pub async fn list_subscribers(
+    query: web::Query<QueryParams>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let search = query.q.trim();
+    let status = match query.status.as_str() {
+        "" => None,
+        s if SUBSCRIPTION_STATUSES.contains(&s) => Some(s),
+        s => return Err(utils::e400(format!("{} is not a valid status.", s))),
+    };
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let filter = SubscriberFilter {
+        pattern: (!search.is_empty()).then(|| like_pattern(search)),
+        status: status.map(str::to_owned),
+    };
+    let total = count_subscribers(&filter, &pool)
+        .await
+        .map_err(utils::e500)?;
+    let page_count = ((total + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    // Pages past the last one show the last one, which also keeps the
+    // offset from overflowing.
+    let page = query.page.unwrap_or(1).clamp(1, page_count);
+    let subscribers = get_subscribers(&filter, page, &pool)
+        .await
+        .map_err(utils::e500)?;
+
+    let mut subscribers_html = String::new();
+    for s in subscribers {
+        writeln!(
+            subscribers_html,
+            r#"<tr><td><a href="/admin/subscribers/{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            s.id,
+            htmlescape::encode_minimal(&s.email),
+            htmlescape::encode_minimal(&s.name),
+            s.status,
+            s.subscribed_at.format("%Y-%m-%d %H:%M"),
+        )
+        .unwrap();
+    }
+
+    let mut status_html = String::new();
+    for s in SUBSCRIPTION_STATUSES {
+        writeln!(
+            status_html,
+            r#"<option value="{0}"{1}>{0}</option>"#,
+            s,
+            if Some(s) == status { " selected" } else { "" }
+        )
+        .unwrap();
+    }
+
+    let page_link = |page: i64| {
+        htmlescape::encode_minimal(&format!(
+            "/admin/subscribers?q={}&status={}&page={}",
+            urlencoding::encode(search),
+            status.unwrap_or_default(),
+            page
+        ))
+    };
+    let mut pagination_html = String::new();
+    if page > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="{}">&lt; Previous</a> "#,
+            page_link(page - 1)
+        )
+        .unwrap();
+    }
+    write!(
+        pagination_html,
+        "Page {} of {} ({} subscribers)",
+        page, page_count, total
+    )
+    .unwrap();
+    if page < page_count {
+        write!(
+            pagination_html,
+            r#" <a href="{}">Next &gt;</a>"#,
+            page_link(page + 1)
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("list.html"),
+            msg_html = msg_html,
+            q = htmlescape::encode_minimal(search),
+            status_html = status_html,
+            subscribers_html = subscribers_html,
+            pagination_html = pagination_html,
+        )))
+}
+
+/// Matches `search` anywhere, treating LIKE wildcards in it literally.
+fn like_pattern(search: &str) -> String {
+    let escaped = search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+#[derive(Debug)]
+struct SubscriberFilter {
+    pattern: Option<String>,
+    status: Option<String>,
+}
+
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: NaiveDateTime,
+}
+
+#[tracing::instrument(name = "Count subscribers", skip(pool))]
+async fn count_subscribers(filter: &SubscriberFilter, pool: &PgPool) -> Result<i64, anyhow::Error> {
+    let count = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM t_subscriptions
+        WHERE ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1)
+            AND ($2::text IS NULL OR status = $2)
+        "#,
+        filter.pattern,
+        filter.status
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to count subscribers.")?;
+
+    Ok(count)
+}
+
+#[tracing::instrument(name = "Get subscribers", skip(pool))]
+async fn get_subscribers(
+    filter: &SubscriberFilter,
+    page: i64,
+    pool: &PgPool,
+) -> Result<Vec<SubscriberRow>, anyhow::Error> {
+    let subscribers = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM t_subscriptions
+        WHERE ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1)
+            AND ($2::text IS NULL OR status = $2)
+        ORDER BY subscribed_at DESC, email
+        LIMIT $3
+        OFFSET $4
+        "#,
+        filter.pattern,
+        filter.status,
+        PAGE_SIZE,
+        (page - 1) * PAGE_SIZE
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve subscribers.")?;
+
+    Ok(subscribers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::like_pattern;
+
+    #[test]
+    fn like_wildcards_are_escaped() {
+        assert_eq!(like_pattern("ursula"), "%ursula%");
+        assert_eq!(like_pattern("100%_\\"), "%100\\%\\_\\\\%");
+    }
+}
@@ -0,0 +1,12 @@
+pub(crate) mod actions;
+pub(crate) mod detail;
+pub(crate) mod import;
+pub(crate) mod list;
+
+pub use actions::{
+    confirm_subscriber, delete_subscriber, erase_subscriber, export_subscriber_data,
+    resend_confirmation, unsubscribe_subscriber,
+};
+pub use detail::subscriber_details;
+pub use import::{download_import_rejections, import_subscribers, import_subscribers_form};
+pub use list::list_subscribers;
@@ -0,0 +1,201 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use std::fmt::Write;
+
+use crate::{authentication::CsrfToken, domain::SubscriberTag, utils};
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TagAction {
+    Add,
+    Remove,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[schema(as = TagFormData)]
+pub struct FormData {
+    email: String,
+    tags: String,
+    action: TagAction,
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/tags",
+    tag = "admin",
+    summary = "Tags",
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The tags in use", content_type = "text/html", body = String),
+    )
+)]
+pub async fn list_tags(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut tags_html = String::new();
+    for tag in get_tag_counts(&pool).await.map_err(utils::e500)? {
+        writeln!(
+            tags_html,
+            "<tr><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&tag.tag),
+            tag.subscribers,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("tags.html"),
+            msg_html = msg_html,
+            tags_html = tags_html,
+            csrf_field = csrf_token.form_field(),
+        )))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/tags",
+    tag = "admin",
+    summary = "Tag a subscriber",
+    request_body(content = FormData, content_type = "application/x-www-form-urlencoded"),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the tags, with a flash message"),
+    )
+)]
+#[tracing::instrument(name = "Update subscriber tags", skip(form, pool))]
+pub async fn update_subscriber_tags(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData {
+        email,
+        tags,
+        action,
+    } = form.0;
+    let tags = match tags
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|t| !t.is_empty())
+        .map(|t| SubscriberTag::parse(t.to_owned()))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(tags) if !tags.is_empty() => tags,
+        Ok(_) => {
+            FlashMessage::error("Enter at least one tag.").send();
+            return Ok(utils::see_other("/admin/tags"));
+        }
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(utils::see_other("/admin/tags"));
+        }
+    };
+    let tags: Vec<String> = tags.iter().map(|t| t.as_ref().to_owned()).collect();
+
+    let email = email.trim();
+    let Some(subscriber_id) = get_subscriber_id(email, &pool).await.map_err(utils::e500)? else {
+        FlashMessage::error(format!(
+            "No subscriber with the email address '{}'.",
+            htmlescape::encode_minimal(email)
+        ))
+        .send();
+        return Ok(utils::see_other("/admin/tags"));
+    };
+    match action {
+        TagAction::Add => add_tags(subscriber_id, &tags, &pool).await,
+        TagAction::Remove => remove_tags(subscriber_id, &tags, &pool).await,
+    }
+    .map_err(utils::e500)?;
+
+    FlashMessage::info(format!(
+        "The tags of '{}' have been updated.",
+        htmlescape::encode_minimal(email)
+    ))
+    .send();
+    Ok(utils::see_other("/admin/tags"))
+}
+
+struct TagCount {
+    tag: String,
+    subscribers: i64,
+}
+
+#[tracing::instrument(name = "Get tag counts", skip(pool))]
+async fn get_tag_counts(pool: &PgPool) -> Result<Vec<TagCount>, anyhow::Error> {
+    let tags = sqlx::query_as!(
+        TagCount,
+        r#"
+        SELECT tag, COUNT(*) AS "subscribers!"
+        FROM t_subscriber_tags
+        GROUP BY tag
+        ORDER BY tag
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve tags.")?;
+
+    Ok(tags)
+}
+
+#[tracing::instrument(name = "Get subscriber id from email", skip(pool))]
+async fn get_subscriber_id(email: &str, pool: &PgPool) -> Result<Option<Uuid>, anyhow::Error> {
+    let subscriber_id =
+        sqlx::query_scalar!("SELECT id FROM t_subscriptions WHERE email = $1", email)
+            .fetch_optional(pool)
+            .await
+            .context("Failed to perform a query to retrieve a subscriber.")?;
+
+    Ok(subscriber_id)
+}
+
+#[tracing::instrument(name = "Add subscriber tags", skip(pool))]
+async fn add_tags(
+    subscriber_id: Uuid,
+    tags: &[String],
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO t_subscriber_tags (subscriber_id, tag)
+        SELECT $1, tag FROM unnest($2::text[]) AS tag
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        tags
+    )
+    .execute(pool)
+    .await
+    .context("Failed to add subscriber tags.")?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Remove subscriber tags", skip(pool))]
+async fn remove_tags(
+    subscriber_id: Uuid,
+    tags: &[String],
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM t_subscriber_tags WHERE subscriber_id = $1 AND tag = ANY($2)",
+        subscriber_id,
+        tags
+    )
+    .execute(pool)
+    .await
+    .context("Failed to remove subscriber tags.")?;
+
+    Ok(())
+}
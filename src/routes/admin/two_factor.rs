@@ -0,0 +1,242 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use std::fmt::Write;
+
+use crate::{
+    authentication::{
+        confirm_enrolment, disable_two_factor, get_two_factor_status, replace_recovery_codes,
+        start_enrolment, verify_second_factor, CsrfToken, UserId,
+    },
+    routes::admin::dashboard::get_username,
+    utils,
+};
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct CodeFormData {
+    #[schema(value_type = String, format = Password)]
+    code: Secret<String>,
+}
+
+fn code_form(action: &str, label: &str, csrf_token: &CsrfToken) -> String {
+    format!(
+        r#"<form action="/admin/2fa/{}" method="post">
+        {}
+        <label>Code from your authenticator app
+            <input type="text" name="code" autocomplete="one-time-code" required>
+        </label>
+        <button type="submit">{}</button>
+    </form>"#,
+        action,
+        csrf_token.form_field(),
+        label
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/2fa",
+    tag = "admin",
+    summary = "Two-factor settings",
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "Whether two-factor authentication is on, with the setup QR code while it is being set up", content_type = "text/html", body = String),
+    )
+)]
+pub async fn two_factor_settings(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let status = get_two_factor_status(pool.get_ref(), *user_id)
+        .await
+        .map_err(utils::e500)?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let content_html = if status.enabled {
+        format!(
+            r#"<p>Two-factor authentication is on. You have {} unused recovery codes.</p>
+    <h2>New recovery codes</h2>
+    <p>This replaces every recovery code you have.</p>
+    {}
+    <h2>Turn off</h2>
+    {}"#,
+            status.unused_recovery_codes,
+            code_form("recovery_codes", "Generate new recovery codes", &csrf_token),
+            code_form("disable", "Turn off two-factor authentication", &csrf_token),
+        )
+    } else if let Some(secret) = status.pending_secret {
+        let username = get_username(*user_id, &pool).await.map_err(utils::e500)?;
+        format!(
+            r#"<p>Add this account to your authenticator app, then enter the code it shows.</p>
+    <p>Setup key: <code>{}</code></p>
+    <p><a href="{}">Open in an authenticator app</a></p>
+    {}"#,
+            secret.to_base32(),
+            htmlescape::encode_attribute(&secret.provisioning_uri(&username)),
+            code_form("confirm", "Turn on", &csrf_token),
+        )
+    } else {
+        format!(
+            r#"<p>Two-factor authentication is off. When it is on, logging in also requires a code from an authenticator app.</p>
+    <form action="/admin/2fa/setup" method="post">
+        {}
+        <button type="submit">Set up two-factor authentication</button>
+    </form>"#,
+            csrf_token.form_field()
+        )
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("two_factor.html"),
+            msg_html = msg_html,
+            content_html = content_html
+        )))
+}
+
+/// Generates a new secret. It is only required at login once a code from it
+/// has been confirmed.
+#[utoipa::path(
+    post,
+    path = "/admin/2fa/setup",
+    tag = "admin",
+    summary = "Start setting up two-factor authentication",
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "To the settings, which show the new secret"),
+    )
+)]
+pub async fn set_up_two_factor(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    start_enrolment(&pool, **user_id)
+        .await
+        .map_err(utils::e500)?;
+    Ok(utils::see_other("/admin/2fa"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/2fa/confirm",
+    tag = "admin",
+    summary = "Turn on two-factor authentication",
+    request_body(content = CodeFormData, content_type = "application/x-www-form-urlencoded"),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The recovery codes, shown once", content_type = "text/html", body = String),
+        (status = 303, description = "Back to the settings when the code is wrong"),
+    )
+)]
+pub async fn confirm_two_factor(
+    form: web::Form<CodeFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match confirm_enrolment(&pool, **user_id, form.code.expose_secret())
+        .await
+        .map_err(utils::e500)?
+    {
+        Some(recovery_codes) => Ok(recovery_codes_page(&recovery_codes)),
+        None => {
+            FlashMessage::error("The code is incorrect.").send();
+            Ok(utils::see_other("/admin/2fa"))
+        }
+    }
+}
+
+/// Requires a current code, so that a session left open is not enough to
+/// remove the second factor.
+#[utoipa::path(
+    post,
+    path = "/admin/2fa/disable",
+    tag = "admin",
+    summary = "Turn off two-factor authentication",
+    request_body(content = CodeFormData, content_type = "application/x-www-form-urlencoded"),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the settings"),
+    )
+)]
+pub async fn turn_off_two_factor(
+    form: web::Form<CodeFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !verify_second_factor(&pool, **user_id, form.code.expose_secret())
+        .await
+        .map_err(utils::e500)?
+    {
+        FlashMessage::error("The code is incorrect.").send();
+        return Ok(utils::see_other("/admin/2fa"));
+    }
+    disable_two_factor(&pool, **user_id)
+        .await
+        .map_err(utils::e500)?;
+    FlashMessage::info("Two-factor authentication has been turned off.").send();
+    Ok(utils::see_other("/admin/2fa"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/2fa/recovery_codes",
+    tag = "admin",
+    summary = "Regenerate recovery codes",
+    request_body(content = CodeFormData, content_type = "application/x-www-form-urlencoded"),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The new recovery codes, shown once", content_type = "text/html", body = String),
+        (status = 303, description = "Back to the settings when the code is wrong"),
+    )
+)]
+pub async fn regenerate_recovery_codes(
+    form: web::Form<CodeFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !verify_second_factor(&pool, **user_id, form.code.expose_secret())
+        .await
+        .map_err(utils::e500)?
+    {
+        FlashMessage::error("The code is incorrect.").send();
+        return Ok(utils::see_other("/admin/2fa"));
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(utils::e500)?;
+    let recovery_codes = replace_recovery_codes(&mut transaction, **user_id)
+        .await
+        .map_err(utils::e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to replace recovery codes.")
+        .map_err(utils::e500)?;
+    Ok(recovery_codes_page(&recovery_codes))
+}
+
+fn recovery_codes_page(recovery_codes: &[String]) -> HttpResponse {
+    let mut codes_html = String::new();
+    for code in recovery_codes {
+        writeln!(codes_html, "<li><code>{}</code></li>", code).unwrap();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("recovery_codes.html"),
+            codes_html = codes_html
+        ))
+}
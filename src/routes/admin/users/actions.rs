@@ -0,0 +1,490 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Duration;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::{issue_user_token, Role, UserId, UserTokenPurpose},
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    startup::ApplicationBaseUrl,
+    utils,
+};
+
+/// How long an invitation link can be used to set a password.
+const INVITATION_VALIDITY: Duration = Duration::days(7);
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct InviteFormData {
+    username: String,
+    email: String,
+    role: Role,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct RoleFormData {
+    role: Role,
+}
+
+struct User {
+    username: String,
+    email: Option<String>,
+    status: String,
+}
+
+/// Usernames are typed at login, so they are kept to a single visible word.
+fn parse_username(username: &str) -> Result<String, &'static str> {
+    let username = username.trim();
+    if username.is_empty() {
+        return Err("The username cannot be empty.");
+    }
+    if username.chars().count() > 64 {
+        return Err("The username cannot be longer than 64 characters.");
+    }
+    if username
+        .chars()
+        .any(|c| c.is_whitespace() || c.is_control())
+    {
+        return Err("The username cannot contain spaces.");
+    }
+    Ok(username.to_owned())
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users",
+    tag = "admin",
+    summary = "Invite a user",
+    request_body(content = InviteFormData, content_type = "application/x-www-form-urlencoded"),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the users, with a flash message"),
+    )
+)]
+#[tracing::instrument(
+    name = "Invite a user",
+    skip(form, pool, email_client, base_url),
+    fields(username = %form.username)
+)]
+pub async fn invite_user(
+    form: web::Form<InviteFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let username = match parse_username(&form.username) {
+        Ok(username) => username,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(utils::see_other("/admin/users"));
+        }
+    };
+    let email = match SubscriberEmail::parse(form.email.trim().to_owned()) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(utils::see_other("/admin/users"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(utils::e500)?;
+    let user_id = Uuid::new_v4();
+    // Conflicts on the username, or on the email ignoring case, insert nothing.
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO t_users (user_id, username, email, status, role)
+        VALUES ($1, $2, $3, 'invited', $4)
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        username,
+        email.as_ref(),
+        form.role.as_str()
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store an invited user.")
+    .map_err(utils::e500)?
+    .rows_affected();
+    if inserted == 0 {
+        FlashMessage::error("A user with this username or email already exists.").send();
+        return Ok(utils::see_other("/admin/users"));
+    }
+    let token = issue_user_token(
+        &mut transaction,
+        user_id,
+        UserTokenPurpose::Invitation,
+        INVITATION_VALIDITY,
+    )
+    .await
+    .map_err(utils::e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to invite a user.")
+        .map_err(utils::e500)?;
+
+    send_invitation_email(&email_client, &email, &username, &base_url, &token)
+        .await
+        .context("Failed to send an invitation email.")
+        .map_err(utils::e500)?;
+
+    FlashMessage::info(format!(
+        "An invitation has been sent to {}.",
+        htmlescape::encode_minimal(email.as_ref())
+    ))
+    .send();
+    Ok(utils::see_other("/admin/users"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_id}/resend_invitation",
+    tag = "admin",
+    summary = "Resend an invitation",
+    params(("user_id" = Uuid, Path, description = "Id of the user")),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the users"),
+    )
+)]
+#[tracing::instrument(name = "Resend an invitation", skip(pool, email_client, base_url))]
+pub async fn resend_invitation(
+    user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let user = get_user(user_id, &pool).await?;
+    let email = match (user.status.as_str(), user.email) {
+        ("invited", Some(email)) => SubscriberEmail::parse(email).map_err(utils::e500)?,
+        _ => {
+            FlashMessage::error(
+                "Only users who have not accepted their invitation yet can be sent a new one.",
+            )
+            .send();
+            return Ok(utils::see_other("/admin/users"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(utils::e500)?;
+    let token = issue_user_token(
+        &mut transaction,
+        user_id,
+        UserTokenPurpose::Invitation,
+        INVITATION_VALIDITY,
+    )
+    .await
+    .map_err(utils::e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store an invitation.")
+        .map_err(utils::e500)?;
+
+    send_invitation_email(&email_client, &email, &user.username, &base_url, &token)
+        .await
+        .context("Failed to send an invitation email.")
+        .map_err(utils::e500)?;
+
+    FlashMessage::info(format!(
+        "A new invitation has been sent to {}.",
+        htmlescape::encode_minimal(email.as_ref())
+    ))
+    .send();
+    Ok(utils::see_other("/admin/users"))
+}
+
+async fn send_invitation_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    username: &str,
+    base_url: &ApplicationBaseUrl,
+    token: &str,
+) -> Result<(), reqwest::Error> {
+    let link = format!("{}/users/set_password?token={}", base_url.0, token);
+    email_client
+        .send_email(
+            email,
+            "You have been invited to manage the newsletter",
+            &format!(
+                r#"<p>You have been invited to manage the newsletter as <b>{}</b>.</p>
+            <p>Click <a href="{}">here</a> to choose your password. The link is valid for 7 days.</p>"#,
+                htmlescape::encode_minimal(username),
+                link
+            ),
+            &format!(
+                "You have been invited to manage the newsletter as {}.\nVisit {} to choose your password. The link is valid for 7 days.",
+                username, link
+            ),
+        )
+        .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_id}/disable",
+    tag = "admin",
+    summary = "Disable a user",
+    params(("user_id" = Uuid, Path, description = "Id of the user")),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the users"),
+    )
+)]
+#[tracing::instrument(name = "Disable a user", skip(pool))]
+pub async fn disable_user(
+    user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let user = get_user(user_id, &pool).await?;
+    if user.status != "active" {
+        FlashMessage::error("Only active users can be disabled.").send();
+        return Ok(utils::see_other("/admin/users"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(utils::e500)?;
+    if is_last_active_owner(&mut transaction, user_id)
+        .await
+        .map_err(utils::e500)?
+    {
+        FlashMessage::error("The last active owner cannot be disabled.").send();
+        return Ok(utils::see_other("/admin/users"));
+    }
+    sqlx::query!(
+        "UPDATE t_users SET status = 'disabled' WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to disable a user.")
+    .map_err(utils::e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to disable a user.")
+        .map_err(utils::e500)?;
+
+    FlashMessage::info(format!(
+        "{} has been disabled.",
+        htmlescape::encode_minimal(&user.username)
+    ))
+    .send();
+    Ok(utils::see_other("/admin/users"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_id}/enable",
+    tag = "admin",
+    summary = "Enable a user",
+    params(("user_id" = Uuid, Path, description = "Id of the user")),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the users"),
+    )
+)]
+#[tracing::instrument(name = "Enable a user", skip(pool))]
+pub async fn enable_user(
+    user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let user = get_user(user_id, &pool).await?;
+    if user.status != "disabled" {
+        FlashMessage::error("Only disabled users can be enabled.").send();
+        return Ok(utils::see_other("/admin/users"));
+    }
+
+    sqlx::query!(
+        "UPDATE t_users SET status = 'active' WHERE user_id = $1",
+        user_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to enable a user.")
+    .map_err(utils::e500)?;
+
+    FlashMessage::info(format!(
+        "{} has been enabled.",
+        htmlescape::encode_minimal(&user.username)
+    ))
+    .send();
+    Ok(utils::see_other("/admin/users"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_id}/delete",
+    tag = "admin",
+    summary = "Delete a user",
+    params(("user_id" = Uuid, Path, description = "Id of the user")),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the users"),
+    )
+)]
+#[tracing::instrument(name = "Delete a user", skip(pool, current_user_id))]
+pub async fn delete_user(
+    user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    current_user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let user = get_user(user_id, &pool).await?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(utils::e500)?;
+    if is_last_active_owner(&mut transaction, user_id)
+        .await
+        .map_err(utils::e500)?
+    {
+        FlashMessage::error("The last active owner cannot be deleted.").send();
+        return Ok(utils::see_other("/admin/users"));
+    }
+    sqlx::query!("DELETE FROM t_users WHERE user_id = $1", user_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete a user.")
+        .map_err(utils::e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a user.")
+        .map_err(utils::e500)?;
+
+    if **current_user_id == user_id {
+        return Ok(utils::see_other("/login"));
+    }
+    FlashMessage::info(format!(
+        "{} has been deleted.",
+        htmlescape::encode_minimal(&user.username)
+    ))
+    .send();
+    Ok(utils::see_other("/admin/users"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_id}/role",
+    tag = "admin",
+    summary = "Change the role of a user",
+    params(("user_id" = Uuid, Path, description = "Id of the user")),
+    request_body(content = RoleFormData, content_type = "application/x-www-form-urlencoded"),
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 303, description = "Back to the users"),
+    )
+)]
+#[tracing::instrument(name = "Change the role of a user", skip(form, pool))]
+pub async fn change_user_role(
+    user_id: web::Path<Uuid>,
+    form: web::Form<RoleFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let user = get_user(user_id, &pool).await?;
+    let role = form.0.role;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(utils::e500)?;
+    if role != Role::Owner
+        && is_last_active_owner(&mut transaction, user_id)
+            .await
+            .map_err(utils::e500)?
+    {
+        FlashMessage::error("The last active owner must remain an owner.").send();
+        return Ok(utils::see_other("/admin/users"));
+    }
+    sqlx::query!(
+        "UPDATE t_users SET role = $1 WHERE user_id = $2",
+        role.as_str(),
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to change the role of a user.")
+    .map_err(utils::e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change the role of a user.")
+        .map_err(utils::e500)?;
+
+    FlashMessage::info(format!(
+        "The role of {} is now {}.",
+        htmlescape::encode_minimal(&user.username),
+        role.as_str()
+    ))
+    .send();
+    Ok(utils::see_other("/admin/users"))
+}
+
+/// Locks the active owners, so that two owners cannot disable or demote each
+/// other at the same time, and tells whether `user_id` is the only one left.
+async fn is_last_active_owner(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let active_owners = sqlx::query_scalar!(
+        "SELECT user_id FROM t_users WHERE status = 'active' AND role = 'owner' FOR UPDATE"
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .context("Failed to lock the active owners.")?;
+    Ok(active_owners == [user_id])
+}
+
+async fn get_user(user_id: Uuid, pool: &PgPool) -> Result<User, actix_web::Error> {
+    sqlx::query_as!(
+        User,
+        "SELECT username, email, status FROM t_users WHERE user_id = $1",
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve a user.")
+    .map_err(utils::e500)?
+    .ok_or_else(|| utils::e404("The user does not exist."))
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok_eq};
+
+    use super::parse_username;
+
+    #[test]
+    fn usernames_are_trimmed() {
+        assert_ok_eq!(parse_username("  ged "), "ged".to_owned());
+    }
+
+    #[test]
+    fn empty_long_or_spaced_usernames_are_rejected() {
+        assert_err!(parse_username("   "));
+        assert_err!(parse_username(&"a".repeat(65)));
+        assert_err!(parse_username("ged sparrowhawk"));
+    }
+}
@@ -0,0 +1,123 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use std::fmt::Write;
+
+use crate::{
+    authentication::{CsrfToken, Role, UserId},
+    utils,
+};
+
+struct User {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    status: String,
+    role: String,
+    created_at: DateTime<Utc>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/users",
+    tag = "admin",
+    summary = "Users",
+    security(("session_cookie" = []), ("api_token" = [])),
+    responses(
+        (status = 200, description = "The users and pending invitations", content_type = "text/html", body = String),
+    )
+)]
+pub async fn list_users(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let current_user_id = *user_id.into_inner();
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let csrf_field = csrf_token.form_field();
+    let mut users_html = String::new();
+    for user in get_users(&pool).await.map_err(utils::e500)? {
+        let action = |action: &str, label: &str| {
+            format!(
+                r#"<form action="/admin/users/{}/{}" method="post">{}<button type="submit">{}</button></form>"#,
+                user.user_id, action, csrf_field, label
+            )
+        };
+        let mut actions_html = match user.status.as_str() {
+            "active" => action("disable", "Disable"),
+            "disabled" => action("enable", "Enable"),
+            _ => action("resend_invitation", "Resend invitation"),
+        };
+        actions_html.push_str(&action("delete", "Delete"));
+
+        let mut role_options = String::new();
+        for role in Role::ALL {
+            write!(
+                role_options,
+                r#"<option value="{0}"{1}>{0}</option>"#,
+                role.as_str(),
+                if role.as_str() == user.role {
+                    " selected"
+                } else {
+                    ""
+                }
+            )
+            .unwrap();
+        }
+        let role_html = format!(
+            r#"<form action="/admin/users/{}/role" method="post">{}<select name="role">{}</select><button type="submit">Change role</button></form>"#,
+            user.user_id, csrf_field, role_options
+        );
+
+        writeln!(
+            users_html,
+            "<tr><td>{}{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&user.username),
+            if user.user_id == current_user_id {
+                " (you)"
+            } else {
+                ""
+            },
+            htmlescape::encode_minimal(user.email.as_deref().unwrap_or("-")),
+            role_html,
+            user.status,
+            user.created_at.format("%Y-%m-%d %H:%M"),
+            actions_html,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("users.html"),
+            msg_html = msg_html,
+            users_html = users_html,
+            csrf_field = csrf_field,
+        )))
+}
+
+#[tracing::instrument(name = "Get users", skip(pool))]
+async fn get_users(pool: &PgPool) -> Result<Vec<User>, anyhow::Error> {
+    sqlx::query_as!(
+        User,
+        r#"
+        SELECT user_id, username, email, status, role, created_at
+        FROM t_users
+        ORDER BY username
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve users.")
+}
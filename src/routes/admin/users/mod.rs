@@ -0,0 +1,7 @@
+pub(crate) mod actions;
+pub(crate) mod list;
+
+pub use actions::{
+    change_user_role, delete_user, disable_user, enable_user, invite_user, resend_invitation,
+};
+pub use list::list_users;
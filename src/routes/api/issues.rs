@@ -0,0 +1,355 @@
+use actix_web::{http::header::LOCATION, web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    api_error::{ApiError, Problem},
+    authentication::UserId,
+    idempotency::NextAction,
+    mailing_lists,
+    routes::admin::{
+        enqueue_delivery_tasks, get_draft, insert_newsletter_issue, remove_published_draft,
+        store_draft,
+    },
+    segment::Segment,
+};
+
+/// An issue is created as a draft, and keeps its id once published.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct NewIssueData {
+    title: String,
+    plaintext: String,
+    html: String,
+    #[serde(default)]
+    track_clicks: bool,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct PublishData {
+    #[serde(default)]
+    list_ids: Vec<Uuid>,
+    #[serde(default)]
+    segment: String,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct Issue {
+    id: Uuid,
+    title: String,
+    /// `draft` or `published`.
+    status: &'static str,
+    published_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deliveries: Option<DeliveryStats>,
+}
+
+/// Where the delivery of a published issue stands, and how it was received.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct DeliveryStats {
+    queued: i64,
+    delivered: i64,
+    failed: i64,
+    invalid_address: i64,
+    unique_opens: i64,
+    clicks: i64,
+    unique_clicks: i64,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct IssueList {
+    issues: Vec<Issue>,
+}
+
+fn issue_location(issue_id: Uuid) -> String {
+    format!("/api/v1/issues/{}", issue_id)
+}
+
+/// Drafts first, then published issues, most recent first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/issues",
+    tag = "api",
+    summary = "List issues",
+    security(("api_token" = [])),
+    responses(
+        (status = 200, description = "Drafts first, then published issues, most recent first", body = IssueList),
+        (status = 401, description = "The API token is missing or invalid", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "The token lacks the scope, or its user the role", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "List issues through the API", skip(pool))]
+pub async fn list_issues(pool: web::Data<PgPool>) -> Result<HttpResponse, ApiError> {
+    let issues: Vec<Issue> = sqlx::query!(
+        r#"
+        SELECT id AS "id!", title AS "title!", published_at
+        FROM (
+            SELECT draft_id AS id, title, NULL::timestamptz AS published_at, updated_at
+            FROM t_newsletter_drafts
+            UNION ALL
+            SELECT newsletter_issue_id, title, published_at, published_at
+            FROM t_newsletter_issues
+        ) issues
+        ORDER BY published_at IS NOT NULL, updated_at DESC
+        "#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to perform a query to retrieve newsletter issues.")?
+    .into_iter()
+    .map(|r| Issue {
+        id: r.id,
+        title: r.title,
+        status: if r.published_at.is_some() {
+            "published"
+        } else {
+            "draft"
+        },
+        published_at: r.published_at,
+        deliveries: None,
+    })
+    .collect();
+
+    Ok(HttpResponse::Ok().json(IssueList { issues }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/issues/{issue_id}",
+    tag = "api",
+    summary = "Get an issue",
+    params(("issue_id" = Uuid, Path, description = "Id of the newsletter issue")),
+    security(("api_token" = [])),
+    responses(
+        (status = 200, description = "The issue, with delivery statistics once published", body = Issue),
+        (status = 404, description = "The issue does not exist", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The API token is missing or invalid", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "The token lacks the scope, or its user the role", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "Get an issue through the API", skip(pool))]
+pub async fn get_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let issue_id = issue_id.into_inner();
+    let published = sqlx::query!(
+        r#"
+        SELECT
+            i.title,
+            i.published_at,
+            (SELECT COUNT(*) FROM t_issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id) AS "queued!",
+            (SELECT COUNT(*) FROM t_issue_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id
+                    AND d.outcome = 'delivered') AS "delivered!",
+            (SELECT COUNT(*) FROM t_issue_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id
+                    AND d.outcome = 'failed') AS "failed!",
+            (SELECT COUNT(*) FROM t_issue_deliveries d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id
+                    AND d.outcome = 'invalid_address') AS "invalid_address!",
+            (SELECT COUNT(*) FROM t_email_opens o
+                WHERE o.newsletter_issue_id = i.newsletter_issue_id) AS "unique_opens!",
+            (SELECT COUNT(*) FROM t_link_clicks c
+                WHERE c.newsletter_issue_id = i.newsletter_issue_id) AS "clicks!",
+            (SELECT COUNT(DISTINCT c.subscriber_id) FROM t_link_clicks c
+                WHERE c.newsletter_issue_id = i.newsletter_issue_id) AS "unique_clicks!"
+        FROM t_newsletter_issues i
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to perform a query to retrieve a newsletter issue.")?;
+    if let Some(r) = published {
+        return Ok(HttpResponse::Ok().json(Issue {
+            id: issue_id,
+            title: r.title,
+            status: "published",
+            published_at: Some(r.published_at),
+            deliveries: Some(DeliveryStats {
+                queued: r.queued,
+                delivered: r.delivered,
+                failed: r.failed,
+                invalid_address: r.invalid_address,
+                unique_opens: r.unique_opens,
+                clicks: r.clicks,
+                unique_clicks: r.unique_clicks,
+            }),
+        }));
+    }
+
+    let title = sqlx::query_scalar!(
+        "SELECT title FROM t_newsletter_drafts WHERE draft_id = $1",
+        issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to perform a query to retrieve a newsletter draft.")?
+    .ok_or_else(|| ApiError::NotFound("The newsletter issue does not exist.".into()))?;
+    Ok(HttpResponse::Ok().json(Issue {
+        id: issue_id,
+        title,
+        status: "draft",
+        published_at: None,
+        deliveries: None,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/issues",
+    tag = "api",
+    summary = "Draft an issue",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Makes retries of the request return the first response")),
+    request_body = NewIssueData,
+    security(("api_token" = [])),
+    responses(
+        (status = 201, description = "The draft", body = Issue, headers(("Location" = String, description = "The URL of the issue"))),
+        (status = 400, description = "A field is missing or invalid", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The idempotency key was used for another request", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The API token is missing or invalid", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "The token lacks the scope, or its user the role", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Create an issue through the API",
+    skip(req, body, pool, user_id)
+)]
+pub async fn create_issue(
+    req: HttpRequest,
+    body: web::Json<NewIssueData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, ApiError> {
+    let idempotency_key = super::idempotency_key(&req)?;
+    let NewIssueData {
+        title,
+        plaintext,
+        html,
+        track_clicks,
+    } = body.into_inner();
+    if title.trim().is_empty() {
+        return Err(ApiError::ValidationError(
+            "The title cannot be empty.".into(),
+        ));
+    }
+
+    let mut transaction =
+        match super::begin(&req, &pool, idempotency_key.as_ref(), &user_id).await? {
+            NextAction::StartProcessing(t) => t,
+            NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+        };
+    let issue_id = Uuid::new_v4();
+    store_draft(
+        &mut *transaction,
+        issue_id,
+        &title,
+        &plaintext,
+        &html,
+        track_clicks,
+        **user_id,
+    )
+    .await?;
+
+    let response = HttpResponse::Created()
+        .insert_header((LOCATION, issue_location(issue_id)))
+        .json(Issue {
+            id: issue_id,
+            title,
+            status: "draft",
+            published_at: None,
+            deliveries: None,
+        });
+    super::finish(transaction, idempotency_key.as_ref(), &user_id, response).await
+}
+
+/// Sends a draft to the confirmed members of the chosen lists, or of the
+/// default list, who match the segment.
+#[utoipa::path(
+    post,
+    path = "/api/v1/issues/{issue_id}/publish",
+    tag = "api",
+    summary = "Publish a draft",
+    params(("issue_id" = Uuid, Path, description = "Id of the newsletter issue"), ("Idempotency-Key" = Option<String>, Header, description = "Makes retries of the request return the first response")),
+    request_body = PublishData,
+    security(("api_token" = [])),
+    responses(
+        (status = 202, description = "The issue is queued for delivery", body = Issue, headers(("Location" = String, description = "The URL of the issue"))),
+        (status = 400, description = "The lists or the segment are invalid", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "The issue does not exist", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "The issue has already been published", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The idempotency key was used for another request", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The API token is missing or invalid", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "The token lacks the scope, or its user the role", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Publish an issue through the API",
+    skip(req, body, pool, user_id)
+)]
+pub async fn publish_issue(
+    req: HttpRequest,
+    issue_id: web::Path<Uuid>,
+    body: web::Json<PublishData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, ApiError> {
+    let idempotency_key = super::idempotency_key(&req)?;
+    let issue_id = issue_id.into_inner();
+    let PublishData { list_ids, segment } = body.into_inner();
+    let segment = Segment::parse(&segment)?;
+
+    let mut transaction =
+        match super::begin(&req, &pool, idempotency_key.as_ref(), &user_id).await? {
+            NextAction::StartProcessing(t) => t,
+            NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+        };
+    let Some(draft) = get_draft(&mut transaction, issue_id).await? else {
+        let published = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM t_newsletter_issues WHERE newsletter_issue_id = $1
+            ) AS "exists!"
+            "#,
+            issue_id
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .context("Failed to check whether a newsletter issue exists.")?;
+        return Err(if published {
+            ApiError::Conflict("The newsletter issue has already been published.".into())
+        } else {
+            ApiError::NotFound("The newsletter issue does not exist.".into())
+        });
+    };
+    let list_ids = mailing_lists::resolve_list_ids(&mut transaction, &list_ids).await?;
+    insert_newsletter_issue(
+        &mut transaction,
+        issue_id,
+        &draft.title,
+        &draft.text_content,
+        &draft.html_content,
+        draft.track_clicks,
+    )
+    .await
+    .context("Failed to store newsletter issue details")?;
+    enqueue_delivery_tasks(&mut transaction, issue_id, &list_ids, &segment)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+    remove_published_draft(&mut transaction, issue_id).await?;
+
+    let response = HttpResponse::Accepted()
+        .insert_header((LOCATION, issue_location(issue_id)))
+        .json(Issue {
+            id: issue_id,
+            title: draft.title,
+            status: "published",
+            published_at: Some(Utc::now()),
+            deliveries: None,
+        });
+    super::finish(transaction, idempotency_key.as_ref(), &user_id, response).await
+}
@@ -0,0 +1,77 @@
+//! The versioned JSON API under `/api/v1`, for scripts holding an API token.
+//! Errors are returned as [`ApiError`] problems, and every POST accepts an
+//! `Idempotency-Key` header so that it can be retried safely. A key is only
+//! replayed for the method and path it was first used with.
+pub(crate) mod issues;
+pub(crate) mod subscribers;
+
+pub use issues::{create_issue, get_issue, list_issues, publish_issue};
+pub use subscribers::{create_subscriber, get_subscriber, list_subscribers};
+
+use actix_web::{HttpRequest, HttpResponse};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    api_error::ApiError,
+    idempotency::{self, IdempotencyKey, NextAction},
+};
+
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+pub async fn not_found() -> Result<HttpResponse, ApiError> {
+    Err(ApiError::NotFound("There is no such resource.".into()))
+}
+
+fn idempotency_key(req: &HttpRequest) -> Result<Option<IdempotencyKey>, ApiError> {
+    req.headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .map(|value| {
+            let value = value.to_str().map_err(|_| {
+                ApiError::ValidationError("The Idempotency-Key header must be text.".into())
+            })?;
+            IdempotencyKey::try_from(value.to_owned())
+                .map_err(|e| ApiError::ValidationError(e.to_string()))
+        })
+        .transpose()
+}
+
+/// Starts the transaction of a POST. If its idempotency key has been used
+/// before, the response saved for it is to be returned instead.
+async fn begin(
+    req: &HttpRequest,
+    pool: &PgPool,
+    idempotency_key: Option<&IdempotencyKey>,
+    user_id: &Uuid,
+) -> Result<NextAction, ApiError> {
+    match idempotency_key {
+        Some(key) => {
+            let fingerprint = format!("{} {}", req.method(), req.match_info().as_str());
+            Ok(idempotency::try_processing(pool, key, user_id, &fingerprint).await?)
+        }
+        None => Ok(NextAction::StartProcessing(pool.begin().await.context(
+            "Failed to acquire a Postgres connection from the pool.",
+        )?)),
+    }
+}
+
+/// Commits the transaction of a POST, along with its response when it has an
+/// idempotency key.
+async fn finish(
+    transaction: Transaction<'static, Postgres>,
+    idempotency_key: Option<&IdempotencyKey>,
+    user_id: &Uuid,
+    response: HttpResponse,
+) -> Result<HttpResponse, ApiError> {
+    match idempotency_key {
+        Some(key) => Ok(idempotency::save_response(transaction, key, user_id, response).await?),
+        None => {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction.")?;
+            Ok(response)
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use actix_web::{http::header::LOCATION, web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    api_error::{ApiError, Problem},
+    attributes,
+    authentication::UserId,
+    domain::SUBSCRIPTION_STATUSES,
+    idempotency::NextAction,
+    routes::{register_subscriber, NewSubscriberData, Signup},
+};
+
+const PAGE_SIZE: i64 = 100;
+
+#[derive(serde::Deserialize, Debug, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListQuery {
+    status: Option<String>,
+    page: Option<i64>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct SubscriberDetails {
+    #[serde(flatten)]
+    subscriber: Subscriber,
+    attributes: serde_json::Value,
+    list_ids: Vec<Uuid>,
+    tags: Vec<String>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct SubscriberPage {
+    subscribers: Vec<Subscriber>,
+    page: i64,
+    page_size: i64,
+    /// How many subscribers match, across all pages.
+    total: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers",
+    tag = "api",
+    summary = "List subscribers",
+    params(ListQuery),
+    security(("api_token" = [])),
+    responses(
+        (status = 200, description = "A page of subscribers, most recent first", body = SubscriberPage),
+        (status = 400, description = "The status is unknown", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The API token is missing or invalid", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "The token lacks the scope, or its user the role", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "List subscribers through the API", skip(pool))]
+pub async fn list_subscribers(
+    query: web::Query<ListQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let status = match query.status.as_deref() {
+        None => None,
+        Some(s) if SUBSCRIPTION_STATUSES.contains(&s) => Some(s),
+        Some(s) => {
+            return Err(ApiError::ValidationError(format!(
+                "{} is not a valid status.",
+                s
+            )))
+        }
+    };
+    let page = query.page.unwrap_or(1).max(1);
+    let offset = (page - 1)
+        .checked_mul(PAGE_SIZE)
+        .ok_or_else(|| ApiError::ValidationError(format!("{} is not a valid page.", page)))?;
+
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM t_subscriptions
+        WHERE $1::text IS NULL OR status = $1
+        "#,
+        status
+    )
+    .fetch_one(pool.get_ref())
+    .await
+    .context("Failed to perform a query to count subscribers.")?;
+    let subscribers: Vec<Subscriber> = sqlx::query!(
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM t_subscriptions
+        WHERE $1::text IS NULL OR status = $1
+        ORDER BY subscribed_at DESC, email
+        LIMIT $2
+        OFFSET $3
+        "#,
+        status,
+        PAGE_SIZE,
+        offset
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to perform a query to retrieve subscribers.")?
+    .into_iter()
+    .map(|r| Subscriber {
+        id: r.id,
+        email: r.email,
+        name: r.name,
+        status: r.status,
+        subscribed_at: r.subscribed_at.and_utc(),
+    })
+    .collect();
+
+    Ok(HttpResponse::Ok().json(SubscriberPage {
+        subscribers,
+        page,
+        page_size: PAGE_SIZE,
+        total,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers/{subscriber_id}",
+    tag = "api",
+    summary = "Get a subscriber",
+    params(("subscriber_id" = Uuid, Path, description = "Id of the subscriber")),
+    security(("api_token" = [])),
+    responses(
+        (status = 200, description = "The subscriber, with their custom fields, lists and tags", body = SubscriberDetails),
+        (status = 404, description = "The subscriber does not exist", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The API token is missing or invalid", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "The token lacks the scope, or its user the role", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(name = "Get a subscriber through the API", skip(pool))]
+pub async fn get_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            s.id,
+            s.email,
+            s.name,
+            s.status,
+            s.subscribed_at,
+            s.attributes,
+            ARRAY(
+                SELECT m.list_id
+                FROM t_list_memberships m
+                WHERE m.subscriber_id = s.id
+                ORDER BY m.list_id
+            ) AS "list_ids!",
+            ARRAY(
+                SELECT t.tag
+                FROM t_subscriber_tags t
+                WHERE t.subscriber_id = s.id
+                ORDER BY t.tag
+            ) AS "tags!"
+        FROM t_subscriptions s
+        WHERE s.id = $1
+        "#,
+        subscriber_id.into_inner()
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to perform a query to retrieve a subscriber.")?
+    .ok_or_else(|| ApiError::NotFound("The subscriber does not exist.".into()))?;
+
+    Ok(HttpResponse::Ok().json(SubscriberDetails {
+        subscriber: Subscriber {
+            id: r.id,
+            email: r.email,
+            name: r.name,
+            status: r.status,
+            subscribed_at: r.subscribed_at.and_utc(),
+        },
+        attributes: r.attributes,
+        list_ids: r.list_ids,
+        tags: r.tags,
+    }))
+}
+
+/// Adds a subscriber pending confirmation, who is sent the confirmation email
+/// in the background.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscribers",
+    tag = "api",
+    summary = "Create a subscriber",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Makes retries of the request return the first response")),
+    request_body = NewSubscriberData,
+    security(("api_token" = [])),
+    responses(
+        (status = 201, description = "The subscriber, pending confirmation", body = Subscriber, headers(("Location" = String, description = "The URL of the subscriber"))),
+        (status = 400, description = "Fields are missing or invalid, each listed in `errors`", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "A subscriber already has this email address", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The idempotency key was used for another request", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "The API token is missing or invalid", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "The token lacks the scope, or its user the role", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+#[tracing::instrument(
+    name = "Create a subscriber through the API",
+    skip(req, body, pool, user_id),
+    fields(subscriber_email = tracing::field::Empty)
+)]
+pub async fn create_subscriber(
+    req: HttpRequest,
+    body: web::Json<NewSubscriberData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, ApiError> {
+    let idempotency_key = super::idempotency_key(&req)?;
+    let definitions = attributes::get_definitions(&pool).await?;
+    let Signup {
+        new_subscriber,
+        attributes,
+        list_ids,
+    } = body.into_inner().validate(&definitions)?;
+    tracing::Span::current().record("subscriber_email", new_subscriber.email.as_ref());
+
+    let mut transaction =
+        match super::begin(&req, &pool, idempotency_key.as_ref(), &user_id).await? {
+            NextAction::StartProcessing(t) => t,
+            NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+        };
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM t_subscriptions WHERE email = $1) AS "exists!""#,
+        new_subscriber.email.as_ref()
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to check whether a subscriber exists.")?;
+    if exists {
+        return Err(ApiError::Conflict(
+            "A subscriber with this email address already exists.".into(),
+        ));
+    }
+    let registration =
+        register_subscriber(&mut transaction, &new_subscriber, &attributes, &list_ids).await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO t_confirmation_email_queue (subscriber_id, subscription_token)
+        VALUES ($1, $2)
+        "#,
+        registration.subscriber_id,
+        registration.confirmation_token
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to enqueue a confirmation email.")?;
+
+    let subscribed_at = sqlx::query_scalar!(
+        "SELECT subscribed_at FROM t_subscriptions WHERE id = $1",
+        registration.subscriber_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to retrieve the new subscriber.")?;
+
+    let response = HttpResponse::Created()
+        .insert_header((
+            LOCATION,
+            format!("/api/v1/subscribers/{}", registration.subscriber_id),
+        ))
+        .json(Subscriber {
+            id: registration.subscriber_id,
+            email: new_subscriber.email.as_ref().to_owned(),
+            name: new_subscriber.name.as_ref().to_owned(),
+            status: "pending_confirmation".into(),
+            subscribed_at: subscribed_at.and_utc(),
+        });
+    super::finish(transaction, idempotency_key.as_ref(), &user_id, response).await
+}
@@ -1,5 +1,14 @@
 use actix_web::HttpResponse;
 
+#[utoipa::path(
+    get,
+    path = "/health_check",
+    tag = "public",
+    summary = "Health check",
+    responses(
+        (status = 200, description = "The application is up"),
+    )
+)]
 pub async fn health_check() -> HttpResponse {
     HttpResponse::Ok().finish()
 }
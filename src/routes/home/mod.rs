@@ -1,7 +1,16 @@
 use actix_web::{http::header::ContentType, HttpResponse};
 
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "public",
+    summary = "Home page",
+    responses(
+        (status = 200, description = "The home page", content_type = "text/html", body = String),
+    )
+)]
 pub async fn home() -> HttpResponse {
     HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(include_str!("home.html"))
-}
\ No newline at end of file
+}
@@ -4,30 +4,48 @@ use actix_web::{
     error::InternalError,
     http::header::ContentType,
     web::{self},
-    HttpResponse,
+    HttpRequest, HttpResponse,
 };
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
 use reqwest::header::LOCATION;
 use secrecy::Secret;
 use sqlx::PgPool;
+use uuid::Uuid;
+
+pub(crate) mod two_factor;
+
+pub use two_factor::{login_second_factor, login_second_factor_form};
 
 use crate::{
-    authentication::{self, AuthError, Credentials},
+    authentication::{self, AuthError, Credentials, CsrfToken, LoginThrottle, PasswordHashing},
+    client_address::client_address_key,
     error::error_chain_fmt,
-    session_state::TypedSession,
+    session_state::{PendingSecondFactor, TypedSession},
     utils,
 };
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LoginQuery {
+    return_to: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug, utoipa::ToSchema)]
 pub struct LoginFormData {
     username: String,
+    #[schema(value_type = String, format = Password)]
     password: Secret<String>,
+    /// The page to go to after logging in, set when a session has expired.
+    return_to: Option<String>,
 }
 
 #[derive(thiserror::Error)]
 pub enum LoginError {
     #[error("Authentication failed.")]
     AuthError(#[source] anyhow::Error),
+    /// The same message whether or not the username exists.
+    #[error("Too many failed login attempts. Please try again later.")]
+    Throttled,
     #[error("Something went wrong.")]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -38,8 +56,22 @@ impl fmt::Debug for LoginError {
     }
 }
 
-#[tracing::instrument(skip(flash_messages))]
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+#[utoipa::path(
+    get,
+    path = "/login",
+    tag = "account",
+    summary = "Login form",
+    params(LoginQuery),
+    responses(
+        (status = 200, description = "The login form", content_type = "text/html", body = String),
+    )
+)]
+#[tracing::instrument(skip(query, flash_messages, csrf_token))]
+pub async fn login_form(
+    query: web::Query<LoginQuery>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> HttpResponse {
     let mut server_messages_html = String::new();
     for m in flash_messages.iter() {
         writeln!(server_messages_html, "<p><i>{}</i></p>", m.content()).unwrap();
@@ -49,48 +81,168 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
         .content_type(ContentType::html())
         .body(format!(
             include_str!("login.html"),
-            error_html = server_messages_html
+            error_html = server_messages_html,
+            csrf_field = csrf_token.form_field(),
+            return_to = htmlescape::encode_attribute(
+                query
+                    .return_to
+                    .as_deref()
+                    .and_then(safe_return_to)
+                    .unwrap_or("")
+            ),
         ))
 }
 
+#[utoipa::path(
+    post,
+    path = "/login",
+    tag = "account",
+    summary = "Log in",
+    request_body(content = LoginFormData, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "To the dashboard, the `return_to` page or the second factor form; back to the login form on failure"),
+    )
+)]
 #[tracing::instrument(
-    skip(form, pool, session),
+    skip(form, pool, session, login_throttle, password_hashing, request),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn login(
     form: web::Form<LoginFormData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
+    login_throttle: web::Data<LoginThrottle>,
+    password_hashing: web::Data<PasswordHashing>,
+    request: HttpRequest,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
+    let LoginFormData {
+        username,
+        password,
+        return_to,
+    } = form.into_inner();
+    let return_to = return_to.as_deref().and_then(safe_return_to);
+    tracing::Span::current().record("username", tracing::field::display(&username));
+    let address = client_address_key(&request);
+    if login_throttle
+        .is_blocked(&username, &address)
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e), return_to))?
+    {
+        return Err(login_redirect(LoginError::Throttled, return_to));
+    }
+
     let credentials = Credentials {
-        username: form.0.username,
-        password: form.0.password,
+        username: username.clone(),
+        password,
     };
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
-    match authentication::validate_credentials(credentials, &pool).await {
+    match authentication::validate_credentials(credentials, &pool, &password_hashing).await {
         Ok(user_id) => {
             tracing::Span::current().record("user_id", tracing::field::display(&user_id));
-            session.renew();
-            session
-                .insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+            let two_factor_enabled = authentication::is_two_factor_enabled(&pool, user_id)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e), return_to))?;
+            if two_factor_enabled {
+                session.renew();
+                session
+                    .insert_pending_second_factor(&PendingSecondFactor::new(
+                        user_id, username, return_to,
+                    ))
+                    .map_err(|e| {
+                        login_redirect(LoginError::UnexpectedError(e.into()), return_to)
+                    })?;
+                return Ok(utils::see_other("/login/2fa"));
+            }
+            start_session(
+                &session,
+                user_id,
+                &username,
+                &login_throttle,
+                &pool,
+                &request,
+            )
+            .await
+            .map_err(|e| login_redirect(LoginError::UnexpectedError(e), return_to))?;
             Ok(HttpResponse::SeeOther()
-                .insert_header((LOCATION, "/admin/dashboard"))
+                .insert_header((LOCATION, return_to.unwrap_or("/admin/dashboard")))
                 .finish())
         }
         Err(e) => {
             let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                AuthError::InvalidCredentials(_) => {
+                    login_throttle
+                        .record_failure(&username, &address)
+                        .await
+                        .map_err(|e| login_redirect(LoginError::UnexpectedError(e), return_to))?;
+                    LoginError::AuthError(e.into())
+                }
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
             };
-            Err(login_redirect(e))
+            Err(login_redirect(e, return_to))
         }
     }
 }
 
-fn login_redirect(e: LoginError) -> InternalError<LoginError> {
+/// Logs `user_id` in on a fresh session, once every factor has been checked.
+/// Only then are the failed logins for `username` forgotten.
+async fn start_session(
+    session: &TypedSession,
+    user_id: Uuid,
+    username: &str,
+    login_throttle: &LoginThrottle,
+    pool: &PgPool,
+    request: &HttpRequest,
+) -> Result<(), anyhow::Error> {
+    login_throttle.record_success(username).await?;
+    let session_id = authentication::register_session(pool, user_id, request).await?;
+    session.renew();
+    session.remove_pending_second_factor();
+    authentication::rotate_csrf_token(session)?;
+    session.insert_user_id(user_id)?;
+    session.insert_logged_in_at(chrono::Utc::now())?;
+    session.insert_session_id(session_id)?;
+    Ok(())
+}
+
+fn login_redirect(e: LoginError, return_to: Option<&str>) -> InternalError<LoginError> {
     FlashMessage::error(e.to_string()).send();
 
-    let response = utils::see_other("/login");
+    let response = utils::see_other(&login_page(return_to));
     InternalError::from_response(e, response)
 }
+
+fn login_page(return_to: Option<&str>) -> String {
+    match return_to {
+        Some(page) => format!("/login?return_to={}", urlencoding::encode(page)),
+        None => "/login".to_owned(),
+    }
+}
+
+/// Only pages of the admin area can be returned to, so that a link to the
+/// login page cannot send the user to another site.
+fn safe_return_to(page: &str) -> Option<&str> {
+    let is_admin_page =
+        page == "/admin" || page.starts_with("/admin/") || page.starts_with("/admin?");
+    (is_admin_page && !page.contains("//") && !page.contains('\\')).then_some(page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::safe_return_to;
+
+    #[test]
+    fn only_admin_pages_can_be_returned_to() {
+        for page in ["/admin/dashboard", "/admin/subscribers?page=2", "/admin"] {
+            assert_eq!(safe_return_to(page), Some(page));
+        }
+        for page in [
+            "https://example.com/admin/",
+            "//example.com/admin/",
+            "/admin//example.com",
+            "/admin/\\example.com",
+            "/administrator",
+            "/login",
+        ] {
+            assert_eq!(safe_return_to(page), None, "{}", page);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt::Write;
+
+use actix_web::{http::header::ContentType, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{verify_second_factor, CsrfToken, LoginThrottle},
+    client_address::client_address_key,
+    session_state::{PendingSecondFactor, TypedSession},
+    utils,
+};
+
+/// After this many incorrect codes the password has to be entered again.
+/// Each one also counts as a failed login towards the [`LoginThrottle`].
+const MAX_FAILED_ATTEMPTS: u32 = 5;
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct SecondFactorFormData {
+    #[schema(value_type = String, format = Password)]
+    code: Secret<String>,
+}
+
+/// Returns the login waiting for a code, if it has not expired.
+fn pending_login(session: &TypedSession) -> Result<Option<PendingSecondFactor>, actix_web::Error> {
+    match session.get_pending_second_factor().map_err(utils::e500)? {
+        Some(pending) if !pending.has_expired() => Ok(Some(pending)),
+        Some(_) => {
+            session.remove_pending_second_factor();
+            FlashMessage::error("Your login has expired. Please log in again.").send();
+            Ok(None)
+        }
+        None => Ok(None),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/login/2fa",
+    tag = "account",
+    summary = "Second factor form",
+    responses(
+        (status = 200, description = "Asks for a code from the authenticator app", content_type = "text/html", body = String),
+        (status = 303, description = "To the login form, when no login is waiting for a code"),
+    )
+)]
+pub async fn login_second_factor_form(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if pending_login(&session)?.is_none() {
+        return Ok(utils::see_other("/login"));
+    }
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("two_factor.html"),
+            msg_html = msg_html,
+            csrf_field = csrf_token.form_field(),
+        )))
+}
+
+/// Completes a login with either a code from the authenticator app or a
+/// recovery code.
+#[utoipa::path(
+    post,
+    path = "/login/2fa",
+    tag = "account",
+    summary = "Complete a login with a code",
+    request_body(content = SecondFactorFormData, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "To the dashboard, or back to a form on failure"),
+    )
+)]
+#[tracing::instrument(
+    skip(form, pool, session, login_throttle, request),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn login_second_factor(
+    form: web::Form<SecondFactorFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    login_throttle: web::Data<LoginThrottle>,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(mut pending) = pending_login(&session)? else {
+        return Ok(utils::see_other("/login"));
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&pending.user_id));
+    let address = client_address_key(&request);
+    if login_throttle
+        .is_blocked(&pending.username, &address)
+        .await
+        .map_err(utils::e500)?
+    {
+        session.log_out();
+        FlashMessage::error(super::LoginError::Throttled.to_string()).send();
+        return Ok(utils::see_other("/login"));
+    }
+
+    if !verify_second_factor(&pool, pending.user_id, form.code.expose_secret())
+        .await
+        .map_err(utils::e500)?
+    {
+        login_throttle
+            .record_failure(&pending.username, &address)
+            .await
+            .map_err(utils::e500)?;
+        pending.failed_attempts += 1;
+        tracing::warn!(
+            failed_attempts = pending.failed_attempts,
+            "Incorrect second factor"
+        );
+        if pending.failed_attempts >= MAX_FAILED_ATTEMPTS {
+            session.log_out();
+            FlashMessage::error("Too many incorrect codes. Please log in again.").send();
+            return Ok(utils::see_other("/login"));
+        }
+        session
+            .insert_pending_second_factor(&pending)
+            .map_err(utils::e500)?;
+        FlashMessage::error("The code is incorrect.").send();
+        return Ok(utils::see_other("/login/2fa"));
+    }
+
+    super::start_session(
+        &session,
+        pending.user_id,
+        &pending.username,
+        &login_throttle,
+        &pool,
+        &request,
+    )
+    .await
+    .map_err(utils::e500)?;
+    Ok(utils::see_other(
+        pending.return_to.as_deref().unwrap_or("/admin/dashboard"),
+    ))
+}
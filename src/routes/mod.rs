@@ -1,13 +1,22 @@
-mod admin;
-mod health_check;
-mod home;
-mod login;
-mod subscriptions;
-mod subscriptions_confirm;
+pub(crate) mod admin;
+pub mod api;
+pub(crate) mod health_check;
+pub(crate) mod home;
+pub(crate) mod login;
+pub(crate) mod set_password;
+pub(crate) mod subscriptions;
+pub(crate) mod subscriptions_confirm;
+pub(crate) mod subscriptions_preferences;
+pub(crate) mod subscriptions_privacy;
+pub(crate) mod tracking;
 
 pub use admin::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
+pub use set_password::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_preferences::*;
+pub use subscriptions_privacy::*;
+pub use tracking::*;
@@ -0,0 +1,456 @@
+use std::fmt::Write;
+
+use actix_web::{http::header::ContentType, web, HttpResponse, ResponseError};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use reqwest::StatusCode;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use tracing::Instrument;
+
+use crate::{
+    authentication::{
+        consume_user_token, find_user_token, hash_password, issue_user_token,
+        revoke_other_sessions, PasswordHashing, PasswordPolicy, UserTokenPurpose,
+    },
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    error,
+    startup::ApplicationBaseUrl,
+    utils,
+};
+
+/// How long a password reset link can be used.
+const RESET_VALIDITY: Duration = Duration::hours(1);
+
+#[derive(thiserror::Error)]
+pub enum SetPasswordError {
+    #[error("This link is invalid or has expired.")]
+    InvalidLink,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SetPasswordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error::error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SetPasswordError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SetPasswordError::InvalidLink => StatusCode::BAD_REQUEST,
+            SetPasswordError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SetPasswordQuery {
+    token: String,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct SetPasswordFormData {
+    token: String,
+    #[schema(value_type = String, format = Password)]
+    new_password: Secret<String>,
+    #[schema(value_type = String, format = Password)]
+    new_password_check: Secret<String>,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct PasswordResetFormData {
+    email: String,
+}
+
+/// Both invitations and password resets end on a form to choose a password,
+/// posted back to the page it was served from.
+fn form_path(purpose: UserTokenPurpose) -> &'static str {
+    match purpose {
+        UserTokenPurpose::Invitation => "/users/set_password",
+        UserTokenPurpose::PasswordReset => "/password_reset/confirm",
+    }
+}
+
+fn form_page(purpose: UserTokenPurpose, token: &str) -> String {
+    format!(
+        "{}?token={}",
+        form_path(purpose),
+        urlencoding::encode(token)
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/set_password",
+    tag = "account",
+    summary = "Invitation form",
+    params(SetPasswordQuery),
+    responses(
+        (status = 200, description = "The form to choose a password", content_type = "text/html", body = String),
+        (status = 400, description = "The invitation is invalid or has expired"),
+    )
+)]
+pub async fn set_password_form(
+    query: web::Query<SetPasswordQuery>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SetPasswordError> {
+    choose_password_form(
+        UserTokenPurpose::Invitation,
+        &query.token,
+        flash_messages,
+        &pool,
+    )
+    .await
+}
+
+/// Completes an invitation: the link can only be used once, and the user can
+/// log in from then on.
+#[utoipa::path(
+    post,
+    path = "/users/set_password",
+    tag = "account",
+    summary = "Accept an invitation",
+    request_body(content = SetPasswordFormData, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "To the login form, or back to the form when the password is rejected"),
+        (status = 400, description = "The invitation is invalid or has expired"),
+    )
+)]
+#[tracing::instrument(
+    name = "Setting the password of an invited user",
+    skip(form, pool, password_hashing, password_policy)
+)]
+pub async fn set_password(
+    form: web::Form<SetPasswordFormData>,
+    pool: web::Data<PgPool>,
+    password_hashing: web::Data<PasswordHashing>,
+    password_policy: web::Data<PasswordPolicy>,
+) -> Result<HttpResponse, SetPasswordError> {
+    choose_password(
+        UserTokenPurpose::Invitation,
+        form.into_inner(),
+        &pool,
+        &password_hashing,
+        &password_policy,
+    )
+    .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/password_reset",
+    tag = "account",
+    summary = "Password reset form",
+    responses(
+        (status = 200, description = "The form to request a password reset", content_type = "text/html", body = String),
+    )
+)]
+pub async fn password_reset_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("password_reset.html"),
+            msg_html = msg_html
+        ))
+}
+
+/// Emails a reset link to the active user with this address, if there is one.
+/// The response does not tell whether there is: the user is looked up and
+/// emailed in the background, so that it takes as long either way.
+#[utoipa::path(
+    post,
+    path = "/password_reset",
+    tag = "account",
+    summary = "Request a password reset",
+    request_body(content = PasswordResetFormData, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Back to the form. A link is emailed if the address belongs to a user"),
+    )
+)]
+#[tracing::instrument(
+    name = "Requesting a password reset",
+    skip(form, pool, email_client, base_url)
+)]
+pub async fn request_password_reset(
+    form: web::Form<PasswordResetFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> HttpResponse {
+    if let Ok(email) = SubscriberEmail::parse(form.0.email.trim().to_owned()) {
+        tokio::spawn(
+            async move {
+                if let Err(e) = email_reset_link(&pool, &email_client, &base_url, &email).await {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to send a password reset email.",
+                    );
+                }
+            }
+            .instrument(tracing::Span::current()),
+        );
+    }
+
+    FlashMessage::info(
+        "If an account uses this address, we have sent it a link to reset its password. \
+        The link is valid for 1 hour.",
+    )
+    .send();
+    utils::see_other("/password_reset")
+}
+
+async fn email_reset_link(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &ApplicationBaseUrl,
+    email: &SubscriberEmail,
+) -> Result<(), anyhow::Error> {
+    let user = sqlx::query!(
+        r#"
+        SELECT user_id, username
+        FROM t_users
+        WHERE lower(email) = lower($1) AND status = 'active'
+        "#,
+        email.as_ref()
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up a user by email.")?;
+    let Some(user) = user else {
+        return Ok(());
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let token = issue_user_token(
+        &mut transaction,
+        user.user_id,
+        UserTokenPurpose::PasswordReset,
+        RESET_VALIDITY,
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a password reset token.")?;
+    send_password_reset_email(email_client, email, &user.username, base_url, &token)
+        .await
+        .context("Failed to send a password reset email.")?;
+    Ok(())
+}
+
+async fn send_password_reset_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    username: &str,
+    base_url: &ApplicationBaseUrl,
+    token: &str,
+) -> Result<(), reqwest::Error> {
+    let link = format!(
+        "{}{}",
+        base_url.0,
+        form_page(UserTokenPurpose::PasswordReset, token)
+    );
+    email_client
+        .send_email(
+            email,
+            "Reset your password",
+            &format!(
+                r#"<p>Click <a href="{}">here</a> to choose a new password for <b>{}</b>. The link is valid for 1 hour.</p>
+            <p>If you did not ask for this, you can ignore this email.</p>"#,
+                link,
+                htmlescape::encode_minimal(username)
+            ),
+            &format!(
+                "Visit {} to choose a new password for {}. The link is valid for 1 hour.\nIf you did not ask for this, you can ignore this email.",
+                link, username
+            ),
+        )
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/password_reset/confirm",
+    tag = "account",
+    summary = "New password form",
+    params(SetPasswordQuery),
+    responses(
+        (status = 200, description = "The form to choose a new password", content_type = "text/html", body = String),
+        (status = 400, description = "The link is invalid or has expired"),
+    )
+)]
+pub async fn reset_password_form(
+    query: web::Query<SetPasswordQuery>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, SetPasswordError> {
+    choose_password_form(
+        UserTokenPurpose::PasswordReset,
+        &query.token,
+        flash_messages,
+        &pool,
+    )
+    .await
+}
+
+/// Sets a new password and logs the user out of every session they had.
+#[utoipa::path(
+    post,
+    path = "/password_reset/confirm",
+    tag = "account",
+    summary = "Reset a password",
+    request_body(content = SetPasswordFormData, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "To the login form, or back to the form when the password is rejected"),
+        (status = 400, description = "The link is invalid or has expired"),
+    )
+)]
+#[tracing::instrument(
+    name = "Resetting a forgotten password",
+    skip(form, pool, password_hashing, password_policy)
+)]
+pub async fn reset_password(
+    form: web::Form<SetPasswordFormData>,
+    pool: web::Data<PgPool>,
+    password_hashing: web::Data<PasswordHashing>,
+    password_policy: web::Data<PasswordPolicy>,
+) -> Result<HttpResponse, SetPasswordError> {
+    choose_password(
+        UserTokenPurpose::PasswordReset,
+        form.into_inner(),
+        &pool,
+        &password_hashing,
+        &password_policy,
+    )
+    .await
+}
+
+async fn choose_password_form(
+    purpose: UserTokenPurpose,
+    token: &str,
+    flash_messages: IncomingFlashMessages,
+    pool: &PgPool,
+) -> Result<HttpResponse, SetPasswordError> {
+    let username = get_token_username(pool, token, purpose).await?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let username = htmlescape::encode_minimal(&username);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("set_password.html"),
+            heading = match purpose {
+                UserTokenPurpose::Invitation => format!("Welcome {}", username),
+                UserTokenPurpose::PasswordReset =>
+                    format!("Choose a new password for {}", username),
+            },
+            msg_html = msg_html,
+            action = form_path(purpose),
+            token = htmlescape::encode_minimal(token),
+        )))
+}
+
+/// The username of the user a valid token was issued to, which the new
+/// password must not contain.
+async fn get_token_username(
+    pool: &PgPool,
+    token: &str,
+    purpose: UserTokenPurpose,
+) -> Result<String, SetPasswordError> {
+    let user_id = find_user_token(pool, token, purpose)
+        .await?
+        .ok_or(SetPasswordError::InvalidLink)?;
+    let username = sqlx::query_scalar!("SELECT username FROM t_users WHERE user_id = $1", user_id)
+        .fetch_one(pool)
+        .await
+        .context("Failed to retrieve the username of a user.")?;
+    Ok(username)
+}
+
+async fn choose_password(
+    purpose: UserTokenPurpose,
+    form: SetPasswordFormData,
+    pool: &PgPool,
+    password_hashing: &PasswordHashing,
+    password_policy: &PasswordPolicy,
+) -> Result<HttpResponse, SetPasswordError> {
+    let SetPasswordFormData {
+        token,
+        new_password,
+        new_password_check,
+    } = form;
+    let username = get_token_username(pool, &token, purpose).await?;
+    if let Err(message) =
+        password_policy.check_new_password(&username, &new_password, &new_password_check)
+    {
+        FlashMessage::error(message).send();
+        return Ok(utils::see_other(&form_page(purpose, &token)));
+    }
+    let password_hash = hash_password(new_password, password_hashing).await?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let user_id = consume_user_token(&mut transaction, &token, purpose)
+        .await?
+        .ok_or(SetPasswordError::InvalidLink)?;
+    let message = match purpose {
+        UserTokenPurpose::Invitation => {
+            sqlx::query!(
+                r#"
+                UPDATE t_users
+                SET password_hash = $1, status = 'active'
+                WHERE user_id = $2
+                "#,
+                password_hash.expose_secret(),
+                user_id
+            )
+            .execute(&mut *transaction)
+            .await
+            .context("Failed to set the password of an invited user.")?;
+            "Your password has been set. You can now log in."
+        }
+        UserTokenPurpose::PasswordReset => {
+            sqlx::query!(
+                r#"
+                UPDATE t_users
+                SET password_hash = $1, sessions_valid_after = $2
+                WHERE user_id = $3
+                "#,
+                password_hash.expose_secret(),
+                Utc::now(),
+                user_id
+            )
+            .execute(&mut *transaction)
+            .await
+            .context("Failed to reset the password of a user.")?;
+            revoke_other_sessions(&mut *transaction, user_id, None).await?;
+            "Your password has been reset. You can now log in."
+        }
+    };
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to set a password.")?;
+
+    FlashMessage::info(message).send();
+    Ok(utils::see_other("/login"))
+}
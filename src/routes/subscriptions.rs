@@ -1,35 +1,58 @@
 use core::fmt;
+use std::collections::HashMap;
 
 use actix_web::{
-    web::{self, Form},
-    HttpResponse, ResponseError,
+    http::header::{CacheControl, CacheDirective, ContentType},
+    web, HttpRequest, HttpResponse, ResponseError,
 };
 use anyhow::Context;
 use chrono::Utc;
 use rand::distributions::DistString;
 use reqwest::StatusCode;
+use serde_json::{Map, Value};
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
+    api_error::{ApiError, Problem},
+    attributes::{self, AttributeDefinition, FORM_FIELD_PREFIX},
+    client_address::client_address_key,
     domain::{NewSubscriber, SubscriberEmail, SubscriberName},
     email_client::EmailClient,
     error,
+    mailing_lists::{self, ListSelectionError},
+    negotiation::{self, ResponseFormat},
+    signup_protection::{FormTokenError, SignupProtection},
     startup::ApplicationBaseUrl,
+    utils,
 };
 
+const THANK_YOU_PAGE: &str = "/subscriptions/thank_you";
+
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
     #[error("{0}")]
     ValidationError(String),
+    #[error("{}", describe(.0))]
+    InvalidFields(Vec<FieldError>),
+    #[error("Too many signups were sent from your address. Please try again later.")]
+    TooManyAttempts,
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
 
+fn describe(errors: &[FieldError]) -> String {
+    let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+    messages.join(" ")
+}
+
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> reqwest::StatusCode {
         match self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::ValidationError(_) | SubscribeError::InvalidFields(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            SubscribeError::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -41,59 +64,449 @@ impl fmt::Debug for SubscribeError {
     }
 }
 
-#[derive(serde::Deserialize)]
+impl From<ListSelectionError> for SubscribeError {
+    fn from(e: ListSelectionError) -> Self {
+        match e {
+            ListSelectionError::UnexpectedError(e) => SubscribeError::UnexpectedError(e),
+            e => SubscribeError::InvalidFields(vec![FieldError::new("list_ids", e.to_string())]),
+        }
+    }
+}
+
+/// A submitted value that was rejected, reported next to its field.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, utoipa::ToSchema)]
+pub struct FieldError {
+    /// `email`, `name`, `list_ids`, or `attributes.<key>` for a custom field.
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &str, message: String) -> Self {
+        Self {
+            field: field.to_owned(),
+            message,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[schema(as = SubscribeFormData)]
 pub struct FormData {
     email: String,
     name: String,
+    #[serde(default, rename = "list_id")]
+    list_ids: Vec<Uuid>,
+    #[serde(flatten)]
+    traps: BotTraps,
+    /// Custom fields, keyed by their form input name.
+    #[serde(flatten)]
+    attributes: HashMap<String, String>,
+}
+
+/// The JSON counterpart of [`FormData`].
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct NewSubscriberData {
+    email: String,
+    name: String,
+    #[serde(default)]
+    list_ids: Vec<Uuid>,
+    /// Custom field values, keyed by the key of their definition.
+    #[serde(default)]
+    attributes: HashMap<String, Value>,
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+/// What scripts post to subscribe: the fields of [`NewSubscriberData`], along
+/// with the same bot traps as the signup forms.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct SignupData {
+    #[serde(flatten)]
+    subscriber: NewSubscriberData,
+    #[serde(flatten)]
+    traps: BotTraps,
+}
 
-    fn try_from(form: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(form.name)?;
-        let email = SubscriberEmail::parse(form.email)?;
+/// The fields of the signup forms that tell bots apart from people.
+#[derive(Default, serde::Deserialize, utoipa::ToSchema)]
+pub struct BotTraps {
+    /// A honeypot: the field is hidden from people, so only bots fill it in.
+    #[serde(default)]
+    website: String,
+    /// Signed as the form was shown. The signup is turned down without it, or
+    /// if the form was filled in faster than a person could. Scripts that
+    /// render their own form get one from `GET /subscriptions/form_token`.
+    #[schema(required = true)]
+    form_token: Option<String>,
+}
 
-        Ok(NewSubscriber { email, name })
+impl BotTraps {
+    /// Whether a bot fell for the honeypot. Bots are not told, so that they
+    /// do not learn to avoid it.
+    fn caught_a_bot(&self, protection: &SignupProtection) -> Result<bool, SubscribeError> {
+        if !self.website.is_empty() {
+            tracing::warn!("Signup dropped as its honeypot field was filled in");
+            return Ok(true);
+        }
+        // A missing token is refused like an invalid one, so that bots cannot
+        // skip the fill time check by leaving it out.
+        match &self.form_token {
+            Some(token) => protection.check_form_token(token),
+            None => Err(FormTokenError::Invalid),
+        }
+        .map_err(|e| SubscribeError::ValidationError(e.to_string()))?;
+        Ok(false)
     }
 }
 
+/// A signup whose fields have all been validated.
+pub struct Signup {
+    pub new_subscriber: NewSubscriber,
+    pub attributes: Map<String, Value>,
+    pub list_ids: Vec<Uuid>,
+}
+
+impl FormData {
+    pub fn validate(self, definitions: &[AttributeDefinition]) -> Result<Signup, SubscribeError> {
+        validate_signup(
+            self.email,
+            self.name,
+            self.list_ids,
+            &self.attributes,
+            definitions,
+        )
+    }
+}
+
+impl NewSubscriberData {
+    pub fn validate(self, definitions: &[AttributeDefinition]) -> Result<Signup, SubscribeError> {
+        // Validated like the values of the signup form, where every value is text.
+        let submitted: HashMap<String, String> = self
+            .attributes
+            .into_iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    Value::String(s) => s,
+                    Value::Null => String::new(),
+                    v => v.to_string(),
+                };
+                (format!("{}{}", FORM_FIELD_PREFIX, key), value)
+            })
+            .collect();
+        validate_signup(
+            self.email,
+            self.name,
+            self.list_ids,
+            &submitted,
+            definitions,
+        )
+    }
+}
+
+/// Checks every field rather than stopping at the first invalid one, so that
+/// all of them can be pointed out at once.
+fn validate_signup(
+    email: String,
+    name: String,
+    list_ids: Vec<Uuid>,
+    submitted_attributes: &HashMap<String, String>,
+    definitions: &[AttributeDefinition],
+) -> Result<Signup, SubscribeError> {
+    let mut errors = Vec::new();
+    let email = SubscriberEmail::parse(email)
+        .map_err(|message| errors.push(FieldError::new("email", message)))
+        .ok();
+    let name = SubscriberName::parse(name)
+        .map_err(|message| errors.push(FieldError::new("name", message)))
+        .ok();
+    let attributes = attributes::validate_all(definitions, submitted_attributes)
+        .map_err(|invalid| {
+            errors.extend(invalid.into_iter().map(|(key, e)| FieldError {
+                field: format!("attributes.{}", key),
+                message: e.to_string(),
+            }))
+        })
+        .ok();
+
+    match (email, name, attributes) {
+        (Some(email), Some(name), Some(attributes)) => Ok(Signup {
+            new_subscriber: NewSubscriber { email, name },
+            attributes,
+            list_ids,
+        }),
+        _ => Err(SubscribeError::InvalidFields(errors)),
+    }
+}
+
+/// What a script learns of a successful signup.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct SubscriptionReceipt {
+    /// Always `pending_confirmation`: the confirmation email is on its way.
+    status: &'static str,
+}
+
+#[utoipa::path(
+    get,
+    path = "/subscriptions",
+    tag = "public",
+    summary = "Signup form",
+    responses(
+        (status = 200, description = "The signup form, with the public lists and custom fields", content_type = "text/html", body = String),
+    )
+)]
+pub async fn subscribe_form(
+    connection_pool: web::Data<PgPool>,
+    signup_protection: web::Data<SignupProtection>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lists = mailing_lists::get_lists(&connection_pool)
+        .await
+        .map_err(utils::e500)?;
+    let definitions = attributes::get_definitions(&connection_pool)
+        .await
+        .map_err(utils::e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("subscriptions.html"),
+            attributes_html = attributes::form_fields_html(&definitions),
+            lists_html = mailing_lists::checkboxes_html(&lists),
+            form_token = signup_protection.form_token()
+        )))
+}
+
+/// Accepts the signup form as well as JSON. Browsers are redirected to a
+/// thank-you page, while scripts get JSON back, including the reason every
+/// invalid field was rejected for.
+#[utoipa::path(
+    post,
+    path = "/subscriptions",
+    tag = "public",
+    summary = "Subscribe",
+    request_body(content(
+        (FormData = "application/x-www-form-urlencoded"),
+        (SignupData = "application/json"),
+    )),
+    responses(
+        (status = 200, description = "The subscriber is pending confirmation and has been sent a confirmation email", body = SubscriptionReceipt),
+        (status = 303, description = "To the thank-you page, when the client prefers HTML"),
+        (status = 400, description = "A field is missing or invalid", content(
+            (Problem = "application/problem+json"),
+            (String = "text/plain"),
+        )),
+        (status = 429, description = "Too many signups were sent from the client address", content(
+            (Problem = "application/problem+json"),
+            (String = "text/plain"),
+        )),
+    )
+)]
 #[tracing::instrument(
     name = "Adding new subscriber",
-    skip(form, connection_pool, email_client, base_url),
+    skip(req, body, connection_pool, email_client, base_url, signup_protection),
     fields(
-        subscriber_name = %form.name,
-        subscriber_email = %form.email))]
+        subscriber_name = tracing::field::Empty,
+        subscriber_email = tracing::field::Empty))]
 pub async fn subscribe(
-    form: Form<FormData>,
+    req: HttpRequest,
+    body: web::Bytes,
     connection_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
-) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    signup_protection: web::Data<SignupProtection>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let outcome = add_subscriber(
+        &req,
+        &body,
+        &connection_pool,
+        &email_client,
+        &base_url,
+        &signup_protection,
+    )
+    .await;
+    match ResponseFormat::negotiate(&req) {
+        ResponseFormat::Json => match outcome {
+            Ok(()) => Ok(HttpResponse::Ok().json(SubscriptionReceipt {
+                status: "pending_confirmation",
+            })),
+            Err(e) => Err(ApiError::from(e).into()),
+        },
+        ResponseFormat::Html => match outcome {
+            Ok(()) => Ok(utils::see_other(THANK_YOU_PAGE)),
+            Err(e) => Err(e.into()),
+        },
+    }
+}
+
+async fn add_subscriber(
+    req: &HttpRequest,
+    body: &[u8],
+    connection_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &ApplicationBaseUrl,
+    signup_protection: &SignupProtection,
+) -> Result<(), SubscribeError> {
+    let address = client_address_key(req);
+    if signup_protection.is_throttled(&address).await? {
+        return Err(SubscribeError::TooManyAttempts);
+    }
+    let definitions = attributes::get_definitions(connection_pool).await?;
+    let (traps, signup) = if negotiation::has_json_body(req) {
+        let SignupData { subscriber, traps } = serde_json::from_slice(body)
+            .map_err(|e| SubscribeError::ValidationError(e.to_string()))?;
+        (traps, subscriber.validate(&definitions))
+    } else {
+        let mut form: FormData = serde_html_form::from_bytes(body)
+            .map_err(|e| SubscribeError::ValidationError(e.to_string()))?;
+        (std::mem::take(&mut form.traps), form.validate(&definitions))
+    };
+    if traps.caught_a_bot(signup_protection)? {
+        return Ok(());
+    }
+    let signup = signup?;
+    let new_subscriber = &signup.new_subscriber;
+    tracing::Span::current()
+        .record("subscriber_name", new_subscriber.name.as_ref())
+        .record("subscriber_email", new_subscriber.email.as_ref());
+
     let mut transaction = connection_pool
         .begin()
         .await
         .with_context(|| "Failed to acquire a Postgres connection from the pool.")?;
-    let subscriber_id = insert_subscriber(&new_subscriber, &mut transaction)
-        .await
-        .with_context(|| "Failed to store a new subscriber.")?;
-    let token = generate_subscription_token();
-    store_token(&mut transaction, &subscriber_id, &token)
-        .await
-        .with_context(|| "Failed to store the confirmation token for a new subscriber.")?;
+    let registration = register_subscriber(
+        &mut transaction,
+        new_subscriber,
+        &signup.attributes,
+        &signup.list_ids,
+    )
+    .await?;
     transaction
         .commit()
         .await
         .with_context(|| "Failed to commit SQL transaction to store a new subscriber.")?;
-    send_confirmation_email(email_client.as_ref(), &new_subscriber, &base_url, &token)
+    send_confirmation_email(
+        email_client,
+        new_subscriber,
+        base_url,
+        &registration.confirmation_token,
+    )
+    .await
+    .with_context(|| "Failed to send a confirmation email.")?;
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/subscriptions/thank_you",
+    tag = "public",
+    summary = "Thank-you page",
+    responses(
+        (status = 200, description = "Asks to check the inbox for the confirmation email", content_type = "text/html", body = String),
+    )
+)]
+pub async fn subscription_thank_you() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(include_str!("subscriptions_thank_you.html"))
+}
+
+/// A script that adds a signup form right where it is included, on any of the
+/// sites allowed by `signup_protection.allowed_origins`:
+///
+/// ```html
+/// <script src="https://newsletter.example.com/subscriptions/widget.js"></script>
+/// ```
+#[utoipa::path(
+    get,
+    path = "/subscriptions/widget.js",
+    tag = "public",
+    summary = "Signup widget",
+    responses(
+        (status = 200, description = "The script of the widget, with a form token of its own", content_type = "text/javascript", body = String),
+    )
+)]
+pub async fn signup_widget(
+    base_url: web::Data<ApplicationBaseUrl>,
+    signup_protection: web::Data<SignupProtection>,
+) -> HttpResponse {
+    let config = serde_json::json!({
+        "action": format!("{}/subscriptions", base_url.0),
+        "formToken": signup_protection.form_token(),
+    });
+    HttpResponse::Ok()
+        .content_type("text/javascript; charset=utf-8")
+        // Every page view gets a token signed as it is shown.
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(format!(
+            "(function (config) {{\n{}}})({});\n",
+            include_str!("subscriptions_widget.js"),
+            config
+        ))
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct FormToken {
+    /// To send back as the `form_token` of the signup.
+    form_token: String,
+}
+
+/// A form token for scripts on the allowed origins that render a signup form
+/// of their own, to fetch as they show it.
+#[utoipa::path(
+    get,
+    path = "/subscriptions/form_token",
+    tag = "public",
+    summary = "Signup form token",
+    responses(
+        (status = 200, description = "A form token signed now", body = FormToken),
+    )
+)]
+pub async fn signup_form_token(signup_protection: web::Data<SignupProtection>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .json(FormToken {
+            form_token: signup_protection.form_token(),
+        })
+}
+
+pub struct Registration {
+    pub subscriber_id: Uuid,
+    pub confirmation_token: String,
+}
+
+/// Stores a subscriber pending confirmation, with their mailing lists and
+/// tokens. Sending the confirmation email is left to the caller.
+#[tracing::instrument(
+    name = "Registering a new subscriber",
+    skip(transaction, new_subscriber, attributes)
+)]
+pub async fn register_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+    attributes: &Map<String, Value>,
+    requested_list_ids: &[Uuid],
+) -> Result<Registration, SubscribeError> {
+    let list_ids = mailing_lists::resolve_list_ids(transaction, requested_list_ids).await?;
+    let subscriber_id = insert_subscriber(new_subscriber, attributes, transaction)
+        .await
+        .with_context(|| "Failed to store a new subscriber.")?;
+    mailing_lists::add_memberships(transaction, subscriber_id, &list_ids)
+        .await
+        .with_context(|| "Failed to add a new subscriber to mailing lists.")?;
+    let confirmation_token = generate_subscription_token();
+    store_token(transaction, &subscriber_id, &confirmation_token)
         .await
-        .with_context(|| "Failed to send a confirmation email.")?;
+        .with_context(|| "Failed to store the confirmation token for a new subscriber.")?;
+    store_preference_token(transaction, &subscriber_id, &generate_subscription_token())
+        .await
+        .with_context(|| "Failed to store the preference token for a new subscriber.")?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(Registration {
+        subscriber_id,
+        confirmation_token,
+    })
 }
 
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = rand::thread_rng();
     rand::distributions::Alphanumeric.sample_string(&mut rng, 48)
 }
@@ -132,22 +545,24 @@ pub async fn send_confirmation_email(
 
 #[tracing::instrument(
     name = "Persisting new subscriber details in database",
-    skip(new_subscriber, transaction)
+    skip(new_subscriber, attributes, transaction)
 )]
 pub async fn insert_subscriber(
     new_subscriber: &NewSubscriber,
+    attributes: &Map<String, Value>,
     transaction: &mut Transaction<'_, Postgres>,
 ) -> Result<Uuid, anyhow::Error> {
     let subscriber_id = Uuid::new_v4();
     sqlx::query!(
         r#"
-            INSERT INTO t_subscriptions (id, email, name, subscribed_at, status)
-            VALUES ($1, $2, $3, $4, 'pending_confirmation')
+            INSERT INTO t_subscriptions (id, email, name, subscribed_at, status, attributes)
+            VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
             "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now().naive_utc()
+        Utc::now().naive_utc(),
+        serde_json::Value::Object(attributes.clone())
     )
     // https://stackoverflow.com/questions/64654769/how-to-build-and-commit-multi-query-transaction-in-sqlx
     .execute(&mut **transaction)
@@ -183,3 +598,29 @@ pub async fn store_token(
 
     Ok(())
 }
+
+#[tracing::instrument(
+    name = "Storing preference token in database",
+    skip(transaction, token)
+)]
+pub async fn store_preference_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: &Uuid,
+    token: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO t_preference_tokens (preference_token, subscriber_id)
+        VALUES ($1, $2)
+        "#,
+        token,
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await
+    .with_context(|| {
+        "A database error was encountered while trying to store a preference token."
+    })?;
+
+    Ok(())
+}
@@ -29,11 +29,23 @@ impl ResponseError for SubscriptionConfirmError {
     }
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct QueryParams {
     subscription_token: String,
 }
 
+#[utoipa::path(
+    get,
+    path = "/subscriptions/confirm",
+    tag = "public",
+    summary = "Confirm a subscription",
+    params(QueryParams),
+    responses(
+        (status = 200, description = "The subscription is confirmed"),
+        (status = 404, description = "The token is unknown"),
+    )
+)]
 #[tracing::instrument(
     name = "Confirming a pending subscription",
     skip(query_params, connection_pool)
@@ -0,0 +1,375 @@
+use std::fmt::Write;
+
+use actix_web::{http::header::ContentType, web, HttpResponse, ResponseError};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::StatusCode;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{DigestFrequency, SubscriberName},
+    error,
+    html_form::HtmlForm,
+    mailing_lists::{self, ListSelectionError},
+    utils,
+};
+
+#[derive(thiserror::Error)]
+pub enum PreferencesError {
+    #[error("No matching subscriber found for the provided token.")]
+    NotFound,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PreferencesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error::error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PreferencesError {
+    fn status_code(&self) -> reqwest::StatusCode {
+        match self {
+            PreferencesError::NotFound => StatusCode::NOT_FOUND,
+            PreferencesError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PreferencesQuery {
+    token: String,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct PreferencesFormData {
+    token: String,
+    name: String,
+    #[serde(default, rename = "list_id")]
+    list_ids: Vec<Uuid>,
+    digest_frequency: DigestFrequency,
+    /// `None` keeps the current pause, `0` resumes delivery.
+    pause_days: Option<u16>,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct UnsubscribeFormData {
+    token: String,
+}
+
+struct Preferences {
+    subscriber_id: Uuid,
+    name: String,
+    status: String,
+    digest_frequency: String,
+    paused_until: Option<DateTime<Utc>>,
+    list_ids: Vec<Uuid>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/subscriptions/preferences",
+    tag = "public",
+    summary = "Preferences page",
+    params(PreferencesQuery),
+    responses(
+        (status = 200, description = "The preferences of the subscriber the token belongs to", content_type = "text/html", body = String),
+        (status = 404, description = "The token is unknown"),
+    )
+)]
+#[tracing::instrument(
+    name = "Showing subscriber preferences",
+    skip(query_params, connection_pool, flash_messages)
+)]
+pub async fn preferences_form(
+    query_params: web::Query<PreferencesQuery>,
+    connection_pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, PreferencesError> {
+    let token = &query_params.token;
+    let preferences = get_preferences(&connection_pool, token)
+        .await?
+        .ok_or(PreferencesError::NotFound)?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    if preferences.status == "unsubscribed" {
+        return Ok(HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(format!(
+                include_str!("subscriptions_unsubscribed.html"),
+                msg_html = msg_html
+            )));
+    }
+
+    let lists = mailing_lists::get_lists(&connection_pool).await?;
+    let mut lists_html = String::new();
+    for list in lists {
+        writeln!(
+            lists_html,
+            r#"<label><input type="checkbox" name="list_id" value="{}"{}> {}</label><br>"#,
+            list.list_id,
+            if preferences.list_ids.contains(&list.list_id) {
+                " checked"
+            } else {
+                ""
+            },
+            htmlescape::encode_minimal(&list.name),
+        )
+        .unwrap();
+    }
+
+    let mut digest_html = String::new();
+    for frequency in DigestFrequency::ALL {
+        writeln!(
+            digest_html,
+            r#"<option value="{0}"{1}>{0}</option>"#,
+            frequency.as_str(),
+            if frequency.as_str() == preferences.digest_frequency {
+                " selected"
+            } else {
+                ""
+            },
+        )
+        .unwrap();
+    }
+
+    let pause_html = match preferences.paused_until {
+        Some(paused_until) if paused_until > Utc::now() => format!(
+            "<p>Your emails are paused until {}.</p>",
+            paused_until.format("%Y-%m-%d")
+        ),
+        _ => String::new(),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("subscriptions_preferences.html"),
+            msg_html = msg_html,
+            token = htmlescape::encode_minimal(token),
+            name = htmlescape::encode_minimal(&preferences.name),
+            lists_html = lists_html,
+            digest_html = digest_html,
+            pause_html = pause_html,
+        )))
+}
+
+#[utoipa::path(
+    post,
+    path = "/subscriptions/preferences",
+    tag = "public",
+    summary = "Update preferences",
+    request_body(content = PreferencesFormData, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Back to the preferences page, with a flash message"),
+        (status = 404, description = "The token is unknown"),
+    )
+)]
+#[tracing::instrument(name = "Updating subscriber preferences", skip(form, connection_pool))]
+pub async fn update_preferences(
+    form: HtmlForm<PreferencesFormData>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PreferencesError> {
+    let form = form.into_inner();
+    let preferences_page = format!("/subscriptions/preferences?token={}", form.token);
+    let preferences = get_preferences(&connection_pool, &form.token)
+        .await?
+        .ok_or(PreferencesError::NotFound)?;
+
+    let name = match SubscriberName::parse(form.name) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(utils::see_other(&preferences_page));
+        }
+    };
+    if form.list_ids.is_empty() {
+        FlashMessage::error("Pick at least one topic, or unsubscribe instead.").send();
+        return Ok(utils::see_other(&preferences_page));
+    }
+
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let list_ids = match mailing_lists::resolve_list_ids(&mut transaction, &form.list_ids).await {
+        Ok(list_ids) => list_ids,
+        Err(ListSelectionError::UnexpectedError(e)) => return Err(e.into()),
+        Err(e) => {
+            FlashMessage::error(e.to_string()).send();
+            return Ok(utils::see_other(&preferences_page));
+        }
+    };
+    let paused_until = match form.pause_days {
+        None => preferences.paused_until,
+        Some(0) => None,
+        Some(days) => Some(Utc::now() + Duration::days(days.into())),
+    };
+    update_subscriber(
+        &mut transaction,
+        preferences.subscriber_id,
+        &name,
+        form.digest_frequency,
+        paused_until,
+    )
+    .await?;
+    replace_memberships(&mut transaction, preferences.subscriber_id, &list_ids).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update subscriber preferences.")?;
+
+    FlashMessage::info("Your preferences have been updated.").send();
+    Ok(utils::see_other(&preferences_page))
+}
+
+#[utoipa::path(
+    post,
+    path = "/subscriptions/unsubscribe",
+    tag = "public",
+    summary = "Unsubscribe",
+    request_body(content = UnsubscribeFormData, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "To the preferences page, which confirms the unsubscription"),
+        (status = 404, description = "The token is unknown"),
+    )
+)]
+#[tracing::instrument(name = "Unsubscribing a subscriber", skip(form, connection_pool))]
+pub async fn unsubscribe(
+    form: web::Form<UnsubscribeFormData>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PreferencesError> {
+    let preferences = get_preferences(&connection_pool, &form.token)
+        .await?
+        .ok_or(PreferencesError::NotFound)?;
+
+    mark_as_unsubscribed(&connection_pool, preferences.subscriber_id).await?;
+
+    FlashMessage::info("You have been unsubscribed.").send();
+    Ok(utils::see_other(&format!(
+        "/subscriptions/preferences?token={}",
+        form.token
+    )))
+}
+
+#[tracing::instrument(name = "Get subscriber preferences", skip_all)]
+async fn get_preferences(
+    connection_pool: &PgPool,
+    token: &str,
+) -> Result<Option<Preferences>, anyhow::Error> {
+    let preferences = sqlx::query_as!(
+        Preferences,
+        r#"
+        SELECT
+            s.id AS subscriber_id,
+            s.name,
+            s.status,
+            s.digest_frequency,
+            s.paused_until,
+            ARRAY(
+                SELECT m.list_id
+                FROM t_list_memberships m
+                WHERE m.subscriber_id = s.id
+            ) AS "list_ids!"
+        FROM t_preference_tokens t
+        JOIN t_subscriptions s ON s.id = t.subscriber_id
+        WHERE t.preference_token = $1
+        "#,
+        token
+    )
+    .fetch_optional(connection_pool)
+    .await
+    .context("A database error was encountered when looking for subscriber preferences.")?;
+
+    Ok(preferences)
+}
+
+#[tracing::instrument(name = "Update subscriber", skip(transaction, name))]
+async fn update_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    name: &SubscriberName,
+    digest_frequency: DigestFrequency,
+    paused_until: Option<DateTime<Utc>>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE t_subscriptions
+        SET name = $1, digest_frequency = $2, paused_until = $3
+        WHERE id = $4
+        "#,
+        name.as_ref(),
+        digest_frequency.as_str(),
+        paused_until,
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("A database error was encountered when updating a subscriber.")?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Replace list memberships", skip(transaction))]
+async fn replace_memberships(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM t_list_memberships
+        WHERE subscriber_id = $1
+            AND NOT (list_id = ANY($2))
+        "#,
+        subscriber_id,
+        list_ids
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("A database error was encountered when removing list memberships.")?;
+    mailing_lists::add_memberships(transaction, subscriber_id, list_ids).await
+}
+
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(connection_pool))]
+pub async fn mark_as_unsubscribed(
+    connection_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    sqlx::query!(
+        "UPDATE t_subscriptions SET status = 'unsubscribed' WHERE id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("A database error was encountered when unsubscribing a subscriber.")?;
+    // issues still waiting in a digest must not go out anymore
+    sqlx::query!(
+        r#"
+        DELETE FROM t_issue_delivery_queue
+        WHERE subscriber_email = (SELECT email FROM t_subscriptions WHERE id = $1)
+        "#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("A database error was encountered when dropping pending deliveries.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber.")?;
+
+    Ok(())
+}
@@ -0,0 +1,348 @@
+use std::fmt::Write;
+
+use actix_web::{http::header::ContentType, web, HttpResponse, ResponseError};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use reqwest::StatusCode;
+use sqlx::PgPool;
+
+use crate::{
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    error,
+    personal_data::{self, ErasureRequester},
+    signed_token,
+    startup::{ApplicationBaseUrl, HmacSecret},
+    utils,
+};
+
+/// How long the links sent by email stay valid.
+const LINK_VALIDITY: Duration = Duration::hours(24);
+
+#[derive(thiserror::Error)]
+pub enum PrivacyError {
+    #[error("This link is invalid or has expired.")]
+    InvalidLink,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PrivacyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error::error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PrivacyError {
+    fn status_code(&self) -> reqwest::StatusCode {
+        match self {
+            PrivacyError::InvalidLink => StatusCode::BAD_REQUEST,
+            PrivacyError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PrivacyRequest {
+    Access,
+    Erasure,
+}
+
+impl PrivacyRequest {
+    fn path(&self) -> &'static str {
+        match self {
+            PrivacyRequest::Access => "/subscriptions/privacy/access",
+            PrivacyRequest::Erasure => "/subscriptions/privacy/erasure",
+        }
+    }
+}
+
+/// Payload of the signed links sent by email. Knowing an address is not
+/// enough to get at its data: the request has to be completed from its inbox.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct PrivacyToken {
+    email: String,
+    request: PrivacyRequest,
+    expires_at: i64,
+}
+
+impl PrivacyToken {
+    fn verify(
+        token: &str,
+        request: PrivacyRequest,
+        secret: &HmacSecret,
+    ) -> Result<PrivacyToken, PrivacyError> {
+        let token: PrivacyToken =
+            signed_token::verify(token, secret).map_err(|_| PrivacyError::InvalidLink)?;
+        if token.request != request || token.expires_at < Utc::now().timestamp() {
+            return Err(PrivacyError::InvalidLink);
+        }
+        Ok(token)
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct PrivacyFormData {
+    email: String,
+    request: PrivacyRequest,
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams, utoipa::ToSchema)]
+#[into_params(parameter_in = Query)]
+pub struct PrivacyTokenQuery {
+    token: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/subscriptions/privacy",
+    tag = "public",
+    summary = "Privacy request form",
+    responses(
+        (status = 200, description = "The form to ask for a copy or the erasure of personal data", content_type = "text/html", body = String),
+    )
+)]
+pub async fn privacy_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("subscriptions_privacy.html"),
+            msg_html = msg_html
+        ))
+}
+
+/// Emails a link to complete the request. The response is the same whether
+/// or not we hold data about the address, so that it cannot be used to find
+/// out who subscribes.
+#[utoipa::path(
+    post,
+    path = "/subscriptions/privacy",
+    tag = "public",
+    summary = "Request a privacy link",
+    request_body(content = PrivacyFormData, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Back to the form. A signed link is emailed if the address is known"),
+    )
+)]
+#[tracing::instrument(
+    name = "Requesting access to or erasure of personal data",
+    skip(form, connection_pool, email_client, base_url, hmac_secret),
+    fields(request = ?form.request)
+)]
+pub async fn request_privacy_link(
+    form: web::Form<PrivacyFormData>,
+    connection_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, PrivacyError> {
+    let PrivacyFormData { email, request } = form.into_inner();
+    if let Ok(email) = SubscriberEmail::parse(email.trim().to_owned()) {
+        if personal_data::holds_data_about(&connection_pool, email.as_ref()).await? {
+            let token = signed_token::sign(
+                &PrivacyToken {
+                    email: email.as_ref().to_owned(),
+                    request,
+                    expires_at: (Utc::now() + LINK_VALIDITY).timestamp(),
+                },
+                &hmac_secret,
+            );
+            send_privacy_email(&email_client, &email, &base_url, request, &token)
+                .await
+                .context("Failed to send a personal data request email.")?;
+        }
+    }
+
+    FlashMessage::info(
+        "If we hold data about this address, we have sent it a link to complete your request. \
+        The link is valid for 24 hours.",
+    )
+    .send();
+    Ok(utils::see_other("/subscriptions/privacy"))
+}
+
+async fn send_privacy_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &ApplicationBaseUrl,
+    request: PrivacyRequest,
+    token: &str,
+) -> Result<(), reqwest::Error> {
+    let link = format!(
+        "{}{}?token={}",
+        base_url.0,
+        request.path(),
+        urlencoding::encode(token)
+    );
+    let (subject, action) = match request {
+        PrivacyRequest::Access => ("Your personal data", "download the data we hold about you"),
+        PrivacyRequest::Erasure => (
+            "Erase your personal data",
+            "erase the data we hold about you",
+        ),
+    };
+    email_client
+        .send_email(
+            email,
+            subject,
+            &format!(
+                r#"<p>Click <a href="{}">here</a> to {}.</p>
+            <p>If you did not ask for this, you can ignore this email.</p>"#,
+                link, action
+            ),
+            &format!(
+                "Visit {} to {}.\nIf you did not ask for this, you can ignore this email.",
+                link, action
+            ),
+        )
+        .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/subscriptions/privacy/access",
+    tag = "public",
+    summary = "Download personal data",
+    params(PrivacyTokenQuery),
+    responses(
+        (status = 200, description = "Everything stored about the address", content_type = "application/json"),
+        (status = 400, description = "The link is invalid or has expired"),
+    )
+)]
+#[tracing::instrument(
+    name = "Downloading personal data",
+    skip(query, connection_pool, hmac_secret)
+)]
+pub async fn download_personal_data(
+    query: web::Query<PrivacyTokenQuery>,
+    connection_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, PrivacyError> {
+    let token = PrivacyToken::verify(&query.token, PrivacyRequest::Access, &hmac_secret)?;
+    let bundle = personal_data::collect(&connection_pool, &token.email).await?;
+    Ok(personal_data_response(&bundle))
+}
+
+/// Shared with the admin action, so that both produce the same bundle.
+pub fn personal_data_response(bundle: &serde_json::Value) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .insert_header((
+            "Content-Disposition",
+            "attachment; filename=\"personal-data.json\"",
+        ))
+        .body(serde_json::to_string_pretty(bundle).expect("Failed to serialize JSON"))
+}
+
+/// Erasure is confirmed with a form, so that link previews and prefetching
+/// cannot trigger it.
+#[utoipa::path(
+    get,
+    path = "/subscriptions/privacy/erasure",
+    tag = "public",
+    summary = "Erasure confirmation page",
+    params(PrivacyTokenQuery),
+    responses(
+        (status = 200, description = "Asks to confirm the erasure", content_type = "text/html", body = String),
+        (status = 400, description = "The link is invalid or has expired"),
+    )
+)]
+pub async fn erasure_form(
+    query: web::Query<PrivacyTokenQuery>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, PrivacyError> {
+    let token = PrivacyToken::verify(&query.token, PrivacyRequest::Erasure, &hmac_secret)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("subscriptions_erasure.html"),
+            email = htmlescape::encode_minimal(&token.email),
+            token = htmlescape::encode_minimal(&query.token),
+        )))
+}
+
+#[utoipa::path(
+    post,
+    path = "/subscriptions/privacy/erasure",
+    tag = "public",
+    summary = "Erase personal data",
+    request_body(content = PrivacyTokenQuery, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "The personal data has been erased", content_type = "text/html", body = String),
+        (status = 400, description = "The link is invalid or has expired"),
+    )
+)]
+#[tracing::instrument(
+    name = "Erasing personal data on request",
+    skip(form, connection_pool, hmac_secret)
+)]
+pub async fn erase_personal_data(
+    form: web::Form<PrivacyTokenQuery>,
+    connection_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, PrivacyError> {
+    let token = PrivacyToken::verify(&form.token, PrivacyRequest::Erasure, &hmac_secret)?;
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    personal_data::erase(&mut transaction, &token.email, ErasureRequester::Subscriber).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase personal data.")?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(include_str!("subscriptions_erased.html")))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    use super::{PrivacyRequest, PrivacyToken};
+    use crate::{signed_token, startup::HmacSecret};
+
+    fn token(request: PrivacyRequest, expires_in: Duration, secret: &HmacSecret) -> String {
+        signed_token::sign(
+            &PrivacyToken {
+                email: "ursula@example.com".into(),
+                request,
+                expires_at: (Utc::now() + expires_in).timestamp(),
+            },
+            secret,
+        )
+    }
+
+    #[test]
+    fn a_token_is_only_valid_for_its_request_and_until_it_expires() {
+        let secret = HmacSecret(Secret::new("top-secret".into()));
+        let access = token(PrivacyRequest::Access, Duration::hours(1), &secret);
+        assert_ok!(PrivacyToken::verify(
+            &access,
+            PrivacyRequest::Access,
+            &secret
+        ));
+        assert_err!(PrivacyToken::verify(
+            &access,
+            PrivacyRequest::Erasure,
+            &secret
+        ));
+
+        let expired = token(PrivacyRequest::Access, -Duration::hours(1), &secret);
+        assert_err!(PrivacyToken::verify(
+            &expired,
+            PrivacyRequest::Access,
+            &secret
+        ));
+    }
+}
@@ -0,0 +1,93 @@
+"use strict";
+// Served wrapped in a function, which is given `config.action`, the URL to
+// post the form to, and `config.formToken`, signed as the script was served.
+var script = document.currentScript;
+var form = document.createElement("form");
+form.className = "zero2prod-signup";
+form.action = config.action;
+form.method = "post";
+form.noValidate = true;
+
+function field(label, name, type) {
+    var wrapper = document.createElement("p");
+    var labelElement = document.createElement("label");
+    var input = document.createElement("input");
+    var error = document.createElement("small");
+    labelElement.textContent = label + " ";
+    input.type = type;
+    input.name = name;
+    input.required = true;
+    error.className = "zero2prod-signup-error";
+    error.setAttribute("data-field", name);
+    labelElement.appendChild(input);
+    wrapper.appendChild(labelElement);
+    wrapper.appendChild(error);
+    form.appendChild(wrapper);
+}
+
+field("Name", "name", "text");
+field("Email", "email", "email");
+
+// The honeypot: hidden from people, and from screen readers.
+var honeypot = document.createElement("div");
+honeypot.style.display = "none";
+honeypot.setAttribute("aria-hidden", "true");
+honeypot.innerHTML =
+    '<label>Leave this field empty <input type="text" name="website" tabindex="-1" autocomplete="off"></label>';
+form.appendChild(honeypot);
+
+var token = document.createElement("input");
+token.type = "hidden";
+token.name = "form_token";
+token.value = config.formToken;
+form.appendChild(token);
+
+var button = document.createElement("button");
+button.type = "submit";
+button.textContent = "Subscribe";
+form.appendChild(button);
+
+var message = document.createElement("p");
+message.className = "zero2prod-signup-message";
+message.setAttribute("role", "status");
+form.appendChild(message);
+
+form.addEventListener("submit", function (event) {
+    event.preventDefault();
+    button.disabled = true;
+    message.textContent = "";
+    form.querySelectorAll(".zero2prod-signup-error").forEach(function (error) {
+        error.textContent = "";
+    });
+    // A form body keeps this a simple cross-origin request, without a preflight.
+    fetch(config.action, {
+        method: "POST",
+        headers: { Accept: "application/json" },
+        body: new URLSearchParams(new FormData(form)),
+    })
+        .then(function (response) {
+            return response.json().then(function (body) {
+                if (response.ok) {
+                    form.replaceChildren(message);
+                    message.textContent =
+                        "Thank you for subscribing! Please check your inbox to confirm your subscription.";
+                    return;
+                }
+                (body.errors || []).forEach(function (error) {
+                    var element = form.querySelector('[data-field="' + error.field + '"]');
+                    if (element) {
+                        element.textContent = error.message;
+                    }
+                });
+                message.textContent = body.errors ? "" : body.detail;
+            });
+        })
+        .catch(function () {
+            message.textContent = "Something went wrong. Please try again.";
+        })
+        .finally(function () {
+            button.disabled = false;
+        });
+});
+
+script.parentNode.insertBefore(form, script);
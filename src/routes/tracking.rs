@@ -0,0 +1,178 @@
+use actix_web::{
+    http::header::{CacheControl, CacheDirective},
+    web, HttpResponse, ResponseError,
+};
+use anyhow::Context;
+use reqwest::{header::LOCATION, StatusCode};
+use sqlx::PgPool;
+
+use crate::{
+    error, signed_token,
+    startup::HmacSecret,
+    tracking::{ClickToken, OpenToken, Tracker, TRACKING_PIXEL},
+};
+
+#[derive(thiserror::Error)]
+pub enum TrackingError {
+    #[error("The tracking link is invalid.")]
+    InvalidToken(#[source] signed_token::SignedTokenError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for TrackingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error::error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for TrackingError {
+    fn status_code(&self) -> reqwest::StatusCode {
+        match self {
+            TrackingError::InvalidToken(_) => StatusCode::NOT_FOUND,
+            TrackingError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/t/c/{token}",
+    tag = "public",
+    summary = "Follow a tracked link",
+    params(("token" = String, Path, description = "Signed link token")),
+    responses(
+        (status = 302, description = "To the original link, once the click is recorded"),
+        (status = 404, description = "The token is invalid"),
+    )
+)]
+#[tracing::instrument(
+    name = "Tracking a link click",
+    skip(token, connection_pool, hmac_secret),
+    fields(newsletter_issue_id=tracing::field::Empty, subscriber_id=tracing::field::Empty)
+)]
+pub async fn track_click(
+    token: web::Path<String>,
+    connection_pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, TrackingError> {
+    let click: ClickToken =
+        signed_token::verify(&token, &hmac_secret).map_err(TrackingError::InvalidToken)?;
+    tracing::Span::current()
+        .record(
+            "newsletter_issue_id",
+            tracing::field::display(&click.newsletter_issue_id),
+        )
+        .record(
+            "subscriber_id",
+            tracing::field::display(&click.subscriber_id),
+        );
+
+    // A reader should always reach the link, even if we fail to record the click.
+    if let Err(e) = record_click(&connection_pool, &click).await {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to record a link click.",
+        );
+    }
+
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, click.url))
+        .finish())
+}
+
+#[tracing::instrument(name = "Recording a link click", skip_all)]
+async fn record_click(connection_pool: &PgPool, click: &ClickToken) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO t_link_clicks (newsletter_issue_id, subscriber_id, url, clicked_at)
+        VALUES ($1, $2, $3, now())
+        "#,
+        click.newsletter_issue_id,
+        click.subscriber_id,
+        click.url,
+    )
+    .execute(connection_pool)
+    .await
+    .context("A database error was encountered while recording a link click.")?;
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/t/o/{token}",
+    tag = "public",
+    summary = "Tracking pixel",
+    params(("token" = String, Path, description = "Signed open token")),
+    responses(
+        (status = 200, description = "A transparent pixel, once the open is recorded", content_type = "image/gif"),
+        (status = 404, description = "The token is invalid"),
+    )
+)]
+#[tracing::instrument(
+    name = "Tracking an email open",
+    skip(token, connection_pool, tracker),
+    fields(newsletter_issue_id=tracing::field::Empty, subscriber_id=tracing::field::Empty)
+)]
+pub async fn track_open(
+    token: web::Path<String>,
+    connection_pool: web::Data<PgPool>,
+    tracker: web::Data<Tracker>,
+) -> Result<HttpResponse, TrackingError> {
+    let open: OpenToken =
+        signed_token::verify(&token, tracker.hmac_secret()).map_err(TrackingError::InvalidToken)?;
+    tracing::Span::current()
+        .record(
+            "newsletter_issue_id",
+            tracing::field::display(&open.newsletter_issue_id),
+        )
+        .record(
+            "subscriber_id",
+            tracing::field::display(&open.subscriber_id),
+        );
+
+    // Pixels embedded before open tracking was switched off keep rendering,
+    // but nothing is recorded for them anymore.
+    if tracker.track_opens() {
+        if let Err(e) = record_open(&connection_pool, &open).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to record an email open.",
+            );
+        }
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(TRACKING_PIXEL))
+}
+
+#[tracing::instrument(name = "Recording an email open", skip_all)]
+async fn record_open(connection_pool: &PgPool, open: &OpenToken) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO t_email_opens (
+            newsletter_issue_id,
+            subscriber_id,
+            open_count,
+            first_opened_at,
+            last_opened_at
+        )
+        VALUES ($1, $2, 1, now(), now())
+        ON CONFLICT (newsletter_issue_id, subscriber_id) DO UPDATE
+        SET open_count = t_email_opens.open_count + 1,
+            last_opened_at = now()
+        "#,
+        open.newsletter_issue_id,
+        open.subscriber_id,
+    )
+    .execute(connection_pool)
+    .await
+    .context("A database error was encountered while recording an email open.")?;
+
+    Ok(())
+}
@@ -0,0 +1,289 @@
+//! A small filter language used to target a subset of the audience.
+//!
+//! A segment is a whitespace separated list of `field:value` terms, all of
+//! which must match. Prefixing a term with `-` negates it and values
+//! containing spaces can be double quoted:
+//!
+//! ```text
+//! tag:beta-tester -tag:churned subscribed_after:2026-03-01 list:"Rust news"
+//! ```
+//!
+//! Supported fields are `tag`, `list` (by name), `subscribed_after`
+//! (inclusive), `subscribed_before` (exclusive) and `attr.<key>` for custom
+//! attributes (compared as text, e.g. `attr.beta:true`); dates are either
+//! `YYYY-MM-DD` or RFC 3339 timestamps. An empty segment matches every
+//! subscriber. Issues are only sent to confirmed subscribers, so there is no
+//! `status` field.
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use sqlx::{Postgres, QueryBuilder};
+
+use crate::{attributes, domain::SubscriberTag};
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum SegmentParseError {
+    #[error("Expected a `field:value` filter, found `{0}`.")]
+    MissingValue(String),
+    #[error("`{0}` is not a known filter field.")]
+    UnknownField(String),
+    #[error("Issues are only sent to confirmed subscribers: `status` cannot be filtered on.")]
+    StatusFilter,
+    #[error("`{0}` is not a valid date.")]
+    InvalidDate(String),
+    #[error("{0}")]
+    InvalidTag(String),
+    #[error("A quoted value is missing its closing quote.")]
+    UnterminatedQuote,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Segment {
+    terms: Vec<Term>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Term {
+    negated: bool,
+    criterion: Criterion,
+}
+
+#[derive(Debug, PartialEq)]
+enum Criterion {
+    Tag(SubscriberTag),
+    List(String),
+    SubscribedAfter(NaiveDateTime),
+    SubscribedBefore(NaiveDateTime),
+    Attribute { key: String, value: String },
+}
+
+impl Segment {
+    pub fn parse(s: &str) -> Result<Segment, SegmentParseError> {
+        let terms = tokenize(s)?
+            .into_iter()
+            .map(parse_term)
+            .collect::<Result<_, _>>()?;
+        Ok(Segment { terms })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Appends one `AND ...` clause per term, binding every value as a query
+    /// parameter. The query must expose `t_subscriptions` under the alias `s`.
+    pub fn push_sql_filter(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        for term in &self.terms {
+            query.push(" AND ");
+            if term.negated {
+                query.push("NOT ");
+            }
+            match &term.criterion {
+                Criterion::Tag(tag) => {
+                    query
+                        .push(
+                            "EXISTS (SELECT 1 FROM t_subscriber_tags st \
+                            WHERE st.subscriber_id = s.id AND st.tag = ",
+                        )
+                        .push_bind(tag.as_ref().to_owned())
+                        .push(")");
+                }
+                Criterion::List(name) => {
+                    query
+                        .push(
+                            "EXISTS (SELECT 1 FROM t_list_memberships lm \
+                            JOIN t_lists l ON l.list_id = lm.list_id \
+                            WHERE lm.subscriber_id = s.id AND l.name = ",
+                        )
+                        .push_bind(name.clone())
+                        .push(")");
+                }
+                Criterion::SubscribedAfter(at) => {
+                    query.push("(s.subscribed_at >= ").push_bind(*at).push(")");
+                }
+                Criterion::SubscribedBefore(at) => {
+                    query.push("(s.subscribed_at < ").push_bind(*at).push(")");
+                }
+                // Subscribers without the attribute compare as NULL with `=`,
+                // which `NOT` would leave NULL and so drop from negated terms.
+                Criterion::Attribute { key, value } => {
+                    query
+                        .push("((s.attributes ->> ")
+                        .push_bind(key.clone())
+                        .push(") IS NOT DISTINCT FROM ")
+                        .push_bind(value.clone())
+                        .push(")");
+                }
+            }
+        }
+    }
+}
+
+/// Splits on whitespace, keeping double quoted sections together and
+/// dropping the quotes themselves.
+fn tokenize(s: &str) -> Result<Vec<String>, SegmentParseError> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for ch in s.chars() {
+        match ch {
+            '"' => in_quotes = !in_quotes,
+            ch if ch.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            ch => current.push(ch),
+        }
+    }
+    if in_quotes {
+        return Err(SegmentParseError::UnterminatedQuote);
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+fn parse_term(token: String) -> Result<Term, SegmentParseError> {
+    let (negated, term) = match token.strip_prefix('-') {
+        Some(term) => (true, term),
+        None => (false, token.as_str()),
+    };
+    let (field, value) = match term.split_once(':') {
+        Some((field, value)) if !value.is_empty() => (field, value),
+        _ => return Err(SegmentParseError::MissingValue(token)),
+    };
+    let criterion = match field {
+        "tag" => Criterion::Tag(
+            SubscriberTag::parse(value.to_owned()).map_err(SegmentParseError::InvalidTag)?,
+        ),
+        "status" => return Err(SegmentParseError::StatusFilter),
+        "list" => Criterion::List(value.to_owned()),
+        "subscribed_after" => Criterion::SubscribedAfter(parse_date(value)?),
+        "subscribed_before" => Criterion::SubscribedBefore(parse_date(value)?),
+        field => match field.strip_prefix("attr.").map(attributes::parse_key) {
+            Some(Ok(key)) => Criterion::Attribute {
+                key,
+                value: value.to_owned(),
+            },
+            _ => return Err(SegmentParseError::UnknownField(field.to_owned())),
+        },
+    };
+    Ok(Term { negated, criterion })
+}
+
+fn parse_date(value: &str) -> Result<NaiveDateTime, SegmentParseError> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap());
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|at| at.naive_utc())
+        .map_err(|_| SegmentParseError::InvalidDate(value.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::assert_ok;
+    use sqlx::{Postgres, QueryBuilder};
+
+    use super::{Segment, SegmentParseError};
+
+    fn to_sql(segment: &str) -> String {
+        let mut query = QueryBuilder::<Postgres>::new("WHERE true");
+        Segment::parse(segment).unwrap().push_sql_filter(&mut query);
+        query.into_sql()
+    }
+
+    #[test]
+    fn empty_segment_matches_everybody() {
+        assert!(Segment::parse("   ").unwrap().is_empty());
+        assert_eq!(to_sql(""), "WHERE true");
+    }
+
+    #[test]
+    fn every_supported_field_is_parsed() {
+        assert_ok!(Segment::parse(
+            "tag:beta -tag:churned list:\"Rust news\" \
+            subscribed_after:2026-03-01 subscribed_before:2026-06-01T12:00:00Z attr.seats:5"
+        ));
+    }
+
+    #[test]
+    fn values_are_bound_as_parameters() {
+        let sql = to_sql("tag:beta list:\"Robert'); DROP TABLE t_lists;--\"");
+        assert!(sql.contains("st.tag = $1"));
+        assert!(sql.contains("l.name = $2"));
+        assert!(!sql.contains("DROP"));
+    }
+
+    #[test]
+    fn attribute_keys_are_bound_as_parameters_too() {
+        assert_eq!(
+            to_sql("attr.company:Acme"),
+            "WHERE true AND ((s.attributes ->> $1) IS NOT DISTINCT FROM $2)"
+        );
+        assert_eq!(
+            Segment::parse("attr.x');--:1").unwrap_err(),
+            SegmentParseError::UnknownField("attr.x');--".into())
+        );
+    }
+
+    #[test]
+    fn negated_terms_are_wrapped_in_not() {
+        assert_eq!(
+            to_sql("-list:Rust"),
+            "WHERE true AND NOT EXISTS (SELECT 1 FROM t_list_memberships lm \
+            JOIN t_lists l ON l.list_id = lm.list_id \
+            WHERE lm.subscriber_id = s.id AND l.name = $1)"
+        );
+    }
+
+    #[test]
+    fn negated_attribute_terms_match_subscribers_without_the_attribute() {
+        assert_eq!(
+            to_sql("-attr.beta:true"),
+            "WHERE true AND NOT ((s.attributes ->> $1) IS NOT DISTINCT FROM $2)"
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        assert_eq!(
+            Segment::parse("country:fr").unwrap_err(),
+            SegmentParseError::UnknownField("country".into())
+        );
+    }
+
+    #[test]
+    fn terms_without_a_value_are_rejected() {
+        assert_eq!(
+            Segment::parse("beta").unwrap_err(),
+            SegmentParseError::MissingValue("beta".into())
+        );
+        assert_eq!(
+            Segment::parse("tag:").unwrap_err(),
+            SegmentParseError::MissingValue("tag:".into())
+        );
+    }
+
+    #[test]
+    fn status_cannot_be_filtered_on() {
+        for segment in ["status:confirmed", "-status:pending_confirmation"] {
+            assert_eq!(
+                Segment::parse(segment).unwrap_err(),
+                SegmentParseError::StatusFilter
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert_eq!(
+            Segment::parse("subscribed_after:March").unwrap_err(),
+            SegmentParseError::InvalidDate("March".into())
+        );
+        assert_eq!(
+            Segment::parse("list:\"Rust news").unwrap_err(),
+            SegmentParseError::UnterminatedQuote
+        );
+    }
+}
@@ -2,12 +2,51 @@ use std::future::{ready, Ready};
 
 use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
 use actix_web::FromRequest;
+use chrono::{DateTime, Duration, Utc};
 use uuid::Uuid;
 
 pub struct TypedSession(Session);
 
+/// Set once the password of a user with two-factor authentication has been
+/// checked. The user is not logged in until they also enter a code.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PendingSecondFactor {
+    pub user_id: Uuid,
+    /// As entered, so that incorrect codes are throttled like passwords.
+    pub username: String,
+    started_at: i64,
+    pub failed_attempts: u32,
+    /// Where to go once the code has been entered.
+    pub return_to: Option<String>,
+}
+
+impl PendingSecondFactor {
+    /// How long the user has to enter a code after their password.
+    const VALIDITY: Duration = Duration::minutes(5);
+
+    pub fn new(user_id: Uuid, username: String, return_to: Option<&str>) -> Self {
+        Self {
+            user_id,
+            username,
+            started_at: Utc::now().timestamp_micros(),
+            failed_attempts: 0,
+            return_to: return_to.map(str::to_owned),
+        }
+    }
+
+    pub fn has_expired(&self) -> bool {
+        DateTime::from_timestamp_micros(self.started_at)
+            .is_none_or(|started_at| started_at + Self::VALIDITY < Utc::now())
+    }
+}
+
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const LOGGED_IN_AT_KEY: &'static str = "logged_in_at";
+    const LAST_SEEN_AT_KEY: &'static str = "last_seen_at";
+    const SESSION_ID_KEY: &'static str = "session_id";
+    const PENDING_SECOND_FACTOR_KEY: &'static str = "pending_second_factor";
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
 
     pub fn renew(&self) {
         self.0.renew();
@@ -21,6 +60,63 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    /// Stored in microseconds since the epoch, the precision of Postgres timestamps.
+    pub fn insert_logged_in_at(&self, at: DateTime<Utc>) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::LOGGED_IN_AT_KEY, at.timestamp_micros())
+    }
+
+    pub fn get_logged_in_at(&self) -> Result<Option<DateTime<Utc>>, SessionGetError> {
+        Ok(self
+            .0
+            .get(Self::LOGGED_IN_AT_KEY)?
+            .and_then(DateTime::from_timestamp_micros))
+    }
+
+    pub fn insert_last_seen_at(&self, at: DateTime<Utc>) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::LAST_SEEN_AT_KEY, at.timestamp_micros())
+    }
+
+    pub fn get_last_seen_at(&self) -> Result<Option<DateTime<Utc>>, SessionGetError> {
+        Ok(self
+            .0
+            .get(Self::LAST_SEEN_AT_KEY)?
+            .and_then(DateTime::from_timestamp_micros))
+    }
+
+    /// The id of the record of this session in Postgres.
+    pub fn insert_session_id(&self, session_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SESSION_ID_KEY, session_id)
+    }
+
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    pub fn insert_pending_second_factor(
+        &self,
+        pending: &PendingSecondFactor,
+    ) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_SECOND_FACTOR_KEY, pending)
+    }
+
+    pub fn get_pending_second_factor(
+        &self,
+    ) -> Result<Option<PendingSecondFactor>, SessionGetError> {
+        self.0.get(Self::PENDING_SECOND_FACTOR_KEY)
+    }
+
+    pub fn remove_pending_second_factor(&self) {
+        self.0.remove(Self::PENDING_SECOND_FACTOR_KEY);
+    }
+
+    pub fn insert_csrf_token(&self, token: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::CSRF_TOKEN_KEY, token)
+    }
+
+    pub fn get_csrf_token(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::CSRF_TOKEN_KEY)
+    }
+
     pub fn log_out(self) {
         self.0.purge();
     }
@@ -0,0 +1,96 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::Sha256;
+
+use crate::startup::HmacSecret;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum SignedTokenError {
+    #[error("The token is malformed.")]
+    Malformed,
+    #[error("The token signature is invalid.")]
+    InvalidSignature,
+}
+
+/// Serializes `payload` and appends an HMAC-SHA256 tag, producing a URL-safe token
+/// of the form `<payload>.<tag>`.
+pub fn sign<T: Serialize>(payload: &T, secret: &HmacSecret) -> String {
+    let payload = serde_json::to_vec(payload).expect("Failed to serialize token payload");
+    let payload = URL_SAFE_NO_PAD.encode(payload);
+    let tag = URL_SAFE_NO_PAD.encode(mac(secret, &payload).finalize().into_bytes());
+
+    format!("{payload}.{tag}")
+}
+
+/// Checks the tag of a token produced by [`sign`] and deserializes its payload.
+pub fn verify<T: DeserializeOwned>(
+    token: &str,
+    secret: &HmacSecret,
+) -> Result<T, SignedTokenError> {
+    let (payload, tag) = token.split_once('.').ok_or(SignedTokenError::Malformed)?;
+    let tag = URL_SAFE_NO_PAD
+        .decode(tag)
+        .map_err(|_| SignedTokenError::Malformed)?;
+    mac(secret, payload)
+        .verify_slice(&tag)
+        .map_err(|_| SignedTokenError::InvalidSignature)?;
+
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload)
+        .map_err(|_| SignedTokenError::Malformed)?;
+    serde_json::from_slice(&payload).map_err(|_| SignedTokenError::Malformed)
+}
+
+fn mac(secret: &HmacSecret, payload: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.0.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+
+    use super::{sign, verify};
+    use crate::startup::HmacSecret;
+
+    fn secret(s: &str) -> HmacSecret {
+        HmacSecret(Secret::new(s.to_owned()))
+    }
+
+    #[test]
+    fn a_signed_payload_round_trips() {
+        let secret = secret("top-secret");
+        let token = sign(&("issue", 42), &secret);
+        assert_ok_eq!(
+            verify::<(String, i32)>(&token, &secret),
+            ("issue".into(), 42)
+        );
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let token = sign(&42, &secret("top-secret"));
+        assert_err!(verify::<i32>(&token, &secret("another-secret")));
+    }
+
+    #[test]
+    fn a_tampered_payload_is_rejected() {
+        let secret = secret("top-secret");
+        let token = sign(&42, &secret);
+        let (_, tag) = token.split_once('.').unwrap();
+        let forged = format!("{}.{}", sign(&43, &secret).split_once('.').unwrap().0, tag);
+        assert_err!(verify::<i32>(&forged, &secret));
+    }
+
+    #[test]
+    fn a_token_without_a_tag_is_rejected() {
+        assert_err!(verify::<i32>("NDI", &secret("top-secret")));
+    }
+}
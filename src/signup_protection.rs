@@ -0,0 +1,159 @@
+//! Keeps bots from signing up, without relying on a third-party service:
+//!
+//! - the signup forms have a honeypot field, hidden from people, that bots
+//!   tend to fill in;
+//! - they carry a signed timestamp of when they were shown, and signups sent
+//!   faster than a person could type are turned down;
+//! - signups are rate limited per client address.
+//!
+//! It also lists the sites the signup widget may be embedded in.
+use actix_cors::Cors;
+use actix_web::http::header::{ACCEPT, CONTENT_TYPE};
+use anyhow::Context;
+use chrono::Utc;
+use redis::aio::ConnectionManager;
+use secrecy::{ExposeSecret, Secret};
+use uuid::Uuid;
+
+use crate::{configuration::SignupProtectionSettings, signed_token, startup::HmacSecret};
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum FormTokenError {
+    #[error("The form is invalid. Please reload the page and try again.")]
+    Invalid,
+    #[error("The form was sent too quickly. Please wait a few seconds and try again.")]
+    TooFast,
+    #[error("The form has expired. Please reload the page and try again.")]
+    Expired,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct FormShown {
+    /// Unix timestamp, in seconds.
+    at: i64,
+}
+
+#[derive(Clone)]
+pub struct SignupProtection {
+    redis: ConnectionManager,
+    hmac_secret: HmacSecret,
+    settings: SignupProtectionSettings,
+}
+
+impl SignupProtection {
+    pub async fn new(
+        redis_uri: &Secret<String>,
+        hmac_secret: HmacSecret,
+        settings: SignupProtectionSettings,
+    ) -> Result<Self, anyhow::Error> {
+        let redis = redis::Client::open(redis_uri.expose_secret().as_str())
+            .context("Invalid Redis URI.")?
+            .get_connection_manager()
+            .await
+            .context("Failed to connect to Redis.")?;
+        Ok(Self {
+            redis,
+            hmac_secret,
+            settings,
+        })
+    }
+
+    /// A token to embed in a signup form as it is shown.
+    pub fn form_token(&self) -> String {
+        signed_token::sign(
+            &FormShown {
+                at: Utc::now().timestamp(),
+            },
+            &self.hmac_secret,
+        )
+    }
+
+    /// Checks that the form the token was embedded in was filled in at a
+    /// human pace.
+    pub fn check_form_token(&self, token: &str) -> Result<(), FormTokenError> {
+        let shown: FormShown =
+            signed_token::verify(token, &self.hmac_secret).map_err(|_| FormTokenError::Invalid)?;
+        check_fill_time(&self.settings, Utc::now().timestamp() - shown.at)
+    }
+
+    /// Counts a signup attempt from `address`, and tells whether there have
+    /// been too many of them lately.
+    #[tracing::instrument(name = "Count signup attempts", skip(self))]
+    pub async fn is_throttled(&self, address: &str) -> Result<bool, anyhow::Error> {
+        let now = Utc::now().timestamp_millis();
+        let window_millis = self.settings.window_seconds as i64 * 1000;
+        let key = format!("{}:address:{}", self.settings.key_prefix, address);
+        let attempt = format!("{}-{}", now, Uuid::new_v4());
+
+        let (attempts,): (u32,) = redis::pipe()
+            .atomic()
+            .zadd(&key, &attempt, now)
+            .ignore()
+            .zrembyscore(&key, "-inf", now - window_millis)
+            .ignore()
+            .pexpire(&key, window_millis)
+            .ignore()
+            .zcard(&key)
+            .query_async(&mut self.redis.clone())
+            .await
+            .context("Failed to record a signup attempt in Redis.")?;
+        if attempts > self.settings.max_attempts_per_address {
+            tracing::warn!(attempts, "Signups from this address are throttled");
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Lets the allowed origins fetch form tokens and read the responses to
+    /// signups posted from their pages, and send them as JSON. Other origins
+    /// are not refused, they just cannot read the response, as for any
+    /// cross-site form.
+    pub fn cors(&self) -> Cors {
+        self.settings.allowed_origins.iter().fold(
+            Cors::default()
+                .allowed_methods(["GET", "POST"])
+                .allowed_headers([ACCEPT, CONTENT_TYPE])
+                .block_on_origin_mismatch(false)
+                .max_age(3600),
+            |cors, origin| cors.allowed_origin(origin),
+        )
+    }
+}
+
+fn check_fill_time(
+    settings: &SignupProtectionSettings,
+    elapsed_seconds: i64,
+) -> Result<(), FormTokenError> {
+    if elapsed_seconds < settings.min_fill_seconds {
+        Err(FormTokenError::TooFast)
+    } else if elapsed_seconds > settings.max_fill_seconds {
+        Err(FormTokenError::Expired)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_fill_time, FormTokenError};
+    use crate::configuration::SignupProtectionSettings;
+
+    #[test]
+    fn forms_must_be_filled_in_at_a_human_pace() {
+        let settings = SignupProtectionSettings {
+            allowed_origins: Vec::new(),
+            min_fill_seconds: 3,
+            max_fill_seconds: 86400,
+            key_prefix: "test".into(),
+            window_seconds: 3600,
+            max_attempts_per_address: 10,
+        };
+        assert_eq!(check_fill_time(&settings, 0), Err(FormTokenError::TooFast));
+        assert_eq!(check_fill_time(&settings, 3), Ok(()));
+        assert_eq!(check_fill_time(&settings, 86400), Ok(()));
+        assert_eq!(
+            check_fill_time(&settings, 86401),
+            Err(FormTokenError::Expired)
+        );
+    }
+}
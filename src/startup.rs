@@ -6,16 +6,41 @@ use actix_web_flash_messages::{storage::CookieMessageStore, FlashMessagesFramewo
 use secrecy::{ExposeSecret, Secret};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use tracing_actix_web::TracingLogger;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
 
 use crate::{
-    authentication::reject_anonymous_users,
-    configuration::{DatabaseSettings, Settings},
+    api_error::extractor_error,
+    authentication::{
+        authenticate_api_tokens, authorize, reject_anonymous_users,
+        reject_requests_without_api_token, verify_csrf_token, LoginThrottle, PasswordHashing,
+        PasswordPolicy,
+    },
+    client_address::TrustedProxies,
+    configuration::{DatabaseSettings, SessionTimeoutSettings, Settings, SubscriberImportSettings},
     email_client::EmailClient,
+    openapi::{ApiDoc, OPENAPI_JSON_PATH},
     routes::{
-        admin_dashboard, change_password, change_password_form, confirm_subscription,
-        get_newsletter_form, health_check, home, log_out, login, login_form, publish_newsletter,
-        subscribe,
+        admin_dashboard, api, change_password, change_password_form, change_user_role,
+        confirm_subscriber, confirm_subscription, confirm_two_factor, count_newsletter_recipients,
+        create_api_token, create_attribute_definition, create_mailing_list, delete_draft,
+        delete_subscriber, delete_user, disable_user, download_import_rejections,
+        download_personal_data, enable_user, erase_personal_data, erase_subscriber, erasure_form,
+        export_issue_deliveries, export_subscriber_data, export_subscribers, get_newsletter_form,
+        health_check, home, import_subscribers, import_subscribers_form, invite_user,
+        issue_details, list_api_tokens, list_attribute_definitions, list_issues,
+        list_mailing_lists, list_sessions, list_subscribers, list_tags, list_users, log_out, login,
+        login_form, login_second_factor, login_second_factor_form, password_reset_form,
+        preferences_form, privacy_form, publish_newsletter, regenerate_recovery_codes,
+        request_password_reset, request_privacy_link, resend_confirmation, resend_invitation,
+        reset_password, reset_password_form, revoke_api_token, revoke_other_user_sessions,
+        revoke_user_session, save_draft, set_password, set_password_form, set_up_two_factor,
+        signup_form_token, signup_widget, subscribe, subscribe_form, subscriber_details,
+        subscription_thank_you, track_click, track_open, turn_off_two_factor, two_factor_settings,
+        unsubscribe, unsubscribe_subscriber, update_preferences, update_subscriber_tags,
     },
+    signup_protection::SignupProtection,
+    tracking::Tracker,
 };
 
 pub struct Application {
@@ -39,6 +64,24 @@ impl Application {
         );
         let tcp_listener = TcpListener::bind(address)?;
         let port = tcp_listener.local_addr().unwrap().port();
+        let tracker = Tracker::new(
+            configuration.application.base_url.clone(),
+            configuration.application.hmac_secret.clone(),
+            &configuration.tracking,
+        );
+        let login_throttle = LoginThrottle::new(
+            &configuration.redis_uri,
+            configuration.login_throttle.clone(),
+        )
+        .await?;
+        let signup_protection = SignupProtection::new(
+            &configuration.redis_uri,
+            configuration.application.hmac_secret.clone(),
+            configuration.signup_protection.clone(),
+        )
+        .await?;
+        let password_hashing = PasswordHashing::new(&configuration.password_hashing)?;
+        let password_policy = PasswordPolicy::new(configuration.password_policy.clone());
         let server = run(
             tcp_listener,
             connection_pool,
@@ -46,6 +89,14 @@ impl Application {
             configuration.application.base_url.clone(),
             configuration.application.hmac_secret.clone(),
             configuration.redis_uri.clone(),
+            tracker,
+            login_throttle,
+            signup_protection,
+            password_hashing,
+            password_policy,
+            configuration.session_timeouts.clone(),
+            configuration.subscriber_import.clone(),
+            TrustedProxies(configuration.application.trusted_proxies.clone()),
         )
         .await?;
 
@@ -67,6 +118,7 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
         .connect_lazy_with(configuration.with_db())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run(
     tcp_listener: TcpListener,
     connection_pool: PgPool,
@@ -74,11 +126,28 @@ async fn run(
     app_base_url: String,
     hmac_secret: HmacSecret,
     redis_uri: Secret<String>,
+    tracker: Tracker,
+    login_throttle: LoginThrottle,
+    signup_protection: SignupProtection,
+    password_hashing: PasswordHashing,
+    password_policy: PasswordPolicy,
+    session_timeouts: SessionTimeoutSettings,
+    subscriber_import: SubscriberImportSettings,
+    trusted_proxies: TrustedProxies,
 ) -> Result<Server, anyhow::Error> {
     let connection_pool = web::Data::new(connection_pool);
     let email_client = web::Data::new(email_client);
     let app_base_url = web::Data::new(ApplicationBaseUrl(app_base_url.to_owned()));
     let hmac_secret = web::Data::new(hmac_secret);
+    let tracker = web::Data::new(tracker);
+    let login_throttle = web::Data::new(login_throttle);
+    let signup_protection = web::Data::new(signup_protection);
+    let password_hashing = web::Data::new(password_hashing);
+    let password_policy = web::Data::new(password_policy);
+    let session_timeouts = web::Data::new(session_timeouts);
+    let subscriber_import = web::Data::new(subscriber_import);
+    let trusted_proxies = web::Data::new(trusted_proxies);
+    let api_docs = SwaggerUi::new("/api/docs/{_:.*}").url(OPENAPI_JSON_PATH, ApiDoc::openapi());
 
     let secret_key = Key::from(hmac_secret.0.expose_secret().as_bytes());
 
@@ -99,21 +168,211 @@ async fn run(
             .app_data(email_client.clone())
             .app_data(app_base_url.clone())
             .app_data(hmac_secret.clone())
+            .app_data(tracker.clone())
+            .app_data(login_throttle.clone())
+            .app_data(signup_protection.clone())
+            .app_data(password_hashing.clone())
+            .app_data(password_policy.clone())
+            .app_data(session_timeouts.clone())
+            .app_data(subscriber_import.clone())
+            .app_data(trusted_proxies.clone())
             .route("/", web::get().to(home))
-            .route("/login", web::get().to(login_form))
-            .route("/login", web::post().to(login))
+            .service(
+                web::resource("/login")
+                    .wrap(from_fn(verify_csrf_token))
+                    .route(web::get().to(login_form))
+                    .route(web::post().to(login)),
+            )
+            .service(
+                web::resource("/login/2fa")
+                    .wrap(from_fn(verify_csrf_token))
+                    .route(web::get().to(login_second_factor_form))
+                    .route(web::post().to(login_second_factor)),
+            )
             .route("/health_check", web::get().to(health_check))
-            .route("/subscriptions", web::post().to(subscribe))
+            .service(
+                web::resource("/subscriptions")
+                    .wrap(signup_protection.cors())
+                    .route(web::get().to(subscribe_form))
+                    .route(web::post().to(subscribe)),
+            )
+            .service(
+                web::resource("/subscriptions/form_token")
+                    .wrap(signup_protection.cors())
+                    .route(web::get().to(signup_form_token)),
+            )
+            .route("/subscriptions/widget.js", web::get().to(signup_widget))
+            .route(
+                "/subscriptions/thank_you",
+                web::get().to(subscription_thank_you),
+            )
             .route(
                 "/subscriptions/confirm",
                 web::get().to(confirm_subscription),
             )
+            .route(
+                "/subscriptions/preferences",
+                web::get().to(preferences_form),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::post().to(update_preferences),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/subscriptions/privacy", web::get().to(privacy_form))
+            .route(
+                "/subscriptions/privacy",
+                web::post().to(request_privacy_link),
+            )
+            .route(
+                "/subscriptions/privacy/access",
+                web::get().to(download_personal_data),
+            )
+            .route(
+                "/subscriptions/privacy/erasure",
+                web::get().to(erasure_form),
+            )
+            .route(
+                "/subscriptions/privacy/erasure",
+                web::post().to(erase_personal_data),
+            )
+            .route("/password_reset", web::get().to(password_reset_form))
+            .route("/password_reset", web::post().to(request_password_reset))
+            .route(
+                "/password_reset/confirm",
+                web::get().to(reset_password_form),
+            )
+            .route("/password_reset/confirm", web::post().to(reset_password))
+            .route("/users/set_password", web::get().to(set_password_form))
+            .route("/users/set_password", web::post().to(set_password))
+            .route("/t/c/{token}", web::get().to(track_click))
+            .route("/t/o/{token}", web::get().to(track_open))
+            .service(api_docs.clone())
+            .service(
+                web::scope("/api/v1")
+                    .app_data(web::JsonConfig::default().error_handler(|e, _| extractor_error(e)))
+                    .app_data(web::PathConfig::default().error_handler(|e, _| extractor_error(e)))
+                    .app_data(web::QueryConfig::default().error_handler(|e, _| extractor_error(e)))
+                    .wrap(from_fn(authorize))
+                    .wrap(from_fn(reject_requests_without_api_token))
+                    .wrap(from_fn(authenticate_api_tokens))
+                    .route("/subscribers", web::get().to(api::list_subscribers))
+                    .route("/subscribers", web::post().to(api::create_subscriber))
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(api::get_subscriber),
+                    )
+                    .route("/issues", web::get().to(api::list_issues))
+                    .route("/issues", web::post().to(api::create_issue))
+                    .route("/issues/{issue_id}", web::get().to(api::get_issue))
+                    .route(
+                        "/issues/{issue_id}/publish",
+                        web::post().to(api::publish_issue),
+                    )
+                    .default_service(web::to(api::not_found)),
+            )
             .service(
                 web::scope("/admin")
+                    .wrap(from_fn(verify_csrf_token))
+                    .wrap(from_fn(authorize))
                     .wrap(from_fn(reject_anonymous_users))
+                    .wrap(from_fn(authenticate_api_tokens))
                     .route("/dashboard", web::get().to(admin_dashboard))
                     .route("/newsletters", web::get().to(get_newsletter_form))
                     .route("/newsletters", web::post().to(publish_newsletter))
+                    .route("/newsletters/drafts", web::post().to(save_draft))
+                    .route(
+                        "/newsletters/drafts/{draft_id}/delete",
+                        web::post().to(delete_draft),
+                    )
+                    .route(
+                        "/newsletters/recipients",
+                        web::get().to(count_newsletter_recipients),
+                    )
+                    .route("/issues", web::get().to(list_issues))
+                    .route("/issues/{issue_id}", web::get().to(issue_details))
+                    .route(
+                        "/issues/{issue_id}/deliveries",
+                        web::get().to(export_issue_deliveries),
+                    )
+                    .route("/lists", web::get().to(list_mailing_lists))
+                    .route("/lists", web::post().to(create_mailing_list))
+                    .route("/subscribers", web::get().to(list_subscribers))
+                    .route("/subscribers/export", web::get().to(export_subscribers))
+                    .route(
+                        "/subscribers/import",
+                        web::get().to(import_subscribers_form),
+                    )
+                    .route("/subscribers/import", web::post().to(import_subscribers))
+                    .route(
+                        "/subscribers/import/{import_id}/rejected.csv",
+                        web::get().to(download_import_rejections),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(subscriber_details),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/confirm",
+                        web::post().to(confirm_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/unsubscribe",
+                        web::post().to(unsubscribe_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/resend_confirmation",
+                        web::post().to(resend_confirmation),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/data",
+                        web::get().to(export_subscriber_data),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/erase",
+                        web::post().to(erase_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/delete",
+                        web::post().to(delete_subscriber),
+                    )
+                    .route("/tags", web::get().to(list_tags))
+                    .route("/attributes", web::get().to(list_attribute_definitions))
+                    .route("/attributes", web::post().to(create_attribute_definition))
+                    .route("/tags", web::post().to(update_subscriber_tags))
+                    .route("/users", web::get().to(list_users))
+                    .route("/users", web::post().to(invite_user))
+                    .route(
+                        "/users/{user_id}/resend_invitation",
+                        web::post().to(resend_invitation),
+                    )
+                    .route("/users/{user_id}/role", web::post().to(change_user_role))
+                    .route("/users/{user_id}/disable", web::post().to(disable_user))
+                    .route("/users/{user_id}/enable", web::post().to(enable_user))
+                    .route("/users/{user_id}/delete", web::post().to(delete_user))
+                    .route("/2fa", web::get().to(two_factor_settings))
+                    .route("/2fa/setup", web::post().to(set_up_two_factor))
+                    .route("/2fa/confirm", web::post().to(confirm_two_factor))
+                    .route("/2fa/disable", web::post().to(turn_off_two_factor))
+                    .route(
+                        "/2fa/recovery_codes",
+                        web::post().to(regenerate_recovery_codes),
+                    )
+                    .route("/sessions", web::get().to(list_sessions))
+                    .route(
+                        "/sessions/revoke_others",
+                        web::post().to(revoke_other_user_sessions),
+                    )
+                    .route(
+                        "/sessions/{session_id}/revoke",
+                        web::post().to(revoke_user_session),
+                    )
+                    .route("/api_tokens", web::get().to(list_api_tokens))
+                    .route("/api_tokens", web::post().to(create_api_token))
+                    .route(
+                        "/api_tokens/{token_id}/revoke",
+                        web::post().to(revoke_api_token),
+                    )
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
                     .route("/logout", web::post().to(log_out)),
@@ -28,7 +28,6 @@ pub fn init_subscriber(subscriber: impl Subscriber + Sync + Send) {
     tracing::subscriber::set_global_default(subscriber).expect("Failed to set global subscriber");
 }
 
-
 pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
 where
     F: FnOnce() -> R + Send + 'static,
@@ -36,4 +35,4 @@ where
 {
     let current_span = tracing::Span::current();
     tokio::task::spawn_blocking(move || current_span.in_scope(f))
-}
\ No newline at end of file
+}
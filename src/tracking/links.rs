@@ -0,0 +1,117 @@
+/// Calls `rewrite` with the decoded value of every `href` attribute in `html` and replaces
+/// the attribute value with the returned URL. Links for which `rewrite` returns `None`
+/// are left untouched.
+pub fn rewrite_links<F>(html: &str, mut rewrite: F) -> String
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some((before, value, quote, after)) = next_href(rest) {
+        output.push_str(before);
+        let decoded = htmlescape::decode_html(value).unwrap_or_else(|_| value.to_owned());
+        match rewrite(&decoded) {
+            Some(url) => output.push_str(&htmlescape::encode_minimal(&url)),
+            None => output.push_str(value),
+        }
+        output.push_str(quote);
+        rest = after;
+    }
+    output.push_str(rest);
+
+    output
+}
+
+/// Finds the next quoted `href` attribute value and splits `html` around it.
+/// Returns the text up to and including the opening quote, the raw attribute value,
+/// the closing quote and the remaining text.
+fn next_href(html: &str) -> Option<(&str, &str, &str, &str)> {
+    let lowercase = html.to_ascii_lowercase();
+    let mut search_from = 0;
+
+    while let Some(offset) = lowercase[search_from..].find("href") {
+        let start = search_from + offset;
+        search_from = start + "href".len();
+
+        let preceded_by_whitespace = html[..start]
+            .chars()
+            .next_back()
+            .is_some_and(char::is_whitespace);
+        if !preceded_by_whitespace {
+            continue;
+        }
+
+        let after_name = html[search_from..].trim_start();
+        let Some(after_equals) = after_name.strip_prefix('=') else {
+            continue;
+        };
+        let after_equals = after_equals.trim_start();
+        let Some(quote) = after_equals
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+        else {
+            continue;
+        };
+
+        let value_start = html.len() - after_equals.len() + 1;
+        let value_end = value_start + html[value_start..].find(quote)?;
+
+        return Some((
+            &html[..value_start],
+            &html[value_start..value_end],
+            &html[value_end..value_end + 1],
+            &html[value_end + 1..],
+        ));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rewrite_links;
+
+    fn track(url: &str) -> Option<String> {
+        url.starts_with("https://")
+            .then(|| format!("https://t.example/?u={}", urlencoding::encode(url)))
+    }
+
+    #[test]
+    fn double_and_single_quoted_links_are_rewritten() {
+        let html = r#"<a href="https://a.com">A</a> <A HREF = 'https://b.com'>B</A>"#;
+        assert_eq!(
+            rewrite_links(html, track),
+            r#"<a href="https://t.example/?u=https%3A%2F%2Fa.com">A</a> <A HREF = 'https://t.example/?u=https%3A%2F%2Fb.com'>B</A>"#
+        );
+    }
+
+    #[test]
+    fn links_rejected_by_the_callback_are_left_untouched() {
+        let html = r##"<a href="mailto:me@example.com">Mail</a><a href="#top">Top</a>"##;
+        assert_eq!(rewrite_links(html, track), html);
+    }
+
+    #[test]
+    fn attribute_values_are_decoded_before_rewriting() {
+        let mut seen = Vec::new();
+        rewrite_links(r#"<a href="https://a.com/?x=1&amp;y=2">A</a>"#, |url| {
+            seen.push(url.to_owned());
+            None
+        });
+        assert_eq!(seen, vec!["https://a.com/?x=1&y=2"]);
+    }
+
+    #[test]
+    fn attributes_merely_ending_in_href_are_ignored() {
+        let html = r#"<a data-href="https://a.com">A</a>"#;
+        assert_eq!(rewrite_links(html, track), html);
+    }
+
+    #[test]
+    fn text_outside_of_links_is_preserved() {
+        let html = "<p>No links here, not even an href.</p>";
+        assert_eq!(rewrite_links(html, track), html);
+    }
+}
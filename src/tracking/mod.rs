@@ -0,0 +1,179 @@
+mod links;
+
+pub use links::rewrite_links;
+
+use uuid::Uuid;
+
+use crate::{configuration::TrackingSettings, signed_token, startup::HmacSecret, utils};
+
+/// A transparent 1x1 GIF served by the open-tracking endpoint.
+pub const TRACKING_PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// Payload of the signed token carried by a `/t/c/{token}` redirect link.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct ClickToken {
+    #[serde(rename = "i")]
+    pub newsletter_issue_id: Uuid,
+    #[serde(rename = "s")]
+    pub subscriber_id: Uuid,
+    #[serde(rename = "u")]
+    pub url: String,
+}
+
+/// Payload of the signed token carried by a `/t/o/{token}` tracking pixel.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct OpenToken {
+    #[serde(rename = "i")]
+    pub newsletter_issue_id: Uuid,
+    #[serde(rename = "s")]
+    pub subscriber_id: Uuid,
+}
+
+/// Builds signed tracking URLs embedded into outgoing newsletter issues.
+#[derive(Clone)]
+pub struct Tracker {
+    base_url: String,
+    hmac_secret: HmacSecret,
+    track_opens: bool,
+}
+
+impl Tracker {
+    pub fn new(base_url: String, hmac_secret: HmacSecret, settings: &TrackingSettings) -> Self {
+        Self {
+            base_url,
+            hmac_secret,
+            track_opens: settings.track_opens,
+        }
+    }
+
+    pub fn hmac_secret(&self) -> &HmacSecret {
+        &self.hmac_secret
+    }
+
+    pub fn track_opens(&self) -> bool {
+        self.track_opens
+    }
+
+    pub fn click_url(&self, newsletter_issue_id: Uuid, subscriber_id: Uuid, url: &str) -> String {
+        let token = ClickToken {
+            newsletter_issue_id,
+            subscriber_id,
+            url: url.to_owned(),
+        };
+        format!(
+            "{}/t/c/{}",
+            self.base_url,
+            signed_token::sign(&token, &self.hmac_secret)
+        )
+    }
+
+    pub fn open_url(&self, newsletter_issue_id: Uuid, subscriber_id: Uuid) -> String {
+        let token = OpenToken {
+            newsletter_issue_id,
+            subscriber_id,
+        };
+        format!(
+            "{}/t/o/{}",
+            self.base_url,
+            signed_token::sign(&token, &self.hmac_secret)
+        )
+    }
+
+    /// Personalises the HTML body of an issue for a single subscriber, rewriting links
+    /// when `track_clicks` is set and appending a tracking pixel when open tracking
+    /// is enabled.
+    pub fn instrument(
+        &self,
+        html: &str,
+        newsletter_issue_id: Uuid,
+        subscriber_id: Uuid,
+        track_clicks: bool,
+    ) -> String {
+        let mut html = if track_clicks {
+            self.track_clicks(html, newsletter_issue_id, subscriber_id)
+        } else {
+            html.to_owned()
+        };
+        if self.track_opens {
+            let pixel = format!(
+                r#"<img src="{}" width="1" height="1" alt="" style="display:none">"#,
+                self.open_url(newsletter_issue_id, subscriber_id)
+            );
+            utils::append_to_html_body(&mut html, &pixel);
+        }
+
+        html
+    }
+
+    /// Rewrites every trackable `href` in `html` to a click-tracking redirect.
+    pub fn track_clicks(
+        &self,
+        html: &str,
+        newsletter_issue_id: Uuid,
+        subscriber_id: Uuid,
+    ) -> String {
+        rewrite_links(html, |url| {
+            let is_trackable = (url.starts_with("http://") || url.starts_with("https://"))
+                && !url.starts_with(&format!("{}/t/", self.base_url));
+            is_trackable.then(|| self.click_url(newsletter_issue_id, subscriber_id, url))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    use super::Tracker;
+    use crate::{configuration::TrackingSettings, startup::HmacSecret};
+
+    fn tracker(track_opens: bool) -> Tracker {
+        Tracker::new(
+            "https://news.example".into(),
+            HmacSecret(Secret::new("top-secret".into())),
+            &TrackingSettings { track_opens },
+        )
+    }
+
+    #[test]
+    fn the_pixel_is_inserted_before_the_closing_body_tag() {
+        let html = tracker(true).instrument(
+            "<html><body><p>Hi</p></BODY></html>",
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            false,
+        );
+        assert!(html.starts_with(r#"<html><body><p>Hi</p><img src="https://news.example/t/o/"#));
+        assert!(html.ends_with(r#"style="display:none"></BODY></html>"#));
+    }
+
+    #[test]
+    fn the_pixel_is_appended_to_fragments() {
+        let html = tracker(true).instrument("<p>Hi</p>", Uuid::new_v4(), Uuid::new_v4(), false);
+        assert!(html.starts_with(r#"<p>Hi</p><img src="https://news.example/t/o/"#));
+    }
+
+    #[test]
+    fn no_pixel_is_added_when_open_tracking_is_disabled() {
+        let html = tracker(false).instrument("<p>Hi</p>", Uuid::new_v4(), Uuid::new_v4(), false);
+        assert_eq!(html, "<p>Hi</p>");
+    }
+
+    #[test]
+    fn links_to_the_tracking_endpoints_are_not_tracked_again() {
+        let tracker = tracker(false);
+        let html = format!(
+            r#"<a href="{}">Tracked</a>"#,
+            tracker.click_url(Uuid::new_v4(), Uuid::new_v4(), "https://a.com")
+        );
+        assert_eq!(
+            tracker.instrument(&html, Uuid::new_v4(), Uuid::new_v4(), true),
+            html
+        );
+    }
+}
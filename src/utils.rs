@@ -8,6 +8,13 @@ where
     actix_web::error::ErrorBadRequest(e)
 }
 
+pub fn e404<T>(e: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    actix_web::error::ErrorNotFound(e)
+}
+
 pub fn e500<T>(e: T) -> actix_web::Error
 where
     T: std::fmt::Debug + std::fmt::Display + 'static,
@@ -20,3 +27,12 @@ pub fn see_other(location: &str) -> HttpResponse {
         .insert_header((LOCATION, location))
         .finish()
 }
+
+/// Inserts `fragment` right before the closing `</body>` tag of `html`,
+/// or appends it when `html` is a fragment without one.
+pub fn append_to_html_body(html: &mut String, fragment: &str) {
+    match html.to_ascii_lowercase().rfind("</body>") {
+        Some(i) => html.insert_str(i, fragment),
+        None => html.push_str(fragment),
+    }
+}
@@ -0,0 +1,241 @@
+use reqwest::StatusCode;
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+
+async fn log_in_as(app: &TestApp, role: &'static str) -> TestUser {
+    let user = TestUser::with_role(role);
+    user.store(&app.connection_pool).await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": user.username,
+            "password": user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    user
+}
+
+async fn save_draft(app: &TestApp, body: &serde_json::Value) -> reqwest::Response {
+    app.post_form("/admin/newsletters/drafts", body).await
+}
+
+fn newsletter_body(draft_id: Option<Uuid>) -> String {
+    let mut body = format!(
+        "title=Newsletter%20title&plaintext=Hello&html=%3Cp%3EHello%3C%2Fp%3E&idempotency_key={}",
+        Uuid::new_v4()
+    );
+    if let Some(draft_id) = draft_id {
+        body.push_str(&format!("&draft_id={}", draft_id));
+    }
+    body
+}
+
+async fn issue_count(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM t_newsletter_issues"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+}
+
+async fn only_draft_id(app: &TestApp) -> Uuid {
+    sqlx::query_scalar!("SELECT draft_id FROM t_newsletter_drafts")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn viewers_can_read_but_get_a_403_page_when_changing_anything() {
+    // Arrange
+    let app = spawn_app().await;
+    log_in_as(&app, "viewer").await;
+
+    // Act - Part 1 - Read
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("You are logged in as viewer."));
+    assert!(!html_page.contains(r#"href="/admin/users""#));
+
+    // Act - Part 2 - Change
+    let response = app.post_lists(&serde_json::json!({ "name": "Rust" })).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Your role does not allow you to do this."));
+    let lists = sqlx::query_scalar!("SELECT name FROM t_lists WHERE name = 'Rust'")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    assert!(lists.is_empty());
+}
+
+#[tokio::test]
+async fn only_owners_can_access_user_management() {
+    // Arrange
+    let app = spawn_app().await;
+    log_in_as(&app, "editor").await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("http://{}/admin/users", &app.app_address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn editors_can_save_drafts_but_not_publish() {
+    // Arrange
+    let app = spawn_app().await;
+    log_in_as(&app, "editor").await;
+
+    // Act - Part 1 - Save a draft
+    let response = save_draft(
+        &app,
+        &serde_json::json!({
+            "title": "Spring <issue>",
+            "plaintext": "Hello",
+            "html": "<p>Hello</p>",
+        }),
+    )
+    .await;
+    let draft_id = only_draft_id(&app).await;
+    let draft_page = format!("/admin/newsletters?draft_id={}", draft_id);
+    assert_is_redirect_to(&response, &draft_page);
+    let html_page = app
+        .api_client
+        .get(format!("http://{}{}", &app.app_address, draft_page))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<p><i>The draft has been saved.</i></p>"));
+    assert!(html_page.contains(r#"value="Spring&#x20;&lt;issue&gt;""#));
+    assert!(html_page.contains("&lt;p&gt;Hello&lt;/p&gt;</textarea>"));
+    assert!(!html_page.contains(r#"<button type="submit">Send</button>"#));
+
+    // Act - Part 2 - Try to publish
+    let response = app.post_newsletters(newsletter_body(Some(draft_id))).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(issue_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn percent_encoded_paths_need_the_same_permission_as_the_page_they_reach() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = log_in_as(&app, "editor").await;
+
+    // Act
+    let publish = app
+        .post_urlencoded("/admin/%6Eewsletters", &newsletter_body(None))
+        .await;
+    let promote = app
+        .post_form(
+            &format!("/admin/%75sers/{}/role", editor.user_id),
+            &serde_json::json!({ "role": "owner" }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(publish.status(), StatusCode::FORBIDDEN);
+    assert_eq!(promote.status(), StatusCode::FORBIDDEN);
+    assert_eq!(issue_count(&app).await, 0);
+    let role = sqlx::query_scalar!(
+        "SELECT role FROM t_users WHERE user_id = $1",
+        editor.user_id
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(role, "editor");
+}
+
+#[tokio::test]
+async fn publishing_a_draft_removes_it() {
+    // Arrange
+    let app = spawn_app().await;
+    log_in_as(&app, "editor").await;
+    save_draft(
+        &app,
+        &serde_json::json!({
+            "title": "Newsletter title",
+            "plaintext": "Hello",
+            "html": "<p>Hello</p>",
+        }),
+    )
+    .await;
+    let draft_id = only_draft_id(&app).await;
+    app.post_logout().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = app.post_newsletters(newsletter_body(Some(draft_id))).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(issue_count(&app).await, 1);
+    let drafts = sqlx::query_scalar!("SELECT draft_id FROM t_newsletter_drafts")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    assert!(drafts.is_empty());
+}
+
+#[tokio::test]
+async fn drafts_can_be_updated_and_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    log_in_as(&app, "editor").await;
+    save_draft(
+        &app,
+        &serde_json::json!({ "title": "First", "plaintext": "Hello", "html": "<p>Hello</p>" }),
+    )
+    .await;
+    let draft_id = only_draft_id(&app).await;
+
+    // Act - Part 1 - Update
+    save_draft(
+        &app,
+        &serde_json::json!({
+            "draft_id": draft_id,
+            "title": "Second",
+            "plaintext": "Hello",
+            "html": "<p>Hello</p>",
+        }),
+    )
+    .await;
+    let title = sqlx::query_scalar!("SELECT title FROM t_newsletter_drafts")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(title, "Second");
+
+    // Act - Part 2 - Delete
+    let response = app
+        .post_form(
+            &format!("/admin/newsletters/drafts/{}/delete", draft_id),
+            &serde_json::json!({}),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let drafts = sqlx::query_scalar!("SELECT draft_id FROM t_newsletter_drafts")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    assert!(drafts.is_empty());
+}
@@ -0,0 +1,305 @@
+use chrono::{Duration, Utc};
+use reqwest::StatusCode;
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_in_lists, create_unconfirmed_subscriber,
+    spawn_app, when_sending_an_email, TestApp,
+};
+
+async fn get_admin_page(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("http://{}{}", &app.app_address, path))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn get_admin_page_html(app: &TestApp, path: &str) -> String {
+    get_admin_page(app, path).await.text().await.unwrap()
+}
+
+async fn post_action(app: &TestApp, subscriber_id: Uuid, action: &str) -> reqwest::Response {
+    app.post_form(
+        &format!("/admin/subscribers/{}/{}", subscriber_id, action),
+        &serde_json::json!({}),
+    )
+    .await
+}
+
+async fn subscriber_id(app: &TestApp, email: &str) -> Uuid {
+    sqlx::query_scalar!("SELECT id FROM t_subscriptions WHERE email = $1", email)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+}
+
+async fn status(app: &TestApp, subscriber_id: Uuid) -> String {
+    sqlx::query_scalar!(
+        "SELECT status FROM t_subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap()
+}
+
+async fn only_subscriber_id(app: &TestApp) -> Uuid {
+    sqlx::query_scalar!("SELECT id FROM t_subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+}
+
+/// Inserts `n` confirmed subscribers directly, one minute apart.
+async fn insert_subscribers(app: &TestApp, n: i64) {
+    for i in 0..n {
+        sqlx::query!(
+            r#"
+            INSERT INTO t_subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, $2, $3, $4, 'confirmed')
+            "#,
+            Uuid::new_v4(),
+            format!("reader{i:02}@example.com"),
+            format!("Reader {i:02}"),
+            (Utc::now() - Duration::minutes(i)).naive_utc()
+        )
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let list_response = get_admin_page(&app, "/admin/subscribers").await;
+    let action_response = post_action(&app, Uuid::new_v4(), "delete").await;
+
+    // Assert
+    assert_is_redirect_to(&list_response, "/login");
+    assert_is_redirect_to(&action_response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_and_filtered_by_status() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscribers(&app, 3).await;
+    sqlx::query!("UPDATE t_subscriptions SET status = 'unsubscribed' WHERE email LIKE 'reader02%'")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+    app.login_test_user().await;
+
+    // Act
+    let by_email = get_admin_page_html(&app, "/admin/subscribers?q=READER01").await;
+    let by_name = get_admin_page_html(&app, "/admin/subscribers?q=Reader%2000").await;
+    let by_status = get_admin_page_html(&app, "/admin/subscribers?status=unsubscribed").await;
+    let wildcard = get_admin_page_html(&app, "/admin/subscribers?q=%25").await;
+
+    // Assert
+    assert!(by_email.contains("reader01@example.com"));
+    assert!(!by_email.contains("reader00@example.com"));
+    assert!(by_name.contains("reader00@example.com"));
+    assert!(!by_name.contains("reader01@example.com"));
+    assert!(by_status.contains("reader02@example.com"));
+    assert!(by_status.contains("(1 subscribers)"));
+    assert!(wildcard.contains("(0 subscribers)"));
+}
+
+#[tokio::test]
+async fn an_invalid_status_filter_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = get_admin_page(&app, "/admin/subscribers?status=happy").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscribers(&app, 30).await;
+    app.login_test_user().await;
+
+    // Act
+    let first_page = get_admin_page_html(&app, "/admin/subscribers").await;
+    let second_page = get_admin_page_html(&app, "/admin/subscribers?page=2").await;
+
+    // Assert
+    // most recent first
+    assert!(first_page.contains("reader00@example.com"));
+    assert!(first_page.contains("reader24@example.com"));
+    assert!(!first_page.contains("reader25@example.com"));
+    assert!(first_page.contains("Page 1 of 2 (30 subscribers)"));
+    assert!(first_page.contains(r#"<a href="/admin/subscribers?q=&amp;status=&amp;page=2">"#));
+    assert!(second_page.contains("reader29@example.com"));
+    assert!(!second_page.contains("reader24@example.com"));
+    assert!(second_page.contains("Page 2 of 2 (30 subscribers)"));
+}
+
+#[tokio::test]
+async fn pages_past_the_last_one_show_the_last_one() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscribers(&app, 30).await;
+    app.login_test_user().await;
+
+    for page in ["3", &i64::MAX.to_string()] {
+        // Act
+        let response = get_admin_page(&app, &format!("/admin/subscribers?page={}", page)).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+        let html_page = response.text().await.unwrap();
+        assert!(html_page.contains("Page 2 of 2 (30 subscribers)"));
+        assert!(html_page.contains("reader29@example.com"));
+    }
+}
+
+#[tokio::test]
+async fn the_detail_page_shows_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let rust_list = app.create_list("Rust news").await;
+    let email = create_confirmed_subscriber_in_lists(&app, &[rust_list]).await;
+    let subscriber_id = subscriber_id(&app, &email).await;
+    app.login_test_user().await;
+
+    // Act
+    let html_page =
+        get_admin_page_html(&app, &format!("/admin/subscribers/{}", subscriber_id)).await;
+
+    // Assert
+    assert!(html_page.contains(&format!("<h1>{}</h1>", email)));
+    assert!(html_page.contains("<tr><th>Status</th><td>confirmed</td></tr>"));
+    assert!(html_page.contains("<tr><th>Lists</th><td>Rust news</td></tr>"));
+    assert!(!html_page.contains("/confirm\""));
+}
+
+#[tokio::test]
+async fn the_detail_page_returns_404_for_unknown_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = get_admin_page(&app, &format!("/admin/subscribers/{}", Uuid::new_v4())).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn admins_can_confirm_and_unsubscribe_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = only_subscriber_id(&app).await;
+    let details_page = format!("/admin/subscribers/{}", subscriber_id);
+    app.login_test_user().await;
+
+    // Act - Part 1 - Confirm
+    let response = post_action(&app, subscriber_id, "confirm").await;
+    assert_is_redirect_to(&response, &details_page);
+    assert!(get_admin_page_html(&app, &details_page)
+        .await
+        .contains("has been confirmed."));
+    assert_eq!(status(&app, subscriber_id).await, "confirmed");
+
+    // Act - Part 2 - Unsubscribe
+    let response = post_action(&app, subscriber_id, "unsubscribe").await;
+    assert_is_redirect_to(&response, &details_page);
+    assert!(get_admin_page_html(&app, &details_page)
+        .await
+        .contains("has been unsubscribed."));
+    assert_eq!(status(&app, subscriber_id).await, "unsubscribed");
+}
+
+#[tokio::test]
+async fn admins_can_resend_a_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = only_subscriber_id(&app).await;
+    app.login_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = post_action(&app, subscriber_id, "resend_confirmation").await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(status(&app, subscriber_id).await, "confirmed");
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_are_not_sent_a_confirmation_email_again() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    let subscriber_id = subscriber_id(&app, &email).await;
+    let details_page = format!("/admin/subscribers/{}", subscriber_id);
+    app.login_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = post_action(&app, subscriber_id, "resend_confirmation").await;
+
+    // Assert
+    assert_is_redirect_to(&response, &details_page);
+    assert!(get_admin_page_html(&app, &details_page)
+        .await
+        .contains("Only subscribers pending confirmation can be sent a confirmation email."));
+}
+
+#[tokio::test]
+async fn admins_can_delete_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    let subscriber_id = subscriber_id(&app, &email).await;
+    app.login_test_user().await;
+
+    // Act
+    let response = post_action(&app, subscriber_id, "delete").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = get_admin_page_html(&app, "/admin/subscribers").await;
+    assert!(html_page.contains(&format!("{} has been deleted.", email)));
+    assert!(html_page.contains("(0 subscribers)"));
+    let response = post_action(&app, subscriber_id, "delete").await;
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,341 @@
+use reqwest::{redirect::Policy, StatusCode};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, when_sending_an_email, TestApp, TestUser};
+
+fn new_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap()
+}
+
+async fn post_user_action(app: &TestApp, user_id: Uuid, action: &str) -> reqwest::Response {
+    app.post_form(
+        &format!("/admin/users/{}/{}", user_id, action),
+        &serde_json::json!({}),
+    )
+    .await
+}
+
+async fn get_users_html(app: &TestApp) -> String {
+    app.api_client
+        .get(format!("http://{}/admin/users", &app.app_address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap()
+}
+
+async fn invite(app: &TestApp, username: &str, email: &str) -> reqwest::Response {
+    app.post_form(
+        "/admin/users",
+        &serde_json::json!({ "username": username, "email": email, "role": "editor" }),
+    )
+    .await
+}
+
+async fn user_status(app: &TestApp, user_id: Uuid) -> Option<String> {
+    sqlx::query_scalar!("SELECT status FROM t_users WHERE user_id = $1", user_id)
+        .fetch_optional(&app.connection_pool)
+        .await
+        .unwrap()
+}
+
+/// The seeded `admin` user is active in every test database.
+async fn seeded_admin_id(app: &TestApp) -> Uuid {
+    sqlx::query_scalar!("SELECT user_id FROM t_users WHERE username = 'admin'")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+}
+
+async fn log_in(app: &TestApp, client: &reqwest::Client, user: &TestUser) -> reqwest::Response {
+    app.submit_form(
+        client,
+        "/login",
+        &serde_json::json!({
+            "username": user.username,
+            "password": user.password
+        }),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_users() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = invite(&app, "ged", "ged@example.com").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn invited_users_choose_a_password_with_a_one_time_link() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Invite
+    let response = invite(&app, "ged", "ged@example.com").await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html_page = get_users_html(&app).await;
+    assert!(html_page.contains("<p><i>An invitation has been sent to ged@example.com.</i></p>"));
+    assert!(html_page.contains("<td>invited</td>"));
+
+    // Act - Part 2 - Follow the link
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let link = app.get_confirmation_links(email_request).html_link;
+    let client = new_client();
+    let html_page = client
+        .get(link.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("Welcome ged"));
+    let token = link
+        .query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .into_owned();
+
+    // Act - Part 3 - The same length rules as a password change apply
+    let response = client
+        .post(format!("http://{}/users/set_password", &app.app_address))
+        .form(&serde_json::json!({
+            "token": token,
+            "new_password": "short",
+            "new_password_check": "short",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, &format!("/users/set_password?token={}", token));
+
+    // Act - Part 4 - Set a password
+    let user = TestUser {
+        user_id: Uuid::nil(),
+        username: "ged".into(),
+        password: Uuid::new_v4().to_string(),
+        role: "editor",
+    };
+    let set_password = || {
+        client
+            .post(format!("http://{}/users/set_password", &app.app_address))
+            .form(&serde_json::json!({
+                "token": token,
+                "new_password": user.password,
+                "new_password_check": user.password,
+            }))
+            .send()
+    };
+    let response = set_password().await.unwrap();
+    assert_is_redirect_to(&response, "/login");
+
+    // Assert
+    let response = log_in(&app, &client, &user).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let response = set_password().await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = client.get(link).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn invited_users_cannot_log_in_before_choosing_a_password() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .mount(&app.email_server)
+        .await;
+    invite(&app, "ged", "ged@example.com").await;
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({ "username": "ged", "password": "" }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn usernames_and_emails_must_be_unique() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    invite(&app, "ged", "ged@example.com").await;
+
+    for (username, email) in [
+        ("ged", "sparrowhawk@example.com"),
+        ("sparrowhawk", "ged@example.com"),
+        ("sparrowhawk", "Ged@Example.com"),
+    ] {
+        // Act
+        let response = invite(&app, username, email).await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/users");
+        assert!(get_users_html(&app)
+            .await
+            .contains("<p><i>A user with this username or email already exists.</i></p>"));
+    }
+}
+
+#[tokio::test]
+async fn disabling_a_user_ends_their_sessions_until_they_are_enabled() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_user = TestUser::generate();
+    other_user.store(&app.connection_pool).await;
+    let other_client = new_client();
+    log_in(&app, &other_client, &other_user).await;
+    app.login_test_user().await;
+
+    // Act - Part 1 - Disable
+    let response = post_user_action(&app, other_user.user_id, "disable").await;
+    assert_is_redirect_to(&response, "/admin/users");
+    assert_eq!(
+        user_status(&app, other_user.user_id).await.as_deref(),
+        Some("disabled")
+    );
+
+    // Act - Part 2 - The disabled user is logged out
+    let response = other_client
+        .get(format!("http://{}/admin/dashboard", &app.app_address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+    let response = log_in(&app, &other_client, &other_user).await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 3 - Enable
+    post_user_action(&app, other_user.user_id, "enable").await;
+
+    // Assert
+    let response = log_in(&app, &other_client, &other_user).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn users_can_be_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_user = TestUser::generate();
+    other_user.store(&app.connection_pool).await;
+    app.login_test_user().await;
+
+    // Act
+    let response = post_user_action(&app, other_user.user_id, "delete").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    assert_eq!(user_status(&app, other_user.user_id).await, None);
+    let html_page = get_users_html(&app).await;
+    assert!(html_page.contains(&format!(
+        "<p><i>{} has been deleted.</i></p>",
+        other_user.username
+    )));
+}
+
+#[tokio::test]
+async fn the_last_active_owner_cannot_be_disabled_deleted_or_demoted() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    post_user_action(&app, seeded_admin_id(&app).await, "disable").await;
+
+    for (action, message) in [
+        ("disable", "The last active owner cannot be disabled."),
+        ("delete", "The last active owner cannot be deleted."),
+        ("role", "The last active owner must remain an owner."),
+    ] {
+        // Act
+        let response = app
+            .post_form(
+                &format!("/admin/users/{}/{}", app.test_user.user_id, action),
+                &serde_json::json!({ "role": "editor" }),
+            )
+            .await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/users");
+        assert!(get_users_html(&app)
+            .await
+            .contains(&format!("<p><i>{}</i></p>", message)));
+        assert_eq!(
+            user_status(&app, app.test_user.user_id).await.as_deref(),
+            Some("active")
+        );
+    }
+    let role = sqlx::query_scalar!(
+        "SELECT role FROM t_users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(role, "owner");
+}
+
+#[tokio::test]
+async fn owners_can_change_the_role_of_a_user() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_user = TestUser::with_role("viewer");
+    other_user.store(&app.connection_pool).await;
+    app.login_test_user().await;
+
+    // Act
+    let response = app
+        .post_form(
+            &format!("/admin/users/{}/role", other_user.user_id),
+            &serde_json::json!({ "role": "editor" }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/users");
+    assert!(get_users_html(&app).await.contains(&format!(
+        "<p><i>The role of {} is now editor.</i></p>",
+        other_user.username
+    )));
+}
+
+#[tokio::test]
+async fn acting_on_an_unknown_user_returns_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = post_user_action(&app, Uuid::new_v4(), "disable").await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,198 @@
+use reqwest::{redirect::Policy, StatusCode};
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+
+/// A client without cookies, as a script would use.
+fn script_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .unwrap()
+}
+
+async fn get_with_token(app: &TestApp, path: &str, token: &str) -> reqwest::Response {
+    script_client()
+        .get(format!("http://{}{}", &app.app_address, path))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn publish_with_token(app: &TestApp, token: &str) -> reqwest::Response {
+    script_client()
+        .post(format!("http://{}/admin/newsletters", &app.app_address))
+        .bearer_auth(token)
+        .form(&serde_json::json!({
+            "title": "Newsletter title",
+            "plaintext": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn issue_count(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM t_newsletter_issues"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn tokens_are_shown_once_and_stored_hashed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let token = app
+        .create_api_token("CMS publishing", &["newsletters:publish"])
+        .await;
+
+    // Assert
+    assert!(token.starts_with("z2p_"));
+    let stored = sqlx::query!("SELECT name, token_hash, scopes FROM t_api_tokens")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(stored.name, "CMS publishing");
+    assert_ne!(stored.token_hash, token);
+    assert_eq!(stored.scopes, vec!["newsletters:publish".to_owned()]);
+    let html_page = app
+        .api_client
+        .get(format!("http://{}/admin/api_tokens", &app.app_address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("CMS publishing"));
+    assert!(!html_page.contains(&token));
+}
+
+#[tokio::test]
+async fn newsletters_can_be_published_with_a_token() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let token = app.create_api_token("CI", &["newsletters:publish"]).await;
+
+    // Act
+    let response = publish_with_token(&app, &token).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(issue_count(&app).await, 1);
+    let last_used_at = sqlx::query_scalar!("SELECT last_used_at FROM t_api_tokens")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert!(last_used_at.is_some());
+}
+
+#[tokio::test]
+async fn a_token_can_only_do_what_its_scopes_allow() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let token = app
+        .create_api_token("Reporting", &["subscribers:read"])
+        .await;
+
+    // Act - Part 1 - Within its scope
+    let response = get_with_token(&app, "/admin/subscribers", &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Act - Part 2 - Outside of it
+    for path in ["/admin/dashboard", "/admin/api_tokens"] {
+        let response = get_with_token(&app, path, &token).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "{}", path);
+    }
+    let response = publish_with_token(&app, &token).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(issue_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn a_token_cannot_do_more_than_the_role_of_its_user() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = TestUser::with_role("viewer");
+    viewer.store(&app.connection_pool).await;
+    app.post_login(&serde_json::json!({
+        "username": viewer.username,
+        "password": viewer.password
+    }))
+    .await;
+    let token = app
+        .create_api_token("Too ambitious", &["newsletters:publish"])
+        .await;
+
+    // Act
+    let response = publish_with_token(&app, &token).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(issue_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn revoked_and_unknown_tokens_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let token = app
+        .create_api_token("Old script", &["subscribers:read"])
+        .await;
+    let token_id = sqlx::query_scalar!("SELECT token_id FROM t_api_tokens")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+
+    // Act - Part 1 - Revoke
+    let response = app
+        .post_form(
+            &format!("/admin/api_tokens/{}/revoke", token_id),
+            &serde_json::json!({}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/api_tokens");
+
+    // Act - Part 2 - Use
+    for token in [token.as_str(), "z2p_not-a-token"] {
+        let response = get_with_token(&app, "/admin/subscribers", token).await;
+
+        // Assert
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()["WWW-Authenticate"],
+            r#"Bearer error="invalid_token""#
+        );
+    }
+}
+
+#[tokio::test]
+async fn other_authorization_schemes_are_left_to_the_session() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("http://{}/admin/dashboard", &app.app_address))
+        .basic_auth("proxy-user", Some("proxy-password"))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+}
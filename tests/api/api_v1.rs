@@ -0,0 +1,386 @@
+use reqwest::{redirect::Policy, StatusCode};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, when_sending_an_email, TestApp};
+
+/// Sends a request to the JSON API as a script would, without cookies.
+async fn api_request(
+    app: &TestApp,
+    method: reqwest::Method,
+    path: &str,
+    token: Option<&str>,
+    body: Option<serde_json::Value>,
+    idempotency_key: Option<&str>,
+) -> reqwest::Response {
+    let client = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .unwrap();
+    let mut request = client.request(
+        method,
+        format!("http://{}/api/v1{}", &app.app_address, path),
+    );
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    if let Some(key) = idempotency_key {
+        request = request.header("Idempotency-Key", key);
+    }
+    request.send().await.expect("Failed to execute request.")
+}
+
+async fn get(app: &TestApp, path: &str, token: &str) -> reqwest::Response {
+    api_request(app, reqwest::Method::GET, path, Some(token), None, None).await
+}
+
+async fn post(
+    app: &TestApp,
+    path: &str,
+    token: &str,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    api_request(
+        app,
+        reqwest::Method::POST,
+        path,
+        Some(token),
+        Some(body),
+        None,
+    )
+    .await
+}
+
+/// Checks that `response` is a problem document with `status`, and returns it.
+async fn assert_is_problem(response: reqwest::Response, status: StatusCode) -> serde_json::Value {
+    assert_eq!(response.status(), status);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], status.as_u16());
+    assert_eq!(problem["title"], status.canonical_reason().unwrap());
+    problem
+}
+
+async fn owner_token(app: &TestApp, scopes: &[&str]) -> String {
+    app.login_test_user().await;
+    app.create_api_token("API tests", scopes).await
+}
+
+fn new_issue() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "plaintext": "Newsletter body as plain text",
+        "html": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+#[tokio::test]
+async fn requests_without_a_valid_token_are_rejected_with_a_problem() {
+    // Arrange
+    let app = spawn_app().await;
+
+    for (token, challenge) in [
+        (None, "Bearer"),
+        (Some("z2p_not-a-token"), r#"Bearer error="invalid_token""#),
+    ] {
+        // Act
+        let response = api_request(
+            &app,
+            reqwest::Method::GET,
+            "/subscribers",
+            token,
+            None,
+            None,
+        )
+        .await;
+
+        // Assert
+        assert_eq!(response.headers()["WWW-Authenticate"], challenge);
+        assert_is_problem(response, StatusCode::UNAUTHORIZED).await;
+    }
+}
+
+#[tokio::test]
+async fn a_token_needs_the_scope_of_the_endpoint() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = owner_token(&app, &["subscribers:read"]).await;
+
+    // Act
+    let response = post(&app, "/issues", &token, new_issue()).await;
+
+    // Assert
+    assert_is_problem(response, StatusCode::FORBIDDEN).await;
+    let response = get(&app, "/subscribers", &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_created_and_read() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = owner_token(&app, &["subscribers:read", "subscribers:write"]).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = post(
+        &app,
+        "/subscribers",
+        &token,
+        serde_json::json!({"name": "le guin", "email": "ursula_le_guin@gmail.com"}),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let location = response.headers()["Location"].to_str().unwrap().to_owned();
+    let created: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(created["status"], "pending_confirmation");
+    assert_eq!(
+        location,
+        format!("/api/v1/subscribers/{}", created["id"].as_str().unwrap())
+    );
+
+    let subscriber: serde_json::Value = get(&app, location.trim_start_matches("/api/v1"), &token)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(subscriber["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(subscriber["list_ids"].as_array().unwrap().len(), 1);
+
+    let page: serde_json::Value = get(&app, "/subscribers?status=pending_confirmation", &token)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["subscribers"][0]["name"], "le guin");
+
+    // The confirmation email is sent in the background.
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn invalid_requests_are_reported_as_problems() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = owner_token(&app, &["subscribers:read", "subscribers:write"]).await;
+    let subscriber = serde_json::json!({"name": "le guin", "email": "ursula_le_guin@gmail.com"});
+    assert_eq!(
+        post(&app, "/subscribers", &token, subscriber.clone())
+            .await
+            .status(),
+        StatusCode::CREATED
+    );
+
+    for (response, status) in [
+        (
+            post(
+                &app,
+                "/subscribers",
+                &token,
+                serde_json::json!({"name": "le guin", "email": "definitely-not-an-email"}),
+            )
+            .await,
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            post(
+                &app,
+                "/subscribers",
+                &token,
+                serde_json::json!({"name": "le guin"}),
+            )
+            .await,
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            post(&app, "/subscribers", &token, subscriber).await,
+            StatusCode::CONFLICT,
+        ),
+        (
+            get(&app, "/subscribers?status=bogus", &token).await,
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            get(&app, &format!("/subscribers?page={}", i64::MAX), &token).await,
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            get(&app, &format!("/subscribers/{}", Uuid::new_v4()), &token).await,
+            StatusCode::NOT_FOUND,
+        ),
+        (
+            get(
+                &app,
+                &format!("/subscribers/{}/history", Uuid::new_v4()),
+                &token,
+            )
+            .await,
+            StatusCode::NOT_FOUND,
+        ),
+    ] {
+        // Act - Assert
+        let problem = assert_is_problem(response, status).await;
+        assert!(!problem["detail"].as_str().unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn an_issue_is_drafted_then_published_once_per_idempotency_key() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = owner_token(&app, &["newsletters:read", "newsletters:publish"]).await;
+    create_confirmed_subscriber(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Draft
+    let response = post(&app, "/issues", &token, new_issue()).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let draft: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(draft["status"], "draft");
+    let issue_id = draft["id"].as_str().unwrap().to_owned();
+
+    // Act - Part 2 - Publish, retrying with the same key
+    let publish_path = format!("/issues/{}/publish", issue_id);
+    let key = Uuid::new_v4().to_string();
+    let mut bodies = Vec::new();
+    for _ in 0..2 {
+        let response = api_request(
+            &app,
+            reqwest::Method::POST,
+            &publish_path,
+            Some(&token),
+            Some(serde_json::json!({})),
+            Some(&key),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        bodies.push(response.text().await.unwrap());
+    }
+    assert_eq!(bodies[0], bodies[1]);
+
+    // Act - Part 3 - Publish again with another key
+    let response = post(&app, &publish_path, &token, serde_json::json!({})).await;
+    assert_is_problem(response, StatusCode::CONFLICT).await;
+
+    // Assert
+    app.dispatch_all_pending_emails().await;
+    let issue: serde_json::Value = get(&app, &format!("/issues/{}", issue_id), &token)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issue["status"], "published");
+    assert_eq!(issue["deliveries"]["queued"], 0);
+    assert_eq!(issue["deliveries"]["delivered"], 1);
+    let issues: serde_json::Value = get(&app, "/issues", &token).await.json().await.unwrap();
+    assert_eq!(issues["issues"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn an_idempotency_key_cannot_be_reused_for_another_request() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = owner_token(&app, &["newsletters:read", "newsletters:publish"]).await;
+    let key = Uuid::new_v4().to_string();
+    let response = api_request(
+        &app,
+        reqwest::Method::POST,
+        "/issues",
+        Some(&token),
+        Some(new_issue()),
+        Some(&key),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let draft: serde_json::Value = response.json().await.unwrap();
+
+    // Act
+    let response = api_request(
+        &app,
+        reqwest::Method::POST,
+        &format!("/issues/{}/publish", draft["id"].as_str().unwrap()),
+        Some(&token),
+        Some(serde_json::json!({})),
+        Some(&key),
+    )
+    .await;
+
+    // Assert
+    assert_is_problem(response, StatusCode::UNPROCESSABLE_ENTITY).await;
+    let issue: serde_json::Value = get(
+        &app,
+        &format!("/issues/{}", draft["id"].as_str().unwrap()),
+        &token,
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(issue["status"], "draft");
+}
+
+#[tokio::test]
+async fn editors_can_draft_but_not_publish_through_the_api() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = crate::helpers::TestUser::with_role("editor");
+    editor.store(&app.connection_pool).await;
+    app.post_login(&serde_json::json!({
+        "username": editor.username,
+        "password": editor.password
+    }))
+    .await;
+    let token = app
+        .create_api_token(
+            "Editor script",
+            &["newsletters:read", "newsletters:publish"],
+        )
+        .await;
+
+    // Act
+    let response = post(&app, "/issues", &token, new_issue()).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let draft: serde_json::Value = response.json().await.unwrap();
+    let issue_id = draft["id"].as_str().unwrap();
+    let response = post(
+        &app,
+        &format!("/issues/{}/publish", issue_id),
+        &token,
+        serde_json::json!({}),
+    )
+    .await;
+    let encoded = post(
+        &app,
+        &format!("/issues/{}/%70ublish", issue_id),
+        &token,
+        serde_json::json!({}),
+    )
+    .await;
+
+    // Assert
+    assert_is_problem(response, StatusCode::FORBIDDEN).await;
+    assert_is_problem(encoded, StatusCode::FORBIDDEN).await;
+    let issue: serde_json::Value = get(&app, &format!("/issues/{}", issue_id), &token)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issue["status"], "draft");
+}
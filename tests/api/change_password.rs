@@ -94,3 +94,44 @@ async fn changing_password_works() {
         .await;
     assert_is_redirect_to(&response, "/admin/dashboard");
 }
+
+#[tokio::test]
+async fn the_new_password_must_satisfy_the_password_policy() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let with_username = format!("my-{}-password", app.test_user.username.to_uppercase());
+
+    for (new_password, message) in [
+        (
+            "Sunshine123",
+            "This password has appeared in a data breach - please choose another one.",
+        ),
+        (
+            "aaaaaaaaaaaaaaaa",
+            "The new password is too easy to guess - try a longer one, or several unrelated words.",
+        ),
+        (
+            with_username.as_str(),
+            "The new password must not contain your username.",
+        ),
+    ] {
+        // Act
+        let response = app
+            .post_change_password(&serde_json::json!({
+                "current_password": &app.test_user.password,
+                "new_password": new_password,
+                "new_password_check": new_password
+            }))
+            .await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/password");
+        let html_page = app.get_change_password_html().await;
+        assert!(
+            html_page.contains(&format!("<p><i>{}</i></p>", message)),
+            "{} was not rejected",
+            new_password
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use reqwest::StatusCode;
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Posts a form to `path` with the given token, or without one.
+async fn post_with_token(
+    app: &TestApp,
+    path: &str,
+    body: serde_json::Value,
+    csrf_token: Option<&str>,
+) -> reqwest::Response {
+    let mut body = body;
+    if let Some(csrf_token) = csrf_token {
+        body["csrf_token"] = csrf_token.into();
+    }
+    app.api_client
+        .post(format!("http://{}{}", &app.app_address, path))
+        .form(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+fn login_body(app: &TestApp) -> serde_json::Value {
+    serde_json::json!({
+        "username": app.test_user.username,
+        "password": app.test_user.password,
+    })
+}
+
+#[tokio::test]
+async fn a_login_without_a_valid_token_is_forbidden() {
+    // Arrange
+    let app = spawn_app().await;
+    app.csrf_token().await;
+
+    for csrf_token in [None, Some("not-the-token")] {
+        // Act
+        let response = post_with_token(&app, "/login", login_body(&app), csrf_token).await;
+
+        // Assert
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admin_forms_without_a_valid_token_are_forbidden() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    for (path, body) in [
+        (
+            "/admin/password",
+            serde_json::json!({
+                "current_password": app.test_user.password,
+                "new_password": new_password,
+                "new_password_check": new_password,
+            }),
+        ),
+        (
+            "/admin/newsletters",
+            serde_json::json!({
+                "title": "Newsletter title",
+                "plaintext": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+                "idempotency_key": Uuid::new_v4().to_string(),
+            }),
+        ),
+        ("/admin/logout", serde_json::json!({})),
+    ] {
+        for csrf_token in [None, Some("not-the-token")] {
+            // Act
+            let response = post_with_token(&app, path, body.clone(), csrf_token).await;
+
+            // Assert
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{}", path);
+        }
+    }
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let issues = sqlx::query_scalar!("SELECT COUNT(*) FROM t_newsletter_issues")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues, Some(0));
+}
+
+#[tokio::test]
+async fn admin_forms_carry_the_token_of_the_session() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let csrf_field = format!(
+        r#"<input type="hidden" name="csrf_token" value="{}">"#,
+        app.csrf_token().await
+    );
+
+    for path in ["/admin/dashboard", "/admin/newsletters", "/admin/password"] {
+        // Act
+        let html_page = app
+            .api_client
+            .get(format!("http://{}{}", &app.app_address, path))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+
+        // Assert
+        assert!(html_page.contains(&csrf_field), "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn a_token_from_before_logging_in_is_not_accepted_afterwards() {
+    // Arrange
+    let app = spawn_app().await;
+    let old_token = app.csrf_token().await;
+    let response = post_with_token(&app, "/login", login_body(&app), Some(&old_token)).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Act
+    let response = post_with_token(
+        &app,
+        "/admin/logout",
+        serde_json::json!({}),
+        Some(&old_token),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_ne!(app.csrf_token().await, old_token);
+    let response = app.post_logout().await;
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,226 @@
+use chrono::Utc;
+use reqwest::{header, StatusCode};
+use serde_json::Value;
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_in_lists, publish_issue, spawn_app,
+    when_sending_an_email, TestApp,
+};
+
+async fn get_export(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("http://{}{}", &app.app_address, path))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn only_issue_id(app: &TestApp) -> Uuid {
+    sqlx::query_scalar!("SELECT newsletter_issue_id FROM t_newsletter_issues")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+}
+
+/// Inserts `n` confirmed subscribers directly, with a `company` attribute.
+async fn insert_subscribers(app: &TestApp, n: usize) {
+    for i in 0..n {
+        sqlx::query!(
+            r#"
+            INSERT INTO t_subscriptions (id, email, name, subscribed_at, status, attributes)
+            VALUES ($1, $2, $3, $4, 'confirmed', $5)
+            "#,
+            Uuid::new_v4(),
+            format!("reader{i:04}@example.com"),
+            format!("Reader, {i:04}"),
+            Utc::now().naive_utc(),
+            serde_json::json!({ "company": format!("Company {i}") })
+        )
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_data() {
+    // Arrange
+    let app = spawn_app().await;
+
+    for path in [
+        "/admin/subscribers/export".to_owned(),
+        format!("/admin/issues/{}/deliveries", Uuid::new_v4()),
+    ] {
+        // Act
+        let response = get_export(&app, &path).await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv_across_several_batches() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    app.post_urlencoded("/admin/attributes", "key=company&label=Company&kind=string")
+        .await;
+    insert_subscribers(&app, 1200).await;
+
+    // Act
+    let response = get_export(&app, "/admin/subscribers/export?format=csv").await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/csv; charset=utf-8"
+    );
+    assert_eq!(
+        response.headers()[header::CONTENT_DISPOSITION],
+        "attachment; filename=\"subscribers.csv\""
+    );
+    let csv = response.text().await.unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 1201);
+    assert_eq!(
+        lines[0],
+        "id,email,name,status,subscribed_at,digest_frequency,paused_until,lists,tags,attr.company"
+    );
+    let row = lines
+        .iter()
+        .find(|l| l.contains("reader0042@example.com"))
+        .unwrap();
+    assert!(row.contains(",\"Reader, 0042\",confirmed,"));
+    assert!(row.ends_with(",immediate,,,,Company 42"));
+}
+
+#[tokio::test]
+async fn subscribers_can_be_exported_as_ndjson() {
+    // Arrange
+    let app = spawn_app().await;
+    let list_id = app.create_list("Rust news").await;
+    let email = create_confirmed_subscriber_in_lists(&app, &[list_id]).await;
+    app.login_test_user().await;
+
+    // Act
+    let response = get_export(&app, "/admin/subscribers/export?format=ndjson").await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/x-ndjson"
+    );
+    let body = response.text().await.unwrap();
+    let lines: Vec<Value> = body
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["email"], email.as_str());
+    assert_eq!(lines[0]["status"], "confirmed");
+    assert_eq!(lines[0]["lists"], serde_json::json!(["Rust news"]));
+    assert_eq!(lines[0]["attributes"], serde_json::json!({}));
+    assert!(lines[0]["subscribed_at"].as_str().unwrap().ends_with('Z'));
+    assert_eq!(lines[0]["paused_until"], Value::Null);
+}
+
+#[tokio::test]
+async fn issue_deliveries_report_pending_then_delivered_and_opened() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    app.login_test_user().await;
+    publish_issue(&app, "<html><body><p>Hello!</p></body></html>", false).await;
+    let path = format!(
+        "/admin/issues/{}/deliveries?format=ndjson",
+        only_issue_id(&app).await
+    );
+
+    // Act - Part 1 - Before delivery
+    let body = get_export(&app, &path).await.text().await.unwrap();
+    let pending: Value = serde_json::from_str(body.trim()).unwrap();
+    assert_eq!(pending["subscriber_email"], email.as_str());
+    assert_eq!(pending["outcome"], "pending");
+
+    // Act - Part 2 - Deliver and open
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let pixel = app
+        .get_html_links(&email_request)
+        .into_iter()
+        .find(|l| l.path().starts_with("/t/o/"))
+        .unwrap();
+    reqwest::get(pixel).await.unwrap();
+
+    // Assert
+    let body = get_export(&app, &path).await.text().await.unwrap();
+    let delivered: Value = serde_json::from_str(body.trim()).unwrap();
+    assert_eq!(delivered["outcome"], "delivered");
+    assert_eq!(delivered["error"], Value::Null);
+    assert_eq!(delivered["open_count"], 1);
+    assert_eq!(delivered["click_count"], 0);
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_exported_with_the_error() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    app.login_test_user().await;
+    publish_issue(&app, "<p>Hello!</p>", false).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act
+    let response = get_export(
+        &app,
+        &format!("/admin/issues/{}/deliveries", only_issue_id(&app).await),
+    )
+    .await;
+
+    // Assert
+    let csv = response.text().await.unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "subscriber_email,outcome,error,attempted_at,open_count,first_opened_at,click_count"
+    );
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].starts_with(&format!("{},failed,", email)));
+    assert!(lines[1].contains("500"));
+}
+
+#[tokio::test]
+async fn exporting_an_unknown_issue_returns_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = get_export(
+        &app,
+        &format!("/admin/issues/{}/deliveries", Uuid::new_v4()),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -3,20 +3,26 @@ use argon2::password_hash::SaltString;
 use argon2::Algorithm::Argon2id;
 use argon2::Version::V0x13;
 use argon2::{Argon2, Params, PasswordHasher};
+use fake::faker::{internet::en::SafeEmail, name::en::Name};
+use fake::Fake;
 use once_cell::sync::Lazy;
 use reqwest::redirect::Policy;
 use reqwest::{header, Response, StatusCode};
 use serde_json::Value;
-use wiremock::MockServer;
+use wiremock::http::Method;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockBuilder, MockServer, ResponseTemplate};
+use zero2prod::confirmation_email_worker::try_send_confirmation_email;
 use zero2prod::email_client::EmailClient;
 use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
-use zero2prod::startup::{get_connection_pool, Application};
+use zero2prod::startup::{get_connection_pool, Application, ApplicationBaseUrl};
+use zero2prod::tracking::Tracker;
 
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use tracing_subscriber::filter::LevelFilter;
 use uuid::Uuid;
 
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
+use zero2prod::configuration::{get_configuration, DatabaseSettings, Settings};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 static TRACING: Lazy<()> = Lazy::new(|| {
@@ -45,12 +51,15 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub tracker: Tracker,
+    pub base_url: ApplicationBaseUrl,
 }
 
 pub struct TestUser {
     pub user_id: uuid::Uuid,
     pub username: String,
     pub password: String,
+    pub role: &'static str,
 }
 
 impl TestUser {
@@ -59,6 +68,14 @@ impl TestUser {
             user_id: uuid::Uuid::new_v4(),
             username: uuid::Uuid::new_v4().to_string(),
             password: uuid::Uuid::new_v4().to_string(),
+            role: "owner",
+        }
+    }
+
+    pub fn with_role(role: &'static str) -> Self {
+        Self {
+            role,
+            ..Self::generate()
         }
     }
 
@@ -70,12 +87,13 @@ impl TestUser {
             .to_string();
         sqlx::query!(
             r#"
-                INSERT INTO t_users (user_id, username, password_hash)
-                VALUES ($1, $2, $3)
+                INSERT INTO t_users (user_id, username, password_hash, role)
+                VALUES ($1, $2, $3, $4)
             "#,
             self.user_id,
             self.username,
             password_hash,
+            self.role,
         )
         .execute(pool)
         .await
@@ -91,10 +109,26 @@ pub struct ConfirmationLinks {
 impl TestApp {
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.connection_pool, &self.email_client)
-                    .await
-                    .unwrap()
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.connection_pool,
+                &self.email_client,
+                &self.tracker,
+                &self.base_url,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_send_confirmation_email(
+                &self.connection_pool,
+                &self.email_client,
+                &self.base_url,
+            )
+            .await
+            .unwrap()
             {
                 break;
             }
@@ -113,9 +147,69 @@ impl TestApp {
     where
         Body: serde::Serialize,
     {
+        self.post_form("/login", body).await
+    }
+
+    /// Reads the CSRF token of the session of `client` from the login form.
+    pub async fn csrf_token_of(&self, client: &reqwest::Client) -> String {
+        let html_page = client
+            .get(format!("http://{}/login", &self.app_address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap();
+        let (_, rest) = html_page
+            .split_once(r#"name="csrf_token" value=""#)
+            .expect("The login form has a CSRF token.");
+        rest.split('"').next().unwrap().to_owned()
+    }
+
+    pub async fn csrf_token(&self) -> String {
+        self.csrf_token_of(&self.api_client).await
+    }
+
+    /// Submits a form to `path` from `client` with its CSRF token, as a browser
+    /// would. `body` must serialize to an object.
+    pub async fn submit_form<Body>(
+        &self,
+        client: &reqwest::Client,
+        path: &str,
+        body: &Body,
+    ) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        let mut body = serde_json::to_value(body).unwrap();
+        body.as_object_mut()
+            .expect("A form is an object.")
+            .insert("csrf_token".into(), self.csrf_token_of(client).await.into());
+        client
+            .post(format!("http://{}{}", &self.app_address, path))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_form<Body>(&self, path: &str, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.submit_form(&self.api_client, path, body).await
+    }
+
+    /// Like [`TestApp::post_form`], for an already URL-encoded body, such as
+    /// one with repeated keys.
+    pub async fn post_urlencoded(&self, path: &str, body: &str) -> Response {
         self.api_client
-            .post(format!("http://{}/login", &self.app_address))
-            .form(body)
+            .post(format!("http://{}{}", &self.app_address, path))
+            .header(
+                header::CONTENT_TYPE,
+                ContentType::form_url_encoded().to_string(),
+            )
+            .body(format!("{}&csrf_token={}", body, self.csrf_token().await))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -156,12 +250,7 @@ impl TestApp {
     where
         Body: serde::Serialize,
     {
-        self.api_client
-            .post(format!("Http://{}/admin/password", &self.app_address))
-            .form(body)
-            .send()
-            .await
-            .expect("Failed to execute request.")
+        self.post_form("/admin/password", body).await
     }
 
     pub async fn get_admin_dashboard_html(&self) -> String {
@@ -169,14 +258,38 @@ impl TestApp {
     }
 
     pub async fn post_logout(&self) -> Response {
-        self.api_client
-            .post(format!("http://{}/admin/logout", &self.app_address))
+        self.post_form("/admin/logout", &serde_json::json!({}))
+            .await
+    }
+
+    /// The form token embedded in a freshly served signup page.
+    pub async fn signup_form_token(&self) -> String {
+        let html_page = self
+            .api_client
+            .get(format!("http://{}/subscriptions", &self.app_address))
             .send()
             .await
             .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap();
+        let (_, rest) = html_page
+            .split_once(r#"name="form_token" value=""#)
+            .expect("The signup page has a form token.");
+        rest.split('"').next().unwrap().to_owned()
     }
 
+    /// Posts the signup form as a person would, with a fresh form token.
     pub async fn post_subscriptions(&self, body: String) -> Response {
+        let body = format!(
+            "{}&form_token={}",
+            body,
+            urlencoding::encode(&self.signup_form_token().await)
+        );
+        self.post_subscriptions_as_is(body).await
+    }
+
+    pub async fn post_subscriptions_as_is(&self, body: String) -> Response {
         self.api_client
             .post(format!("http://{}/subscriptions", &self.app_address))
             .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
@@ -188,7 +301,7 @@ impl TestApp {
 
     pub async fn get_newsletters_html(&self) -> String {
         self.api_client
-            .get(&format!("http://{}/admin/newsletters", &self.app_address))
+            .get(format!("http://{}/admin/newsletters", &self.app_address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -198,16 +311,84 @@ impl TestApp {
     }
 
     pub async fn post_newsletters(&self, body: String) -> Response {
+        self.post_urlencoded("/admin/newsletters", &body).await
+    }
+
+    pub async fn post_lists<Body>(&self, body: &Body) -> Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_form("/admin/lists", body).await
+    }
+
+    pub async fn get_lists_html(&self) -> String {
         self.api_client
-            .post(&format!("http://{}/admin/newsletters", &self.app_address))
-            .header(
-                header::CONTENT_TYPE,
-                ContentType::form_url_encoded().to_string(),
-            )
-            .body(body)
+            .get(format!("http://{}/admin/lists", &self.app_address))
             .send()
             .await
             .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Creates a mailing list straight in the database and returns its id.
+    pub async fn create_list(&self, name: &str) -> Uuid {
+        let list_id = Uuid::new_v4();
+        sqlx::query!(
+            "INSERT INTO t_lists (list_id, name, created_at) VALUES ($1, $2, now())",
+            list_id,
+            name
+        )
+        .execute(&self.connection_pool)
+        .await
+        .expect("Failed to create a mailing list.");
+        list_id
+    }
+
+    pub async fn get_issue_html(&self, issue_id: Uuid) -> String {
+        self.api_client
+            .get(format!(
+                "http://{}/admin/issues/{}",
+                &self.app_address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Creates an API token for the logged-in user from the admin UI and
+    /// returns it.
+    pub async fn create_api_token(&self, name: &str, scopes: &[&str]) -> String {
+        let mut body = format!("name={}", urlencoding::encode(name));
+        for scope in scopes {
+            body.push_str(&format!("&scope={}", scope));
+        }
+        let response = self.post_urlencoded("/admin/api_tokens", &body).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let html_page = response.text().await.unwrap();
+        let (_, rest) = html_page.split_once("<code>").unwrap();
+        rest.split("</code>").next().unwrap().to_owned()
+    }
+
+    /// Extracts the links from the HTML body of an email, pointing them at the test app's port
+    /// when they target the application itself.
+    pub fn get_html_links(&self, email_request: &wiremock::Request) -> Vec<reqwest::Url> {
+        let body: Value = serde_json::from_slice(&email_request.body).unwrap();
+        linkify::LinkFinder::new()
+            .kinds(&[linkify::LinkKind::Url])
+            .links(body["html"].as_str().unwrap())
+            .map(|l| {
+                let mut link = reqwest::Url::parse(l.as_str()).unwrap();
+                if link.host_str() == Some("127.0.0.1") {
+                    link.set_port(Some(self.app_port)).unwrap();
+                }
+                link
+            })
+            .collect()
     }
 
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
@@ -225,8 +406,8 @@ impl TestApp {
             confirmation_link
         };
 
-        let html_link = get_link(&body["html"].as_str().unwrap());
-        let text_link = get_link(&body["text"].as_str().unwrap());
+        let html_link = get_link(body["html"].as_str().unwrap());
+        let text_link = get_link(body["text"].as_str().unwrap());
 
         ConfirmationLinks {
             html_link,
@@ -238,6 +419,11 @@ impl TestApp {
 /// Starts an instance of this app in the background and returns the address it's running at
 /// e.g. "127.0.0.1:8000"
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Like [`spawn_app`], with the configuration adjusted by `configure`.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
     let email_server = MockServer::start().await;
 
@@ -246,6 +432,12 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
         c.email_client.api_url = email_server.uri();
+        // Every test app counts its failed logins and signups on its own.
+        c.login_throttle.key_prefix = Uuid::new_v4().to_string();
+        c.signup_protection.key_prefix = Uuid::new_v4().to_string();
+        // Tests fill in the signup forms faster than a person could.
+        c.signup_protection.min_fill_seconds = 0;
+        configure(&mut c);
         c
     };
 
@@ -255,7 +447,7 @@ pub async fn spawn_app() -> TestApp {
         .await
         .expect("Failed to build application.");
     let port = app.port();
-    let _ = tokio::spawn(app.run_until_stopped());
+    tokio::spawn(app.run_until_stopped());
 
     let client = reqwest::Client::builder()
         .redirect(Policy::none())
@@ -271,6 +463,12 @@ pub async fn spawn_app() -> TestApp {
         test_user: TestUser::generate(),
         api_client: client,
         email_client: configuration.email_client.client(),
+        tracker: Tracker::new(
+            configuration.application.base_url.clone(),
+            configuration.application.hmac_secret.clone(),
+            &configuration.tracking,
+        ),
+        base_url: ApplicationBaseUrl(configuration.application.base_url.clone()),
     };
     test_app.test_user.store(&test_app.connection_pool).await;
     test_app
@@ -300,3 +498,92 @@ pub fn assert_is_redirect_to(response: &Response, location: &str) {
     assert_eq!(response.status(), StatusCode::SEE_OTHER);
     assert_eq!(response.headers().get(header::LOCATION).unwrap(), location);
 }
+
+pub fn when_sending_an_email() -> MockBuilder {
+    Mock::given(path("")).and(method(Method::Post))
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    create_confirmed_subscriber_in_lists(app, &[]).await;
+}
+
+/// Subscribes and confirms a new subscriber, returning their email address.
+/// An empty `list_ids` subscribes them to the default list.
+pub async fn create_confirmed_subscriber_in_lists(app: &TestApp, list_ids: &[Uuid]) -> String {
+    let (email, confirmation_links) = subscribe_to_lists(app, list_ids).await;
+
+    reqwest::get(confirmation_links.html_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    email
+}
+
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    subscribe_to_lists(app, &[]).await.1
+}
+
+async fn subscribe_to_lists(app: &TestApp, list_ids: &[Uuid]) -> (String, ConfirmationLinks) {
+    let name: String = Name().fake();
+    let email: String = SafeEmail().fake();
+    let mut body = format!("name={name}&email={email}")
+        .replace(' ', "%20")
+        .replace('@', "%40");
+    for list_id in list_ids {
+        body.push_str(&format!("&list_id={list_id}"));
+    }
+
+    // prevent trying to send a confirmation email to Mailtrap
+    let _mock_guard = Mock::given(method(Method::Post))
+        .and(path(""))
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.to_string())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    (email, app.get_confirmation_links(email_request))
+}
+
+/// Publishes an issue with the given HTML body through the admin form.
+pub async fn publish_issue(app: &TestApp, html: &str, track_clicks: bool) {
+    publish_issue_to_lists(app, html, track_clicks, &[]).await;
+}
+
+pub async fn publish_issue_to_lists(
+    app: &TestApp,
+    html: &str,
+    track_clicks: bool,
+    list_ids: &[Uuid],
+) {
+    let mut body = format!(
+        "title=Newsletter%20title\
+        &plaintext=Newsletter%20body%20as%20plain%20text\
+        &html={}\
+        &idempotency_key={}",
+        urlencoding::encode(html),
+        uuid::Uuid::new_v4()
+    );
+    if track_clicks {
+        body.push_str("&track_clicks=true");
+    }
+    for list_id in list_ids {
+        body.push_str(&format!("&list_id={list_id}"));
+    }
+    let response = app.post_newsletters(body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
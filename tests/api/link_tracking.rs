@@ -0,0 +1,172 @@
+use reqwest::{header, StatusCode};
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, publish_issue, spawn_app,
+    when_sending_an_email, TestApp,
+};
+
+/// Links in the HTML body of the last email sent, leaving out the open-tracking pixel.
+async fn links_in_last_sent_email(app: &TestApp) -> Vec<reqwest::Url> {
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_html_links(&email_request)
+        .into_iter()
+        .filter(|l| !l.path().starts_with("/t/o/") && l.path() != "/subscriptions/preferences")
+        .collect()
+}
+
+#[tokio::test]
+async fn links_are_rewritten_to_tracking_redirects_when_click_tracking_is_enabled() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_issue(
+        &app,
+        r#"<p>Read <a href="https://example.com/article">this</a></p>"#,
+        true,
+    )
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let links = links_in_last_sent_email(&app).await;
+    assert_eq!(links.len(), 1);
+    assert!(links[0].path().starts_with("/t/c/"));
+}
+
+#[tokio::test]
+async fn links_are_left_untouched_when_click_tracking_is_disabled() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_issue(
+        &app,
+        r#"<p>Read <a href="https://example.com/article">this</a></p>"#,
+        false,
+    )
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let links = links_in_last_sent_email(&app).await;
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].as_str(), "https://example.com/article");
+}
+
+#[tokio::test]
+async fn a_tracked_link_redirects_to_the_original_url_and_is_counted() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .mount(&app.email_server)
+        .await;
+    publish_issue(
+        &app,
+        r#"<p>Read <a href="https://example.com/article?a=1&amp;b=2">this</a></p>"#,
+        true,
+    )
+    .await;
+    app.dispatch_all_pending_emails().await;
+    let tracked_link = links_in_last_sent_email(&app).await.remove(0);
+
+    // Act - Part 1 - Follow the tracked link twice
+    for _ in 0..2 {
+        let response = app
+            .api_client
+            .get(tracked_link.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            response.headers().get(header::LOCATION).unwrap(),
+            "https://example.com/article?a=1&b=2"
+        );
+    }
+
+    // Act - Part 2 - Look at the issue page
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM t_newsletter_issues")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    let html_page = app.get_issue_html(issue_id).await;
+
+    // Assert
+    assert!(html_page
+        .contains("<tr><td>https://example.com/article?a=1&amp;b=2</td><td>2</td><td>1</td></tr>"));
+}
+
+#[tokio::test]
+async fn a_tampered_tracking_link_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .mount(&app.email_server)
+        .await;
+    publish_issue(
+        &app,
+        r#"<a href="https://example.com/article">this</a>"#,
+        true,
+    )
+    .await;
+    app.dispatch_all_pending_emails().await;
+    let mut tracked_link = links_in_last_sent_email(&app).await.remove(0);
+
+    // Act
+    let tampered_path = format!("{}x", tracked_link.path());
+    tracked_link.set_path(&tampered_path);
+    let response = app.api_client.get(tracked_link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_issue_statistics() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!(
+            "http://{}/admin/issues/{}",
+            &app.app_address,
+            uuid::Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
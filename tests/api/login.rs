@@ -1,3 +1,6 @@
+use argon2::{
+    password_hash::SaltString, Algorithm::Argon2id, Argon2, Params, PasswordHasher, Version::V0x13,
+};
 use uuid::Uuid;
 
 use crate::helpers::{assert_is_redirect_to, spawn_app};
@@ -82,3 +85,39 @@ async fn you_must_be_logged_in_to_change_your_password() {
     // Assert
     assert_is_redirect_to(&response, "/login");
 }
+
+#[tokio::test]
+async fn an_outdated_password_hash_is_upgraded_on_login() {
+    // Arrange
+    let app = spawn_app().await;
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let weak_hash = Argon2::new(Argon2id, V0x13, Params::new(4096, 1, 1, None).unwrap())
+        .hash_password(app.test_user.password.as_bytes(), &salt)
+        .unwrap()
+        .to_string();
+    sqlx::query!(
+        "UPDATE t_users SET password_hash = $1 WHERE user_id = $2",
+        weak_hash,
+        app.test_user.user_id
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app.login_test_user().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Assert
+    let stored_hash = sqlx::query_scalar!(
+        r#"SELECT password_hash AS "password_hash!" FROM t_users WHERE user_id = $1"#,
+        app.test_user.user_id
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert!(stored_hash.starts_with("$argon2id$v=19$m=15000,t=2,p=1$"));
+    app.post_logout().await;
+    let response = app.login_test_user().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
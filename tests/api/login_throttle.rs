@@ -0,0 +1,180 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+
+const THROTTLED: &str = "<p><i>Too many failed login attempts. Please try again later.</i></p>";
+
+async fn fail_login(app: &TestApp, username: &str) {
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": username,
+            "password": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    app.get_login_html().await;
+}
+
+/// Logs in with an `X-Forwarded-For` header, as a reverse proxy would send.
+async fn post_login_forwarded_for(
+    app: &TestApp,
+    address: &str,
+    username: &str,
+    password: &str,
+) -> reqwest::Response {
+    app.api_client
+        .post(format!("http://{}/login", &app.app_address))
+        .header("X-Forwarded-For", address)
+        .form(&serde_json::json!({
+            "username": username,
+            "password": password,
+            "csrf_token": app.csrf_token().await,
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn a_username_is_locked_out_after_repeated_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..5 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+
+    // Act
+    let response = app.login_test_user().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(THROTTLED));
+}
+
+#[tokio::test]
+async fn the_lockout_does_not_reveal_whether_a_username_exists() {
+    // Arrange
+    let app = spawn_app().await;
+    let unknown_username = Uuid::new_v4().to_string();
+    for _ in 0..5 {
+        fail_login(&app, &unknown_username).await;
+    }
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": unknown_username,
+            "password": "whatever",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(THROTTLED));
+}
+
+#[tokio::test]
+async fn a_successful_login_resets_the_failure_count() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..4 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    let response = app.login_test_user().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    // Act
+    for _ in 0..4 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    let response = app.login_test_user().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn too_many_failures_from_one_address_are_throttled() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..20 {
+        fail_login(&app, &Uuid::new_v4().to_string()).await;
+    }
+
+    // Act
+    let response = app.login_test_user().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(THROTTLED));
+}
+
+#[tokio::test]
+async fn forwarded_addresses_are_ignored_without_a_trusted_proxy() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..20 {
+        post_login_forwarded_for(
+            &app,
+            &format!("203.0.113.{}", i),
+            &Uuid::new_v4().to_string(),
+            "whatever",
+        )
+        .await;
+    }
+
+    // Act
+    let response = post_login_forwarded_for(
+        &app,
+        "198.51.100.1",
+        &app.test_user.username,
+        &app.test_user.password,
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(THROTTLED));
+}
+
+#[tokio::test]
+async fn clients_behind_a_trusted_proxy_are_counted_apart() {
+    // Arrange
+    let app =
+        spawn_app_with(|c| c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()])
+            .await;
+    for _ in 0..20 {
+        post_login_forwarded_for(&app, "203.0.113.7", &Uuid::new_v4().to_string(), "whatever")
+            .await;
+    }
+
+    // Act - Part 1 - Another client
+    let response = post_login_forwarded_for(
+        &app,
+        "198.51.100.1",
+        &app.test_user.username,
+        &app.test_user.password,
+    )
+    .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    // Act - Part 2 - The throttled client
+    let response = post_login_forwarded_for(
+        &app,
+        "203.0.113.7",
+        &app.test_user.username,
+        &app.test_user.password,
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains(THROTTLED));
+}
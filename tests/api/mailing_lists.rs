@@ -0,0 +1,126 @@
+use reqwest::StatusCode;
+use serde_json::Value;
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_in_lists, publish_issue_to_lists, spawn_app,
+    when_sending_an_email, TestApp,
+};
+
+/// Recipient of the most recently sent email.
+async fn last_recipient(app: &TestApp) -> String {
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: Value = serde_json::from_slice(&email_request.body).unwrap();
+    body["to"]["email"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn issues_are_delivered_only_to_members_of_the_chosen_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    let rust_list = app.create_list("Rust").await;
+    let go_list = app.create_list("Go").await;
+    let rustacean = create_confirmed_subscriber_in_lists(&app, &[rust_list]).await;
+    create_confirmed_subscriber_in_lists(&app, &[go_list]).await;
+    app.login_test_user().await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_issue_to_lists(&app, "<p>Hi</p>", false, &[rust_list]).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(last_recipient(&app).await, rustacean);
+}
+
+#[tokio::test]
+async fn members_of_several_chosen_lists_receive_a_single_copy() {
+    // Arrange
+    let app = spawn_app().await;
+    let rust_list = app.create_list("Rust").await;
+    let go_list = app.create_list("Go").await;
+    create_confirmed_subscriber_in_lists(&app, &[rust_list, go_list]).await;
+    app.login_test_user().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_issue_to_lists(&app, "<p>Hi</p>", false, &[rust_list, go_list]).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Mock verifies on Drop that a single email has been sent
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&list_id={}",
+        uuid::Uuid::new_v4()
+    );
+
+    // Act
+    let response = app.post_subscriptions(body).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn the_subscription_form_offers_every_list() {
+    // Arrange
+    let app = spawn_app().await;
+    let list_id = app.create_list("Rust").await;
+
+    // Act
+    let html_page = app
+        .api_client
+        .get(format!("http://{}/subscriptions", &app.app_address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains(&format!(r#"value="{list_id}"> Rust</label>"#)));
+    assert!(html_page.contains("checked> Newsletter</label>"));
+}
+
+#[tokio::test]
+async fn admins_can_create_a_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act - Part 1 - Create a list
+    let response = app.post_lists(&serde_json::json!({ "name": "Rust" })).await;
+    assert_is_redirect_to(&response, "/admin/lists");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("<p><i>The list 'Rust' has been created.</i></p>"));
+    assert!(html_page.contains("<tr><td>Rust</td><td>0</td></tr>"));
+
+    // Act - Part 3 - Try to create it again
+    app.post_lists(&serde_json::json!({ "name": "Rust" })).await;
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("A list named 'Rust' already exists."));
+}
@@ -1,8 +1,30 @@
 mod admin_dashboard;
+mod admin_roles;
+mod admin_subscribers;
+mod admin_users;
+mod api_tokens;
+mod api_v1;
 mod change_password;
+mod csrf;
+mod exports;
 mod health_check;
 mod helpers;
+mod link_tracking;
 mod login;
+mod login_throttle;
+mod mailing_lists;
 mod newsletter;
+mod open_tracking;
+mod openapi;
+mod password_reset;
+mod personal_data;
+mod segments;
+mod session_timeouts;
+mod sessions;
+mod signup_widget;
+mod subscriber_attributes;
+mod subscriber_import;
+mod subscription_preferences;
 mod subscriptions;
 mod subscriptions_confirm;
+mod two_factor;
@@ -1,17 +1,12 @@
 use std::time::Duration;
 
-use fake::{
-    faker::{internet::en::SafeEmail, name::en::Name},
-    Fake,
-};
 use reqwest::StatusCode;
-use wiremock::{
-    http::Method,
-    matchers::{any, method, path},
-    Mock, MockBuilder, ResponseTemplate,
-};
+use wiremock::{matchers::any, Mock, ResponseTemplate};
 
-use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+    when_sending_an_email,
+};
 
 #[tokio::test]
 pub async fn newsletter_creation_is_idempotent() {
@@ -32,7 +27,7 @@ pub async fn newsletter_creation_is_idempotent() {
         &plaintext=Newsletter%20body%20as%20plain%20text\
         &html=<p>Newsletter%20body%20as%20HTML</p>\
         &idempotency_key={}",
-        uuid::Uuid::new_v4().to_string()
+        uuid::Uuid::new_v4()
     );
     let response = app.post_newsletters(form_data.clone()).await;
     assert_is_redirect_to(&response, "/admin/newsletters");
@@ -73,7 +68,7 @@ pub async fn concurrent_form_submission_is_handled_gracefully() {
         &plaintext=Newsletter%20body%20as%20plain%20text\
         &html=<p>Newsletter%20body%20as%20HTML</p>\
         &idempotency_key={}",
-        uuid::Uuid::new_v4().to_string()
+        uuid::Uuid::new_v4()
     );
     let response1 = app.post_newsletters(form_data.clone());
     let response2 = app.post_newsletters(form_data.clone());
@@ -146,7 +141,7 @@ pub async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
         &plaintext=Newsletter%20body%20as%20plain%20text\
         &html=<p>Newsletter%20body%20as%20HTML</p>\
         &idempotency_key={}",
-        uuid::Uuid::new_v4().to_string()
+        uuid::Uuid::new_v4()
     );
     let response = app.post_newsletters(newsletter_body.to_owned()).await;
 
@@ -174,7 +169,7 @@ pub async fn newsletters_are_delivered_to_confirmed_subscribers() {
         &plaintext=Newsletter%20body%20as%20plain%20text\
         &html=<p>Newsletter%20body%20as%20HTML</p>\
         &idempotency_key={}",
-        uuid::Uuid::new_v4().to_string()
+        uuid::Uuid::new_v4()
     );
 
     let response = app.post_newsletters(newsletter_body.to_owned()).await;
@@ -184,48 +179,3 @@ pub async fn newsletters_are_delivered_to_confirmed_subscribers() {
     app.dispatch_all_pending_emails().await;
     // Mock verifies on drop we have sent the emails
 }
-
-fn when_sending_an_email() -> MockBuilder {
-    Mock::given(path("")).and(method(Method::Post))
-}
-
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_links = create_unconfirmed_subscriber(app).await;
-
-    reqwest::get(confirmation_links.html_link)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
-}
-
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let name: String = Name().fake();
-    let email: String = SafeEmail().fake();
-    let body = format!("name={name}&email={email}")
-        .replace(" ", "%20")
-        .replace("@", "%40");
-
-    // prevent trying to send a confirmation email to Mailtrap
-    let _mock_guard = Mock::given(method(Method::Post))
-        .and(path(""))
-        .respond_with(ResponseTemplate::new(StatusCode::OK))
-        .named("Create unconfirmed subscriber")
-        .expect(1)
-        .mount_as_scoped(&app.email_server)
-        .await;
-
-    app.post_subscriptions(body.to_string())
-        .await
-        .error_for_status()
-        .unwrap();
-
-    let email_request = &app
-        .email_server
-        .received_requests()
-        .await
-        .unwrap()
-        .pop()
-        .unwrap();
-    app.get_confirmation_links(&email_request)
-}
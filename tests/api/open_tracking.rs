@@ -0,0 +1,100 @@
+use reqwest::{header, StatusCode};
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    create_confirmed_subscriber, publish_issue, spawn_app, when_sending_an_email, TestApp,
+};
+
+async fn tracking_pixel_in_last_sent_email(app: &TestApp) -> Option<reqwest::Url> {
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_html_links(&email_request)
+        .into_iter()
+        .find(|l| l.path().starts_with("/t/o/"))
+}
+
+#[tokio::test]
+async fn a_tracking_pixel_is_embedded_in_delivered_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_issue(&app, "<html><body><p>Hello!</p></body></html>", false).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let pixel = tracking_pixel_in_last_sent_email(&app).await.unwrap();
+    let response = app.api_client.get(pixel).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(header::CONTENT_TYPE).unwrap(),
+        "image/gif"
+    );
+}
+
+#[tokio::test]
+async fn opens_are_reported_on_the_admin_dashboard() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app, "<p>Hello!</p>", false).await;
+    app.dispatch_all_pending_emails().await;
+    let pixel = tracking_pixel_in_last_sent_email(&app).await.unwrap();
+
+    // Act - Part 1 - Open the email three times
+    for _ in 0..3 {
+        app.api_client
+            .get(pixel.clone())
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    // Act - Part 2 - Look at the dashboard
+    let html_page = app.get_admin_dashboard_html().await;
+
+    // Assert
+    assert!(html_page.contains("<td>1</td><td>3</td></tr>"));
+}
+
+#[tokio::test]
+async fn a_tampered_tracking_pixel_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app, "<p>Hello!</p>", false).await;
+    app.dispatch_all_pending_emails().await;
+    let mut pixel = tracking_pixel_in_last_sent_email(&app).await.unwrap();
+
+    // Act
+    let tampered_path = pixel.path().replace("/t/o/", "/t/o/x");
+    pixel.set_path(&tampered_path);
+    let response = app.api_client.get(pixel).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
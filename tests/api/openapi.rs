@@ -0,0 +1,130 @@
+use std::collections::BTreeSet;
+
+use crate::helpers::spawn_app;
+
+/// Lists the `(method, path)` of every route registered in `startup::run`,
+/// by reading its source: the test should fail when a handler is routed
+/// without being added to the OpenAPI document.
+fn registered_routes() -> BTreeSet<(String, String)> {
+    let source = include_str!("../../src/startup.rs");
+    let source = &source[source.find("async fn run(").expect("`run` is gone")..];
+
+    fn string_at(s: &str) -> String {
+        s[..s.find('"').unwrap()].to_owned()
+    }
+    fn method_at(s: &str) -> String {
+        s[..s.find('(').unwrap()].to_owned()
+    }
+
+    let mut routes = BTreeSet::new();
+    // The scopes and resources enclosing the current position, with the
+    // depth of parentheses they were opened at.
+    let mut prefixes: Vec<(String, usize)> = Vec::new();
+    let mut depth = 0;
+    for (i, c) in source.char_indices() {
+        let rest = &source[i..];
+        let prefix = prefixes.last().map(|(p, _)| p.as_str()).unwrap_or("");
+        if let Some(rest) = rest
+            .strip_prefix("web::scope(\"")
+            .or_else(|| rest.strip_prefix("web::resource(\""))
+        {
+            prefixes.push((format!("{}{}", prefix, string_at(rest)), depth));
+        } else if let Some(rest) = rest.strip_prefix(".route(") {
+            let rest = rest.trim_start();
+            // Routes of a resource have no path of their own.
+            let path = match rest.strip_prefix('"') {
+                Some(rest) => string_at(rest),
+                None => String::new(),
+            };
+            let method = &rest[rest.find("web::").unwrap() + "web::".len()..];
+            routes.insert((method_at(method), format!("{}{}", prefix, path)));
+        }
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if prefixes.last().is_some_and(|(_, d)| depth < *d) {
+                    prefixes.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+    routes
+}
+
+#[test]
+fn routes_are_read_from_the_startup_module() {
+    let routes = registered_routes();
+
+    for (method, path) in [
+        ("get", "/"),
+        ("post", "/login/2fa"),
+        ("get", "/t/c/{token}"),
+        ("post", "/api/v1/issues/{issue_id}/publish"),
+        ("post", "/admin/logout"),
+    ] {
+        assert!(
+            routes.contains(&(method.to_owned(), path.to_owned())),
+            "{} {} was not found",
+            method,
+            path
+        );
+    }
+}
+
+#[tokio::test]
+async fn every_route_is_in_the_openapi_document() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("http://{}/api/openapi.json", &app.app_address))
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let document: serde_json::Value = response.json().await.unwrap();
+    assert!(document["openapi"].as_str().unwrap().starts_with("3.1"));
+    let missing: Vec<_> = registered_routes()
+        .into_iter()
+        .filter(|(method, path)| document["paths"][path][method].is_null())
+        .collect();
+    assert!(
+        missing.is_empty(),
+        "These routes are not documented: {:?}",
+        missing
+    );
+}
+
+#[tokio::test]
+async fn the_api_docs_are_served_without_external_assets() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+
+    // Act
+    let html = client
+        .get(format!("http://{}/api/docs/", &app.app_address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap();
+    let script = client
+        .get(format!(
+            "http://{}/api/docs/swagger-ui-bundle.js",
+            &app.app_address
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(html.contains("swagger-ui-bundle.js"));
+    assert!(!html.contains("src=\"http"));
+    assert!(!html.contains("href=\"http"));
+    assert_eq!(script.status().as_u16(), 200);
+}
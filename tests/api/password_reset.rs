@@ -0,0 +1,273 @@
+use std::time::{Duration, Instant};
+
+use reqwest::{redirect::Policy, StatusCode};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, when_sending_an_email, TestApp};
+
+const EMAIL: &str = "test-user@example.com";
+
+async fn give_test_user_an_email(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE t_users SET email = $1 WHERE user_id = $2",
+        EMAIL,
+        app.test_user.user_id
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+}
+
+async fn post_password_reset(app: &TestApp, email: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("http://{}/password_reset", &app.app_address))
+        .form(&serde_json::json!({ "email": email }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn post_new_password(
+    client: &reqwest::Client,
+    app: &TestApp,
+    token: &str,
+    password: &str,
+) -> reqwest::Response {
+    client
+        .post(format!(
+            "http://{}/password_reset/confirm",
+            &app.app_address
+        ))
+        .form(&serde_json::json!({
+            "token": token,
+            "new_password": password,
+            "new_password_check": password,
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// The email is sent in the background, after the response.
+async fn wait_for_email(app: &TestApp) -> wiremock::Request {
+    for _ in 0..50 {
+        if let Some(request) = app.email_server.received_requests().await.unwrap().pop() {
+            return request;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("No email was sent.");
+}
+
+/// Requests a reset for the test user and returns the token sent by email.
+async fn request_reset_token(app: &TestApp) -> String {
+    let _mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = post_password_reset(app, &EMAIL.to_uppercase()).await;
+    assert_is_redirect_to(&response, "/password_reset");
+    let email_request = wait_for_email(app).await;
+    let link = app.get_confirmation_links(&email_request).html_link;
+    assert_eq!(link.path(), "/password_reset/confirm");
+    link.query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+#[tokio::test]
+async fn the_login_page_links_to_the_password_reset_form() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let html_page = app.get_login_html().await;
+
+    // Assert
+    assert!(html_page.contains(r#"<a href="/password_reset">Forgot your password?</a>"#));
+}
+
+#[tokio::test]
+async fn requesting_a_reset_for_an_unknown_address_does_not_reveal_it() {
+    // Arrange
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = post_password_reset(&app, "nobody@example.com").await;
+    assert_is_redirect_to(&response, "/password_reset");
+
+    // Assert
+    let html_page = app
+        .api_client
+        .get(format!("http://{}/password_reset", &app.app_address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("If an account uses this address, we have sent it a link"));
+}
+
+#[tokio::test]
+async fn the_response_does_not_wait_for_the_email_to_be_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK).set_delay(Duration::from_secs(3)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let started = Instant::now();
+    let response = post_password_reset(&app, EMAIL).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/password_reset");
+    assert!(started.elapsed() < Duration::from_secs(3));
+    wait_for_email(&app).await;
+}
+
+#[tokio::test]
+async fn the_token_is_stored_hashed() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+
+    // Act
+    let token = request_reset_token(&app).await;
+
+    // Assert
+    let stored = sqlx::query_scalar!("SELECT token_hash FROM t_user_tokens")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored, token);
+}
+
+#[tokio::test]
+async fn a_reset_applies_the_password_length_rules() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    let token = request_reset_token(&app).await;
+
+    // Act
+    let response = post_new_password(&app.api_client, &app, &token, "short").await;
+
+    // Assert
+    let form_page = format!("/password_reset/confirm?token={}", token);
+    assert_is_redirect_to(&response, &form_page);
+    let html_page = app
+        .api_client
+        .get(format!("http://{}{}", &app.app_address, form_page))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(
+        "The new password is too short - its length must be between 8 and 128 characters."
+    ));
+}
+
+#[tokio::test]
+async fn a_reset_rejects_breached_passwords() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    let token = request_reset_token(&app).await;
+
+    // Act
+    let response = post_new_password(&app.api_client, &app, &token, "Password123").await;
+
+    // Assert
+    let form_page = format!("/password_reset/confirm?token={}", token);
+    assert_is_redirect_to(&response, &form_page);
+    let html_page = app
+        .api_client
+        .get(format!("http://{}{}", &app.app_address, form_page))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("This password has appeared in a data breach"));
+}
+
+#[tokio::test]
+async fn a_reset_logs_out_every_session_and_cannot_be_replayed() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    app.login_test_user().await;
+    let token = request_reset_token(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+    let other_client = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+
+    // Act - Part 1 - Reset
+    let response = post_new_password(&other_client, &app, &token, &new_password).await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - The existing session is gone
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 3 - The new password works, the old one does not
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": app.test_user.username,
+            "password": app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": app.test_user.username,
+            "password": new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Assert
+    let response =
+        post_new_password(&other_client, &app, &token, &Uuid::new_v4().to_string()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn expired_tokens_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    give_test_user_an_email(&app).await;
+    let token = request_reset_token(&app).await;
+    sqlx::query!("UPDATE t_user_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = post_new_password(&app.api_client, &app, &token, "a-long-password").await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
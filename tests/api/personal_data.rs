@@ -0,0 +1,305 @@
+use reqwest::{header, StatusCode};
+use serde_json::Value;
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_in_lists, publish_issue, spawn_app,
+    when_sending_an_email, TestApp,
+};
+
+async fn post_privacy_request(app: &TestApp, email: &str, request: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("http://{}/subscriptions/privacy", &app.app_address))
+        .form(&serde_json::json!({ "email": email, "request": request }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+/// Requests a link for `email` and returns the one found in the email sent.
+async fn request_link(app: &TestApp, email: &str, request: &str) -> reqwest::Url {
+    let _mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = post_privacy_request(app, email, request).await;
+    assert_is_redirect_to(&response, "/subscriptions/privacy");
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request).html_link
+}
+
+/// Creates a confirmed subscriber who has received one issue.
+async fn create_subscriber_with_history(app: &TestApp) -> String {
+    let email = create_confirmed_subscriber_in_lists(app, &[]).await;
+    app.login_test_user().await;
+    let _mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_issue(app, "<p>Hello!</p>", false).await;
+    app.dispatch_all_pending_emails().await;
+    app.post_logout().await;
+    email
+}
+
+async fn erasure_log(app: &TestApp) -> Vec<(String, Option<Uuid>, i32)> {
+    sqlx::query!("SELECT email_hash, requested_by_user_id, subscription_count FROM t_erasure_log")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.email_hash, r.requested_by_user_id, r.subscription_count))
+        .collect()
+}
+
+async fn assert_nothing_identifies(app: &TestApp, email: &str) {
+    let remaining = sqlx::query_scalar!(
+        r#"
+        SELECT
+            (SELECT count(*) FROM t_subscriptions WHERE lower(email) = lower($1))
+            + (SELECT count(*) FROM t_issue_deliveries WHERE lower(subscriber_email) = lower($1))
+            + (SELECT count(*) FROM t_preference_tokens)
+            + (SELECT count(*) FROM t_subscription_tokens)
+            + (SELECT count(*) FROM t_list_memberships) AS "remaining!"
+        "#,
+        email
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(remaining, 0);
+}
+
+#[tokio::test]
+async fn requesting_a_link_for_an_unknown_address_does_not_reveal_it() {
+    // Arrange
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Request a link
+    let response = post_privacy_request(&app, "nobody@example.com", "access").await;
+    assert_is_redirect_to(&response, "/subscriptions/privacy");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app
+        .api_client
+        .get(format!("http://{}/subscriptions/privacy", &app.app_address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains(
+        "If we hold data about this address, we have sent it a link to complete your request."
+    ));
+}
+
+#[tokio::test]
+async fn subscribers_can_download_everything_stored_about_them() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_subscriber_with_history(&app).await;
+
+    // Act
+    let link = request_link(&app, &email.to_uppercase(), "access").await;
+    let response = reqwest::get(link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+    let bundle: Value = response.json().await.unwrap();
+    assert_eq!(bundle["subscriptions"][0]["email"], email.as_str());
+    assert_eq!(bundle["subscriptions"][0]["status"], "confirmed");
+    assert_eq!(bundle["subscriptions"][0]["lists"][0], "Newsletter");
+    assert_eq!(bundle["deliveries"][0]["title"], "Newsletter title");
+    assert_eq!(bundle["deliveries"][0]["outcome"], "delivered");
+}
+
+#[tokio::test]
+async fn subscribers_can_erase_their_data() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_subscriber_with_history(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO t_link_clicks (newsletter_issue_id, subscriber_id, url, clicked_at)
+        SELECT i.newsletter_issue_id, s.id, 'https://example.com/?ref=' || s.email, now()
+        FROM t_newsletter_issues i, t_subscriptions s
+        "#
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    let link = request_link(&app, &email, "erasure").await;
+
+    // Act - Part 1 - The link leads to a confirmation form
+    let html_page = reqwest::get(link.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(r#"<form action="/subscriptions/privacy/erasure" method="post">"#));
+    let token = link
+        .query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .into_owned();
+
+    // Act - Part 2 - Confirm
+    let response = app
+        .api_client
+        .post(format!(
+            "http://{}/subscriptions/privacy/erasure",
+            &app.app_address
+        ))
+        .form(&serde_json::json!({ "token": token }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Your personal data has been erased."));
+    assert_nothing_identifies(&app, &email).await;
+    let anonymised = sqlx::query!("SELECT name, status, attributes FROM t_subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(anonymised.name, "Erased subscriber");
+    assert_eq!(anonymised.status, "unsubscribed");
+    let click_url = sqlx::query_scalar!("SELECT url FROM t_link_clicks")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(click_url, "(erased)");
+    let log = erasure_log(&app).await;
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].1, None);
+    assert_eq!(log[0].2, 1);
+    assert!(!log[0].0.contains('@'));
+}
+
+#[tokio::test]
+async fn links_cannot_be_tampered_with_or_reused_for_another_request() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    let mut link = request_link(&app, &email, "access").await;
+
+    // Act - Part 1 - Use an access link to erase
+    let token = link
+        .query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .into_owned();
+    let response = app
+        .api_client
+        .post(format!(
+            "http://{}/subscriptions/privacy/erasure",
+            &app.app_address
+        ))
+        .form(&serde_json::json!({ "token": token }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Act - Part 2 - Tamper with the token
+    link.set_query(Some(&format!("token=x{}", token)));
+    let response = reqwest::get(link).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Assert
+    assert!(erasure_log(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn admins_can_download_and_erase_the_data_of_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_subscriber_with_history(&app).await;
+    let subscriber_id =
+        sqlx::query_scalar!("SELECT id FROM t_subscriptions WHERE email = $1", email)
+            .fetch_one(&app.connection_pool)
+            .await
+            .unwrap();
+    app.login_test_user().await;
+
+    // Act - Part 1 - Download
+    let bundle: Value = app
+        .api_client
+        .get(format!(
+            "http://{}/admin/subscribers/{}/data",
+            &app.app_address, subscriber_id
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(bundle["email"], email.as_str());
+    assert_eq!(bundle["deliveries"].as_array().unwrap().len(), 1);
+
+    // Act - Part 2 - Erase
+    let response = app
+        .post_form(
+            &format!("/admin/subscribers/{}/erase", subscriber_id),
+            &serde_json::json!({}),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    assert_nothing_identifies(&app, &email).await;
+    let log = erasure_log(&app).await;
+    assert_eq!(log[0].1, Some(app.test_user.user_id));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_erase_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    let subscriber_id =
+        sqlx::query_scalar!("SELECT id FROM t_subscriptions WHERE email = $1", email)
+            .fetch_one(&app.connection_pool)
+            .await
+            .unwrap();
+
+    // Act
+    let response = app
+        .post_form(
+            &format!("/admin/subscribers/{}/erase", subscriber_id),
+            &serde_json::json!({}),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert!(erasure_log(&app).await.is_empty());
+}
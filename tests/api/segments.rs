@@ -0,0 +1,266 @@
+use reqwest::StatusCode;
+use serde_json::Value;
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_in_lists, spawn_app, when_sending_an_email,
+    TestApp,
+};
+
+async fn tag_subscriber(app: &TestApp, email: &str, tags: &str) -> reqwest::Response {
+    app.post_form(
+        "/admin/tags",
+        &serde_json::json!({ "email": email, "tags": tags, "action": "add" }),
+    )
+    .await
+}
+
+async fn get_recipient_count(app: &TestApp, query: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!(
+            "http://{}/admin/newsletters/recipients?{query}",
+            &app.app_address
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn recipient_count(app: &TestApp, segment: &str) -> u64 {
+    let query = format!("segment={}", urlencoding::encode(segment));
+    let body: Value = get_recipient_count(app, &query)
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    body["recipients"].as_u64().unwrap()
+}
+
+async fn publish_issue_to_segment(app: &TestApp, segment: &str) -> reqwest::Response {
+    app.post_newsletters(format!(
+        "title=Newsletter%20title\
+        &plaintext=Newsletter%20body%20as%20plain%20text\
+        &html=%3Cp%3EHi%3C%2Fp%3E\
+        &segment={}\
+        &idempotency_key={}",
+        urlencoding::encode(segment),
+        Uuid::new_v4()
+    ))
+    .await
+}
+
+#[tokio::test]
+async fn issues_are_delivered_only_to_subscribers_matching_the_segment() {
+    // Arrange
+    let app = spawn_app().await;
+    let beta_tester = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    create_confirmed_subscriber_in_lists(&app, &[]).await;
+    app.login_test_user().await;
+    let response = tag_subscriber(&app, &beta_tester, "Beta-Tester, vip").await;
+    assert_is_redirect_to(&response, "/admin/tags");
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = publish_issue_to_segment(&app, "tag:beta-tester").await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["to"]["email"], beta_tester.as_str());
+}
+
+#[tokio::test]
+async fn negated_attribute_terms_include_subscribers_without_the_attribute() {
+    // Arrange
+    let app = spawn_app().await;
+    let tester = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    let _ = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    let opted_out = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    for (email, beta) in [(&tester, "true"), (&opted_out, "false")] {
+        sqlx::query!(
+            "UPDATE t_subscriptions SET attributes = jsonb_build_object('beta', $2::text) \
+            WHERE email = $1",
+            email,
+            beta
+        )
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+    }
+    app.login_test_user().await;
+
+    // Act & Assert
+    assert_eq!(recipient_count(&app, "attr.beta:true").await, 1);
+    assert_eq!(recipient_count(&app, "-attr.beta:true").await, 2);
+}
+
+#[tokio::test]
+async fn recipient_count_reflects_the_segment() {
+    // Arrange
+    let app = spawn_app().await;
+    let rust_list = app.create_list("Rust news").await;
+    let veteran = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    let newcomer = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    let rustacean = create_confirmed_subscriber_in_lists(&app, &[rust_list]).await;
+    sqlx::query!(
+        "UPDATE t_subscriptions SET subscribed_at = '2020-01-01' WHERE email = $1",
+        veteran
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    app.login_test_user().await;
+    tag_subscriber(&app, &newcomer, "beta").await;
+    tag_subscriber(&app, &rustacean, "beta").await;
+
+    // Act & Assert
+    assert_eq!(recipient_count(&app, "").await, 2);
+    assert_eq!(recipient_count(&app, "tag:beta").await, 1);
+    assert_eq!(recipient_count(&app, "-tag:beta").await, 1);
+    assert_eq!(
+        recipient_count(&app, "subscribed_before:2021-01-01").await,
+        1
+    );
+    assert_eq!(
+        recipient_count(&app, "subscribed_after:2021-01-01").await,
+        1
+    );
+    let body: Value = get_recipient_count(
+        &app,
+        &format!("list_id={rust_list}&segment=list%3A%22Rust%20news%22"),
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(body["recipients"], 1);
+}
+
+#[tokio::test]
+async fn invalid_segments_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let count_response = get_recipient_count(&app, "segment=country%3Afr").await;
+    let publish_response = publish_issue_to_segment(&app, "subscribed_after:March").await;
+
+    // Assert
+    assert_eq!(count_response.status().as_u16(), 400);
+    assert_eq!(
+        count_response.text().await.unwrap(),
+        "`country` is not a known filter field."
+    );
+    assert_eq!(publish_response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn status_terms_are_rejected_rather_than_sending_to_nobody() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_in_lists(&app, &[]).await;
+    app.login_test_user().await;
+
+    // Act
+    let count_response = get_recipient_count(&app, "segment=status%3Apending_confirmation").await;
+    let publish_response = publish_issue_to_segment(&app, "status:confirmed").await;
+
+    // Assert
+    assert_eq!(count_response.status().as_u16(), 400);
+    assert!(count_response
+        .text()
+        .await
+        .unwrap()
+        .contains("`status` cannot be filtered on"));
+    assert_eq!(publish_response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_newsletter_form_shows_the_default_recipient_count() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_in_lists(&app, &[]).await;
+    app.login_test_user().await;
+
+    // Act
+    let html_page = app.get_newsletters_html().await;
+
+    // Assert
+    assert!(html_page.contains(r#"<output id="recipient-count">1</output>"#));
+}
+
+#[tokio::test]
+async fn invalid_tags_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    app.login_test_user().await;
+
+    // Act - Part 1 - Tag
+    let response = tag_subscriber(&app, &email, "beta tester!").await;
+    assert_is_redirect_to(&response, "/admin/tags");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app
+        .api_client
+        .get(format!("http://{}/admin/tags", &app.app_address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("tester! is not a valid tag."));
+}
+
+#[tokio::test]
+async fn tagging_an_unknown_subscriber_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act - Part 1 - Tag
+    let response = tag_subscriber(&app, "nobody@example.com", "beta").await;
+    assert_is_redirect_to(&response, "/admin/tags");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app
+        .api_client
+        .get(format!("http://{}/admin/tags", &app.app_address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("No subscriber with the email address 'nobody@example.com'."));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_tags() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = tag_subscriber(&app, "ursula@example.com", "beta").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app_with};
+
+#[tokio::test]
+async fn an_idle_session_expires_and_the_page_is_returned_to_after_login() {
+    // Arrange
+    let app = spawn_app_with(|c| c.session_timeouts.idle_seconds = 1).await;
+    app.login_test_user().await;
+    let page = format!("http://{}/admin/subscribers?page=2", &app.app_address);
+
+    // Act - Part 1 - The session expires
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+    let response = app.api_client.get(&page).send().await.unwrap();
+    assert_is_redirect_to(
+        &response,
+        "/login?return_to=%2Fadmin%2Fsubscribers%3Fpage%3D2",
+    );
+
+    // Act - Part 2 - The login page explains why
+    let html_page = app
+        .api_client
+        .get(format!(
+            "http://{}/login?return_to=%2Fadmin%2Fsubscribers%3Fpage%3D2",
+            &app.app_address
+        ))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(
+        "<p><i>You have been logged out after a period of inactivity. Please log in again.</i></p>"
+    ));
+    assert!(html_page
+        .contains(r#"name="return_to" value="&#x2F;admin&#x2F;subscribers&#x3F;page&#x3D;2""#));
+
+    // Act - Part 3 - Logging in again goes back to the page
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "return_to": "/admin/subscribers?page=2",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers?page=2");
+    let response = app.api_client.get(&page).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn a_used_session_still_expires_after_the_absolute_timeout() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.session_timeouts.idle_seconds = 60;
+        c.session_timeouts.absolute_seconds = 2;
+    })
+    .await;
+    app.login_test_user().await;
+
+    // Act
+    for _ in 0..2 {
+        let response = app.get_admin_dashboard().await;
+        assert_eq!(response.status(), StatusCode::OK);
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let response = app.get_admin_dashboard().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login?return_to=%2Fadmin%2Fdashboard");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Your session has expired. Please log in again.</i></p>"));
+}
+
+#[tokio::test]
+async fn return_to_cannot_leave_the_admin_area() {
+    // Arrange
+    let app = spawn_app_with(|_| {}).await;
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "return_to": "https://example.com/admin/",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
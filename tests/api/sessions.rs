@@ -0,0 +1,174 @@
+use reqwest::{redirect::Policy, StatusCode};
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Another browser, logged in as the test user.
+async fn log_in_elsewhere(app: &TestApp) -> reqwest::Client {
+    let client = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .cookie_store(true)
+        .user_agent("Other browser/1.0")
+        .build()
+        .unwrap();
+    let response = app
+        .submit_form(
+            &client,
+            "/login",
+            &serde_json::json!({
+                "username": app.test_user.username,
+                "password": app.test_user.password,
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    client
+}
+
+async fn get_dashboard(app: &TestApp, client: &reqwest::Client) -> reqwest::Response {
+    client
+        .get(format!("http://{}/admin/dashboard", &app.app_address))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn get_sessions_html(app: &TestApp) -> String {
+    app.api_client
+        .get(format!("http://{}/admin/sessions", &app.app_address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+async fn post(app: &TestApp, path: &str) -> reqwest::Response {
+    app.post_form(path, &serde_json::json!({})).await
+}
+
+async fn session_ids(app: &TestApp) -> Vec<Uuid> {
+    sqlx::query_scalar!(
+        "SELECT session_id FROM t_user_sessions WHERE user_id = $1 ORDER BY created_at",
+        app.test_user.user_id
+    )
+    .fetch_all(&app.connection_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn the_sessions_page_lists_every_session_of_the_user() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    log_in_elsewhere(&app).await;
+
+    // Act
+    let html_page = get_sessions_html(&app).await;
+
+    // Assert
+    assert!(html_page.contains("<td>This session</td>"));
+    assert!(html_page.contains("<td>Other browser/1.0</td>"));
+    assert!(html_page.contains("<td>127.0.0.1</td>"));
+    assert_eq!(html_page.matches("/revoke\" method=\"post\">").count(), 1);
+}
+
+#[tokio::test]
+async fn a_revoked_session_is_logged_out() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let other_client = log_in_elsewhere(&app).await;
+    let other_session_id = session_ids(&app).await[1];
+
+    // Act
+    let response = post(
+        &app,
+        &format!("/admin/sessions/{}/revoke", other_session_id),
+    )
+    .await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+
+    // Assert
+    let response = get_dashboard(&app, &other_client).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn sessions_of_other_users_cannot_be_revoked() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = post(&app, &format!("/admin/sessions/{}/revoke", Uuid::new_v4())).await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn all_other_sessions_can_be_revoked_at_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let clients = [log_in_elsewhere(&app).await, log_in_elsewhere(&app).await];
+
+    // Act
+    let response = post(&app, "/admin/sessions/revoke_others").await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+
+    // Assert
+    assert!(get_sessions_html(&app)
+        .await
+        .contains("<p><i>2 other sessions have been logged out.</i></p>"));
+    for client in &clients {
+        let response = get_dashboard(&app, client).await;
+        assert_is_redirect_to(&response, "/login");
+    }
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn changing_the_password_logs_out_other_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let other_client = log_in_elsewhere(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    // Assert
+    let response = get_dashboard(&app, &other_client).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn logging_out_removes_the_session_record() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    assert_eq!(session_ids(&app).await.len(), 1);
+
+    // Act
+    app.post_logout().await;
+
+    // Assert
+    assert!(session_ids(&app).await.is_empty());
+}
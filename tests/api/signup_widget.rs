@@ -0,0 +1,354 @@
+use reqwest::{header, StatusCode};
+use wiremock::{
+    http::Method,
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+
+const BODY: &str = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+impl TestApp {
+    /// The form token embedded in a freshly served widget.
+    async fn get_widget_form_token(&self) -> String {
+        let script = self
+            .api_client
+            .get(format!(
+                "http://{}/subscriptions/widget.js",
+                &self.app_address
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap();
+        let marker = r#""formToken":""#;
+        let token = &script[script.find(marker).unwrap() + marker.len()..];
+        token[..token.find('"').unwrap()].to_owned()
+    }
+
+    async fn count_subscribers(&self) -> i64 {
+        sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM t_subscriptions"#)
+            .fetch_one(&self.connection_pool)
+            .await
+            .unwrap()
+    }
+}
+
+async fn mock_email_server(app: &TestApp, expected_emails: u64) {
+    Mock::given(path(""))
+        .and(method(Method::Post))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(expected_emails)
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn the_widget_script_is_served_with_a_fresh_form_token() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!(
+            "http://{}/subscriptions/widget.js",
+            &app.app_address
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()[header::CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .starts_with("text/javascript"));
+    assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+    let script = response.text().await.unwrap();
+    assert!(script.contains(r#"name = "form_token""#));
+    assert!(!app.get_widget_form_token().await.is_empty());
+}
+
+#[tokio::test]
+async fn signups_sent_faster_than_a_person_could_are_turned_down() {
+    // Arrange
+    let app = spawn_app_with(|c| c.signup_protection.min_fill_seconds = 3).await;
+    mock_email_server(&app, 0).await;
+    let token = app.get_widget_form_token().await;
+
+    // Act
+    let response = app
+        .post_subscriptions_as_is(format!("{}&form_token={}", BODY, token))
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(response.text().await.unwrap().contains("too quickly"));
+    assert_eq!(app.count_subscribers().await, 0);
+}
+
+#[tokio::test]
+async fn signups_filled_in_at_a_human_pace_are_accepted() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app, 1).await;
+    let token = app.get_widget_form_token().await;
+
+    // Act
+    let response = app
+        .post_subscriptions_as_is(format!("{}&website=&form_token={}", BODY, token))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/subscriptions/thank_you");
+    assert_eq!(app.count_subscribers().await, 1);
+}
+
+#[tokio::test]
+async fn a_forged_form_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions_as_is(format!("{}&form_token=eyJhdCI6MH0.forged", BODY))
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(app.count_subscribers().await, 0);
+}
+
+#[tokio::test]
+async fn signups_without_a_form_token_are_refused() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app, 0).await;
+
+    // Act
+    let form = app.post_subscriptions_as_is(BODY.into()).await;
+    let json = app
+        .api_client
+        .post(format!("http://{}/subscriptions", &app.app_address))
+        .json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com"
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(form.status(), StatusCode::BAD_REQUEST);
+    assert!(form.text().await.unwrap().contains("The form is invalid"));
+    assert_eq!(json.status(), StatusCode::BAD_REQUEST);
+    let problem: serde_json::Value = json.json().await.unwrap();
+    assert_eq!(
+        problem["detail"],
+        "The form is invalid. Please reload the page and try again."
+    );
+    assert_eq!(app.count_subscribers().await, 0);
+}
+
+#[tokio::test]
+async fn the_signup_page_embeds_the_bot_traps() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let html_page = app
+        .api_client
+        .get(format!("http://{}/subscriptions", &app.app_address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains(r#"name="website""#));
+    assert!(html_page.contains(r#"<input type="hidden" name="form_token" value=""#));
+}
+
+#[tokio::test]
+async fn a_filled_in_honeypot_drops_the_signup_without_telling_the_bot() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app, 0).await;
+
+    // Act
+    let form = app
+        .post_subscriptions(format!("{}&website=https%3A%2F%2Fspam.example", BODY))
+        .await;
+    let json = app
+        .api_client
+        .post(format!("http://{}/subscriptions", &app.app_address))
+        .json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "website": "https://spam.example"
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_is_redirect_to(&form, "/subscriptions/thank_you");
+    assert_eq!(json.status(), StatusCode::OK);
+    assert_eq!(app.count_subscribers().await, 0);
+}
+
+#[tokio::test]
+async fn signups_are_rate_limited_per_address() {
+    // Arrange
+    let app = spawn_app_with(|c| c.signup_protection.max_attempts_per_address = 2).await;
+    for _ in 0..2 {
+        app.post_subscriptions("name=&email=".into()).await;
+    }
+
+    // Act
+    let form = app.post_subscriptions(BODY.into()).await;
+    let json = app
+        .api_client
+        .post(format!("http://{}/subscriptions", &app.app_address))
+        .json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com"
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(form.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(json.status(), StatusCode::TOO_MANY_REQUESTS);
+    let problem: serde_json::Value = json.json().await.unwrap();
+    assert_eq!(problem["status"], 429);
+    assert_eq!(app.count_subscribers().await, 0);
+}
+
+#[tokio::test]
+async fn allowed_origins_can_post_signups_from_the_browser() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.signup_protection.allowed_origins = vec!["https://blog.example.com".into()]
+    })
+    .await;
+    let preflight = |origin: &'static str| {
+        app.api_client
+            .request(
+                reqwest::Method::OPTIONS,
+                format!("http://{}/subscriptions", &app.app_address),
+            )
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type")
+            .send()
+    };
+
+    // Act
+    let allowed = preflight("https://blog.example.com").await.unwrap();
+    let other = preflight("https://elsewhere.example.com").await.unwrap();
+    let post = app
+        .api_client
+        .post(format!("http://{}/subscriptions", &app.app_address))
+        .header(header::ORIGIN, "https://blog.example.com")
+        .header(header::ACCEPT, "application/json")
+        .form(&[("name", ""), ("email", "")])
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(allowed.status().is_success());
+    assert_eq!(
+        allowed.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://blog.example.com"
+    );
+    assert!(other
+        .headers()
+        .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .is_none());
+    assert_eq!(post.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        post.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://blog.example.com"
+    );
+}
+
+#[tokio::test]
+async fn allowed_origins_can_fetch_a_form_token_for_their_own_form() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.signup_protection.allowed_origins = vec!["https://blog.example.com".into()]
+    })
+    .await;
+    mock_email_server(&app, 1).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!(
+            "http://{}/subscriptions/form_token",
+            &app.app_address
+        ))
+        .header(header::ORIGIN, "https://blog.example.com")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+        "https://blog.example.com"
+    );
+    assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+    let body: serde_json::Value = response.json().await.unwrap();
+    let signup = app
+        .api_client
+        .post(format!("http://{}/subscriptions", &app.app_address))
+        .header(header::ORIGIN, "https://blog.example.com")
+        .header(header::ACCEPT, "application/json")
+        .json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "form_token": body["form_token"],
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(signup.status(), StatusCode::OK);
+    assert_eq!(app.count_subscribers().await, 1);
+}
+
+#[tokio::test]
+async fn the_signup_page_still_posts_from_its_own_origin() {
+    // Arrange
+    let app = spawn_app().await;
+    mock_email_server(&app, 1).await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("http://{}/subscriptions", &app.app_address))
+        .header(header::ORIGIN, format!("http://{}", &app.app_address))
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(format!(
+            "{}&form_token={}",
+            BODY,
+            app.get_widget_form_token().await
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_is_redirect_to(&response, "/subscriptions/thank_you");
+}
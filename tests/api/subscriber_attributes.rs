@@ -0,0 +1,214 @@
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, when_sending_an_email, TestApp};
+
+async fn post_attribute_definition(app: &TestApp, body: &str) -> reqwest::Response {
+    app.post_urlencoded("/admin/attributes", body).await
+}
+
+async fn define_attributes(app: &TestApp) {
+    app.login_test_user().await;
+    for body in [
+        "key=company&label=Company&kind=string&required=true&min=&max=",
+        "key=seats&label=Seats&kind=number&min=1&max=500",
+        "key=beta&label=Beta%20programme&kind=boolean",
+        "key=birthday&label=Birthday&kind=date",
+    ] {
+        let response = post_attribute_definition(app, body).await;
+        assert_is_redirect_to(&response, "/admin/attributes");
+    }
+}
+
+/// Subscribes and confirms `email`, returning the stored attributes.
+async fn subscribe_and_confirm(app: &TestApp, email: &str, attributes: &str) -> Value {
+    let _mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(format!(
+        "name=Ursula&email={}&{attributes}",
+        urlencoding::encode(email)
+    ))
+    .await
+    .error_for_status()
+    .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    sqlx::query_scalar!(
+        "SELECT attributes FROM t_subscriptions WHERE email = $1",
+        email
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn valid_attributes_are_stored_with_their_type() {
+    // Arrange
+    let app = spawn_app().await;
+    define_attributes(&app).await;
+
+    // Act
+    let attributes = subscribe_and_confirm(
+        &app,
+        "ursula@example.com",
+        "attr.company=Earthsea&attr.seats=12&attr.beta=true&attr.birthday=1929-10-21&attr.unknown=x",
+    )
+    .await;
+
+    // Assert
+    assert_eq!(
+        attributes,
+        json!({ "company": "Earthsea", "seats": 12, "beta": true, "birthday": "1929-10-21" })
+    );
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_when_attributes_are_invalid() {
+    // Arrange
+    let app = spawn_app().await;
+    define_attributes(&app).await;
+    let test_cases = vec![
+        ("attr.seats=12", "Company is required."),
+        (
+            "attr.company=Earthsea&attr.seats=many",
+            "Seats must be a number.",
+        ),
+        (
+            "attr.company=Earthsea&attr.seats=0",
+            "Seats must be at least 1.",
+        ),
+        (
+            "attr.company=Earthsea&attr.beta=maybe",
+            "Beta programme must be true or false.",
+        ),
+        (
+            "attr.company=Earthsea&attr.birthday=21/10/1929",
+            "Birthday must be a date formatted as YYYY-MM-DD.",
+        ),
+    ];
+
+    for (attributes, error_message) in test_cases {
+        // Act
+        let response = app
+            .post_subscriptions(format!(
+                "name=Ursula&email=ursula%40example.com&{attributes}"
+            ))
+            .await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 400, "{attributes}");
+        assert_eq!(response.text().await.unwrap(), error_message);
+    }
+}
+
+#[tokio::test]
+async fn the_signup_form_shows_custom_fields() {
+    // Arrange
+    let app = spawn_app().await;
+    define_attributes(&app).await;
+
+    // Act
+    let html_page = app
+        .api_client
+        .get(format!("http://{}/subscriptions", &app.app_address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains(r#"<input type="text" name="attr.company" required>"#));
+    assert!(html_page.contains(r#"<input type="number" step="any" name="attr.seats">"#));
+    assert!(html_page.contains(r#"<input type="checkbox" name="attr.beta" value="true">"#));
+    assert!(html_page.contains(r#"<input type="date" name="attr.birthday">"#));
+}
+
+#[tokio::test]
+async fn invalid_definitions_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response =
+        post_attribute_definition(&app, "key=referral%20source&label=Source&kind=string").await;
+    assert_is_redirect_to(&response, "/admin/attributes");
+
+    // Assert
+    let html_page = app
+        .api_client
+        .get(format!("http://{}/admin/attributes", &app.app_address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("referral source is not a valid field key"));
+}
+
+#[tokio::test]
+async fn attributes_personalise_issues_and_target_segments() {
+    // Arrange
+    let app = spawn_app().await;
+    define_attributes(&app).await;
+    subscribe_and_confirm(&app, "ursula@example.com", "attr.company=Earth%26sea").await;
+    subscribe_and_confirm(&app, "ged@example.com", "attr.company=Roke").await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(format!(
+            "title=News%20for%20{{{{%20attr.company%20}}}}\
+            &plaintext=Hi%20{{{{%20name%20}}}}\
+            &html={}\
+            &segment=attr.company%3A%22Earth%26sea%22\
+            &idempotency_key={}",
+            urlencoding::encode("<p>Seats: {{ attr.seats | unknown }} at {{ attr.company }}</p>"),
+            Uuid::new_v4()
+        ))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["to"]["email"], "ursula@example.com");
+    assert_eq!(body["subject"], "News for Earth&sea");
+    assert!(body["text"].as_str().unwrap().starts_with("Hi Ursula"));
+    assert!(body["html"]
+        .as_str()
+        .unwrap()
+        .contains("<p>Seats: unknown at Earth&amp;sea</p>"));
+}
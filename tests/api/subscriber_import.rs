@@ -0,0 +1,398 @@
+use reqwest::multipart::{Form, Part};
+use reqwest::StatusCode;
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber_in_lists, spawn_app, spawn_app_with,
+    when_sending_an_email, TestApp,
+};
+
+async fn post_import(app: &TestApp, csv: &str, mode: &str, list_ids: &[Uuid]) -> reqwest::Response {
+    let mut form = Form::new()
+        .text("csrf_token", app.csrf_token().await)
+        .text("mode", mode.to_owned());
+    for list_id in list_ids {
+        form = form.text("list_id", list_id.to_string());
+    }
+    form = form.part(
+        "file",
+        Part::bytes(csv.as_bytes().to_vec())
+            .file_name("subscribers.csv")
+            .mime_str("text/csv")
+            .unwrap(),
+    );
+    app.api_client
+        .post(format!(
+            "http://{}/admin/subscribers/import",
+            &app.app_address
+        ))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn get_import_page_html(app: &TestApp) -> String {
+    app.api_client
+        .get(format!(
+            "http://{}/admin/subscribers/import",
+            &app.app_address
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap()
+}
+
+async fn get_import_id(app: &TestApp) -> Uuid {
+    sqlx::query_scalar!("SELECT import_id FROM t_subscriber_imports")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+}
+
+async fn get_rejections_report(app: &TestApp) -> reqwest::Response {
+    let import_id = get_import_id(app).await;
+    app.api_client
+        .get(format!(
+            "http://{}/admin/subscribers/import/{}/rejected.csv",
+            &app.app_address, import_id
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn statuses(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!("SELECT email, status FROM t_subscriptions ORDER BY email")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.email, r.status))
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = post_import(
+        &app,
+        "email,name\nursula@example.com,Ursula\n",
+        "confirmed",
+        &[],
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert!(statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn imported_subscribers_can_be_confirmed_straight_away() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let list_id = app.create_list("Rust news").await;
+    let csv = "name,Email,company\r\nUrsula,ursula@example.com,Earthsea\r\n\"Sparrowhawk, Ged\",ged@example.com,Roke";
+
+    // Act - Part 1 - Import
+    let response = post_import(&app, csv, "confirmed", &[list_id]).await;
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = get_import_page_html(&app).await;
+    assert!(html_page.contains("<p><i>Imported 2 subscribers.</i></p>"));
+
+    // Assert
+    assert_eq!(
+        statuses(&app).await,
+        vec![
+            ("ged@example.com".to_owned(), "confirmed".to_owned()),
+            ("ursula@example.com".to_owned(), "confirmed".to_owned()),
+        ]
+    );
+    let members = sqlx::query_scalar!(
+        "SELECT count(*) FROM t_list_memberships WHERE list_id = $1",
+        list_id
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(members, Some(2));
+    let preference_tokens = sqlx::query_scalar!("SELECT count(*) FROM t_preference_tokens")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(preference_tokens, Some(2));
+}
+
+#[tokio::test]
+async fn imported_subscribers_can_be_sent_a_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let csv = "email,name\nursula@example.com,Ursula\nged@example.com,Ged\n";
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Import
+    let response = post_import(&app, csv, "send_confirmation", &[]).await;
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+
+    // Act - Part 2 - Send the queued confirmation emails
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_requests[0]);
+    reqwest::get(confirmation_links.html_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let mut statuses: Vec<String> = statuses(&app).await.into_iter().map(|s| s.1).collect();
+    statuses.sort();
+    assert_eq!(statuses, vec!["confirmed", "pending_confirmation"]);
+}
+
+#[tokio::test]
+async fn rejected_rows_can_be_downloaded_with_a_reason() {
+    // Arrange
+    let app = spawn_app().await;
+    let existing_email = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    app.login_test_user().await;
+    let csv = format!(
+        "email,name\n\
+        ursula@example.com,Ursula\n\
+        not-an-email,Ged\n\
+        URSULA@example.com,\"Ursula, again\"\n\
+        {},Somebody\n\
+        tenar@example.com,\n",
+        existing_email.to_uppercase()
+    );
+
+    // Act - Part 1 - Import
+    let response = post_import(&app, &csv, "confirmed", &[]).await;
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = get_import_page_html(&app).await;
+    assert!(html_page.contains("Imported 1 subscriber. 4 rows were rejected"));
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/subscribers/import/{}/rejected.csv">"#,
+        get_import_id(&app).await
+    )));
+
+    // Act - Part 3 - Download the report
+    let response = get_rejections_report(&app).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    assert!(response.headers()["Content-Disposition"]
+        .to_str()
+        .unwrap()
+        .starts_with("attachment"));
+    let report = response.text().await.unwrap();
+    assert_eq!(
+        report,
+        format!(
+            "row,email,name,reason\r\n\
+            3,not-an-email,Ged,not-an-email is not a valid email address.\r\n\
+            4,URSULA@example.com,\"Ursula, again\",Duplicate of row 2.\r\n\
+            5,{},Somebody,Already subscribed.\r\n\
+            6,tenar@example.com,, is not a valid subscruber name.\r\n",
+            existing_email.to_uppercase()
+        )
+    );
+    assert_eq!(statuses(&app).await.len(), 2);
+}
+
+#[tokio::test]
+async fn a_file_without_email_and_name_columns_is_refused() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act - Part 1 - Import
+    let response = post_import(
+        &app,
+        "address,full name\nursula@example.com,Ursula\n",
+        "confirmed",
+        &[],
+    )
+    .await;
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = get_import_page_html(&app).await;
+
+    // Assert
+    assert!(html_page.contains(
+        "<p><i>The file must have a header row with an `email` and a `name` column.</i></p>"
+    ));
+    assert!(statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn the_report_of_an_unknown_import_is_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!(
+            "http://{}/admin/subscribers/import/{}/rejected.csv",
+            &app.app_address,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn files_larger_than_the_limit_are_refused() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscriber_import.max_file_bytes = 64).await;
+    app.login_test_user().await;
+    let csv = format!(
+        "email,name\n{}",
+        (0..10)
+            .map(|i| format!("reader{}@example.com,Reader\n", i))
+            .collect::<String>()
+    );
+
+    // Act - Part 1 - Import
+    let response = post_import(&app, &csv, "confirmed", &[]).await;
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = get_import_page_html(&app).await;
+
+    // Assert
+    assert!(html_page.contains("<p><i>The file is larger than the limit of 64 bytes.</i></p>"));
+    assert!(statuses(&app).await.is_empty());
+    let imports = sqlx::query_scalar!("SELECT count(*) FROM t_subscriber_imports")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(imports, Some(0));
+}
+
+#[tokio::test]
+async fn files_larger_than_a_batch_are_imported_whole() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let csv = format!(
+        "email,name\n{}",
+        (0..2500)
+            .map(|i| format!(
+                "reader{}@example.com,Reader\nnot-an-email-{},Reader\n",
+                i, i
+            ))
+            .collect::<String>()
+    );
+
+    // Act - Part 1 - Import
+    let response = post_import(&app, &csv, "confirmed", &[]).await;
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = get_import_page_html(&app).await;
+
+    // Assert
+    assert!(html_page.contains("Imported 2500 subscribers. 2500 rows were rejected"));
+    assert_eq!(statuses(&app).await.len(), 2500);
+    let rejections = sqlx::query_scalar!("SELECT count(*) FROM t_subscriber_import_rejections")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(rejections, Some(2500));
+}
+
+#[tokio::test]
+async fn the_file_must_come_after_the_import_mode() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let form = Form::new()
+        .text("csrf_token", app.csrf_token().await)
+        .part(
+            "file",
+            Part::bytes(b"email,name\nursula@example.com,Ursula\n".to_vec())
+                .file_name("subscribers.csv")
+                .mime_str("text/csv")
+                .unwrap(),
+        )
+        .text("mode", "confirmed");
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!(
+            "http://{}/admin/subscribers/import",
+            &app.app_address
+        ))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn the_csrf_token_is_not_accepted_in_the_query_string() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let html_page = get_import_page_html(&app).await;
+    assert!(html_page.contains(r#"<form action="/admin/subscribers/import" method="post""#));
+    let form = Form::new().text("mode", "confirmed").part(
+        "file",
+        Part::bytes(b"email,name\nursula@example.com,Ursula\n".to_vec())
+            .file_name("subscribers.csv")
+            .mime_str("text/csv")
+            .unwrap(),
+    );
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!(
+            "http://{}/admin/subscribers/import?csrf_token={}",
+            &app.app_address,
+            app.csrf_token().await
+        ))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert!(statuses(&app).await.is_empty());
+}
@@ -0,0 +1,300 @@
+use reqwest::StatusCode;
+use serde_json::Value;
+use uuid::Uuid;
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_confirmed_subscriber_in_lists,
+    publish_issue, spawn_app, when_sending_an_email, TestApp,
+};
+
+async fn preference_token(app: &TestApp, email: &str) -> String {
+    sqlx::query!(
+        r#"
+        SELECT t.preference_token
+        FROM t_preference_tokens t
+        JOIN t_subscriptions s ON s.id = t.subscriber_id
+        WHERE s.email = $1
+        "#,
+        email
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap()
+    .preference_token
+}
+
+async fn get_preferences(app: &TestApp, token: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!(
+            "http://{}/subscriptions/preferences?token={token}",
+            &app.app_address
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn post_preferences(app: &TestApp, body: String) -> reqwest::Response {
+    app.api_client
+        .post(format!(
+            "http://{}/subscriptions/preferences",
+            &app.app_address
+        ))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn post_unsubscribe(app: &TestApp, token: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!(
+            "http://{}/subscriptions/unsubscribe",
+            &app.app_address
+        ))
+        .form(&[("token", token)])
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+fn default_list_id() -> Uuid {
+    Uuid::parse_str("5b1f8ac3-6f0e-4bb1-9d0a-0c5d8cb0e2a4").unwrap()
+}
+
+#[tokio::test]
+async fn issue_emails_link_to_the_preference_center() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_issue(&app, "<p>Hi</p>", false).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: Value = serde_json::from_slice(&email_request.body).unwrap();
+    let link = app
+        .get_html_links(&email_request)
+        .into_iter()
+        .find(|l| l.path() == "/subscriptions/preferences")
+        .unwrap();
+    assert!(body["text"]
+        .as_str()
+        .unwrap()
+        .contains(link.query().unwrap()));
+    let response = reqwest::get(link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Subscription preferences"));
+}
+
+#[tokio::test]
+async fn unknown_preference_tokens_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = get_preferences(&app, "not-a-real-token").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn subscribers_can_update_their_preferences() {
+    // Arrange
+    let app = spawn_app().await;
+    let rust_list = app.create_list("Rust").await;
+    let email = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    let token = preference_token(&app, &email).await;
+
+    // Act - Part 1 - Update
+    let response = post_preferences(
+        &app,
+        format!(
+            "token={token}&name=Ursula&list_id={rust_list}&digest_frequency=weekly&pause_days="
+        ),
+    )
+    .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/subscriptions/preferences?token={token}"),
+    );
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = get_preferences(&app, &token).await.text().await.unwrap();
+    assert!(html_page.contains("Your preferences have been updated."));
+
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT id, name, digest_frequency, paused_until FROM t_subscriptions WHERE email = $1",
+        email
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.name, "Ursula");
+    assert_eq!(saved.digest_frequency, "weekly");
+    assert!(saved.paused_until.is_none());
+    let lists: Vec<Uuid> = sqlx::query_scalar!(
+        "SELECT list_id FROM t_list_memberships WHERE subscriber_id = $1",
+        saved.id
+    )
+    .fetch_all(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(lists, vec![rust_list]);
+}
+
+#[tokio::test]
+async fn deselecting_every_list_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    let token = preference_token(&app, &email).await;
+
+    // Act
+    let response = post_preferences(
+        &app,
+        format!("token={token}&name=Ursula&digest_frequency=immediate"),
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(
+        &response,
+        &format!("/subscriptions/preferences?token={token}"),
+    );
+    let html_page = get_preferences(&app, &token).await.text().await.unwrap();
+    assert!(html_page.contains("Pick at least one topic, or unsubscribe instead."));
+}
+
+#[tokio::test]
+async fn paused_subscribers_do_not_receive_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    let token = preference_token(&app, &email).await;
+    post_preferences(
+        &app,
+        format!(
+            "token={token}&name=Ursula&list_id={}&digest_frequency=immediate&pause_days=30",
+            default_list_id()
+        ),
+    )
+    .await;
+    app.login_test_user().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_issue(&app, "<p>Hi</p>", false).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    let token = preference_token(&app, &email).await;
+
+    // Act - Part 1 - Unsubscribe
+    let response = post_unsubscribe(&app, &token).await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/subscriptions/preferences?token={token}"),
+    );
+    let html_page = get_preferences(&app, &token).await.text().await.unwrap();
+    assert!(html_page.contains("You have been unsubscribed."));
+
+    // Act - Part 2 - Publish
+    app.login_test_user().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app, "<p>Hi</p>", false).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn digest_subscribers_receive_pending_issues_in_a_single_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = create_confirmed_subscriber_in_lists(&app, &[]).await;
+    let token = preference_token(&app, &email).await;
+    post_preferences(
+        &app,
+        format!(
+            "token={token}&name=Ursula&list_id={}&digest_frequency=daily",
+            default_list_id()
+        ),
+    )
+    .await;
+    app.login_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(StatusCode::OK))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Nothing goes out before the digest is due
+    let confirmation_emails = app.email_server.received_requests().await.unwrap().len();
+    publish_issue(&app, "<p>First issue</p>", false).await;
+    publish_issue(&app, "<p>Second issue</p>", false).await;
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(
+        app.email_server.received_requests().await.unwrap().len(),
+        confirmation_emails
+    );
+
+    // Act - Part 2 - Make the digest due
+    sqlx::query!("UPDATE t_issue_delivery_queue SET execute_after = now() - interval '1 minute'")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html = body["html"].as_str().unwrap();
+    assert!(html.contains("First issue"));
+    assert!(html.contains("Second issue"));
+    assert_eq!(body["subject"], "Your daily digest: 2 new issues");
+}
@@ -5,10 +5,10 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::spawn_app;
+use crate::helpers::{assert_is_redirect_to, spawn_app};
 
 #[tokio::test]
-async fn subscribe_returns_200_when_valid_form_data() {
+async fn subscribe_redirects_to_the_thank_you_page_when_valid_form_data() {
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
 
@@ -20,8 +20,113 @@ async fn subscribe_returns_200_when_valid_form_data() {
         .await;
 
     let response = app.post_subscriptions(body.into()).await;
+    assert_is_redirect_to(&response, "/subscriptions/thank_you");
+
+    let html_page = app
+        .api_client
+        .get(format!(
+            "http://{}/subscriptions/thank_you",
+            &app.app_address
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("Thank you for subscribing!"));
+}
+
+#[tokio::test]
+async fn subscribe_accepts_json_and_answers_with_json() {
+    let app = spawn_app().await;
+
+    Mock::given(path(""))
+        .and(method(Method::Post))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .api_client
+        .post(format!("http://{}/subscriptions", &app.app_address))
+        .json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "form_token": app.signup_form_token().await
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
 
     assert_eq!(StatusCode::OK, response.status());
+    let receipt: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(receipt["status"], "pending_confirmation");
+    let saved = sqlx::query!("SELECT email, name FROM t_subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.name, "le guin");
+}
+
+#[tokio::test]
+async fn subscribe_reports_every_invalid_field_to_json_clients() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("http://{}/subscriptions", &app.app_address))
+        .json(&serde_json::json!({
+            "name": "",
+            "email": "not-an-email",
+            "form_token": app.signup_form_token().await
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    let fields: Vec<&str> = problem["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["email", "name"]);
+    let saved = sqlx::query!("SELECT email FROM t_subscriptions")
+        .fetch_optional(&app.connection_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn subscribe_answers_form_posts_with_json_when_it_is_accepted() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("http://{}/subscriptions", &app.app_address))
+        .header("Accept", "application/json")
+        .form(&[
+            ("name", "le guin"),
+            ("email", ""),
+            ("form_token", &app.signup_form_token().await),
+        ])
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["errors"][0]["field"], "email");
 }
 
 #[tokio::test]
@@ -0,0 +1,232 @@
+use hmac::{Hmac, Mac};
+use reqwest::StatusCode;
+use sha1::Sha1;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// The TOTP code `steps` periods away from now, computed independently of the
+/// application.
+fn totp_code(secret: &[u8], steps: i64) -> String {
+    let step = chrono::Utc::now().timestamp() / 30 + steps;
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).unwrap();
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[19] & 0x0f) as usize;
+    let truncated = u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap());
+    format!("{:06}", (truncated & 0x7fff_ffff) % 1_000_000)
+}
+
+async fn post_2fa(app: &TestApp, path: &str, code: &str) -> reqwest::Response {
+    app.post_form(path, &serde_json::json!({ "code": code }))
+        .await
+}
+
+async fn get_html(app: &TestApp, path: &str) -> String {
+    app.api_client
+        .get(format!("http://{}{}", &app.app_address, path))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap()
+}
+
+async fn stored_secret(app: &TestApp) -> Vec<u8> {
+    sqlx::query_scalar!(
+        r#"SELECT totp_secret AS "totp_secret!" FROM t_users WHERE user_id = $1"#,
+        app.test_user.user_id
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap()
+}
+
+/// Turns on two-factor authentication for the logged-in test user and returns
+/// the secret and the recovery codes.
+async fn enable_two_factor(app: &TestApp) -> (Vec<u8>, Vec<String>) {
+    let response = post_2fa(app, "/admin/2fa/setup", "").await;
+    assert_is_redirect_to(&response, "/admin/2fa");
+    let secret = stored_secret(app).await;
+    let response = post_2fa(app, "/admin/2fa/confirm", &totp_code(&secret, 0)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let html_page = response.text().await.unwrap();
+    let recovery_codes = html_page
+        .split("<li><code>")
+        .skip(1)
+        .map(|s| s.split("</code>").next().unwrap().to_owned())
+        .collect();
+    (secret, recovery_codes)
+}
+
+/// Logs out and enters the password again, which leaves the login waiting
+/// for a second factor.
+async fn log_in_again(app: &TestApp) {
+    app.post_logout().await;
+    let response = app.login_test_user().await;
+    assert_is_redirect_to(&response, "/login/2fa");
+}
+
+#[tokio::test]
+async fn enrolment_shows_the_setup_key_and_requires_a_valid_code() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    // Act - Part 1 - Start the setup
+    post_2fa(&app, "/admin/2fa/setup", "").await;
+    let html_page = get_html(&app, "/admin/2fa").await;
+    assert!(html_page.contains("Setup key: <code>"));
+    assert!(html_page.contains("Open in an authenticator app"));
+
+    // Act - Part 2 - A wrong code does not turn it on
+    let secret = stored_secret(&app).await;
+    let wrong_code = format!(
+        "{:06}",
+        (totp_code(&secret, 0).parse::<u32>().unwrap() + 1) % 1_000_000
+    );
+    let response = post_2fa(&app, "/admin/2fa/confirm", &wrong_code).await;
+    assert_is_redirect_to(&response, "/admin/2fa");
+    let html_page = get_html(&app, "/admin/2fa").await;
+    assert!(html_page.contains("<p><i>The code is incorrect.</i></p>"));
+
+    // Act - Part 3 - Logging in does not ask for a code yet
+    app.post_logout().await;
+    let response = app.login_test_user().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Act - Part 4 - A valid code turns it on
+    let (_, recovery_codes) = enable_two_factor(&app).await;
+
+    // Assert
+    assert_eq!(recovery_codes.len(), 10);
+    let html_page = get_html(&app, "/admin/2fa").await;
+    assert!(
+        html_page.contains("Two-factor authentication is on. You have 10 unused recovery codes.")
+    );
+}
+
+#[tokio::test]
+async fn a_pending_login_is_not_logged_in_until_the_code_is_entered() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let (secret, _) = enable_two_factor(&app).await;
+    log_in_again(&app).await;
+
+    // Act - Part 1 - The admin area sends back to the code form
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login/2fa");
+
+    // Act - Part 2 - The code used to turn it on cannot be replayed
+    let response = post_2fa(&app, "/login/2fa", &totp_code(&secret, 0)).await;
+    assert_is_redirect_to(&response, "/login/2fa");
+    let html_page = get_html(&app, "/login/2fa").await;
+    assert!(html_page.contains("<p><i>The code is incorrect.</i></p>"));
+
+    // Act - Part 3 - A newer code logs in
+    let response = post_2fa(&app, "/login/2fa", &totp_code(&secret, 1)).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Assert
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn recovery_codes_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let (_, recovery_codes) = enable_two_factor(&app).await;
+
+    // Act - Part 1 - A recovery code logs in, whatever its case
+    log_in_again(&app).await;
+    let response = post_2fa(&app, "/login/2fa", &recovery_codes[0].to_uppercase()).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Act - Part 2 - It cannot be used a second time
+    log_in_again(&app).await;
+    let response = post_2fa(&app, "/login/2fa", &recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/login/2fa");
+
+    // Assert
+    let response = post_2fa(&app, "/login/2fa", &recovery_codes[1]).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html_page = get_html(&app, "/admin/2fa").await;
+    assert!(html_page.contains("You have 8 unused recovery codes."));
+}
+
+#[tokio::test]
+async fn too_many_incorrect_codes_require_the_password_again() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let (secret, _) = enable_two_factor(&app).await;
+    log_in_again(&app).await;
+
+    // Act
+    for _ in 0..4 {
+        let response = post_2fa(&app, "/login/2fa", "not-a-code").await;
+        assert_is_redirect_to(&response, "/login/2fa");
+    }
+    let response = post_2fa(&app, "/login/2fa", "not-a-code").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many incorrect codes. Please log in again."));
+    let response = post_2fa(&app, "/login/2fa", &totp_code(&secret, 1)).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn incorrect_codes_count_towards_the_login_lockout() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    enable_two_factor(&app).await;
+
+    // Act
+    for _ in 0..5 {
+        log_in_again(&app).await;
+        let response = post_2fa(&app, "/login/2fa", "not-a-code").await;
+        assert_is_redirect_to(&response, "/login/2fa");
+    }
+    let response = app.login_test_user().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(
+        html_page.contains("<p><i>Too many failed login attempts. Please try again later.</i></p>")
+    );
+}
+
+#[tokio::test]
+async fn turning_off_two_factor_requires_a_code() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let (_, recovery_codes) = enable_two_factor(&app).await;
+
+    // Act - Part 1 - Without a valid code
+    let response = post_2fa(&app, "/admin/2fa/disable", "123").await;
+    assert_is_redirect_to(&response, "/admin/2fa");
+
+    // Act - Part 2 - With a recovery code
+    let response = post_2fa(&app, "/admin/2fa/disable", &recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/admin/2fa");
+
+    // Assert
+    let html_page = get_html(&app, "/admin/2fa").await;
+    assert!(html_page.contains("Two-factor authentication has been turned off."));
+    app.post_logout().await;
+    let response = app.login_test_user().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let remaining = sqlx::query_scalar!("SELECT count(*) FROM t_recovery_codes")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, Some(0));
+}